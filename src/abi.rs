@@ -0,0 +1,561 @@
+//! Encodes RPC arguments, and decodes RPC outputs, in the format that the generated TypeScript
+//! clients produce from the schemas of `gen::typescript::quote_schema_ty`: little-endian
+//! numbers, a `u32` length before variable-length values, and a `u8` tag before options,
+//! results, and enum variants. Values are represented as JSON.
+
+use std::convert::TryInto as _;
+
+use oasis_rpc::{EnumFields, Type, TypeDef};
+use serde_json::{Map, Value};
+
+use crate::errors::Result;
+
+/// Encodes `json_values` as the consecutive arguments of `types`. `type_defs` are the types
+/// defined by the interface, which `Type::Defined` refers to.
+pub fn encode_args(
+    type_defs: &[TypeDef],
+    types: &[Type],
+    json_values: &[Value],
+) -> Result<Vec<u8>> {
+    if types.len() != json_values.len() {
+        bail!(
+            "expected {} argument(s), but {} were given",
+            types.len(),
+            json_values.len()
+        );
+    }
+    let codec = Codec { type_defs };
+    let mut buf = Vec::new();
+    for (ty, value) in types.iter().zip(json_values.iter()) {
+        codec.encode(ty, value, &mut buf)?;
+    }
+    Ok(buf)
+}
+
+/// Decodes `bytes`, which must contain exactly one value of type `ty`, into JSON.
+#[cfg_attr(not(test), allow(dead_code))] // Used by tooling that reads RPC outputs.
+pub fn decode(type_defs: &[TypeDef], ty: &Type, mut bytes: &[u8]) -> Result<Value> {
+    let value = Codec { type_defs }.decode(ty, &mut bytes)?;
+    if !bytes.is_empty() {
+        bail!("{} unexpected byte(s) after the value", bytes.len());
+    }
+    Ok(value)
+}
+
+/// Removes a little-endian `$ty` from the front of `$bytes`.
+macro_rules! decode_le {
+    ($ty:ty, $bytes:expr) => {
+        <$ty>::from_le_bytes(
+            take($bytes, std::mem::size_of::<$ty>())?
+                .try_into()
+                .unwrap(),
+        )
+    };
+}
+
+struct Codec<'a> {
+    type_defs: &'a [TypeDef],
+}
+
+impl<'a> Codec<'a> {
+    fn type_def(&self, namespace: &Option<String>, name: &str) -> Result<&'a TypeDef> {
+        if let Some(namespace) = namespace {
+            bail!(
+                "`{}::{}` is imported, which is not yet supported",
+                namespace,
+                name
+            );
+        }
+        self.type_defs
+            .iter()
+            .find(|type_def| type_def_name(type_def) == name)
+            .ok_or_else(|| anyhow!("the interface does not define `{}`", name))
+    }
+
+    fn encode(&self, ty: &Type, value: &Value, buf: &mut Vec<u8>) -> Result<()> {
+        match ty {
+            Type::Bool => match value {
+                Value::Bool(b) => buf.push(*b as u8),
+                _ => bail!("expected a boolean, but found `{}`", value),
+            },
+            Type::U8 => buf.push(uint(value, 8)? as u8),
+            Type::I8 => buf.push(int(value, 8)? as u8),
+            Type::U16 => buf.extend(&(uint(value, 16)? as u16).to_le_bytes()),
+            Type::I16 => buf.extend(&(int(value, 16)? as i16).to_le_bytes()),
+            Type::U32 => buf.extend(&(uint(value, 32)? as u32).to_le_bytes()),
+            Type::I32 => buf.extend(&(int(value, 32)? as i32).to_le_bytes()),
+            Type::U64 => buf.extend(&uint(value, 64)?.to_le_bytes()),
+            Type::I64 => buf.extend(&int(value, 64)?.to_le_bytes()),
+            Type::F32 => buf.extend(&(float(value)? as f32).to_le_bytes()),
+            Type::F64 => buf.extend(&float(value)?.to_le_bytes()),
+            Type::Bytes => {
+                let bytes = bytes(value)?;
+                encode_len(bytes.len(), buf);
+                buf.extend(bytes);
+            }
+            Type::String => match value {
+                Value::String(s) => {
+                    encode_len(s.len(), buf);
+                    buf.extend(s.as_bytes());
+                }
+                _ => bail!("expected a string, but found `{}`", value),
+            },
+            Type::Address => {
+                let addr = bytes(value)?;
+                if addr.len() != 20 {
+                    bail!("expected a 20-byte address, but found `{}`", value);
+                }
+                buf.extend(addr);
+            }
+            Type::Balance => {
+                let balance = match value {
+                    Value::Number(n) => n.as_u64().map(u128::from),
+                    Value::String(s) => s.parse().ok(),
+                    _ => None,
+                };
+                match balance {
+                    Some(balance) => buf.extend(&u128::to_le_bytes(balance)),
+                    None => bail!("expected a balance, but found `{}`", value),
+                }
+            }
+            Type::RpcError => bail!("`RpcError` cannot be encoded"),
+            Type::Defined { namespace, ty } => match self.type_def(namespace, ty)? {
+                TypeDef::Struct { fields, .. } => self.encode_fields(
+                    fields.iter().map(|f| (f.name.as_str(), &f.ty)).collect(),
+                    value,
+                    buf,
+                )?,
+                TypeDef::Event { fields, .. } => self.encode_fields(
+                    fields.iter().map(|f| (f.name.as_str(), &f.ty)).collect(),
+                    value,
+                    buf,
+                )?,
+                TypeDef::Enum { variants, .. } => {
+                    // A unit variant is its name and any other is `{ "Variant": fields }`.
+                    let (variant_name, fields_value) = match value {
+                        Value::String(name) => (name.as_str(), &Value::Null),
+                        Value::Object(obj) if obj.len() == 1 => {
+                            let (name, fields_value) = obj.iter().next().unwrap();
+                            (name.as_str(), fields_value)
+                        }
+                        _ => bail!("expected a variant of `{}`, but found `{}`", ty, value),
+                    };
+                    let (i, variant) = variants
+                        .iter()
+                        .enumerate()
+                        .find(|(_, v)| v.name == variant_name)
+                        .ok_or_else(|| {
+                            anyhow!("`{}` has no variant named `{}`", ty, variant_name)
+                        })?;
+                    buf.push(i as u8);
+                    match &variant.fields {
+                        Some(EnumFields::Named(fields)) => self.encode_fields(
+                            fields.iter().map(|f| (f.name.as_str(), &f.ty)).collect(),
+                            fields_value,
+                            buf,
+                        )?,
+                        Some(EnumFields::Tuple(tys)) => match fields_value {
+                            Value::Array(_) => {
+                                self.encode_seq(tys.iter().collect(), fields_value, buf)?
+                            }
+                            _ if tys.len() == 1 => self.encode(&tys[0], fields_value, buf)?,
+                            _ => bail!("expected the fields of `{}`", variant_name),
+                        },
+                        None => (),
+                    }
+                }
+            },
+            Type::Tuple(tys) => self.encode_seq(tys.iter().collect(), value, buf)?,
+            Type::Array(ty, len) => match (&**ty, value) {
+                (Type::U8, Value::String(_)) => {
+                    let bytes = bytes(value)?;
+                    if bytes.len() as u64 != *len {
+                        bail!("expected {} bytes, but found `{}`", len, value);
+                    }
+                    buf.extend(bytes);
+                }
+                _ => self.encode_seq(vec![&**ty; *len as usize], value, buf)?,
+            },
+            Type::List(ty) | Type::Set(ty) => match value {
+                Value::Array(items) => {
+                    encode_len(items.len(), buf);
+                    for item in items.iter() {
+                        self.encode(ty, item, buf)?;
+                    }
+                }
+                _ => bail!("expected an array, but found `{}`", value),
+            },
+            Type::Map(k_ty, v_ty) => match value {
+                // Non-string keys are given as their JSON (e.g., `{ "1": "one" }`).
+                Value::Object(entries) => {
+                    encode_len(entries.len(), buf);
+                    for (k, v) in entries.iter() {
+                        let k = match &**k_ty {
+                            Type::String => Value::String(k.to_string()),
+                            _ => serde_json::from_str(k)
+                                .map_err(|_| anyhow!("`{}` is not a valid key", k))?,
+                        };
+                        self.encode(k_ty, &k, buf)?;
+                        self.encode(v_ty, v, buf)?;
+                    }
+                }
+                _ => bail!("expected an object, but found `{}`", value),
+            },
+            Type::Optional(ty) => match value {
+                Value::Null => buf.push(0),
+                _ => {
+                    buf.push(1);
+                    self.encode(ty, value, buf)?;
+                }
+            },
+            Type::Result(ok_ty, err_ty) => match value.as_object().map(|obj| obj.iter().next()) {
+                Some(Some((tag, ok))) if tag == "Ok" => {
+                    buf.push(0);
+                    self.encode(ok_ty, ok, buf)?;
+                }
+                Some(Some((tag, err))) if tag == "Err" => {
+                    buf.push(1);
+                    self.encode(err_ty, err, buf)?;
+                }
+                _ => bail!(
+                    "expected `{{\"Ok\": ...}}` or `{{\"Err\": ...}}`, but found `{}`",
+                    value
+                ),
+            },
+        }
+        Ok(())
+    }
+
+    /// Encodes the fields of a struct from an object or, for a tuple struct, an array.
+    fn encode_fields(
+        &self,
+        fields: Vec<(&str, &Type)>,
+        value: &Value,
+        buf: &mut Vec<u8>,
+    ) -> Result<()> {
+        match value {
+            Value::Object(obj) => {
+                for (name, ty) in fields {
+                    let field_value = obj
+                        .get(name)
+                        .ok_or_else(|| anyhow!("missing field `{}` in `{}`", name, value))?;
+                    self.encode(ty, field_value, buf)?;
+                }
+                Ok(())
+            }
+            Value::Array(_) => {
+                self.encode_seq(fields.into_iter().map(|(_, ty)| ty).collect(), value, buf)
+            }
+            _ => bail!("expected an object, but found `{}`", value),
+        }
+    }
+
+    fn encode_seq(&self, tys: Vec<&Type>, value: &Value, buf: &mut Vec<u8>) -> Result<()> {
+        match value {
+            Value::Array(items) if items.len() == tys.len() => {
+                for (ty, item) in tys.into_iter().zip(items.iter()) {
+                    self.encode(ty, item, buf)?;
+                }
+                Ok(())
+            }
+            _ => bail!(
+                "expected an array of {} items, but found `{}`",
+                tys.len(),
+                value
+            ),
+        }
+    }
+
+    fn decode(&self, ty: &Type, bytes: &mut &[u8]) -> Result<Value> {
+        Ok(match ty {
+            Type::Bool => match decode_le!(u8, bytes) {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                b => bail!("`{}` is not a boolean", b),
+            },
+            Type::U8 => decode_le!(u8, bytes).into(),
+            Type::I8 => decode_le!(i8, bytes).into(),
+            Type::U16 => decode_le!(u16, bytes).into(),
+            Type::I16 => decode_le!(i16, bytes).into(),
+            Type::U32 => decode_le!(u32, bytes).into(),
+            Type::I32 => decode_le!(i32, bytes).into(),
+            Type::U64 => decode_le!(u64, bytes).into(),
+            Type::I64 => decode_le!(i64, bytes).into(),
+            Type::F32 => decode_le!(f32, bytes).into(),
+            Type::F64 => decode_le!(f64, bytes).into(),
+            Type::Bytes => {
+                let len = decode_len(bytes)?;
+                Value::String(format!("0x{}", hex::encode(take(bytes, len)?)))
+            }
+            Type::String => {
+                let len = decode_len(bytes)?;
+                Value::String(String::from_utf8(take(bytes, len)?.to_vec())?)
+            }
+            Type::Address => Value::String(format!("0x{}", hex::encode(take(bytes, 20)?))),
+            Type::Balance => Value::String(decode_le!(u128, bytes).to_string()),
+            Type::RpcError => bail!("`RpcError` cannot be decoded"),
+            Type::Defined { namespace, ty } => match self.type_def(namespace, ty)? {
+                TypeDef::Struct { fields, .. } => {
+                    self.decode_fields(fields.iter().map(|f| (f.name.as_str(), &f.ty)), bytes)?
+                }
+                TypeDef::Event { fields, .. } => {
+                    self.decode_fields(fields.iter().map(|f| (f.name.as_str(), &f.ty)), bytes)?
+                }
+                TypeDef::Enum { variants, .. } => {
+                    let i = decode_le!(u8, bytes);
+                    let variant = variants
+                        .get(i as usize)
+                        .ok_or_else(|| anyhow!("`{}` has no variant {}", ty, i))?;
+                    let fields_value = match &variant.fields {
+                        Some(EnumFields::Named(fields)) => self.decode_fields(
+                            fields.iter().map(|f| (f.name.as_str(), &f.ty)),
+                            bytes,
+                        )?,
+                        Some(EnumFields::Tuple(tys)) => self.decode_seq(tys.iter(), bytes)?,
+                        None => return Ok(Value::String(variant.name.to_string())),
+                    };
+                    let mut obj = Map::new();
+                    obj.insert(variant.name.to_string(), fields_value);
+                    Value::Object(obj)
+                }
+            },
+            Type::Tuple(tys) => self.decode_seq(tys.iter(), bytes)?,
+            Type::Array(ty, len) => {
+                self.decode_seq(std::iter::repeat(&**ty).take(*len as usize), bytes)?
+            }
+            Type::List(ty) | Type::Set(ty) => {
+                let len = decode_len(bytes)?;
+                self.decode_seq(std::iter::repeat(&**ty).take(len), bytes)?
+            }
+            Type::Map(k_ty, v_ty) => {
+                let len = decode_len(bytes)?;
+                let mut entries = Map::new();
+                for _ in 0..len {
+                    let k = match self.decode(k_ty, bytes)? {
+                        Value::String(k) => k,
+                        k => k.to_string(),
+                    };
+                    entries.insert(k, self.decode(v_ty, bytes)?);
+                }
+                Value::Object(entries)
+            }
+            Type::Optional(ty) => match decode_le!(u8, bytes) {
+                0 => Value::Null,
+                1 => self.decode(ty, bytes)?,
+                tag => bail!("`{}` is not a valid option tag", tag),
+            },
+            Type::Result(ok_ty, err_ty) => {
+                let (tag, ty) = match decode_le!(u8, bytes) {
+                    0 => ("Ok", ok_ty),
+                    1 => ("Err", err_ty),
+                    tag => bail!("`{}` is not a valid result tag", tag),
+                };
+                let mut obj = Map::new();
+                obj.insert(tag.to_string(), self.decode(ty, bytes)?);
+                Value::Object(obj)
+            }
+        })
+    }
+
+    fn decode_fields<'f>(
+        &self,
+        fields: impl Iterator<Item = (&'f str, &'f Type)>,
+        bytes: &mut &[u8],
+    ) -> Result<Value> {
+        let mut obj = Map::new();
+        for (name, ty) in fields {
+            obj.insert(name.to_string(), self.decode(ty, bytes)?);
+        }
+        Ok(Value::Object(obj))
+    }
+
+    fn decode_seq<'t>(
+        &self,
+        tys: impl Iterator<Item = &'t Type>,
+        bytes: &mut &[u8],
+    ) -> Result<Value> {
+        Ok(Value::Array(
+            tys.map(|ty| self.decode(ty, bytes))
+                .collect::<Result<_>>()?,
+        ))
+    }
+}
+
+fn type_def_name(type_def: &TypeDef) -> &str {
+    match type_def {
+        TypeDef::Struct { name, .. } | TypeDef::Enum { name, .. } | TypeDef::Event { name, .. } => {
+            name
+        }
+    }
+}
+
+/// Returns the unsigned integer in `value`, which may also be a string for large values.
+fn uint(value: &Value, bits: u32) -> Result<u64> {
+    let n = match value {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    };
+    match n {
+        Some(n) if bits == 64 || n < 1 << bits => Ok(n),
+        _ => bail!("expected a u{}, but found `{}`", bits, value),
+    }
+}
+
+fn int(value: &Value, bits: u32) -> Result<i64> {
+    let n = match value {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    };
+    match n {
+        Some(n) if bits == 64 || (-(1 << (bits - 1))..1 << (bits - 1)).contains(&n) => Ok(n),
+        _ => bail!("expected an i{}, but found `{}`", bits, value),
+    }
+}
+
+fn float(value: &Value) -> Result<f64> {
+    value
+        .as_f64()
+        .ok_or_else(|| anyhow!("expected a number, but found `{}`", value))
+}
+
+/// Returns the bytes in `value`, which is either a hex string or an array of bytes.
+fn bytes(value: &Value) -> Result<Vec<u8>> {
+    match value {
+        Value::String(s) => hex::decode(s.trim_start_matches("0x"))
+            .map_err(|_| anyhow!("expected a hex string, but found `{}`", value)),
+        Value::Array(items) => items.iter().map(|b| Ok(uint(b, 8)? as u8)).collect(),
+        _ => bail!("expected bytes, but found `{}`", value),
+    }
+}
+
+fn encode_len(len: usize, buf: &mut Vec<u8>) {
+    buf.extend(&(len as u32).to_le_bytes());
+}
+
+fn decode_len(bytes: &mut &[u8]) -> Result<usize> {
+    Ok(decode_le!(u32, bytes) as usize)
+}
+
+fn take<'b>(bytes: &mut &'b [u8], len: usize) -> Result<&'b [u8]> {
+    if bytes.len() < len {
+        bail!("expected {} more byte(s), but found {}", len, bytes.len());
+    }
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(taken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use oasis_rpc::Field;
+    use serde_json::json;
+
+    fn point_defs() -> Vec<TypeDef> {
+        vec![TypeDef::Struct {
+            name: "Point".to_string(),
+            fields: vec![
+                Field {
+                    name: "x".to_string(),
+                    ty: Type::I32,
+                },
+                Field {
+                    name: "label".to_string(),
+                    ty: Type::Optional(Box::new(Type::String)),
+                },
+            ],
+        }]
+    }
+
+    fn round_trip(ty: Type, value: Value) -> Vec<u8> {
+        let type_defs = point_defs();
+        let encoded = encode_args(&type_defs, std::slice::from_ref(&ty), &[value.clone()]).unwrap();
+        assert_eq!(decode(&type_defs, &ty, &encoded).unwrap(), value);
+        encoded
+    }
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!(round_trip(Type::Bool, json!(true)), vec![1]);
+        assert_eq!(round_trip(Type::U16, json!(258)), vec![2, 1]);
+        assert_eq!(round_trip(Type::I8, json!(-1)), vec![0xff]);
+        assert_eq!(
+            round_trip(Type::U64, json!(u64::max_value())),
+            vec![0xff; 8]
+        );
+        round_trip(Type::F64, json!(1.5));
+        round_trip(
+            Type::Balance,
+            json!("340282366920938463463374607431768211455"),
+        );
+        round_trip(
+            Type::Address,
+            json!("0xb8b3666d8fea887d97ab54f571b8e5020c5c8b58"),
+        );
+
+        let type_defs = point_defs();
+        assert!(encode_args(&type_defs, &[Type::U8], &[json!(256)]).is_err());
+        assert!(encode_args(&type_defs, &[Type::I16], &[json!(-32769)]).is_err());
+        assert!(encode_args(&type_defs, &[Type::Bool], &[json!(1)]).is_err());
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(
+            round_trip(Type::String, json!("hi")),
+            vec![2, 0, 0, 0, b'h', b'i']
+        );
+        assert_eq!(
+            round_trip(Type::Bytes, json!("0x0102")),
+            vec![2, 0, 0, 0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn test_vectors() {
+        assert_eq!(
+            round_trip(Type::List(Box::new(Type::U8)), json!([1, 2, 3])),
+            vec![3, 0, 0, 0, 1, 2, 3]
+        );
+        assert_eq!(
+            round_trip(Type::Array(Box::new(Type::U8), 2), json!([1, 2])),
+            vec![1, 2]
+        );
+        round_trip(
+            Type::Tuple(vec![Type::U32, Type::String]),
+            json!([7, "seven"]),
+        );
+        round_trip(
+            Type::Map(Box::new(Type::String), Box::new(Type::U8)),
+            json!({ "a": 1, "b": 2 }),
+        );
+        round_trip(
+            Type::Result(Box::new(Type::U8), Box::new(Type::String)),
+            json!({ "Err": "oops" }),
+        );
+    }
+
+    #[test]
+    fn test_defined_struct() {
+        let point = || Type::Defined {
+            namespace: None,
+            ty: "Point".to_string(),
+        };
+        assert_eq!(
+            round_trip(point(), json!({ "x": -2, "label": null })),
+            vec![0xfe, 0xff, 0xff, 0xff, 0]
+        );
+        round_trip(
+            Type::List(Box::new(point())),
+            json!([{ "x": 1, "label": "one" }, { "x": 2, "label": null }]),
+        );
+
+        let type_defs = point_defs();
+        let err = encode_args(&type_defs, &[point()], &[json!({ "x": 1 })]).unwrap_err();
+        assert!(err.to_string().starts_with("missing field `label`"));
+        assert!(decode(&type_defs, &point(), &[0, 0, 0, 0, 0, 0]).is_err());
+    }
+}
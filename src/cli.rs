@@ -15,6 +15,18 @@ pub fn build_app<'a, 'b>() -> App<'a, 'b> {
         (about: crate_description!())
         (version: version_str)
         (@setting InferSubcommands)
+        (@arg log_verbose: -v +multiple "Log more verbosely. Pass twice to also log traces")
+        (@arg log_level: --("log-level") +global +takes_value
+            possible_value[trace debug info warn error]
+            "Set which logs to print. `RUST_LOG`, if set, takes precedence")
+        (@arg config_path: --config +global +takes_value value_name("PATH")
+            "Use the config file at PATH instead of the default one")
+        (@arg workspace_root: --("workspace-root") +global +takes_value value_name("DIR")
+            "Use DIR as the workspace root [default: the git repo or nearest manifest's dir]")
+        (@arg no_dotenv: --("no-dotenv") +global
+            "Don't load environment variables from `.env` in the current directory or workspace")
+        (@arg timeout: --timeout +global +takes_value env("OASIS_HTTP_TIMEOUT")
+            "Seconds to wait for network operations before giving up [default: 30]")
         (@subcommand init =>
             (about: "Create a new Oasis package")
             (@arg quiet: +multiple -q --quiet "Decrease verbosity")
@@ -22,15 +34,75 @@ pub fn build_app<'a, 'b>() -> App<'a, 'b> {
             (@group type =>
                 (@arg rust: --rust "Create a new Rust service")
             )
+            (@arg template: --template +takes_value
+                "Copy the project from a local template directory instead of the default template")
+            (@arg nested: --nested "Allow creating the package inside of an existing project")
+            (@arg no_git: --("no-git") "Don't initialize a git repository for the package")
         )
         (@subcommand build =>
             (about: "Build services for the Oasis platform")
             (@arg debug: --debug "Build without optimizations")
+            (@arg release: --release conflicts_with[debug]
+                "Build with optimizations, even if `build.default_release` is false")
             (@arg verbose: +multiple -v --verbose "Increase verbosity")
             (@arg quiet: +multiple -q --quiet "Decrease verbosity")
             (@arg stack_size: +takes_value --stack-size
                 "Set the amount of linear memory allocated to program stack (in bytes)")
             (@arg wasi: --wasi "Build a vanilla WASI service")
+            (@arg wasi_abi: --("wasi-abi") +takes_value possible_value[unstable preview1]
+                "Set the WASI version of services [default: preview1 if --wasi, else unstable]")
+            (@arg target_triple: --("target-triple") +takes_value value_name("TRIPLE")
+                "Compile Rust services for TRIPLE [default: wasm32-wasi]")
+            (@arg examples: --examples "Also build Rust examples")
+            (@arg no_clients: --("no-clients") "Skip generating clients that no app needs")
+            (@arg wasm_opt: --("wasm-opt") +takes_value +require_equals min_values(0)
+                possible_values(&["0", "1", "2", "3", "4", "s", "z"])
+                "Optimize services using wasm-opt at the given level [default: z]")
+            (@arg strip: --strip +takes_value possible_value[all custom none]
+                "Set which custom sections to strip [default: none if --debug, else custom]")
+            (@arg profile_build: --("profile-build") "Print how long each build phase takes")
+            (@arg explain: --explain
+                "Print why each target is built, what it yields, and which tool builds it")
+            (@arg if_changed: --("if-changed")
+                "Skip targets whose sources, by content hash, are unchanged since the last build")
+            (@arg message_format: --("message-format") +takes_value possible_value[human json]
+                "Set how cargo prints messages. `json` omits oasis' own status messages")
+            (@arg diagnostics_json: --("diagnostics-json") +takes_value value_name("FILE")
+                "Also save the compiler messages of the built Rust targets to FILE, by target")
+            (@arg artifact_name: --("artifact-name") +takes_value
+                "Also save the selected service as NAME.wasm. Requires exactly one service target")
+            (@arg interface_only: --("interface-only")
+                conflicts_with[no_clients ts_types_only if_changed artifact_name]
+                "Only write the interfaces of the selected services, without generating clients")
+            (@arg out_dir: --("out-dir") +takes_value value_name("DIR") requires[interface_only]
+                "Where `--interface-only` writes the interfaces. Defaults to the current directory")
+            (@arg print_artifact: --("print-artifact") conflicts_with[message_format]
+                "After building, print only the absolute path of each selected service's wasm")
+            (@arg locked_interface: --("locked-interface") +takes_value +require_equals
+                min_values(0) value_name("DIR")
+                "Fail on breaking changes to DIR/<service>.interface.json [default: manifest dir]")
+            (@arg update_interface: --("update-interface")
+                "Rewrite the `<service>.interface.json` used by `--locked-interface`")
+            (@arg include_sources: --("include-sources")
+                "Embed the hashes of each service's source files in an `oasis_sources` section")
+            (@arg ts_types_only: --("ts-types-only")
+                "Generate TypeScript type declarations instead of full service clients")
+            (@arg locked: --locked "Require the lockfile to be up-to-date")
+            (@arg frozen: --frozen "Require an up-to-date lockfile and no network access")
+            (@arg offline: --offline "Build without accessing the network")
+            (@arg features: --features +takes_value +multiple number_of_values(1)
+                value_name("FEATURES")
+                "Activate these Cargo features of the Rust packages that declare them \
+                 (use PACKAGE/FEATURE for a single package)")
+            (@arg all_features: --("all-features") "Activate all Cargo features")
+            (@arg no_default_features: --("no-default-features")
+                "Don't activate the default Cargo features")
+            (@arg env: --env +takes_value +multiple number_of_values(1)
+                "Set an environment variable (KEY=VALUE) for the build tool")
+            (@arg all: --all conflicts_with[TARGETS]
+                "Select every target in the workspace, not just those in the current directory")
+            (@arg exclude: --exclude +takes_value +multiple number_of_values(1)
+                "Skip the targets with this name, glob, or path unless other targets need them")
             (@arg TARGETS: +multiple "Specify names or paths of services and apps to build")
             (@arg builder_args: +raw "Args to pass to language-specific build tool")
         )
@@ -38,42 +110,166 @@ pub fn build_app<'a, 'b>() -> App<'a, 'b> {
             (about: "Run tests against a simulated Oasis runtime")
             (@arg verbose: +multiple -v --verbose "Increase verbosity")
             (@arg quiet: +multiple -q --quiet "Decrease verbosity")
-            (@arg debug: --release "Build with optimizations")
+            (@arg release: --release "Build with optimizations")
+            (@arg locked: --locked "Require the lockfile to be up-to-date")
+            (@arg frozen: --frozen "Require an up-to-date lockfile and no network access")
+            (@arg offline: --offline "Build without accessing the network")
+            (@arg features: --features +takes_value +multiple number_of_values(1)
+                value_name("FEATURES")
+                "Activate these Cargo features of the Rust packages that declare them \
+                 (use PACKAGE/FEATURE for a single package)")
+            (@arg all_features: --("all-features") "Activate all Cargo features")
+            (@arg no_default_features: --("no-default-features")
+                "Don't activate the default Cargo features")
+            (@arg changed_since: --("changed-since") +takes_value
+                "Only test targets affected by changes since the given git ref")
+            (@arg test_timeout: --("test-timeout") +takes_value value_name("SECONDS")
+                "Fail a target's tests if they take longer than SECONDS")
             (@arg profile: -p --profile default_value[local]
                 "Set testing profile. Run `oasis config profile` \nto list available profiles.")
+            (@arg gateway: --gateway +takes_value
+                "Use this gateway and `--credential` instead of a profile")
+            (@arg credential: --credential +takes_value env("OASIS_CREDENTIAL") +hide_env_values
+                "Set the API token or private key/mnemonic used with `--gateway`")
+            (@arg env: --env +takes_value +multiple number_of_values(1)
+                "Set an environment variable (KEY=VALUE) for the build and test tools")
+            (@arg no_build: --("no-build") "Use existing build artifacts instead of building")
+            (@arg all: --all conflicts_with[TARGETS]
+                "Select every target in the workspace, not just those in the current directory")
+            (@arg exclude: --exclude +takes_value +multiple number_of_values(1)
+                "Skip the targets with this name, glob, or path unless other targets need them")
             (@arg TARGETS: +multiple "Specify names or paths of services and apps to build")
             (@arg tester_args: +raw "Args to pass to language-specific test tool")
         )
+        (@subcommand bench =>
+            (about: "Run the benches of Rust services and apps")
+            (@arg verbose: +multiple -v --verbose "Increase verbosity")
+            (@arg quiet: +multiple -q --quiet "Decrease verbosity")
+            (@arg locked: --locked "Require the lockfile to be up-to-date")
+            (@arg frozen: --frozen "Require an up-to-date lockfile and no network access")
+            (@arg offline: --offline "Build without accessing the network")
+            (@arg env: --env +takes_value +multiple number_of_values(1)
+                "Set an environment variable (KEY=VALUE) for the build tool and benches")
+            (@arg no_build: --("no-build") "Use existing build artifacts instead of building")
+            (@arg TARGETS: +multiple "Specify names or paths of benches to run")
+            (@arg bencher_args: +raw "Args to pass to the bench harness")
+        )
         (@subcommand deploy =>
             (about: "Deploy services to the Oasis blockchain")
             (@arg verbose: +multiple -v --verbose "Increase verbosity")
             (@arg quiet: +multiple -q --quiet "Decrease verbosity")
             (@arg profile: -p --profile default_value[default]
                 "Set testing profile. Run `oasis config profile` \nto list available profiles.")
+            (@arg gateway: --gateway +takes_value
+                "Use this gateway and `--credential` instead of a profile")
+            (@arg credential: --credential +takes_value env("OASIS_CREDENTIAL") +hide_env_values
+                "Set the API token or private key/mnemonic used with `--gateway`")
+            (@arg env: --env +takes_value +multiple number_of_values(1)
+                "Set an environment variable (KEY=VALUE) for the build and deployment tools")
+            (@arg no_build: --("no-build") "Use existing build artifacts instead of building")
+            (@arg to_file: --("to-file") +takes_value value_name("BUNDLE")
+                "Package the services, their interfaces, and a manifest into BUNDLE (a .tar.gz) \
+                 instead of deploying")
+            (@arg all: --all conflicts_with[TARGETS]
+                "Select every target in the workspace, not just those in the current directory")
             (@arg TARGETS: +multiple "Specify names or paths of services and apps to build")
             (@arg deployer_args: +raw "Args to pass to language-specific deployment tool")
         )
+        (@subcommand run =>
+            (about: "Build and deploy a service, then call one of its RPCs")
+            (@arg verbose: +multiple -v --verbose "Increase verbosity")
+            (@arg quiet: +multiple -q --quiet "Decrease verbosity")
+            (@arg profile: -p --profile default_value[local]
+                "Set the profile to deploy with. A local chain is started if needed.")
+            (@arg env: --env +takes_value +multiple number_of_values(1)
+                "Set an environment variable (KEY=VALUE) for the build tool and client")
+            (@arg ctor_args: --("ctor-arg") +takes_value +multiple number_of_values(1)
+                "Pass a JSON-encoded argument to the service's constructor")
+            (@arg SERVICE: +required "Specify the name or path of the service to run")
+            (@arg RPC: "Specify the RPC to call once the service is deployed")
+            (@arg ARGS: +multiple "JSON-encoded arguments to the RPC")
+        )
         (@subcommand clean =>
             (about: "Remove build products")
+            (@arg exclude: --exclude +takes_value +multiple number_of_values(1)
+                "Don't clean the targets with this name, glob, or path")
             (@arg TARGETS: +multiple "Specify names or paths of services and apps to clean")
         )
         (@subcommand chain =>
             (about: "Run a local Oasis blockchain")
             (@arg verbose: +multiple -v --verbose "Increase verbosity")
+            (@arg eth_port: --("eth-port") +takes_value value_name("PORT")
+                "Serve Web3 on PORT instead of 8546. Pass 0 to pick free ports for the chain \
+                 and gateway")
+            (@arg detach: --detach "Run the chain in the background and return immediately")
+            (@arg wait_for_ready: --("wait-for-ready") +takes_value +require_equals
+                min_values(0) value_name("SECONDS") requires[detach]
+                "Return only once the detached chain and gateway accept connections, or fail after \
+                 SECONDS (default 30)")
+            (@arg log_dir: --("log-dir") +takes_value value_name("DIR") requires[detach]
+                "Append the output of the detached chain and gateway to logs in DIR")
+            (@arg chain_args: +raw "Args to pass to oasis-chain")
         )
         (@subcommand config =>
             (about: "View and edit configuration options")
+            (@setting SubcommandsNegateReqs)
+            (@arg dry_run: --("dry-run")
+                "Print the configuration file that would result from the edit instead of saving it")
             (@arg KEY: +required "The configuration key to set")
             (@arg VALUE: "The new configuration value")
+            (@subcommand migrate =>
+                (about: "Upgrade a configuration file written by an older version of oasis")
+            )
+            (@subcommand profile =>
+                (about: "List or copy profiles")
+                (@subcommand copy =>
+                    (about: "Create a profile with the same settings as an existing one")
+                    (@arg SRC: +required "The profile to copy")
+                    (@arg DST: +required "The name of the new profile")
+                )
+            )
         )
         (@subcommand ifextract =>
             (about: "Extract interface definition(s) from a service.wasm")
             (@arg out_dir: -o --out +takes_value
                 "Where to write the interface.json(s). \
                  Defaults to current directory. Pass `-` to write to stdout.")
+            (@arg refresh: --refresh "Fetch remote interfaces even if they were recently cached")
             (@arg IMPORT_LOC: +required "The location (URL or path) to service.wasm file(s)")
         )
+        (@subcommand ifdiff =>
+            (about: "Compare two versions of a service's interface")
+            (@arg json: --json "Print the changes as JSON")
+            (@arg OLD: +required "The old interface.json, or the location of the old service.wasm")
+            (@arg NEW: +required "The new interface.json, or the location of the new service.wasm")
+        )
+        (@subcommand graph =>
+            (about: "Print the dependency graph of all targets in the workspace")
+            (@arg format: -f --format +takes_value possible_value[dot mermaid json]
+                default_value[dot] "The format in which to print the graph")
+        )
+        (@subcommand whoami =>
+            (about: "Show the account of a profile's credential")
+            (@arg profile: -p --profile default_value[default]
+                "Set the profile. Run `oasis config profile` \nto list available profiles.")
+        )
+        (@subcommand size =>
+            (about: "Analyze the size and imports of a service.wasm")
+            (@arg WASM: +required "The path to the service.wasm. Pass `-` to read from stdin.")
+        )
+        (@subcommand verify =>
+            (about: "Check that a service.wasm is well-formed for the Oasis platform")
+            (@arg WASM: +required "The path to the service.wasm. Pass `-` to read from stdin.")
+        )
+        (@subcommand update =>
+            (about: "Update the Oasis toolchain to the latest release")
+            (@arg check: --check "Only report whether an update is available, failing if it is")
+        )
         (@subcommand upload_metrics => (@setting Hidden))
+        (@subcommand update_check => (@setting Hidden))
+        (@subcommand dirs =>
+            (about: "Print the directories used for config, data, caches, and binaries")
+            (@setting Hidden))
         (@subcommand gen_completions => (@setting Hidden))
     )
     .subcommand(
@@ -1,6 +1,19 @@
-use std::{collections::BTreeMap, ffi::OsString, io, path::Path, process::Stdio};
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    fs,
+    io::{self, BufRead as _, BufReader},
+    os::unix::process::CommandExt as _,
+    path::Path,
+    process::{Child, ExitStatus, Stdio},
+    sync::mpsc::{self, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
 
 use crate::{
+    config::{Config, Profile},
     emit,
     errors::{CliError, Error, Result},
     workspace::{Project, ProjectKind, Target},
@@ -41,7 +54,7 @@ impl From<i64> for Verbosity {
 #[macro_export]
 macro_rules! cmd {
     ($(in $curdir:expr,)? $prog:expr, $( $arg:expr ),+) => {{
-        let mut cmd = std::process::Command::new($prog);
+        let mut cmd = std::process::Command::new(&$prog);
         $(cmd.current_dir(&$curdir);)?
         cmd.envs(std::env::vars_os());
         $( cmd.arg($arg); )+
@@ -50,8 +63,8 @@ macro_rules! cmd {
             anyhow!(
                 "could not invoke `{}`: {}",
                 &[
-                    $prog.to_string(),
-                    $(std::ffi::OsString::from($arg).into_string().unwrap()),+
+                    std::path::Path::new(&$prog).display().to_string(),
+                    $(std::ffi::OsString::from($arg).to_string_lossy().into_owned()),+
                 ].join(" "),
                 e
             )
@@ -59,10 +72,14 @@ macro_rules! cmd {
         .and_then(|output| {
             if !output.status.success() {
                 let err_msg = [
-                    std::str::from_utf8(&output.stdout).unwrap(),
-                    std::str::from_utf8(&output.stderr).unwrap()
+                    String::from_utf8_lossy(&output.stdout),
+                    String::from_utf8_lossy(&output.stderr),
                 ].join("\n");
-                Err(anyhow!("`{}` exited with error:\n{}", $prog, err_msg.trim()))
+                Err(anyhow!(
+                    "`{}` exited with error:\n{}",
+                    std::path::Path::new(&$prog).display(),
+                    err_msg.trim()
+                ))
             } else {
                 Ok(output)
             }
@@ -70,27 +87,250 @@ macro_rules! cmd {
     }}
 }
 
+/// Options that control whether the build tool may update the lockfile or use the network.
+/// These mirror Cargo's manifest options and are translated for npm/yarn when installing.
+#[derive(Clone, Copy, Default)]
+pub struct ManifestOptions {
+    pub locked: bool,
+    pub frozen: bool,
+    pub offline: bool,
+}
+
+impl ManifestOptions {
+    pub fn new(m: &clap::ArgMatches) -> Self {
+        Self {
+            locked: m.is_present("locked"),
+            frozen: m.is_present("frozen"),
+            offline: m.is_present("offline"),
+        }
+    }
+}
+
+/// The Cargo features with which to build and test Rust services and apps.
+#[derive(Clone, Default)]
+pub struct FeatureOptions<'a> {
+    /// The features given by `--features`, each of which may list several features.
+    pub features: Vec<&'a str>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+}
+
+impl<'a> FeatureOptions<'a> {
+    pub fn new(m: &'a clap::ArgMatches) -> Self {
+        Self {
+            features: m.values_of("features").unwrap_or_default().collect(),
+            all_features: m.is_present("all_features"),
+            no_default_features: m.is_present("no_default_features"),
+        }
+    }
+
+    /// Returns the Cargo args that select these features for `proj`. A feature given as
+    /// `FEATURE` goes to each package that declares it, and `PACKAGE/FEATURE` only to `PACKAGE`.
+    pub fn cargo_args(&self, proj: &Project) -> Vec<String> {
+        let features: Vec<_> = self
+            .requested()
+            .filter_map(|feature| feature_of(proj, feature))
+            .collect();
+        let mut args = Vec::new();
+        if !features.is_empty() {
+            args.push("--features".to_string());
+            args.push(features.join(" "));
+        }
+        if self.all_features {
+            args.push("--all-features".to_string());
+        }
+        if self.no_default_features {
+            args.push("--no-default-features".to_string());
+        }
+        args
+    }
+
+    /// Checks that each feature given by `--features` is declared by one of `projects`, as it
+    /// would otherwise be silently left out.
+    pub fn check_declared(&self, projects: &[&Project]) -> Result<()> {
+        for feature in self.requested() {
+            if !projects
+                .iter()
+                .any(|proj| feature_of(proj, feature).is_some())
+            {
+                bail!(
+                    "no package that is being built has the feature `{}`",
+                    feature
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns each feature listed by `--features`, which separates them by commas or spaces.
+    fn requested(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.features
+            .iter()
+            .flat_map(|&features| features.split(&[',', ' '][..]))
+            .filter(|feature| !feature.is_empty())
+    }
+}
+
+/// Returns the name by which `proj` declares `feature`, if `feature` selects one of its features.
+fn feature_of<'f>(proj: &Project, feature: &'f str) -> Option<&'f str> {
+    let (package_name, name) = match feature.find('/') {
+        Some(i) => (Some(&feature[..i]), &feature[i + 1..]),
+        None => (None, feature),
+    };
+    let is_selected = package_name
+        .map(|package_name| proj.package_name.as_deref() == Some(package_name))
+        .unwrap_or(true);
+    if is_selected && proj.features.contains(name) {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+/// The `RUSTC_WRAPPER` that builds Oasis services, unless overridden.
+const DEFAULT_RUSTC_WRAPPER: &str = "oasis-build";
+
+/// Returns the `RUSTC_WRAPPER` with which to build Rust services and apps. `OASIS_BUILD_BIN`
+/// takes precedence over `build.rustc_wrapper`, which takes precedence over `oasis-build`.
+/// An override must be an absolute path or the name of an executable on the `PATH`.
+pub fn rustc_wrapper(config: &Config) -> Result<String> {
+    let wrapper = match std::env::var("OASIS_BUILD_BIN")
+        .ok()
+        .or_else(|| config.rustc_wrapper())
+    {
+        Some(wrapper) => wrapper,
+        None => return Ok(DEFAULT_RUSTC_WRAPPER.to_string()),
+    };
+    let wrapper_path = Path::new(&wrapper);
+    if wrapper_path.components().count() > 1 {
+        if !wrapper_path.is_absolute() {
+            return Err(anyhow!(
+                "the rustc wrapper `{}` must be an absolute path or a name on the PATH",
+                wrapper
+            ));
+        }
+        if !wrapper_path.is_file() {
+            return Err(anyhow!("the rustc wrapper `{}` does not exist", wrapper));
+        }
+    } else if !is_on_path(&wrapper) {
+        return Err(CliError::ExecNotFound(wrapper).into());
+    }
+    Ok(wrapper)
+}
+
+fn is_on_path(name: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(name).is_file()))
+        .unwrap_or_default()
+}
+
+/// Variables that the CLI sets for the build tool and which `--env` cannot override.
+const REQUIRED_ENVS: &[&str] = &["RUSTC_WRAPPER", "OASIS_PROFILE"];
+
+/// Parses the `KEY=VALUE` pairs given by `--env`.
+pub fn user_envs<'a>(m: &'a clap::ArgMatches) -> Result<Vec<(&'a str, &'a str)>> {
+    m.values_of("env")
+        .unwrap_or_default()
+        .map(|kv| {
+            let mut kv_parts = kv.splitn(2, '=');
+            match (kv_parts.next(), kv_parts.next()) {
+                (Some(k), Some(v)) if !k.is_empty() => {
+                    if REQUIRED_ENVS.contains(&k) {
+                        warn!(
+                            "`--env {}` may be overridden by the value that oasis requires",
+                            k
+                        );
+                    }
+                    Ok((k, v))
+                }
+                _ => Err(anyhow!("invalid `--env {}`. Expected `KEY=VALUE`.", kv)),
+            }
+        })
+        .collect()
+}
+
+/// Returns an env map containing the `--env` variables. Variables that are later inserted by
+/// the CLI take precedence, and the variables inherited from the environment are overridden.
+pub fn user_env_map(user_envs: &[(&str, &str)]) -> BTreeMap<OsString, OsString> {
+    user_envs
+        .iter()
+        .map(|(k, v)| (OsString::from(k), OsString::from(v)))
+        .collect()
+}
+
+/// Returns the profile given by `--gateway` and `--credential`, if any, which is used instead
+/// of a profile from the config file.
+pub fn ephemeral_profile(m: &clap::ArgMatches) -> Result<Option<Profile>> {
+    let gateway = match m.value_of("gateway") {
+        Some(gateway) => gateway,
+        None => return Ok(None),
+    };
+    if m.occurrences_of("profile") > 0 {
+        bail!("`--gateway` cannot be used with `--profile`");
+    }
+    let credential = m
+        .value_of("credential")
+        .ok_or_else(|| anyhow!("`--gateway` requires `--credential` or `OASIS_CREDENTIAL`"))?;
+    Ok(Some(Profile::ephemeral(gateway, credential)?))
+}
+
+/// Touched in `node_modules` after a successful install so that changes to the lockfile
+/// can be detected on subsequent builds.
+const INSTALL_STAMP_FILE: &str = ".oasis-install-stamp";
+const NODE_LOCKFILES: &[&str] = &["package-lock.json", "yarn.lock"];
+
 pub struct BuildTool<'a> {
     project: &'a Project,
+    /// The target, or else the project directory, named in errors.
+    subject: String,
     workdir: &'a Path,
     kind: BuildToolKind,
+    manifest_opts: ManifestOptions,
+    /// How long the tool may run before it is killed.
+    timeout: Option<Duration>,
+    /// Receives each line that the tool prints to stdout, instead of the terminal.
+    on_stdout_line: Option<Box<dyn FnMut(&str) + 'a>>,
 }
 
 impl<'a> BuildTool<'a> {
     pub fn for_target(target: &'a Target) -> Self {
-        Self::for_project(&target.project)
+        Self {
+            subject: target.name.to_string(),
+            ..Self::for_project(&target.project)
+        }
     }
 
     pub fn for_project(project: &'a Project) -> Self {
+        let workdir = project.manifest_path.parent().unwrap();
         Self {
             project,
-            workdir: project.manifest_path.parent().unwrap(), // TODO: fixup for lerna
+            subject: workdir.display().to_string(),
+            workdir,
             kind: BuildToolKind::detect(project),
+            manifest_opts: ManifestOptions::default(),
+            timeout: None,
+            on_stdout_line: None,
         }
     }
 
+    pub fn manifest_options(mut self, manifest_opts: ManifestOptions) -> Self {
+        self.manifest_opts = manifest_opts;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Passes each line of the build tool's stdout to `on_stdout_line` rather than printing it.
+    pub fn on_stdout_line(mut self, on_stdout_line: impl FnMut(&str) + 'a) -> Self {
+        self.on_stdout_line = Some(Box::new(on_stdout_line));
+        self
+    }
+
     pub fn build(
-        self,
+        mut self,
         mut args: Vec<&'a str>,
         envs: BTreeMap<OsString, OsString>,
         verbosity: Verbosity,
@@ -100,10 +340,20 @@ impl<'a> BuildTool<'a> {
                 self.install_node_modules()?;
             }
             BuildToolKind::Cargo => {
-                args.push("--locked");
+                self.push_cargo_manifest_args(&mut args);
             }
         }
-        self.run("build", args, envs, verbosity)
+        let mut on_stdout_line = self.on_stdout_line.take();
+        self.run_in(
+            self.workdir,
+            "build",
+            args,
+            envs,
+            verbosity,
+            on_stdout_line
+                .as_mut()
+                .map(|f| &mut **f as &mut dyn FnMut(&str)),
+        )
     }
 
     pub fn test(
@@ -117,12 +367,22 @@ impl<'a> BuildTool<'a> {
                 self.install_node_modules()?;
             }
             BuildToolKind::Cargo => {
-                args.push("--locked");
+                self.push_cargo_manifest_args(&mut args);
             }
         }
         self.run("test", args, envs, verbosity)
     }
 
+    pub fn bench(
+        self,
+        mut args: Vec<&'a str>,
+        envs: BTreeMap<OsString, OsString>,
+        verbosity: Verbosity,
+    ) -> Result<()> {
+        self.push_cargo_manifest_args(&mut args);
+        self.run("bench", args, envs, verbosity)
+    }
+
     pub fn deploy(
         self,
         args: Vec<&'a str>,
@@ -147,58 +407,101 @@ impl<'a> BuildTool<'a> {
     fn run(
         &self,
         subcommand: &'a str,
-        mut builder_args: Vec<&'a str>,
+        builder_args: Vec<&'a str>,
+        envs: BTreeMap<OsString, OsString>,
+        verbosity: Verbosity,
+    ) -> Result<()> {
+        self.run_in(
+            self.workdir,
+            subcommand,
+            builder_args,
+            envs,
+            verbosity,
+            None,
+        )
+    }
+
+    fn run_in(
+        &self,
+        workdir: &'a Path,
+        subcommand: &'a str,
+        builder_args: Vec<&'a str>,
         mut envs: BTreeMap<OsString, OsString>,
         verbosity: Verbosity,
+        on_stdout_line: Option<&mut dyn FnMut(&str)>,
     ) -> Result<()> {
-        let mut args = Vec::new();
+        let mut args: Vec<&OsStr> = Vec::new();
 
         if let BuildToolKind::Cargo = self.kind {
-            args.push(concat!("+", rust_toolchain!()));
+            args.push(concat!("+", rust_toolchain!()).as_ref());
         }
 
         match self.kind {
             BuildToolKind::Cargo => {
-                args.push(subcommand);
+                args.push(subcommand.as_ref());
                 if verbosity < Verbosity::Normal {
-                    args.push("--quiet");
+                    args.push("--quiet".as_ref());
                 } else if verbosity == Verbosity::High {
-                    args.push("--verbose");
+                    args.push("--verbose".as_ref());
                 } else if verbosity == Verbosity::Debug {
-                    args.push("-vvv")
+                    args.push("-vvv".as_ref())
                 }
-                args.push("--manifest-path");
-                args.push(self.project.manifest_path.to_str().unwrap())
+                args.push("--manifest-path".as_ref());
+                args.push(self.project.manifest_path.as_os_str())
             }
             BuildToolKind::Npm => {
                 if verbosity < Verbosity::Normal {
-                    args.push("--silent");
+                    args.push("--silent".as_ref());
                 } else if verbosity >= Verbosity::Verbose {
-                    args.push("--verbose");
+                    args.push("--verbose".as_ref());
                 }
-                args.push("--prefix");
-                args.push(self.workdir.to_str().unwrap());
-                args.push(subcommand);
+                args.push("--prefix".as_ref());
+                args.push(workdir.as_os_str());
+                args.push(subcommand.as_ref());
             }
             BuildToolKind::Yarn => {
                 if verbosity < Verbosity::Normal {
-                    args.push("--silent");
+                    args.push("--silent".as_ref());
                 } else if verbosity >= Verbosity::Verbose {
-                    args.push("--verbose");
+                    args.push("--verbose".as_ref());
                 }
-                args.push("--cwd");
-                args.push(self.workdir.to_str().unwrap());
-                args.push(subcommand);
+                args.push("--cwd".as_ref());
+                args.push(workdir.as_os_str());
+                args.push(subcommand.as_ref());
             }
         }
 
-        args.append(&mut builder_args);
+        args.extend(builder_args.into_iter().map(OsStr::new));
 
         for (k, v) in std::env::vars_os() {
             envs.entry(k).or_insert(v);
         }
 
-        run_cmd_internal(self.name(), args, Some(envs), verbosity)
+        run_cmd_internal(
+            self.name(),
+            args,
+            Some(envs),
+            verbosity,
+            self.timeout,
+            on_stdout_line,
+        )
+        .with_context(|| {
+            let action = match subcommand {
+                "build" => "building",
+                "test" => "testing",
+                "bench" => "benchmarking",
+                "deploy" => "deploying",
+                "clean" => "cleaning",
+                _ => subcommand,
+            };
+            format!(
+                "{} `{}` (`{} {}`) failed",
+                action,
+                self.subject,
+                self.name(),
+                subcommand
+            )
+        })
     }
 
     fn name(&self) -> &str {
@@ -209,20 +512,75 @@ impl<'a> BuildTool<'a> {
         }
     }
 
+    /// Cargo is always run with `--locked` so that builds never rewrite `Cargo.lock`.
+    fn push_cargo_manifest_args(&self, args: &mut Vec<&'a str>) {
+        let ManifestOptions {
+            frozen, offline, ..
+        } = self.manifest_opts;
+        args.push(if frozen { "--frozen" } else { "--locked" });
+        if offline {
+            args.push("--offline");
+        }
+    }
+
+    /// Returns the directory in which node modules are installed. For packages in a monorepo,
+    /// this is the monorepo root, which is where dependencies are hoisted.
+    fn install_dir(&self) -> &'a Path {
+        let project: &'a Project = self.project;
+        project.monorepo_root.as_deref().unwrap_or(self.workdir)
+    }
+
     fn install_node_modules(&self) -> Result<()> {
-        if !self.workdir.join("node_modules").is_dir() {
-            if let Err(e) = self.run(
-                "install",
-                Vec::new(),      /* args */
-                BTreeMap::new(), /* envs */
-                Verbosity::Silent,
-            ) {
-                emit!(cmd.build.error, {
-                    "cause": format!("{} install", self.name())
-                });
-                return Err(e);
+        let install_dir = self.install_dir();
+        let node_modules_dir = install_dir.join("node_modules");
+        let stamp_path = node_modules_dir.join(INSTALL_STAMP_FILE);
+        let install_reason = if !node_modules_dir.is_dir() {
+            "missing"
+        } else if node_modules_are_stale(install_dir, &stamp_path) {
+            "stale"
+        } else {
+            return Ok(());
+        };
+
+        let ManifestOptions {
+            locked,
+            frozen,
+            offline,
+        } = self.manifest_opts;
+        let mut args = Vec::new();
+        let subcommand = match self.kind {
+            BuildToolKind::Npm if frozen || locked => "ci",
+            BuildToolKind::Yarn if frozen || locked => {
+                args.push("--frozen-lockfile");
+                "install"
             }
+            _ => "install",
+        };
+        if offline {
+            args.push("--offline");
+        }
+        if let Err(e) = self.run_in(
+            install_dir,
+            subcommand,
+            args,
+            BTreeMap::new(), /* envs */
+            Verbosity::Silent,
+            None, /* on_stdout_line */
+        ) {
+            emit!(cmd.build.error, {
+                "cause": format!("{} {}", self.name(), subcommand),
+                "install_reason": install_reason,
+            });
+            return Err(e);
         }
+
+        if node_modules_dir.is_dir() {
+            fs::write(&stamp_path, b"")?;
+        }
+        emit!(cmd.build.install, {
+            "tool": self.name(),
+            "install_reason": install_reason,
+        });
         Ok(())
     }
 }
@@ -234,19 +592,16 @@ pub enum BuildToolKind {
 }
 
 impl BuildToolKind {
-    fn detect(project: &Project) -> Self {
+    pub fn detect(project: &Project) -> Self {
         match project.kind {
             ProjectKind::Wasm => unreachable!("wasm is not buildable"),
             ProjectKind::Rust => BuildToolKind::Cargo,
             ProjectKind::JavaScript { .. } | ProjectKind::TypeScript { .. } => {
-                if project
-                    .manifest_path
-                    .parent()
-                    .unwrap()
-                    .join("yarn.lock")
-                    .is_file()
-                    || cmd!("which", "yarn").is_ok()
-                {
+                let install_dir = project
+                    .monorepo_root
+                    .as_deref()
+                    .unwrap_or_else(|| project.manifest_path.parent().unwrap());
+                if install_dir.join("yarn.lock").is_file() || cmd!("which", "yarn").is_ok() {
                     BuildToolKind::Yarn
                 } else {
                     BuildToolKind::Npm
@@ -254,33 +609,276 @@ impl BuildToolKind {
             }
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuildToolKind::Cargo => "cargo",
+            BuildToolKind::Npm => "npm",
+            BuildToolKind::Yarn => "yarn",
+        }
+    }
 }
 
+/// Returns whether a lockfile has been modified since the last successful install.
+/// A lockfile with no stamp means that the modules were installed outside of `oasis`.
+fn node_modules_are_stale(install_dir: &Path, stamp_path: &Path) -> bool {
+    let mtime = |path: &Path| fs::metadata(path).and_then(|md| md.modified()).ok();
+    let lockfile_mtime = NODE_LOCKFILES
+        .iter()
+        .filter_map(|lockfile| mtime(&install_dir.join(lockfile)))
+        .max();
+    match (lockfile_mtime, mtime(stamp_path)) {
+        (Some(lockfile_mtime), Some(stamp_mtime)) => lockfile_mtime > stamp_mtime,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+/// Runs `name` to completion, or kills it once `timeout` elapses. If `on_stdout_line` is given,
+/// the command's stdout is read, line by line and lossily decoded, as it runs.
 fn run_cmd_internal(
     name: &str,
-    args: Vec<&str>,
+    args: Vec<&OsStr>,
     envs: Option<BTreeMap<OsString, OsString>>,
     verbosity: Verbosity,
+    timeout: Option<Duration>,
+    on_stdout_line: Option<&mut dyn FnMut(&str)>,
 ) -> Result<()> {
-    let (stdout, stderr) = match verbosity {
+    let (mut stdout, stderr) = match verbosity {
         Verbosity::Silent => (Stdio::null(), Stdio::null()),
         _ => (Stdio::inherit(), Stdio::inherit()),
     };
+    if on_stdout_line.is_some() {
+        stdout = Stdio::piped();
+    }
     let mut cmd = std::process::Command::new(name.to_string());
     cmd.args(args).stdout(stdout).stderr(stderr);
 
     if let Some(envs) = envs {
         cmd.envs(envs);
     }
+    if timeout.is_some() {
+        // The command gets its own process group so that the processes that it starts (e.g., a
+        // test harness's workers) are killed along with it. Commands without a timeout are left
+        // in this process's group so that they still get the terminal's signals.
+        unsafe {
+            cmd.pre_exec(|| match libc::setpgid(0, 0) {
+                0 => Ok(()),
+                _ => Err(io::Error::last_os_error()),
+            });
+        }
+    }
     debug!("running command: {:?}", cmd);
-    let output = cmd.output().map_err(|e| match e.kind() {
+    let mut child = cmd.spawn().map_err(|e| match e.kind() {
         io::ErrorKind::NotFound => CliError::ExecNotFound(name.to_string()).into(),
         _ => Error::from(e),
     })?;
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let kill = |child: &mut Child, err: Error| -> Result<()> {
+        match timeout {
+            // The negated pid signals the child's whole process group.
+            Some(_) => unsafe {
+                libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+            },
+            None => {
+                child.kill().ok();
+            }
+        }
+        child.wait()?;
+        Err(err)
+    };
+    let timed_out = || Error::from(CliError::ProcessTimeout(name.to_string(), timeout.unwrap()));
+
+    if let (Some(on_stdout_line), Some(child_stdout)) = (on_stdout_line, child.stdout.take()) {
+        // The lines are read on another thread so that a command that stops writing without
+        // exiting still times out.
+        let (line_tx, line_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut child_stdout = BufReader::new(child_stdout);
+            let mut buf = Vec::new();
+            loop {
+                buf.clear();
+                let line = match child_stdout.read_until(b'\n', &mut buf) {
+                    Ok(0) => break,
+                    Ok(_) => Ok(String::from_utf8_lossy(&buf)
+                        .trim_end_matches('\n')
+                        .trim_end_matches('\r')
+                        .to_string()),
+                    Err(e) => Err(e),
+                };
+                let is_err = line.is_err();
+                if line_tx.send(line).is_err() || is_err {
+                    break;
+                }
+            }
+        });
+        loop {
+            let line = match deadline {
+                Some(deadline) => {
+                    line_rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => line_rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match line {
+                Ok(Ok(line)) => on_stdout_line(&line),
+                Ok(Err(e)) => return kill(&mut child, e.into()),
+                Err(RecvTimeoutError::Timeout) => return kill(&mut child, timed_out()),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+    let status = match deadline {
+        Some(deadline) => {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match wait_timeout(&mut child, remaining)? {
+                Some(status) => status,
+                None => return kill(&mut child, timed_out()),
+            }
+        }
+        None => child.wait()?,
+    };
 
-    if output.status.success() {
+    if status.success() {
         Ok(())
     } else {
-        Err(CliError::ProcessExit(name.to_string(), output.status.code().unwrap()).into())
+        Err(CliError::ProcessExit(name.to_string(), status.code().unwrap_or(-1)).into())
+    }
+}
+
+/// Waits for `child` to exit, returning `None` if it is still running after `timeout`.
+fn wait_timeout(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(50);
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= timeout {
+            return Ok(None);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_matches(args: &[&str]) -> clap::ArgMatches<'static> {
+        clap::App::new("oasis")
+            .arg(
+                clap::Arg::with_name("env")
+                    .long("env")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+            .get_matches_from(std::iter::once("oasis").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn test_user_envs() {
+        let m = env_matches(&["--env", "A=1", "--env", "B=x=y", "--env", "C="]);
+        assert_eq!(
+            user_envs(&m).unwrap(),
+            vec![("A", "1"), ("B", "x=y"), ("C", "")]
+        );
+        assert!(user_envs(&env_matches(&[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_user_envs_invalid() {
+        assert!(user_envs(&env_matches(&["--env", "A"])).is_err());
+        assert!(user_envs(&env_matches(&["--env", "=1"])).is_err());
+    }
+
+    #[test]
+    fn test_run_cmd_internal() {
+        let run = |name, args: &[&str], timeout| {
+            let args = args.iter().map(OsStr::new).collect();
+            run_cmd_internal(name, args, None, Verbosity::Silent, timeout, None)
+                .unwrap_err()
+                .downcast::<CliError>()
+                .unwrap()
+                .to_string()
+        };
+        assert!(run_cmd_internal("true", Vec::new(), None, Verbosity::Silent, None, None).is_ok());
+        assert_eq!(
+            run("oasis-no-such-tool", &[], None),
+            CliError::ExecNotFound("oasis-no-such-tool".to_string()).to_string()
+        );
+        assert_eq!(
+            run("sh", &["-c", "exit 3"], None),
+            CliError::ProcessExit("sh".to_string(), 3).to_string()
+        );
+        assert_eq!(
+            run("sleep", &["10"], Some(Duration::from_millis(100))),
+            "process `sleep` timed out after 100ms"
+        );
+    }
+
+    #[test]
+    fn test_run_cmd_internal_stdout_lines() {
+        let mut lines = Vec::new();
+        let mut on_stdout_line = |line: &str| lines.push(line.to_string());
+        run_cmd_internal(
+            "printf",
+            vec![OsStr::new("a\\nb\\n")],
+            None,
+            Verbosity::Silent,
+            None,
+            Some(&mut on_stdout_line),
+        )
+        .unwrap();
+        assert_eq!(lines, vec!["a", "b"]);
+
+        let mut lines = Vec::new();
+        let mut on_stdout_line = |line: &str| lines.push(line.to_string());
+        let err = run_cmd_internal(
+            "sh",
+            vec![
+                OsStr::new("-c"),
+                OsStr::new(r"printf 'a\377\r\n' && sleep 10"),
+            ],
+            None,
+            Verbosity::Silent,
+            Some(Duration::from_millis(100)),
+            Some(&mut on_stdout_line),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "process `sh` timed out after 100ms");
+        assert_eq!(lines, vec!["a\u{fffd}"]);
+    }
+
+    #[test]
+    fn test_run_cmd_internal_timeout_kills_descendants() {
+        let marker = std::env::temp_dir().join(format!("oasis-timeout-{}", std::process::id()));
+        let script = format!("(sleep 1 && touch '{}') & wait", marker.display());
+        let err = run_cmd_internal(
+            "sh",
+            vec![OsStr::new("-c"), OsStr::new(&script)],
+            None,
+            Verbosity::Silent,
+            Some(Duration::from_millis(100)),
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "process `sh` timed out after 100ms");
+        std::thread::sleep(Duration::from_millis(1500));
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn test_cmd_non_utf8_output() {
+        let err = cmd!(
+            "sh",
+            "-c",
+            r"printf 'out\377' && printf 'err\376' >&2 && exit 1"
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.starts_with("`sh` exited with error"));
+        assert!(err.contains("out\u{fffd}"));
+        assert!(err.contains("err\u{fffd}"));
     }
 }
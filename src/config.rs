@@ -1,6 +1,8 @@
 use std::{
+    convert::TryFrom,
     fmt, fs,
-    os::unix::fs::PermissionsExt as _,
+    io::Write as _,
+    os::unix::fs::{OpenOptionsExt as _, PermissionsExt as _},
     path::{Path, PathBuf},
     str::FromStr,
 };
@@ -14,7 +16,12 @@ use crate::{
 
 pub struct Config {
     doc: toml_edit::Document,
+    /// Whether `doc` has changes that `save` has yet to write.
     dirty: bool,
+    /// Whether edits are only previewed, as by `oasis config --dry-run`, and never saved.
+    dry_run: bool,
+    /// The file given by `--config`, which is used instead of the default config file.
+    path: Option<PathBuf>,
 }
 
 macro_rules! default_gateway_url {
@@ -28,11 +35,69 @@ const MNEMONIC_PHRASE_LEN: usize = 12;
 const PRIVATE_KEY_BYTES: usize = 32;
 const API_TOKEN_BYTES: usize = 32 + std::mem::size_of::<u32>();
 
+/// How long to wait for each gateway of a profile when looking for one that's reachable.
+const GATEWAY_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// The size, in bytes, at which the log file is rotated unless `logging.max_size` is set.
+const DEFAULT_LOG_MAX_SIZE: u64 = 1 << 20;
+
+/// The size, in bytes, of the metrics file at which it is uploaded unless
+/// `telemetry.upload_threshold_bytes` is set.
+const DEFAULT_UPLOAD_THRESHOLD_BYTES: u64 = 50 * 1024;
+
+/// How long to wait after an upload before starting another, unless
+/// `telemetry.upload_min_interval_secs` is set.
+const DEFAULT_UPLOAD_MIN_INTERVAL_SECS: u64 = 60 * 60;
+
+/// The keys of each table of the config file, against which a loaded file is checked for typos.
+/// The keys of the legacy schema are included, as `oasis config migrate` still reads them.
+const KNOWN_KEYS: &[(&str, &[&str])] = &[
+    ("build", &["default_release", "rustc_wrapper"]),
+    (
+        "logging",
+        &[
+            "enabled",
+            "dir",
+            "max_size",
+            "path_stdout",
+            "path_stderr",
+            "id",
+            "user_id",
+        ],
+    ),
+    (
+        "profile.*",
+        &[
+            "gateway",
+            "credential",
+            "endpoint",
+            "private_key",
+            "mnemonic",
+        ],
+    ),
+    (
+        "telemetry",
+        &[
+            "enabled",
+            "user_id",
+            "upload_threshold_bytes",
+            "upload_min_interval_secs",
+        ],
+    ),
+    ("toolchain", &["update_check"]),
+];
+
+const HARDENED: u32 = 1 << 31;
+/// The BIP-44 path of the first Ethereum-style account of a mnemonic (i.e. `m/44'/60'/0'/0/0`).
+const ACCOUNT_DERIVATION_PATH: [u32; 5] = [44 | HARDENED, 60 | HARDENED, HARDENED, 0, 0];
+
 macro_rules! profile_config_help {
     () => {
         r#"Available options are:
 
     gateway      URL of the developer or Web3  gateway used for testing/deployment.
+                 To fail over between gateways, edit the config file to make this an
+                 array of URLs, which are tried in order.
 
     credential   The API token or private key/mnemonic used to authenticate to the
                  developer or Web3 gateway, respectively.
@@ -61,7 +126,10 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             doc: toml_edit::Document::from_str(default_config_toml!()).unwrap(),
-            dirty: true,
+            // Not saved unless edited, so that a config that failed to load isn't overwritten.
+            dirty: false,
+            dry_run: false,
+            path: None,
         }
     }
 }
@@ -69,31 +137,78 @@ impl Default for Config {
 impl Config {
     pub fn new() -> Self {
         let mut config = Self::default();
-        *config.doc.as_table_mut().entry("telemetry") =
-            toml_edit::Item::Table(Telemetry::new().into());
+        // Fill in the default `[telemetry]` rather than replacing it, which would move it.
+        let telemetry = config.doc.as_table_mut().entry("telemetry");
+        set_value(
+            telemetry.as_table_mut().unwrap(),
+            "user_id",
+            Telemetry::new().user_id,
+        );
         config
     }
 
-    pub fn load() -> Result<Self> {
-        let config_path = Self::default_path()?;
-        if !config_path.exists() {
+    /// Loads the config file at `path` or, if `None`, at the default location.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let config_path = match path {
+            Some(path) => path.to_path_buf(),
+            None => Self::default_path()?,
+        };
+        let mut config = if !config_path.exists() {
             if !Self::skip_generate() {
-                Self::generate(&config_path)
+                Self::generate(&config_path)?
             } else {
-                Ok(Self::default())
+                Self::default()
             }
         } else {
             debug!("loading configuration from `{}`", config_path.display());
-            Self::read_from_file(&config_path)
+            let config = Self::read_from_file(&config_path)?;
+            // Unknown keys are only warned about so that older CLIs can read newer config files.
+            for key in config.unknown_keys() {
+                warn!(
+                    "ignoring unknown configuration key `{}` in `{}`",
+                    key,
+                    config_path.display()
+                );
+            }
+            config
+        };
+        config.path = path.map(Path::to_path_buf);
+        Ok(config)
+    }
+
+    /// Writes the config file if it has unsaved changes.
+    pub fn save(&mut self) -> Result<()> {
+        if !Self::skip_generate() && self.dirty && !self.dry_run {
+            self.write_to_file(self.path()?)?;
+            self.dirty = false;
         }
+        Ok(())
     }
 
-    pub fn save(&self) -> Result<()> {
-        if !Self::skip_generate() && !self.dirty {
-            self.write_to_file(Self::default_path()?)
-        } else {
-            Ok(())
+    /// Keeps subsequent edits from being saved.
+    pub fn set_dry_run(&mut self) {
+        self.dry_run = true;
+    }
+
+    /// Returns the contents of the config file as they would be saved, but with each
+    /// profile's credential redacted.
+    pub fn to_redacted_string(&self) -> String {
+        let mut doc =
+            toml_edit::Document::from_str(&self.doc.to_string_in_original_order()).unwrap();
+        if doc.as_table().contains_key("profile") {
+            if let Some(profiles) = doc.as_table_mut().entry("profile").as_table_mut() {
+                let profile_names: Vec<String> =
+                    profiles.iter().map(|(name, _)| name.to_string()).collect();
+                for profile_name in profile_names.iter() {
+                    if let Some(profile) = profiles.entry(profile_name).as_table_mut() {
+                        if profile.contains_key("credential") {
+                            *profile.entry("credential") = toml_edit::value("<redacted>");
+                        }
+                    }
+                }
+            }
         }
+        doc.to_string_in_original_order()
     }
 
     pub fn get(&self, key: &str) -> Option<String> {
@@ -194,7 +309,7 @@ impl Config {
                         ));
                     }
                 };
-                *profile.entry(profile_key.unwrap()) = toml_edit::value(canon_value);
+                set_value(profile, profile_key.unwrap(), canon_value);
             }
             Some("telemetry") => {
                 let telemetry_key = key_comps.next();
@@ -206,6 +321,24 @@ impl Config {
                 }
                 match telemetry_key {
                     Some("enabled") => self.enable_telemetry(value.parse()?),
+                    Some(key @ "upload_threshold_bytes")
+                    | Some(key @ "upload_min_interval_secs") => {
+                        let num: u64 = value.parse().map_err(|_| {
+                            anyhow!("`telemetry.{}` must be a non-negative integer", key)
+                        })?;
+                        // TOML integers are `i64`s.
+                        let num = i64::try_from(num).map_err(|_| {
+                            anyhow!("`telemetry.{}` must be at most {}", key, i64::max_value())
+                        })?;
+                        let telemetry = self
+                            .doc
+                            .as_table_mut()
+                            .entry("telemetry")
+                            .or_insert(toml_edit::Item::Table(Telemetry::default().into()))
+                            .as_table_mut()
+                            .ok_or_else(|| anyhow!("`telemetry` is not a table"))?;
+                        set_value(telemetry, key, num);
+                    }
                     Some("user_id") => {
                         return Err(anyhow!(
                             "we'd prefer if you didn't modify `user_id`. \
@@ -215,20 +348,173 @@ impl Config {
                     }
                     _ => {
                         return Err(anyhow!(
-                            "unknown configuration option: `{}`. Available options are `enabled`.",
+                            "unknown configuration option: `{}`. Available options are `enabled`, \
+                             `upload_threshold_bytes`, `upload_min_interval_secs`.",
                             key
                         ))
                     }
                 }
             }
+            Some("build") => {
+                let build_key = key_comps.next();
+                if let Some(extra_comp) = key_comps.next() {
+                    return Err(anyhow!(
+                        "unknown build configuration subkey `{}`.",
+                        extra_comp
+                    ));
+                }
+                let build_value = match build_key {
+                    Some("default_release") => {
+                        let default_release: bool = value.parse().map_err(|_| {
+                            anyhow!("`build.default_release` must be `true` or `false`")
+                        })?;
+                        toml_edit::Value::from(default_release)
+                    }
+                    Some("rustc_wrapper") => toml_edit::Value::from(Self::read_value(value)),
+                    _ => {
+                        return Err(anyhow!(
+                            "unknown configuration option: `{}`. \
+                             Available options are `default_release`, `rustc_wrapper`.",
+                            key
+                        ))
+                    }
+                };
+                let build = self
+                    .doc
+                    .as_table_mut()
+                    .entry("build")
+                    .or_insert(toml_edit::table())
+                    .as_table_mut()
+                    .ok_or_else(|| anyhow!("`build` is not a table"))?;
+                set_value(build, build_key.unwrap(), build_value);
+            }
+            Some("toolchain") => {
+                match (key_comps.next(), key_comps.next()) {
+                    (Some("update_check"), None) => {}
+                    _ => {
+                        return Err(anyhow!(
+                            "unknown configuration option: `{}`. \
+                             Available options are `update_check`.",
+                            key
+                        ))
+                    }
+                }
+                let update_check: bool = value
+                    .parse()
+                    .map_err(|_| anyhow!("`toolchain.update_check` must be `true` or `false`"))?;
+                let toolchain = self
+                    .doc
+                    .as_table_mut()
+                    .entry("toolchain")
+                    .or_insert(toml_edit::table())
+                    .as_table_mut()
+                    .ok_or_else(|| anyhow!("`toolchain` is not a table"))?;
+                set_value(toolchain, "update_check", update_check);
+            }
+            Some("logging") => {
+                let logging_key = key_comps.next();
+                if let Some(extra_comp) = key_comps.next() {
+                    return Err(anyhow!(
+                        "unknown logging configuration subkey `{}`.",
+                        extra_comp
+                    ));
+                }
+                let logging_value = match logging_key {
+                    Some("enabled") => {
+                        let enabled: bool = value
+                            .parse()
+                            .map_err(|_| anyhow!("`logging.enabled` must be `true` or `false`"))?;
+                        toml_edit::Value::from(enabled)
+                    }
+                    Some("dir") => toml_edit::Value::from(Self::read_value(value)),
+                    Some("max_size") => {
+                        let max_size: u32 = value
+                            .parse()
+                            .map_err(|_| anyhow!("`logging.max_size` must be a number of bytes"))?;
+                        toml_edit::Value::from(i64::from(max_size))
+                    }
+                    _ => {
+                        return Err(anyhow!(
+                            "unknown configuration option: `{}`. \
+                             Available options are `enabled`, `dir`, `max_size`.",
+                            key
+                        ))
+                    }
+                };
+                let logging = self
+                    .doc
+                    .as_table_mut()
+                    .entry("logging")
+                    .or_insert(toml_edit::table())
+                    .as_table_mut()
+                    .ok_or_else(|| anyhow!("`logging` is not a table"))?;
+                set_value(logging, logging_key.unwrap(), logging_value);
+            }
             Some(key) => return Err(anyhow!("unknown configuration option: `{}`", key)),
             None => {
                 return Err(anyhow!(
-                    "available configuration options are: `profile`, `telemetry`",
+                    "available configuration options are: \
+                     `build`, `logging`, `profile`, `telemetry`, `toolchain`",
                 ))
             }
         }
 
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Upgrades the config file from the schema of an older CLI. The original file is kept
+    /// as `config.toml.bak`.
+    pub fn migrate(&mut self) -> Result<()> {
+        emit!(cmd.config.migrate);
+        let config_path = self.path()?;
+        if !config_path.is_file() {
+            bail!(
+                "there is no configuration file at `{}`",
+                config_path.display()
+            );
+        }
+        if !self.migrate_legacy() {
+            println!("`{}` is already up to date.", config_path.display());
+            return Ok(());
+        }
+        let backup_path = config_path.with_extension("toml.bak");
+        fs::copy(&config_path, &backup_path)?;
+        self.write_to_file(&config_path)?;
+        self.dirty = false;
+        println!(
+            "Migrated `{}`. The original was saved to `{}`.",
+            config_path.display(),
+            backup_path.display()
+        );
+        Ok(())
+    }
+
+    /// Adds `profile.<dst>` as a copy of `profile.<src>`, keeping its comments and formatting.
+    pub fn copy_profile(&mut self, src: &str, dst: &str) -> Result<()> {
+        emit!(cmd.config.copy_profile);
+
+        if dst.is_empty() || dst.contains('.') {
+            bail!("invalid profile name `{}`", dst);
+        }
+        let profile_error = |name: &str, kind| ProfileError {
+            name: name.to_string(),
+            kind,
+        };
+        let profiles = match self.doc.as_table_mut().entry("profile").as_table_mut() {
+            Some(profiles) => profiles,
+            None => return Err(profile_error(src, ProfileErrorKind::MissingProfile).into()),
+        };
+        let profile = match profiles.get(src) {
+            Some(toml_edit::Item::Table(profile)) => profile.clone(),
+            _ => return Err(profile_error(src, ProfileErrorKind::MissingProfile).into()),
+        };
+        if profiles.contains_key(dst) {
+            return Err(profile_error(dst, ProfileErrorKind::ProfileExists).into());
+        }
+        *profiles.entry(dst) = toml_edit::Item::Table(profile);
+
+        self.dirty = true;
         Ok(())
     }
 
@@ -238,16 +524,70 @@ impl Config {
             .get("telemetry")
             .and_then(|t| t.as_table())
             .map(|t| t.into())
-            .unwrap_or_else(|| Telemetry {
-                enabled: false,
-                user_id: "".to_string(),
-            })
+            .unwrap_or_default()
+    }
+
+    /// Returns whether `oasis build` should build in release mode when neither `--debug`
+    /// nor `--release` is given, if configured by `build.default_release`.
+    pub fn default_release(&self) -> Option<bool> {
+        self.doc
+            .as_table()
+            .get("build")
+            .and_then(|t| t.as_table())
+            .and_then(|t| t.get("default_release"))
+            .and_then(|v| v.as_bool())
+    }
+
+    /// Returns whether to occasionally check for a newer toolchain, as opted into by
+    /// `toolchain.update_check`.
+    pub fn update_check(&self) -> bool {
+        self.doc
+            .as_table()
+            .get("toolchain")
+            .and_then(|t| t.as_table())
+            .and_then(|t| t.get("update_check"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or_default()
+    }
+
+    /// Returns the `RUSTC_WRAPPER` used to build services, if configured by `build.rustc_wrapper`.
+    pub fn rustc_wrapper(&self) -> Option<String> {
+        self.doc
+            .as_table()
+            .get("build")
+            .and_then(|t| t.as_table())
+            .and_then(|t| t.get("rustc_wrapper"))
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string())
+    }
+
+    pub fn logging(&self) -> Logging {
+        let logging = self
+            .doc
+            .as_table()
+            .get("logging")
+            .and_then(|t| t.as_table());
+        let get = |key| logging.and_then(|t| t.get(key));
+        Logging {
+            enabled: get("enabled").and_then(|v| v.as_bool()).unwrap_or_default(),
+            dir: get("dir").and_then(|v| v.as_str()).map(PathBuf::from),
+            max_size: get("max_size")
+                .and_then(|v| v.as_integer())
+                .filter(|max_size| *max_size > 0)
+                .map(|max_size| max_size as u64)
+                .unwrap_or(DEFAULT_LOG_MAX_SIZE),
+        }
     }
 
     pub fn profile(&self, profile_name: &str) -> Result<Profile, ProfileError> {
         Profile::try_from_table(profile_name, self.profile_raw(profile_name))
     }
 
+    /// Returns the gateways of a profile, which needn't have a credential.
+    pub fn profile_gateways(&self, profile_name: &str) -> Result<Vec<Url>, ProfileError> {
+        gateways_from_table(profile_name, self.profile_raw(profile_name))
+    }
+
     pub fn profile_raw(&self, profile_name: &str) -> Option<&toml_edit::Table> {
         self.doc
             .as_table()
@@ -269,6 +609,7 @@ impl Config {
         });
 
         config.write_to_file(path)?;
+        config.dirty = false;
 
         println!("Created new configuration file at `{}`.\n", path.display());
 
@@ -278,6 +619,14 @@ impl Config {
         Ok(config)
     }
 
+    /// Returns the path from which this config was loaded and to which it is saved.
+    fn path(&self) -> Result<PathBuf> {
+        match &self.path {
+            Some(path) => Ok(path.to_path_buf()),
+            None => Self::default_path(),
+        }
+    }
+
     fn default_path() -> Result<PathBuf> {
         let mut config_path = crate::oasis_xdg_dir!(config)?;
         config_path.push("config.toml");
@@ -289,17 +638,100 @@ impl Config {
             .map_err(|err| CliError::ReadFile(path.display().to_string(), err.to_string()))?;
         let doc = toml_edit::Document::from_str(&config_string)
             .map_err(|err| CliError::ConfigParse(path.display().to_string(), err.to_string()))?;
-        Ok(Self { doc, dirty: false })
+        Ok(Self {
+            doc,
+            dirty: false,
+            dry_run: false,
+            path: None,
+        })
     }
 
+    /// Writes the config to a temporary file beside `path` and then renames it into place,
+    /// so that an interrupted write can't leave a truncated config (and lose credentials).
+    /// A symlinked config is written through the link, which is left in place.
     fn write_to_file(&self, path: impl AsRef<Path>) -> Result<()> {
-        fs::write(&path, self.doc.to_string_in_original_order())?;
+        let path = fs::canonicalize(path.as_ref()).unwrap_or_else(|_| path.as_ref().to_path_buf());
+        let path = path.as_path();
+        let tmp_path = Self::tmp_path(path);
+        let write_tmp = || -> std::io::Result<()> {
+            let mut tmp_file = fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600 /* o+rw */)
+                .open(&tmp_path)?;
+            // `mode` applies only to new files, so a leftover temporary file is fixed, too.
+            tmp_file.set_permissions(fs::Permissions::from_mode(0o600))?;
+            tmp_file.write_all(self.doc.to_string_in_original_order().as_bytes())?;
+            tmp_file.sync_all()
+        };
+        if let Err(e) = write_tmp() {
+            fs::remove_file(&tmp_path).ok();
+            return Err(CliError::WriteFile(path.display().to_string(), e.to_string()).into());
+        }
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
 
-        let mut perms = fs::metadata(&path)?.permissions();
-        perms.set_mode(0o600 /* o+rw */);
-        fs::set_permissions(&path, perms)?;
+    /// Returns the temporary file for `path`, which is named for this process so that
+    /// concurrent `oasis` commands don't write to the same one.
+    fn tmp_path(path: &Path) -> PathBuf {
+        let mut file_name = std::ffi::OsString::from(".");
+        file_name.push(path.file_name().unwrap_or_default());
+        file_name.push(format!(".{}.tmp", std::process::id()));
+        path.with_file_name(file_name)
+    }
 
-        Ok(())
+    /// Returns the dotted paths of the keys that aren't in `KNOWN_KEYS` (e.g., typos).
+    fn unknown_keys(&self) -> Vec<String> {
+        let table_keys = |item: &toml_edit::Item| -> Vec<String> {
+            match item {
+                toml_edit::Item::Table(tab) => tab
+                    .iter()
+                    .filter(|(_, itm)| !itm.is_none())
+                    .map(|(key, _)| key.to_string())
+                    .collect(),
+                toml_edit::Item::Value(toml_edit::Value::InlineTable(tab)) => {
+                    tab.iter().map(|(key, _)| key.to_string()).collect()
+                }
+                _ => Vec::new(),
+            }
+        };
+        let known_keys = |table: &str| {
+            KNOWN_KEYS
+                .iter()
+                .find(|(name, _)| *name == table)
+                .map(|(_, keys)| *keys)
+        };
+
+        let mut unknown = Vec::new();
+        for (name, item) in self.doc.as_table().iter() {
+            if item.is_none() {
+                continue;
+            }
+            if name == "profile" {
+                let profile_keys = known_keys("profile.*").unwrap();
+                for profile_name in table_keys(item) {
+                    let profile = &item[profile_name.as_str()];
+                    for key in table_keys(profile) {
+                        if !profile_keys.contains(&key.as_str()) {
+                            unknown.push(format!("profile.{}.{}", profile_name, key));
+                        }
+                    }
+                }
+                continue;
+            }
+            match known_keys(name) {
+                Some(keys) => unknown.extend(
+                    table_keys(item)
+                        .into_iter()
+                        .filter(|key| !keys.contains(&key.as_str()))
+                        .map(|key| format!("{}.{}", name, key)),
+                ),
+                None => unknown.push(name.to_string()),
+            }
+        }
+        unknown
     }
 
     fn skip_generate() -> bool {
@@ -309,14 +741,97 @@ impl Config {
     }
 
     fn enable_telemetry(&mut self, enabled: bool) {
-        *self
+        let telemetry = self
             .doc
             .as_table_mut()
             .entry("telemetry")
             .or_insert(toml_edit::Item::Table(Telemetry::default().into()))
             .as_table_mut()
-            .unwrap()
-            .entry("enabled") = toml_edit::value(enabled);
+            .unwrap();
+        set_value(telemetry, "enabled", enabled);
+        self.dirty = true;
+    }
+
+    /// Rewrites the schema used before `[telemetry]` existed. Its profiles could be inline
+    /// tables and had an `endpoint` and a `private_key` or `mnemonic` instead of a `gateway`
+    /// and `credential`, and telemetry was configured by a `[logging]` that also had the paths
+    /// of the (never rotated) output logs.
+    /// Returns whether anything needed to be migrated.
+    fn migrate_legacy(&mut self) -> bool {
+        let mut migrated = false;
+        let root = self.doc.as_table_mut();
+
+        if root.contains_key("profile") {
+            if let Some(profiles) = root.entry("profile").as_table_mut() {
+                let profile_names: Vec<String> =
+                    profiles.iter().map(|(name, _)| name.to_string()).collect();
+                for profile_name in profile_names.iter() {
+                    let profile = profiles.entry(profile_name);
+                    let expanded_profile = profile.as_inline_table().map(|inline_profile| {
+                        let mut tab = toml_edit::Table::new();
+                        for (key, value) in inline_profile.iter() {
+                            *tab.entry(key) = toml_edit::Item::Value(value.clone());
+                        }
+                        tab
+                    });
+                    if let Some(tab) = expanded_profile {
+                        *profile = toml_edit::Item::Table(tab);
+                        migrated = true;
+                    }
+                    let profile = match profile.as_table_mut() {
+                        Some(profile) => profile,
+                        None => continue,
+                    };
+                    for (legacy_key, key) in &[
+                        ("endpoint", "gateway"),
+                        ("private_key", "credential"),
+                        ("mnemonic", "credential"),
+                    ] {
+                        let legacy_value = match profile.remove(legacy_key) {
+                            Some(legacy_value) => legacy_value,
+                            None => continue,
+                        };
+                        migrated = true;
+                        if let Some(legacy_value) = legacy_value.as_str() {
+                            if !profile.contains_key(key) {
+                                *profile.entry(key) = toml_edit::value(legacy_value);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let is_legacy_logging = root
+            .get("logging")
+            .and_then(|logging| logging.as_table())
+            .map(|logging| {
+                logging.contains_key("path_stdout") || logging.contains_key("path_stderr")
+            })
+            .unwrap_or_default();
+        if is_legacy_logging {
+            let logging = root.remove("logging").unwrap();
+            migrated = true;
+            if !root.contains_key("telemetry") {
+                let mut telemetry = Telemetry::new();
+                if let Some(logging) = logging.as_table() {
+                    telemetry.enabled = logging
+                        .get("enabled")
+                        .and_then(|e| e.as_bool())
+                        .unwrap_or_default();
+                    if let Some(user_id) = logging
+                        .get("id")
+                        .or_else(|| logging.get("user_id"))
+                        .and_then(|id| id.as_str())
+                    {
+                        telemetry.user_id = user_id.to_string();
+                    }
+                }
+                *root.entry("telemetry") = toml_edit::Item::Table(telemetry.into());
+            }
+        }
+
+        migrated
     }
 
     fn read_value(value: &str) -> String {
@@ -331,10 +846,24 @@ impl Config {
     }
 }
 
-#[derive(Default)]
 pub struct Telemetry {
     pub enabled: bool,
     pub user_id: String,
+    /// The size, in bytes, of the metrics file at which it is uploaded.
+    pub upload_threshold_bytes: u64,
+    /// The minimum number of seconds between uploads.
+    pub upload_min_interval_secs: u64,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            user_id: String::new(),
+            upload_threshold_bytes: DEFAULT_UPLOAD_THRESHOLD_BYTES,
+            upload_min_interval_secs: DEFAULT_UPLOAD_MIN_INTERVAL_SECS,
+        }
+    }
 }
 
 impl Telemetry {
@@ -346,14 +875,22 @@ impl Telemetry {
             .encode_lower(&mut user_id);
 
         Telemetry {
-            enabled: false,
             user_id: String::from_utf8(user_id).unwrap(),
+            ..Default::default()
         }
     }
 }
 
 impl<T: std::borrow::Borrow<toml_edit::Table>> From<T> for Telemetry {
     fn from(tab: T) -> Self {
+        let get_u64 = |key| {
+            tab.borrow()
+                .get(key)
+                .and_then(|v| v.as_integer())
+                .filter(|v| *v >= 0)
+                .map(|v| v as u64)
+        };
+        let defaults = Self::default();
         Self {
             enabled: tab
                 .borrow()
@@ -366,6 +903,10 @@ impl<T: std::borrow::Borrow<toml_edit::Table>> From<T> for Telemetry {
                 .and_then(|u| u.as_str())
                 .map(|u| u.to_string())
                 .unwrap_or_default(),
+            upload_threshold_bytes: get_u64("upload_threshold_bytes")
+                .unwrap_or(defaults.upload_threshold_bytes),
+            upload_min_interval_secs: get_u64("upload_min_interval_secs")
+                .unwrap_or(defaults.upload_min_interval_secs),
         }
     }
 }
@@ -379,8 +920,18 @@ impl From<Telemetry> for toml_edit::Table {
     }
 }
 
+/// Whether, where, and how much the CLI logs to a file, as configured by `[logging]`.
+pub struct Logging {
+    pub enabled: bool,
+    /// Defaults to `logs` in the Oasis data dir.
+    pub dir: Option<PathBuf>,
+    /// The size, in bytes, at which the log file is compressed and a new one is started.
+    pub max_size: u64,
+}
+
 pub struct Profile {
-    pub gateway: Url,
+    /// The gateways of the profile, in order of preference. There is always at least one.
+    pub gateways: Vec<Url>,
     pub credential: Credential,
 }
 
@@ -399,6 +950,92 @@ impl fmt::Display for Credential {
     }
 }
 
+impl Credential {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Credential::Mnemonic(_) => "mnemonic",
+            Credential::PrivateKey(_) => "private key",
+            Credential::ApiToken(_) => "API token",
+        }
+    }
+
+    /// Returns the address of the account that the credential controls. API tokens are
+    /// not associated with an account.
+    pub fn address(&self) -> Option<String> {
+        let secret_key = match self {
+            Credential::Mnemonic(mnemonic) => derive_secret_key(mnemonic)?,
+            Credential::PrivateKey(key) => {
+                secp256k1::SecretKey::from_slice(&hex::decode(key).ok()?).ok()?
+            }
+            Credential::ApiToken(_) => return None,
+        };
+        let secp = secp256k1::Secp256k1::signing_only();
+        let public_key =
+            secp256k1::PublicKey::from_secret_key(&secp, &secret_key).serialize_uncompressed();
+        Some(format!(
+            "0x{}",
+            hex::encode(&keccak256(&public_key[1..])[12..])
+        ))
+    }
+
+    /// Returns the start of the credential followed by asterisks, which is safe to print.
+    pub fn masked(&self) -> String {
+        let credential = self.to_string();
+        let prefix: String = credential.chars().take(4).collect();
+        format!(
+            "{}{}",
+            prefix,
+            "*".repeat(credential.chars().count() - prefix.chars().count())
+        )
+    }
+}
+
+/// Derives the secret key of the first account of a BIP-39 mnemonic according to BIP-32.
+fn derive_secret_key(mnemonic: &str) -> Option<secp256k1::SecretKey> {
+    use hmac::Mac as _;
+    type HmacSha512 = hmac::Hmac<sha2::Sha512>;
+    let hmac_sha512 = |key: &[u8], data: &[u8]| {
+        let mut mac = HmacSha512::new_varkey(key).unwrap();
+        mac.input(data);
+        mac.result().code()
+    };
+
+    let mut seed = [0u8; 64];
+    pbkdf2::pbkdf2::<HmacSha512>(mnemonic.as_bytes(), b"mnemonic", 2048, &mut seed);
+
+    let secp = secp256k1::Secp256k1::signing_only();
+    let master = hmac_sha512(b"Bitcoin seed", &seed);
+    let mut secret_key = secp256k1::SecretKey::from_slice(&master[..32]).ok()?;
+    let mut chain_code = master[32..].to_vec();
+    for &index in ACCOUNT_DERIVATION_PATH.iter() {
+        let mut data = Vec::with_capacity(37);
+        if index & HARDENED != 0 {
+            data.push(0);
+            data.extend_from_slice(&secret_key[..]);
+        } else {
+            data.extend_from_slice(
+                &secp256k1::PublicKey::from_secret_key(&secp, &secret_key).serialize(),
+            );
+        }
+        data.extend_from_slice(&index.to_be_bytes());
+        let child = hmac_sha512(&chain_code, &data);
+        let mut child_key = secp256k1::SecretKey::from_slice(&child[..32]).ok()?;
+        child_key.add_assign(&secret_key[..]).ok()?;
+        secret_key = child_key;
+        chain_code = child[32..].to_vec();
+    }
+    Some(secret_key)
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    use tiny_keccak::Hasher as _;
+    let mut hasher = tiny_keccak::Keccak::v256();
+    hasher.update(data);
+    let mut hash = [0u8; 32];
+    hasher.finalize(&mut hash);
+    hash
+}
+
 impl FromStr for Credential {
     type Err = Error;
 
@@ -421,54 +1058,150 @@ impl FromStr for Credential {
     }
 }
 
+macro_rules! profile_err {
+    ($profile_name:expr, missing) => {
+        ProfileError {
+            name: $profile_name.to_string(),
+            kind: ProfileErrorKind::MissingProfile,
+        }
+    };
+    ($profile_name:expr, $key:expr, missing) => {
+        ProfileError {
+            name: $profile_name.to_string(),
+            kind: ProfileErrorKind::MissingKey($key),
+        }
+    };
+    ($profile_name:expr, $key:expr, $cause:expr) => {
+        ProfileError {
+            name: $profile_name.to_string(),
+            kind: ProfileErrorKind::InvalidKey($key, $cause.to_string()),
+        }
+    };
+}
+
 impl Profile {
+    /// Creates a profile that is not in the config file, e.g. for a one-off gateway.
+    pub fn ephemeral(gateway: &str, credential: &str) -> Result<Self> {
+        Ok(Self {
+            gateways: vec![parse_gateway_url(gateway)
+                .map_err(|e| anyhow!("invalid gateway `{}`: {}", gateway, e))?],
+            credential: Credential::from_str(credential)
+                .map_err(|e| anyhow!("invalid credential: {}", e))?,
+        })
+    }
+
     fn try_from_table(
         profile_name: &str,
         profile_tab: Option<&toml_edit::Table>,
     ) -> Result<Self, ProfileError> {
-        macro_rules! err {
-            (missing) => {
-                ProfileError {
-                    name: profile_name.to_string(),
-                    kind: ProfileErrorKind::MissingProfile,
-                }
-            };
-            ($key:expr, missing) => {
-                ProfileError {
-                    name: profile_name.to_string(),
-                    kind: ProfileErrorKind::MissingKey($key),
-                }
-            };
-            ($key:expr, $cause:expr) => {
-                ProfileError {
-                    name: profile_name.to_string(),
-                    kind: ProfileErrorKind::InvalidKey($key, $cause.to_string()),
-                }
-            };
-        }
-
-        let profile = match profile_tab {
-            Some(tab) => tab,
-            None => return Err(err!(missing)),
-        };
+        let profile = profile_tab.ok_or_else(|| profile_err!(profile_name, missing))?;
         Ok(Self {
-            gateway: profile
-                .get("gateway")
-                .and_then(|gw| gw.as_str())
-                .ok_or_else(|| err!("gateway", missing))
-                .and_then(|gw| parse_gateway_url(gw).map_err(|e| err!("gateway", e)))?,
+            gateways: gateways_from_table(profile_name, profile_tab)?,
             credential: Credential::from_str(
                 profile
                     .get("credential")
                     .and_then(|c| c.as_str())
-                    .ok_or_else(|| err!("credential", missing))?,
+                    .ok_or_else(|| profile_err!(profile_name, "credential", missing))?,
             )
-            .map_err(|e| err!("credential", e))?,
+            .map_err(|e| profile_err!(profile_name, "credential", e))?,
         })
     }
+
+    /// Returns the first gateway that accepts connections. A lone gateway is returned without
+    /// being checked, and the first gateway is returned if none of them are reachable.
+    pub fn reachable_gateway(&self) -> &Url {
+        reachable_gateway(&self.gateways)
+    }
 }
 
-fn parse_gateway_url(url_str: &str) -> Result<Url> {
+/// Parses the gateways of a profile, which don't need its credential (e.g., to write a bundle
+/// that will be deployed later).
+fn gateways_from_table(
+    profile_name: &str,
+    profile_tab: Option<&toml_edit::Table>,
+) -> Result<Vec<Url>, ProfileError> {
+    let profile = profile_tab.ok_or_else(|| profile_err!(profile_name, missing))?;
+    // `gateway` is either a single URL or an array of URLs in order of preference.
+    let gateway_strs: Vec<Option<&str>> = match profile.get("gateway") {
+        Some(gw) => match (gw.as_str(), gw.as_array()) {
+            (Some(gw), _) => vec![Some(gw)],
+            (None, Some(gws)) => gws.iter().map(|gw| gw.as_str()).collect(),
+            (None, None) => {
+                return Err(profile_err!(
+                    profile_name,
+                    "gateway",
+                    "must be a URL or an array of URLs"
+                ))
+            }
+        },
+        None => return Err(profile_err!(profile_name, "gateway", missing)),
+    };
+    if gateway_strs.is_empty() {
+        return Err(profile_err!(
+            profile_name,
+            "gateway",
+            "must contain at least one URL"
+        ));
+    }
+    gateway_strs
+        .into_iter()
+        .map(|gw| {
+            let gw =
+                gw.ok_or_else(|| profile_err!(profile_name, "gateway", "must contain only URLs"))?;
+            parse_gateway_url(gw).map_err(|e| profile_err!(profile_name, "gateway", e))
+        })
+        .collect()
+}
+
+/// Returns the first of `gateways` that accepts connections, as `Profile::reachable_gateway`.
+pub fn reachable_gateway(gateways: &[Url]) -> &Url {
+    if gateways.len() > 1 {
+        for gateway in gateways.iter() {
+            if is_reachable(gateway) {
+                return gateway;
+            }
+            debug!("gateway `{}` is unreachable", gateway);
+        }
+        warn!(
+            "none of the gateways are reachable, so using `{}`",
+            gateways[0]
+        );
+    }
+    &gateways[0]
+}
+
+pub fn is_reachable(url: &Url) -> bool {
+    use std::net::ToSocketAddrs as _;
+    let (host, port) = match (url.host_str(), url.port_or_known_default()) {
+        (Some(host), Some(port)) => (host, port),
+        _ => return false,
+    };
+    (host, port)
+        .to_socket_addrs()
+        .map(|mut addrs| {
+            addrs.any(|addr| {
+                std::net::TcpStream::connect_timeout(&addr, GATEWAY_CONNECT_TIMEOUT).is_ok()
+            })
+        })
+        .unwrap_or_default()
+}
+
+/// Sets `tab[key]` to `value` while keeping the whitespace and comments around the existing
+/// value, if any, so that hand-written config files keep their formatting.
+fn set_value(tab: &mut toml_edit::Table, key: &str, value: impl Into<toml_edit::Value>) {
+    let item = tab.entry(key);
+    let value = match item.as_value() {
+        Some(old_value) => toml_edit::decorated(
+            value.into(),
+            old_value.decor().prefix(),
+            old_value.decor().suffix(),
+        ),
+        None => value.into(),
+    };
+    *item = toml_edit::Item::Value(value);
+}
+
+pub fn parse_gateway_url(url_str: &str) -> Result<Url> {
     let url = Url::parse(url_str)?;
     if !url.has_host() {
         return Err(anyhow!("URL must specify a domain"));
@@ -485,3 +1218,255 @@ fn parse_gateway_url(url_str: &str) -> Result<Url> {
 
     Ok(url)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVNET_MNEMONIC: &str =
+        "range drive remove bleak mule satisfy mandate east lion minimum unfold ready";
+
+    fn parse_profile(profile_toml: &str) -> Result<Profile, ProfileError> {
+        let doc = toml_edit::Document::from_str(profile_toml).unwrap();
+        Profile::try_from_table(
+            "test",
+            doc.as_table().get("profile").and_then(|p| p.as_table()),
+        )
+    }
+
+    fn temp_config() -> (PathBuf, Config) {
+        let path = std::env::temp_dir().join(format!("{}.toml", uuid::Uuid::new_v4()));
+        fs::write(&path, default_config_toml!()).unwrap();
+        let config = Config::load(Some(&path)).unwrap();
+        (path, config)
+    }
+
+    #[test]
+    fn test_edit_then_save() {
+        let (path, mut config) = temp_config();
+        config.edit("build.default_release", "false").unwrap();
+        config.save().unwrap();
+        let saved = Config::load(Some(&path)).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved.default_release(), Some(false));
+        assert!(!saved.dirty);
+    }
+
+    #[test]
+    fn test_get_does_not_save() {
+        let (path, mut config) = temp_config();
+        assert_eq!(config.get("telemetry.enabled").as_deref(), Some("false"));
+        fs::remove_file(&path).unwrap();
+        config.save().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_failed_edit_does_not_save() {
+        let (path, mut config) = temp_config();
+        assert!(config.edit("build.nonexistent", "1").is_err());
+        fs::remove_file(&path).unwrap();
+        config.save().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_interrupted_write_keeps_original() {
+        let (path, mut config) = temp_config();
+        let original = fs::read_to_string(&path).unwrap();
+        config.edit("build.default_release", "false").unwrap();
+
+        // The temporary file can't be written if a directory is in its place.
+        let tmp_path = Config::tmp_path(&path);
+        fs::create_dir(&tmp_path).unwrap();
+        assert!(config.save().is_err());
+        fs::remove_dir(&tmp_path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+
+        config.save().unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert!(!tmp_path.exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_through_symlink() {
+        let (path, _) = temp_config();
+        let link_path = path.with_extension("link.toml");
+        std::os::unix::fs::symlink(&path, &link_path).unwrap();
+        let mut config = Config::load(Some(&link_path)).unwrap();
+        config.edit("build.default_release", "false").unwrap();
+        config.save().unwrap();
+
+        let is_symlink = fs::symlink_metadata(&link_path)
+            .unwrap()
+            .file_type()
+            .is_symlink();
+        let saved = Config::load(Some(&path)).unwrap();
+        fs::remove_file(&link_path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(is_symlink);
+        assert_eq!(saved.default_release(), Some(false));
+    }
+
+    #[test]
+    fn test_copy_profile() {
+        let mut config = Config::default();
+        config.copy_profile("local", "staging").unwrap();
+        assert!(config.dirty);
+        assert_eq!(
+            config.get("profile.staging.gateway").as_deref(),
+            Some("ws://localhost:8546")
+        );
+        assert_eq!(
+            config.get("profile.staging.credential").as_deref(),
+            Some(DEVNET_MNEMONIC)
+        );
+        let toml = config.doc.to_string_in_original_order();
+        assert!(toml.contains("[profile.staging]\ngateway = \"ws://localhost:8546\"  # web3\n"));
+
+        // The copy is independent of the original.
+        config
+            .edit("profile.staging.gateway", "http://localhost:1234")
+            .unwrap();
+        assert_eq!(
+            config.get("profile.local.gateway").as_deref(),
+            Some("ws://localhost:8546")
+        );
+    }
+
+    #[test]
+    fn test_copy_profile_invalid() {
+        let mut config = Config::default();
+        let err = config.copy_profile("local", "default").unwrap_err();
+        assert_eq!(err.to_string(), "`profile.default` already exists");
+        let err = config.copy_profile("staging", "new").unwrap_err();
+        assert_eq!(err.to_string(), "`profile.staging` does not exist");
+        assert!(config.copy_profile("local", "a.b").is_err());
+        assert!(!config.dirty);
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let doc = r#"
+[profle.default]
+gateway = "ws://localhost:8546"
+
+[profile.default]
+gatway = "ws://localhost:8546"
+credential = "abc"
+
+[profile.legacy]
+endpoint = "ws://localhost:8546"
+
+[telemetry]
+enabeld = true
+user_id = "abc"
+
+[logging]
+max_size = 1
+"#;
+        let config = Config {
+            doc: toml_edit::Document::from_str(doc).unwrap(),
+            ..Config::default()
+        };
+        assert_eq!(
+            config.unknown_keys(),
+            vec!["profle", "profile.default.gatway", "telemetry.enabeld"]
+        );
+        assert!(Config::default().unknown_keys().is_empty());
+    }
+
+    #[test]
+    fn test_profile_gateway() {
+        let profile = parse_profile(&format!(
+            "[profile]\ngateway = \"ws://localhost:8546\"\ncredential = \"{}\"",
+            DEVNET_MNEMONIC
+        ))
+        .unwrap();
+        assert_eq!(
+            profile.gateways,
+            vec![Url::parse("ws://localhost:8546").unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_profile_gateways() {
+        let profile = parse_profile(&format!(
+            "[profile]\ngateway = [\"ws://localhost:8546\", \"{}\"]\ncredential = \"{}\"",
+            DEFAULT_GATEWAY_URL, DEVNET_MNEMONIC
+        ))
+        .unwrap();
+        assert_eq!(
+            profile.gateways,
+            vec![
+                Url::parse("ws://localhost:8546").unwrap(),
+                Url::parse(DEFAULT_GATEWAY_URL).unwrap()
+            ]
+        );
+
+        for invalid_gateway in &["[]", "[1]", "[\"not://a-url!\"]", "1"] {
+            let invalid_profile = parse_profile(&format!(
+                "[profile]\ngateway = {}\ncredential = \"{}\"",
+                invalid_gateway, DEVNET_MNEMONIC
+            ));
+            assert!(invalid_profile.is_err(), "{}", invalid_gateway);
+        }
+    }
+
+    #[test]
+    fn test_reachable_gateway() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let unbound_port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let url = |port| Url::parse(&format!("ws://127.0.0.1:{}", port)).unwrap();
+        let profile = Profile {
+            gateways: vec![
+                url(unbound_port),
+                url(listener.local_addr().unwrap().port()),
+            ],
+            credential: Credential::from_str(DEVNET_MNEMONIC).unwrap(),
+        };
+        assert_eq!(profile.reachable_gateway(), &profile.gateways[1]);
+    }
+
+    #[test]
+    fn test_mnemonic_address() {
+        let credential = Credential::from_str(DEVNET_MNEMONIC).unwrap();
+        assert_eq!(credential.kind(), "mnemonic");
+        assert_eq!(
+            credential.address().unwrap(),
+            "0xb8b3666d8fea887d97ab54f571b8e5020c5c8b58"
+        );
+    }
+
+    #[test]
+    fn test_private_key_address() {
+        let credential = Credential::from_str(
+            "0x77827066de994266ffc685a8165e6f1b62c671ff801ba08475ca4c8b41ebf388",
+        )
+        .unwrap();
+        assert_eq!(credential.kind(), "private key");
+        assert_eq!(
+            credential.address().unwrap(),
+            "0x1b55d7597f803e9b32ffc607be9241e9a91ff722"
+        );
+    }
+
+    #[test]
+    fn test_api_token_masked() {
+        let token = base64::encode(&[7u8; API_TOKEN_BYTES]);
+        let credential = Credential::from_str(&token).unwrap();
+        assert_eq!(credential.kind(), "API token");
+        assert!(credential.address().is_none());
+        let masked = credential.masked();
+        assert_eq!(&masked[..4], &token[..4]);
+        assert_eq!(masked[4..], "*".repeat(token.len() - 4));
+    }
+}
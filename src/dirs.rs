@@ -1,5 +1,7 @@
 //! The functions in this module are based on those from the `dirs` crate except they always
 //! return directories according to the XDG specification even if on a non-Linux OS.
+//! The `oasis_*_dir` functions return the directories in which `oasis` keeps its own files,
+//! which may be moved by setting `OASIS_CONFIG_DIR` or `OASIS_DATA_DIR` to an absolute path.
 //! The functions assume that the user has a home directory. Make sure to call `has_home_dir`
 //! before using any of the panicking functions in this module.
 
@@ -51,6 +53,22 @@ pub fn bin_dir() -> PathBuf {
         })
 }
 
+pub fn oasis_config_dir() -> PathBuf {
+    env::var_os("OASIS_CONFIG_DIR")
+        .and_then(is_absolute_path)
+        .unwrap_or_else(|| config_dir().join("oasis"))
+}
+
+pub fn oasis_data_dir() -> PathBuf {
+    env::var_os("OASIS_DATA_DIR")
+        .and_then(is_absolute_path)
+        .unwrap_or_else(|| data_dir().join("oasis"))
+}
+
+pub fn oasis_cache_dir() -> PathBuf {
+    cache_dir().join("oasis")
+}
+
 #[macro_export]
 macro_rules! ensure_dir {
     ($dir:expr) => {
@@ -88,7 +106,14 @@ macro_rules! ensure_xdg_dir {
 
 #[macro_export]
 macro_rules! oasis_xdg_dir {
-    ($dir:ident) => {
-        $crate::ensure_xdg_dir!($dir.push("oasis"));
-    };
+    ($dir:ident) => {{
+        use crate::dirs::*;
+        let dir = concat_idents!(oasis_, $dir, _dir)();
+        $crate::ensure_dir!(
+            dir,
+            "{} dir `{}` is a file",
+            stringify!($dir),
+            dir.display()
+        )
+    }};
 }
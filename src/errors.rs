@@ -1,4 +1,4 @@
-use std::fmt;
+use std::{fmt, time::Duration};
 
 pub use anyhow::{Error, Result};
 
@@ -10,6 +10,9 @@ pub enum CliError {
     #[error("process `{0}` exited with code `{1}`")]
     ProcessExit(String, i32),
 
+    #[error("process `{0}` timed out after {1:?}")]
+    ProcessTimeout(String, Duration),
+
     #[error("errored to parse `{0}`: `{1}`")]
     ConfigParse(String, String),
 
@@ -19,11 +22,32 @@ pub enum CliError {
     #[error("could not read file `{0}`: `{1}`")]
     ReadFile(String, String),
 
+    #[error("could not write file `{0}`: `{1}`")]
+    WriteFile(String, String),
+
     #[error("destination path `{0}` already exists")]
     FileAlreadyExists(String),
 
     #[error("unknown toolchain version: `{}`", .0)]
     UnknownToolchain(String),
+
+    #[error("`{0}` has not been built: could not find `{1}`")]
+    MissingArtifact(String, String),
+
+    #[error("could not import `{0}`: no such file")]
+    ImportNotFound(String),
+
+    #[error("could not import `{0}`: the network is unreachable: {1}")]
+    ImportUnreachable(String, String),
+
+    #[error("could not import `{0}`: the server responded with HTTP status {1}")]
+    ImportHttpStatus(String, u16),
+
+    #[error("could not import `{0}`: not a valid service: {1}")]
+    InvalidImport(String, String),
+
+    #[error("could not import `{0}`: {1}")]
+    ImportFailed(String, String),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -34,8 +58,17 @@ pub enum WorkspaceError {
     #[error("could not find dependency `{0}` in the current workspace")]
     MissingDependency(String),
 
+    #[error("could not resolve dependency `{0}` imported from `{1}`: {2}")]
+    UnresolvableImport(String, String, String),
+
     #[error("`{0}` has a circular dependency on `{1}`")]
     CircularDependency(String, String),
+
+    #[error("no targets matched `{0}`")]
+    NoMatchingTargets(String),
+
+    #[error("no Oasis projects found under `{0}`. Run `oasis init` to create one.")]
+    NoProjects(String),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -48,6 +81,7 @@ impl fmt::Display for ProfileError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.kind {
             ProfileErrorKind::MissingProfile => write!(f, "`profile.{}` does not exist", self.name),
+            ProfileErrorKind::ProfileExists => write!(f, "`profile.{}` already exists", self.name),
             ProfileErrorKind::MissingKey(key) => {
                 write!(f, "`profile.{}` is missing field: `{}`.", self.name, key)
             }
@@ -61,6 +95,7 @@ impl fmt::Display for ProfileError {
 #[derive(Debug)]
 pub enum ProfileErrorKind {
     MissingProfile,
+    ProfileExists,
     MissingKey(&'static str),
     InvalidKey(&'static str, String),
 }
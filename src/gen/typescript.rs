@@ -4,8 +4,8 @@ use proc_macro2::{Ident, Literal, TokenStream};
 use quote::{format_ident, quote};
 
 macro_rules! format_ts_ident {
-    (@import, $name:expr) => {
-        format_ts_ident!(@raw, $name.to_mixed_case())
+    (@binding, $name:expr) => {
+        format_ts_ident!(@raw, escape_reserved(var_name(&$name)))
     };
     (@class, $name:expr) => {
         format_ts_ident!(@raw, $name.to_camel_case())
@@ -26,12 +26,12 @@ pub fn generate(iface: &Interface, bytecode: &[u8]) -> TokenStream {
     let bytecode_str = base64::encode(bytecode);
 
     let imports = iface.imports.iter().map(|imp| {
-        let import_ident = format_ts_ident!(@var, imp.name);
+        let import_ident = format_ts_ident!(@binding, imp.name);
         let import_path = format!("./{}", module_name(&imp.name));
         quote!(import * as #import_ident from #import_path;)
     });
 
-    let type_defs = generate_type_defs(&iface.type_defs);
+    let type_defs = generate_type_defs(&iface.type_defs, false /* decls_only */);
 
     let deploy_function = generate_deploy_function(&service_ident, &iface.constructor);
     let rpc_functions = generate_rpc_functions(&service_ident, &iface.functions);
@@ -63,7 +63,71 @@ pub fn generate(iface: &Interface, bytecode: &[u8]) -> TokenStream {
     }
 }
 
-fn generate_type_defs(type_defs: &[oasis_rpc::TypeDef]) -> Vec<TokenStream> {
+/// Generates only the type declarations (i.e. a `.d.ts`) for a service's interface.
+/// Unlike `generate`, the output contains no encoding, deployment, or RPC machinery.
+pub fn generate_declarations(iface: &Interface) -> TokenStream {
+    let service_ident = format_ts_ident!(@class, iface.name);
+
+    let imports = iface.imports.iter().map(|imp| {
+        let import_ident = format_ts_ident!(@binding, imp.name);
+        let import_path = format!("./{}", module_name(&imp.name));
+        quote!(import * as #import_ident from #import_path;)
+    });
+
+    let type_decls = generate_type_defs(&iface.type_defs, true /* decls_only */);
+
+    let rpc_decls = iface.functions.iter().map(|rpc| {
+        let fn_ident = format_ts_ident!(@var, rpc.name);
+        let rpc_ret_ty = quote_rpc_ret_ty(rpc.output.as_ref());
+        if rpc.inputs.is_empty() {
+            quote!(#fn_ident(options?: oasis.RpcOptions): Promise<#rpc_ret_ty>;)
+        } else {
+            let arg_decls = rpc.inputs.iter().map(generate_field_decl);
+            quote! {
+                #fn_ident(
+                    args: { #(#arg_decls;)* },
+                    options?: oasis.RpcOptions
+                ): Promise<#rpc_ret_ty>;
+            }
+        }
+    });
+
+    quote! {
+        import * as oasis from "oasis-std";
+
+        #(#imports)*
+
+        #(#type_decls)*
+
+        export interface #service_ident {
+            readonly address: oasis.Address;
+
+            #(#rpc_decls)*
+        }
+    }
+}
+
+/// Generates the types of an interface as classes that can be ABI-encoded or, if
+/// `decls_only`, as bare declarations.
+fn generate_type_defs(type_defs: &[oasis_rpc::TypeDef], decls_only: bool) -> Vec<TokenStream> {
+    let gen_struct = |name: &str, fields: &[oasis_rpc::Field], variant_idx| {
+        if decls_only {
+            generate_struct_decl(name, fields)
+        } else {
+            generate_struct_class(name, fields, quote!(), variant_idx)
+        }
+    };
+    let gen_tuple = |name: &str, tys: &[oasis_rpc::Type], variant_idx| {
+        if decls_only {
+            generate_tuple_decl(name, tys)
+        } else {
+            generate_tuple_class(name, tys, quote!(), variant_idx)
+        }
+    };
+    let field_tys = |fields: &[oasis_rpc::Field]| -> Vec<oasis_rpc::Type> {
+        fields.iter().map(|f| f.ty.clone()).collect()
+    };
+
     type_defs
         .iter()
         .map(|type_def| {
@@ -72,67 +136,42 @@ fn generate_type_defs(type_defs: &[oasis_rpc::TypeDef]) -> Vec<TokenStream> {
             match type_def {
                 TypeDef::Struct { name, fields } => {
                     if fields.iter().any(|f| f.name.parse::<u32>().is_ok()) {
-                        generate_tuple_class(
-                            &name,
-                            &fields.iter().map(|f| f.ty.clone()).collect::<Vec<_>>(),
-                            quote!(),
-                            None,
-                        )
+                        gen_tuple(name, &field_tys(fields), None)
                     } else {
-                        generate_struct_class(name, fields, quote!(), None)
+                        gen_struct(name, fields, None)
                     }
                 }
                 TypeDef::Enum { name, variants } => {
                     let type_ident = format_ts_ident!(@class, name);
-
                     let variant_idents: Vec<_> = variants
                         .iter()
                         .map(|v| format_ts_ident!(@class, v.name))
                         .collect();
-                    let variant_classes =
-                        variants
-                            .iter()
-                            .enumerate()
-                            .map(|(i, variant)| match &variant.fields {
-                                Some(oasis_rpc::EnumFields::Named(fields)) => {
-                                    let is_tuple = fields
-                                        .iter()
-                                        .enumerate()
-                                        .all(|(i, field)| field.name == i.to_string());
-                                    if !is_tuple {
-                                        generate_struct_class(
-                                            &variant.name,
-                                            fields,
-                                            quote!(),
-                                            Some(i),
-                                        )
-                                    } else {
-                                        generate_tuple_class(
-                                            &variant.name,
-                                            &fields
-                                                .iter()
-                                                .map(|f| f.ty.clone())
-                                                .collect::<Vec<_>>(),
-                                            quote!(),
-                                            Some(i),
-                                        )
-                                    }
-                                }
-                                Some(oasis_rpc::EnumFields::Tuple(tys)) => {
-                                    generate_tuple_class(&variant.name, &tys, quote!(), Some(i))
+                    let variant_defs: Vec<_> = variants
+                        .iter()
+                        .enumerate()
+                        .map(|(i, variant)| match &variant.fields {
+                            Some(oasis_rpc::EnumFields::Named(fields)) => {
+                                let is_tuple = fields
+                                    .iter()
+                                    .enumerate()
+                                    .all(|(i, field)| field.name == i.to_string());
+                                if !is_tuple {
+                                    gen_struct(&variant.name, fields, Some(i))
+                                } else {
+                                    gen_tuple(&variant.name, &field_tys(fields), Some(i))
                                 }
-                                None => generate_tuple_class(
-                                    &variant.name,
-                                    &[], /* no fields */
-                                    quote!(),
-                                    Some(i),
-                                ),
-                            });
-
-                    quote! {
-                        export module #type_ident {
-                            #(#variant_classes)*
-
+                            }
+                            Some(oasis_rpc::EnumFields::Tuple(tys)) => {
+                                gen_tuple(&variant.name, tys, Some(i))
+                            }
+                            None => gen_tuple(&variant.name, &[] /* no fields */, Some(i)),
+                        })
+                        .collect();
+                    let variant_helpers = if decls_only {
+                        quote!()
+                    } else {
+                        quote! {
                             export function abiDecode(decoder: oasis.Decoder): #type_ident {
                                 const variantId = decoder.readU8();
                                 return (#type_ident as any).VARIANTS[variantId].abiDecode(decoder);
@@ -149,6 +188,13 @@ fn generate_type_defs(type_defs: &[oasis_rpc::TypeDef]) -> Vec<TokenStream> {
                                 return false;
                             }
                         }
+                    };
+                    quote! {
+                        export module #type_ident {
+                            #(#variant_defs)*
+
+                            #variant_helpers
+                        }
                         export type #type_ident = #(#type_ident.#variant_idents)|*;
                     }
                 }
@@ -156,49 +202,6 @@ fn generate_type_defs(type_defs: &[oasis_rpc::TypeDef]) -> Vec<TokenStream> {
                     name,
                     fields: indexed_fields,
                 } => {
-                    let event_ident = format_ts_ident!(@class, name);
-                    let topic_names = indexed_fields.iter().map(|f| var_name(&f.name));
-                    let topic_idents: Vec<_> = indexed_fields
-                        .iter()
-                        .map(|f| format_ts_ident!(@var, &f.name))
-                        .collect();
-                    let topic_tys = indexed_fields.iter().map(|f| quote_ty(&f.ty));
-                    let topic_schema_tys = indexed_fields.iter().map(|f| quote_schema_ty(&f.ty));
-                    let topics_arg = if !indexed_fields.is_empty() {
-                        quote!(topics?: { #(#topic_idents?: #topic_tys),* })
-                    } else {
-                        quote!()
-                    };
-                    let maybe_dot = make_operator("?.");
-
-                    let extra_members = quote! {
-                        public static async subscribe(
-                            gateway: oasis.Gateway,
-                            address: oasis.Address | null,
-                            #topics_arg
-                        ): Promise<oasis.Subscription<#event_ident>> {
-                            const encodedTopics = [
-                                oasis.encodeEventTopic("string", #event_ident.name),
-                            ];
-                            #(
-                                if (topics #maybe_dot hasOwnProperty(#topic_names)) {
-                                    encodedTopics.push(
-                                        oasis.encodeEventTopic(
-                                            #topic_schema_tys,
-                                            topics.#topic_idents,
-                                        )
-                                    );
-                                }
-                            )*
-                            return gateway.subscribe(
-                                address,
-                                encodedTopics,
-                                async (payload: Uint8Array) => {
-                                    return oasis.abiDecode(#event_ident, payload);
-                                }
-                            );
-                        }
-                    };
                     let fields: Vec<_> = indexed_fields
                         .iter()
                         .cloned()
@@ -207,13 +210,88 @@ fn generate_type_defs(type_defs: &[oasis_rpc::TypeDef]) -> Vec<TokenStream> {
                             ty: f.ty,
                         })
                         .collect();
-                    generate_struct_class(name, &fields, extra_members, None)
+                    if decls_only {
+                        generate_struct_decl(name, &fields)
+                    } else {
+                        let subscribe_fn = generate_event_subscribe_function(name, indexed_fields);
+                        generate_struct_class(name, &fields, subscribe_fn, None)
+                    }
                 }
             }
         })
         .collect()
 }
 
+fn generate_event_subscribe_function(
+    name: &str,
+    indexed_fields: &[oasis_rpc::IndexedField],
+) -> TokenStream {
+    let event_ident = format_ts_ident!(@class, name);
+    let topic_names = indexed_fields.iter().map(|f| var_name(&f.name));
+    let topic_idents: Vec<_> = indexed_fields
+        .iter()
+        .map(|f| format_ts_ident!(@var, &f.name))
+        .collect();
+    let topic_tys = indexed_fields.iter().map(|f| quote_ty(&f.ty));
+    let topic_schema_tys = indexed_fields.iter().map(|f| quote_schema_ty(&f.ty));
+    let topics_arg = if !indexed_fields.is_empty() {
+        quote!(topics?: { #(#topic_idents?: #topic_tys),* })
+    } else {
+        quote!()
+    };
+    let maybe_dot = make_operator("?.");
+
+    quote! {
+        public static async subscribe(
+            gateway: oasis.Gateway,
+            address: oasis.Address | null,
+            #topics_arg
+        ): Promise<oasis.Subscription<#event_ident>> {
+            const encodedTopics = [
+                oasis.encodeEventTopic("string", #event_ident.name),
+            ];
+            #(
+                if (topics #maybe_dot hasOwnProperty(#topic_names)) {
+                    encodedTopics.push(
+                        oasis.encodeEventTopic(
+                            #topic_schema_tys,
+                            topics.#topic_idents,
+                        )
+                    );
+                }
+            )*
+            return gateway.subscribe(
+                address,
+                encodedTopics,
+                async (payload: Uint8Array) => {
+                    return oasis.abiDecode(#event_ident, payload);
+                }
+            );
+        }
+    }
+}
+
+fn generate_struct_decl(struct_name: &str, fields: &[oasis_rpc::Field]) -> TokenStream {
+    let class_ident = format_ts_ident!(@class, struct_name);
+    let field_decls = fields.iter().map(generate_field_decl);
+    quote! {
+        export interface #class_ident {
+            #(#field_decls;)*
+        }
+    }
+}
+
+fn generate_tuple_decl(tuple_name: &str, tys: &[oasis_rpc::Type]) -> TokenStream {
+    let class_ident = format_ts_ident!(@class, tuple_name);
+    let field_idents = (0..tys.len()).map(Literal::usize_unsuffixed);
+    let field_tys = tys.iter().map(quote_ty);
+    quote! {
+        export interface #class_ident {
+            #(#field_idents: #field_tys;)*
+        }
+    }
+}
+
 fn generate_struct_class<'a>(
     struct_name: &str,
     fields: &'a [oasis_rpc::Field],
@@ -232,16 +310,36 @@ fn generate_struct_class<'a>(
         .map(|field| quote_schema_ty(&field.ty))
         .collect();
 
+    let len_checks = fields
+        .iter()
+        .zip(field_idents.iter())
+        .map(|(field, field_ident)| {
+            quote_len_check(&field.name, quote!(fields.#field_ident), &field.ty)
+        });
+
     let variant_encoder = variant_idx.map(|idx| {
         let idx_lit = Literal::usize_unsuffixed(idx);
         quote!(encoder.writeU8(#idx_lit);)
     });
 
+    // A struct without fields is constructed as `new Unit()` rather than `new Unit({})`.
+    let (ctor_params, decoded_fields) = if fields.is_empty() {
+        (quote!(), quote!())
+    } else {
+        (
+            quote!(fields: { #(#field_decls;)* }),
+            quote! {{
+                #(#field_idents: oasis.abiDecode(#field_schema_tys as oasis.Schema, decoder)),*
+            }},
+        )
+    };
+
     quote! {
         export class #class_ident implements oasis.AbiEncodable {
             #(public #field_decls;)*
 
-            public constructor(fields: { #(#field_decls;)* }) {
+            public constructor(#ctor_params) {
+                #(#len_checks)*
                 #(this.#field_idents = fields.#field_idents;)*
             }
 
@@ -251,9 +349,7 @@ fn generate_struct_class<'a>(
             }
 
             public static abiDecode(decoder: oasis.Decoder): #class_ident {
-                return new #class_ident({
-                    #(#field_idents: oasis.abiDecode(#field_schema_tys as oasis.Schema, decoder)),*
-                });
+                return new #class_ident(#decoded_fields);
             }
 
             #extra_members
@@ -278,6 +374,11 @@ fn generate_tuple_class(
         .unzip();
     let field_tys: Vec<_> = tys.iter().map(|ty| quote_ty(ty)).collect();
     let field_schema_tys: Vec<_> = tys.iter().map(quote_schema_ty).collect();
+    let len_checks = tys
+        .iter()
+        .zip(arg_idents.iter())
+        .enumerate()
+        .map(|(i, (ty, arg_ident))| quote_len_check(&i.to_string(), quote!(#arg_ident), ty));
 
     let variant_encoder = variant_idx.map(|idx| {
         let idx_lit = Literal::usize_unsuffixed(idx);
@@ -289,6 +390,7 @@ fn generate_tuple_class(
             #(public #field_idents: #field_tys;)*
 
             public constructor(#(#arg_idents: #field_tys),*) {
+                #(#len_checks)*
                 #(this[#field_idents] = #arg_idents;)*
             }
 
@@ -312,8 +414,9 @@ fn generate_deploy_function(service_ident: &Ident, ctor: &oasis_rpc::Constructor
     let arg_idents: Vec<_> = ctor
         .inputs
         .iter()
-        .map(|field| format_ts_ident!(@var, field.name))
+        .map(|field| format_ts_ident!(@binding, field.name))
         .collect();
+    let arg_destructs = ctor.inputs.iter().map(generate_arg_destruct);
     let arg_tys: Vec<_> = ctor
         .inputs
         .iter()
@@ -332,7 +435,7 @@ fn generate_deploy_function(service_ident: &Ident, ctor: &oasis_rpc::Constructor
     let arg_decls = ctor.inputs.iter().map(generate_field_decl);
     let (deploy_args, final_encode_call) = if !ctor.inputs.is_empty() {
         (
-            quote!({ #(#arg_idents),* }: { #(#arg_decls;)* },),
+            quote!({ #(#arg_destructs),* }: { #(#arg_decls;)* },),
             quote! {
                 oasis.abiEncode(
                     [ #(#arg_schema_tys as oasis.Schema),* ],
@@ -385,55 +488,18 @@ fn generate_rpc_functions<'a>(
         let arg_idents: Vec<_> = rpc
             .inputs
             .iter()
-            .map(|inp| format_ts_ident!(@var, inp.name))
+            .map(|inp| format_ts_ident!(@binding, inp.name))
             .collect();
+        let arg_destructs: Vec<_> = rpc.inputs.iter().map(generate_arg_destruct).collect();
         let arg_tys: Vec<_> = rpc.inputs.iter().map(|inp| quote_ty(&inp.ty)).collect();
         let arg_schema_tys = rpc.inputs.iter().map(|inp| quote_schema_ty(&inp.ty));
 
         let fn_ident = format_ts_ident!(@var, rpc.name);
         let make_payload_ident = format_ident!("make{}Payload", rpc.name.to_camel_case());
-        let rpc_ret_ty = rpc
-            .output
-            .as_ref()
-            .map(|out_ty| {
-                quote_ty(match out_ty {
-                    oasis_rpc::Type::Result(box ok_ty, _) => ok_ty,
-                    _ => out_ty,
-                })
-            })
-            .unwrap_or_else(|| quote!(void));
-        let returner = rpc
-            .output
-            .as_ref()
-            .and_then(|output| {
-                use oasis_rpc::Type::{Result, Tuple};
-                match output {
-                    Tuple(tys) | Result(box Tuple(tys), _) if tys.is_empty() => None,
-                    oasis_rpc::Type::Result(box ok_ty, _) => {
-                        let quot_schema_ty = quote_schema_ty(ok_ty);
-                        //^ unwrap one layer of result, as the outer error is derived
-                        // from the tx status code.
-                        Some(quote! {
-                            return oasis.abiDecode(#quot_schema_ty as oasis.Schema, res);
-                        })
-                    }
-                    _ => {
-                        let quot_schema_ty = quote_schema_ty(output);
-                        Some(quote! {
-                            return oasis.abiDecode(#quot_schema_ty as oasis.Schema, res);
-                        })
-                    }
-                }
-            })
-            .unwrap_or_else(|| quote!(return;));
+        let rpc_ret_ty = quote_rpc_ret_ty(rpc.output.as_ref());
+        let returner = quote_rpc_returner(rpc.output.as_ref());
         let rpc_try_catch = gen_rpc_err_handler(
-            rpc.output.as_ref().and_then(|output| {
-                if let oasis_rpc::Type::Result(_, box err_ty) = output {
-                    Some(err_ty)
-                } else {
-                    None
-                }
-            }),
+            split_rpc_output(rpc.output.as_ref()).1,
             quote! {
                 const res = await this.gateway.rpc(this.address, payload, options);
                 #returner
@@ -457,7 +523,7 @@ fn generate_rpc_functions<'a>(
             let arg_decls = rpc.inputs.iter().map(generate_field_decl);
             quote! {
                 public async #fn_ident(
-                    { #(#arg_idents),* }: { #(#arg_decls;)* },
+                    { #(#arg_destructs),* }: { #(#arg_decls;)* },
                     options?: oasis.RpcOptions
                 ): Promise<#rpc_ret_ty> {
                     const payload = #service_ident.#make_payload_ident(#(#arg_idents),*);
@@ -478,6 +544,75 @@ fn generate_rpc_functions<'a>(
     })
 }
 
+/// Splits an RPC's output into the type of the decoded return value and the type of the
+/// error thrown on execution failure. Only the outermost `Result` is unwrapped, as its error
+/// is derived from the tx status code. Any wrappers below it (e.g., `Result<Option<T>, E>`)
+/// are part of the decoded value. A unit return value decodes to nothing.
+fn split_rpc_output(
+    output: Option<&oasis_rpc::Type>,
+) -> (Option<&oasis_rpc::Type>, Option<&oasis_rpc::Type>) {
+    use oasis_rpc::Type::{Result, Tuple};
+    let (ok_ty, err_ty) = match output {
+        Some(Result(box ok_ty, box err_ty)) => (Some(ok_ty), Some(err_ty)),
+        output => (output, None),
+    };
+    match ok_ty {
+        Some(Tuple(tys)) if tys.is_empty() => (None, err_ty),
+        ok_ty => (ok_ty, err_ty),
+    }
+}
+
+/// Returns the type of the value resolved by an RPC's `Promise`.
+fn quote_rpc_ret_ty(output: Option<&oasis_rpc::Type>) -> TokenStream {
+    split_rpc_output(output)
+        .0
+        .map(quote_ty)
+        .unwrap_or_else(|| quote!(void))
+}
+
+/// Returns the statement that decodes an RPC's successful return value.
+fn quote_rpc_returner(output: Option<&oasis_rpc::Type>) -> TokenStream {
+    match split_rpc_output(output).0 {
+        Some(ok_ty) => {
+            let quot_schema_ty = quote_schema_ty(ok_ty);
+            quote!(return oasis.abiDecode(#quot_schema_ty as oasis.Schema, res);)
+        }
+        None => quote!(return;),
+    }
+}
+
+/// Returns a statement that throws if `value` is a fixed-size byte array of the wrong length.
+/// Decoded values pass through the generated constructors, so this also validates decoding.
+fn quote_len_check(
+    field_name: &str,
+    value: TokenStream,
+    ty: &oasis_rpc::Type,
+) -> Option<TokenStream> {
+    match ty {
+        oasis_rpc::Type::Array(box oasis_rpc::Type::U8, len) => {
+            let quot_len = Literal::u64_unsuffixed(*len);
+            let err_msg = format!("`{}` must be {} bytes long", var_name(field_name), len);
+            Some(quote! {
+                if (#value.length !== #quot_len) {
+                    throw new RangeError(#err_msg);
+                }
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Returns the destructuring pattern that binds a named argument, e.g. `class: class_`.
+fn generate_arg_destruct(field: &oasis_rpc::Field) -> TokenStream {
+    let field_name = format_ts_ident!(@var, &field.name);
+    let binding = format_ts_ident!(@binding, &field.name);
+    if field_name == binding {
+        quote!(#field_name)
+    } else {
+        quote!(#field_name: #binding)
+    }
+}
+
 fn generate_field_decl(field: &oasis_rpc::Field) -> TokenStream {
     let field_name = format_ts_ident!(@var, &field.name);
     let field_ty = quote_ty(&field.ty);
@@ -504,7 +639,7 @@ fn quote_ty(ty: &oasis_rpc::Type) -> TokenStream {
         Defined { namespace, ty } => {
             let ty_ident = format_ts_ident!(@class, ty);
             if let Some(ns) = namespace {
-                let ns_ident = format_ts_ident!(@import, ns);
+                let ns_ident = format_ts_ident!(@binding, ns);
                 quote!(#ns_ident.#ty_ident)
             } else {
                 quote!(#ty_ident)
@@ -518,7 +653,13 @@ fn quote_ty(ty: &oasis_rpc::Type) -> TokenStream {
                 quote!([ #(#quot_tys),* ])
             }
         }
-        List(box U8) | Array(box U8, _) => quote!(Uint8Array),
+        Array(box U8, len) => {
+            // Brand fixed-size byte arrays (e.g., hashes) with their length so that callers
+            // need to assert that a plain `Uint8Array` has the expected length.
+            let quot_len = Literal::u64_unsuffixed(*len);
+            quote!(Uint8Array & { readonly length: #quot_len })
+        }
+        List(box U8) => quote!(Uint8Array),
         List(box I8) | Array(box I8, _) => quote!(Int8Array),
         List(box U16) | Array(box U16, _) => quote!(Uint16Array),
         List(box I16) | Array(box I16, _) => quote!(Int16Array),
@@ -542,8 +683,10 @@ fn quote_ty(ty: &oasis_rpc::Type) -> TokenStream {
             quote!(oasis.Map<#quot_k_ty, #quot_v_ty>)
         }
         Optional(ty) => {
+            // `oasis.abiDecode` yields `null` for a decoded `None`, but users may leave
+            // optional fields `undefined` when constructing values to be encoded.
             let quot_ty = quote_ty(ty);
-            quote!(#quot_ty | undefined)
+            quote!(#quot_ty | null | undefined)
         }
         Result(ok_ty, err_ty) => {
             let quot_ok_ty = quote_ty(ok_ty);
@@ -575,7 +718,7 @@ fn quote_schema_ty(ty: &oasis_rpc::Type) -> TokenStream {
         Defined { namespace, ty } => {
             let ty_ident = format_ts_ident!(@class, ty);
             if let Some(ns) = namespace {
-                let ns_ident = format_ts_ident!(@import, ns);
+                let ns_ident = format_ts_ident!(@binding, ns);
                 quote!(#ns_ident.#ty_ident)
             } else {
                 quote!(#ty_ident)
@@ -638,10 +781,77 @@ pub fn module_name(iface_name: impl AsRef<str>) -> String {
     iface_name.as_ref().to_kebab_case()
 }
 
-fn var_name(name: &str) -> String {
+/// Returns the name of the class generated for a service.
+pub fn class_name(iface_name: impl AsRef<str>) -> String {
+    iface_name.as_ref().to_camel_case()
+}
+
+pub fn var_name(name: &str) -> String {
     name.to_mixed_case()
 }
 
+/// Words that can't name a variable or parameter in (strict mode) TypeScript.
+const RESERVED_WORDS: &[&str] = &[
+    "arguments",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "eval",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+/// Appends an underscore to `ident` if it is a reserved word (e.g., a parameter named `class`).
+/// Only bindings are escaped; member names like `this.class` are valid as-is and are part of
+/// the client's public API.
+fn escape_reserved(mut ident: String) -> String {
+    if RESERVED_WORDS.contains(&ident.as_str()) {
+        ident.push('_');
+    }
+    ident
+}
+
 pub fn make_operator(chars: &str) -> TokenStream {
     use proc_macro2::{Punct, Spacing, TokenTree};
     chars
@@ -660,3 +870,264 @@ pub fn make_operator(chars: &str) -> TokenStream {
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use oasis_rpc::Type;
+
+    #[test]
+    fn test_optional_ty() {
+        let opt_ty = Type::Optional(Box::new(Type::U64));
+        assert_eq!(quote_ty(&opt_ty).to_string(), "bigint | null | undefined");
+        assert_eq!(
+            quote_schema_ty(&opt_ty).to_string(),
+            quote!(["Option", "u64"]).to_string()
+        );
+    }
+
+    #[test]
+    fn test_optional_field_decl() {
+        let field = oasis_rpc::Field {
+            name: "maybe_value".to_string(),
+            ty: Type::Optional(Box::new(Type::String)),
+        };
+        assert_eq!(
+            generate_field_decl(&field).to_string(),
+            quote!(maybeValue?: string | null | undefined).to_string()
+        );
+    }
+
+    #[test]
+    fn test_fixed_bytes_field() {
+        let field = oasis_rpc::Field {
+            name: "owner_addr".to_string(),
+            ty: Type::Array(Box::new(Type::U8), 20),
+        };
+        assert_eq!(
+            generate_field_decl(&field).to_string(),
+            quote!(ownerAddr: Uint8Array & { readonly length: 20 }).to_string()
+        );
+        assert_eq!(
+            quote_schema_ty(&field.ty).to_string(),
+            quote!(["u8", 20]).to_string()
+        );
+        assert_eq!(
+            quote_ty(&Type::List(Box::new(Type::U8))).to_string(),
+            "Uint8Array"
+        );
+
+        let len_check = quote! {
+            if (fields.ownerAddr.length !== 20) {
+                throw new RangeError("`ownerAddr` must be 20 bytes long");
+            }
+        };
+        assert!(generate_struct_class("Owned", &[field], quote!(), None)
+            .to_string()
+            .contains(&len_check.to_string()));
+    }
+
+    fn my_err_ty() -> Type {
+        Type::Defined {
+            namespace: None,
+            ty: "MyErr".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_rpc_ret_result_optional() {
+        let output = Type::Result(
+            Box::new(Type::Optional(Box::new(Type::U64))),
+            Box::new(my_err_ty()),
+        );
+        assert_eq!(
+            quote_rpc_ret_ty(Some(&output)).to_string(),
+            quote!(bigint | null | undefined).to_string()
+        );
+        assert_eq!(
+            quote_rpc_returner(Some(&output)).to_string(),
+            quote!(return oasis.abiDecode(["Option", "u64"] as oasis.Schema, res);).to_string()
+        );
+        assert_eq!(split_rpc_output(Some(&output)).1, Some(&my_err_ty()));
+    }
+
+    #[test]
+    fn test_rpc_ret_nested_result() {
+        let output = Type::Result(
+            Box::new(Type::Result(Box::new(Type::U32), Box::new(Type::String))),
+            Box::new(my_err_ty()),
+        );
+        assert_eq!(
+            quote_rpc_ret_ty(Some(&output)).to_string(),
+            quote!(oasis.Result<number, string>).to_string()
+        );
+        assert_eq!(
+            quote_rpc_returner(Some(&output)).to_string(),
+            quote!(return oasis.abiDecode(["Result", "u32", "string"] as oasis.Schema, res);)
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_rpc_ret_tuple() {
+        let output = Type::Tuple(vec![Type::U32, Type::String]);
+        assert_eq!(
+            quote_rpc_ret_ty(Some(&output)).to_string(),
+            quote!([number, string]).to_string()
+        );
+        assert_eq!(
+            quote_rpc_returner(Some(&output)).to_string(),
+            quote!(return oasis.abiDecode(["u32", "string"] as oasis.Schema, res);).to_string()
+        );
+        assert_eq!(split_rpc_output(Some(&output)).1, None);
+    }
+
+    #[test]
+    fn test_reserved_word_idents() {
+        let fields = vec![
+            oasis_rpc::Field {
+                name: "class".to_string(),
+                ty: Type::String,
+            },
+            oasis_rpc::Field {
+                name: "function".to_string(),
+                ty: Type::U32,
+            },
+        ];
+        let struct_class = generate_struct_class("Lesson", &fields, quote!(), None).to_string();
+        assert!(struct_class.contains(&quote!(public class: string;).to_string()));
+        assert!(struct_class.contains(&quote!(this.function = fields.function;).to_string()));
+        assert!(!struct_class.contains("class_"));
+
+        let rpc = oasis_rpc::Function {
+            name: "delete".to_string(),
+            mutability: oasis_rpc::StateMutability::Mutable,
+            inputs: fields,
+            output: None,
+        };
+        let service_ident = format_ident!("Lessons");
+        let rpc_fns = generate_rpc_functions(&service_ident, std::slice::from_ref(&rpc))
+            .next()
+            .unwrap()
+            .to_string();
+        assert!(rpc_fns.contains("public async delete ("));
+        let destructured_args =
+            quote!({ class: class_, function: function_ }: { class: string; function: number; });
+        assert!(rpc_fns.contains(&destructured_args.to_string()));
+        assert!(rpc_fns.contains(&quote!(makeDeletePayload(class_, function_)).to_string()));
+
+        assert_eq!(
+            format_ts_ident!(@binding, "default").to_string(),
+            "default_"
+        );
+        assert_eq!(var_name("my_class"), "myClass");
+    }
+
+    #[test]
+    fn test_fieldless_struct() {
+        let struct_class = generate_struct_class("Unit", &[], quote!(), None).to_string();
+        assert!(struct_class.contains(&quote!(public constructor() {}).to_string()));
+        assert!(struct_class.contains(&quote!(return new Unit();).to_string()));
+        assert!(!struct_class.contains("fields"));
+    }
+
+    #[test]
+    fn test_no_arg_constructor() {
+        let ctor = oasis_rpc::Constructor {
+            inputs: Vec::new(),
+            error: None,
+        };
+        let deploy_fn = generate_deploy_function(&format_ident!("Counter"), &ctor).to_string();
+        let deploy_sig = quote! {
+            public static async deploy(
+                gateway: oasis.Gateway,
+                options?: oasis.DeployOptions,
+            ): Promise<Counter>
+        };
+        assert!(deploy_fn.contains(&deploy_sig.to_string()));
+        assert!(deploy_fn.contains(&quote!(Counter.makeDeployPayload();).to_string()));
+        let payload_sig = quote!(private static makeDeployPayload(): Buffer);
+        assert!(deploy_fn.contains(&payload_sig.to_string()));
+        assert!(!deploy_fn.contains(&quote!({}).to_string()));
+    }
+
+    #[test]
+    fn test_no_rpc_functions() {
+        assert!(generate_rpc_functions(&format_ident!("Counter"), &[])
+            .next()
+            .is_none());
+
+        let rpc = oasis_rpc::Function {
+            name: "get_count".to_string(),
+            mutability: oasis_rpc::StateMutability::Immutable,
+            inputs: Vec::new(),
+            output: Some(Type::U32),
+        };
+        let rpc_fns = generate_rpc_functions(&format_ident!("Counter"), std::slice::from_ref(&rpc))
+            .next()
+            .unwrap()
+            .to_string();
+        let rpc_sig = quote! {
+            public async getCount(options?: oasis.RpcOptions): Promise<number>
+        };
+        assert!(rpc_fns.contains(&rpc_sig.to_string()));
+        let payload_sig = quote!(private static makeGetCountPayload(): Buffer);
+        assert!(rpc_fns.contains(&payload_sig.to_string()));
+    }
+
+    /// Checks for the leftovers of an empty repetition that would make TypeScript fail to
+    /// parse: a separator with nothing before or between it, or an empty destructuring.
+    fn assert_well_formed(ts: &str) {
+        for bad in &["( ,", "{ ,", "[ ,", ", ,", "; ;", "{ } :", ": { }"] {
+            assert!(!ts.contains(bad), "`{}` in generated client:\n{}", bad, ts);
+        }
+    }
+
+    #[test]
+    fn test_empty_service() {
+        use crate::subcommands::ifextract::fixture;
+        let mut iface = fixture::interface();
+        iface.functions.clear();
+        iface.constructor.inputs.clear();
+
+        let client = generate(&iface, b"\0asm").to_string();
+        assert_well_formed(&client);
+        assert!(client.contains(&quote!(export class Fixture).to_string()));
+        assert!(client.contains(&quote!(public static async connect).to_string()));
+        assert!(client.contains(&quote!(public static async deploy).to_string()));
+        assert!(!client.contains("public async"));
+
+        let decls = generate_declarations(&iface).to_string();
+        assert_well_formed(&decls);
+        let service_decl = quote! {
+            export interface Fixture {
+                readonly address: oasis.Address;
+            }
+        };
+        assert!(decls.contains(&service_decl.to_string()));
+    }
+
+    #[test]
+    fn test_rpc_ret_unit() {
+        let output = Type::Result(Box::new(Type::Tuple(Vec::new())), Box::new(my_err_ty()));
+        assert_eq!(quote_rpc_ret_ty(Some(&output)).to_string(), "void");
+        assert_eq!(quote_rpc_returner(Some(&output)).to_string(), "return ;");
+        assert_eq!(quote_rpc_ret_ty(None).to_string(), "void");
+    }
+
+    #[test]
+    fn test_declarations_only() {
+        use crate::subcommands::ifextract::fixture;
+        let mut iface = fixture::interface();
+        iface.functions = vec![fixture::rpc("get_count", Vec::new(), Some(Type::U32))];
+
+        let decls = generate_declarations(&iface).to_string();
+        let rpc_decl = quote!(getCount(options?: oasis.RpcOptions): Promise<number>;);
+        assert!(decls.contains(&rpc_decl.to_string()));
+        assert!(decls.contains(&quote!(export interface Fixture).to_string()));
+        for impl_only in &["connect", "deploy", "BYTECODE", "Payload", "class"] {
+            assert!(!decls.contains(impl_only));
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Sends the CLI's own log records to stderr and, if enabled by `[logging]`, also to a log file
+//! that is compressed and replaced once it grows too large.
+
+use std::{
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock, RwLockReadGuard},
+};
+
+use once_cell::sync::OnceCell;
+
+use crate::{config::Logging, ensure_dir, errors::Result, oasis_xdg_dir};
+
+const LOG_FILE_NAME: &str = "oasis.log";
+/// How many compressed segments of the log file are kept.
+const MAX_SEGMENTS: usize = 5;
+/// The most verbose level written to the log file, regardless of `RUST_LOG`.
+const FILE_LEVEL: log::LevelFilter = log::LevelFilter::Debug;
+
+static STDERR_LOGGER: OnceCell<RwLock<env_logger::Logger>> = OnceCell::new();
+static LOG_FILE: OnceCell<Mutex<RotatingFile>> = OnceCell::new();
+
+struct Logger;
+
+/// Installs the logger. Records are only written to stderr, at the level given by `RUST_LOG`
+/// or else `info`, until `set_stderr_level` and `enable_file_logging`.
+pub fn init() {
+    let stderr_logger = build_stderr_logger(None);
+    log::set_max_level(stderr_logger.filter());
+    STDERR_LOGGER.set(RwLock::new(stderr_logger)).ok();
+    log::set_boxed_logger(Box::new(Logger)).unwrap();
+}
+
+/// Sets the level of the records written to stderr, unless `RUST_LOG` was set.
+pub fn set_stderr_level(level: log::LevelFilter) {
+    if std::env::var_os("RUST_LOG").is_some() {
+        return;
+    }
+    let stderr_logger = build_stderr_logger(Some(level));
+    let file_level = if LOG_FILE.get().is_some() {
+        FILE_LEVEL
+    } else {
+        log::LevelFilter::Off
+    };
+    log::set_max_level(stderr_logger.filter().max(file_level));
+    *STDERR_LOGGER.get().unwrap().write().unwrap() = stderr_logger;
+}
+
+fn build_stderr_logger(level: Option<log::LevelFilter>) -> env_logger::Logger {
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    builder.format(log_format);
+    if let Some(level) = level {
+        builder.filter_level(level);
+    }
+    builder.build()
+}
+
+fn log_format(fmt: &mut env_logger::fmt::Formatter, record: &log::Record) -> io::Result<()> {
+    use colored::*;
+
+    let level = match record.level() {
+        log::Level::Trace => "trace".bold().white(),
+        log::Level::Debug => "debug".bold().white(),
+        log::Level::Info => "info".bold().blue(),
+        log::Level::Warn => "warning".bold().yellow(),
+        log::Level::Error => "error".bold().red(),
+    };
+
+    writeln!(fmt, "{}: {}", level, record.args())
+}
+
+fn stderr_logger() -> RwLockReadGuard<'static, env_logger::Logger> {
+    STDERR_LOGGER.get().unwrap().read().unwrap()
+}
+
+pub fn enable_file_logging(logging: &Logging) -> Result<()> {
+    if !logging.enabled {
+        return Ok(());
+    }
+    let log_dir = match &logging.dir {
+        Some(dir) => dir.clone(),
+        None => oasis_xdg_dir!(data)?.join("logs"),
+    };
+    let log_file = RotatingFile::open(ensure_dir!(log_dir)?, logging.max_size)?;
+    if LOG_FILE.set(Mutex::new(log_file)).is_ok() {
+        log::set_max_level(log::max_level().max(FILE_LEVEL));
+    }
+    Ok(())
+}
+
+/// Returns whether a record comes from the CLI rather than from one of its dependencies.
+fn is_own_record(metadata: &log::Metadata) -> bool {
+    let crate_name = module_path!().split("::").next().unwrap();
+    metadata.target().split("::").next() == Some(crate_name)
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        stderr_logger().enabled(metadata)
+            || (LOG_FILE.get().is_some() && metadata.level() <= FILE_LEVEL)
+    }
+
+    fn log(&self, record: &log::Record) {
+        stderr_logger().log(record);
+        let log_file = match LOG_FILE.get() {
+            Some(log_file) => log_file,
+            None => return,
+        };
+        if record.level() > FILE_LEVEL || !is_own_record(record.metadata()) {
+            return;
+        }
+        let line = serde_json::json!({
+            "time": chrono::Utc::now().to_rfc3339(),
+            "level": record.level().to_string().to_lowercase(),
+            "target": record.target(),
+            "message": record.args().to_string(),
+        });
+        // There's nowhere left to report a failure to log.
+        log_file.lock().unwrap().write_line(&line.to_string()).ok();
+    }
+
+    fn flush(&self) {
+        stderr_logger().flush();
+        if let Some(log_file) = LOG_FILE.get() {
+            log_file.lock().unwrap().file.flush().ok();
+        }
+    }
+}
+
+/// A log file that, upon reaching `max_size`, is gzipped into a timestamped segment and
+/// started anew. Only the newest `MAX_SEGMENTS` segments are kept.
+struct RotatingFile {
+    dir: PathBuf,
+    max_size: u64,
+    file: fs::File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(dir: PathBuf, max_size: u64) -> io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOG_FILE_NAME))?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            dir,
+            max_size,
+            file,
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 + 1 > self.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let log_path = self.dir.join(LOG_FILE_NAME);
+        let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%S%.6f");
+        // Rotations can happen in quick succession, so disambiguate equal timestamps.
+        let segment_path = (0..)
+            .map(|n| {
+                self.dir
+                    .join(format!("oasis.{}-{:03}.log.gz", timestamp, n))
+            })
+            .find(|segment_path| !segment_path.exists())
+            .unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(
+            fs::File::create(&segment_path)?,
+            flate2::Compression::default(),
+        );
+        io::copy(&mut fs::File::open(&log_path)?, &mut encoder)?;
+        encoder.finish()?;
+
+        self.file = fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&log_path)?;
+        self.size = 0;
+        prune_segments(&self.dir)
+    }
+}
+
+/// Removes all but the newest `MAX_SEGMENTS` segments, which sort by their timestamps.
+fn prune_segments(dir: &Path) -> io::Result<()> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let file_name = entry?.file_name().to_string_lossy().into_owned();
+        if file_name.starts_with("oasis.") && file_name.ends_with(".log.gz") {
+            segments.push(file_name);
+        }
+    }
+    segments.sort();
+    for segment in segments.iter().rev().skip(MAX_SEGMENTS) {
+        fs::remove_file(dir.join(segment))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read as _;
+
+    #[test]
+    fn test_rotate() {
+        let dir = std::env::temp_dir().join(format!("oasis-logs-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut log_file = RotatingFile::open(dir.clone(), 16).unwrap();
+        for i in 0..(MAX_SEGMENTS + 3) {
+            log_file.write_line(&format!("line {:09}", i)).unwrap();
+        }
+
+        let mut segments: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|file_name| file_name.ends_with(".log.gz"))
+            .collect();
+        segments.sort();
+        assert_eq!(segments.len(), MAX_SEGMENTS);
+
+        // Each line fills a segment, so the newest segment holds the second-to-last line.
+        let mut newest_segment = String::new();
+        flate2::read::GzDecoder::new(fs::File::open(dir.join(segments.last().unwrap())).unwrap())
+            .read_to_string(&mut newest_segment)
+            .unwrap();
+        assert_eq!(newest_segment, format!("line {:09}\n", MAX_SEGMENTS + 1));
+        assert_eq!(
+            fs::read_to_string(dir.join(LOG_FILE_NAME)).unwrap(),
+            format!("line {:09}\n", MAX_SEGMENTS + 2)
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -9,6 +9,7 @@ extern crate log;
 #[macro_use]
 extern crate serde;
 
+mod abi;
 mod cli;
 mod command;
 mod config;
@@ -17,6 +18,7 @@ mod dirs;
 mod errors;
 mod gen;
 mod help;
+mod logger;
 mod subcommands;
 mod telemetry;
 mod utils;
@@ -25,9 +27,7 @@ mod workspace;
 use subcommands::*;
 
 fn main() {
-    env_logger::from_env(env_logger::Env::default().default_filter_or("info"))
-        .format(log_format)
-        .init();
+    logger::init();
 
     if !dirs::has_home_dir() {
         error!("could not determine home directory. Please ensure that $HOME is set.");
@@ -35,34 +35,123 @@ fn main() {
         std::process::exit(1);
     }
 
-    let mut config = config::Config::load().unwrap_or_else(|err| {
+    // `--workspace-root` also locates the workspace's `.env`.
+    if let Some(root) = path_arg("--workspace-root") {
+        workspace::set_root_override(root);
+    }
+
+    // Arguments after `--` are passed through to other tools, so they can't disable `.env`.
+    if !std::env::args()
+        .skip(1)
+        .take_while(|arg| arg != "--")
+        .any(|arg| arg == "--no-dotenv")
+    {
+        load_dotenv();
+    }
+
+    let config_path = path_arg("--config");
+    let mut config = config::Config::load(config_path.as_deref()).unwrap_or_else(|err| {
+        // The default config would be saved to the default location, not to the chosen one.
+        if let Some(config_path) = &config_path {
+            error!(
+                "could not load config file `{}`: {}",
+                config_path.display(),
+                err
+            );
+            std::process::exit(1);
+        }
         warn!("could not load config file: {}", err);
         Default::default()
     });
 
+    if let Err(err) = logger::enable_file_logging(&config.logging()) {
+        warn!("could not enable logging to a file: {}", err);
+    }
+
     if let Err(err) = telemetry::init(&config) {
         warn!("could not enable telemetry: {}", err);
     };
 
     let app_m = cli::build_app().get_matches();
+
+    set_log_level(&app_m);
+    set_workspace_root(&app_m);
+    if let Err(err) = set_http_timeout(&app_m) {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+
+    if let Err(err) = toolchain::notify_update(&config, app_m.subcommand_name()) {
+        debug!("could not check for a toolchain update: {}", err);
+    }
+
+    let start = std::time::Instant::now();
     let result = match app_m.subcommand() {
         ("init", Some(m)) => InitOptions::new(&m).exec(),
-        ("build", Some(m)) => BuildOptions::new(&m).exec(),
+        ("build", Some(m)) => BuildOptions::new(&m, &config).exec(),
         ("chain", Some(m)) => ChainOptions::new(&m).exec(),
         ("test", Some(m)) => TestOptions::new(&m, &config).exec(),
+        ("bench", Some(m)) => BenchOptions::new(&m, &config).exec(),
         ("clean", Some(m)) => clean(
             &m.values_of("TARGETS")
                 .unwrap_or_default()
                 .collect::<Vec<_>>(),
+            &m.values_of("exclude")
+                .unwrap_or_default()
+                .collect::<Vec<_>>(),
         ),
         ("ifextract", Some(m)) => ifextract(
             m.value_of("IMPORT_LOC").unwrap(),
             std::path::Path::new(m.value_of("out_dir").unwrap_or(".")),
+            m.is_present("refresh"),
+        ),
+        ("ifdiff", Some(m)) => ifdiff(
+            m.value_of("OLD").unwrap(),
+            m.value_of("NEW").unwrap(),
+            m.is_present("json"),
         ),
+        ("graph", Some(m)) => graph(m.value_of("format").unwrap()),
+        ("whoami", Some(m)) => whoami(&config, m.value_of("profile").unwrap()),
+        ("size", Some(m)) => size(m.value_of("WASM").unwrap()),
+        ("verify", Some(m)) => verify(m.value_of("WASM").unwrap()),
         ("deploy", Some(m)) => DeployOptions::new(&m, &config).exec(),
+        ("run", Some(m)) => RunOptions::new(&m, &config).exec(),
+        ("config", Some(m)) if m.subcommand_matches("migrate").is_some() => config.migrate(),
+        ("config", Some(m)) if m.subcommand_matches("profile").is_some() => {
+            match m
+                .subcommand_matches("profile")
+                .unwrap()
+                .subcommand_matches("copy")
+            {
+                Some(cm) => {
+                    if m.is_present("dry_run") {
+                        config.set_dry_run();
+                    }
+                    let (src, dst) = (cm.value_of("SRC").unwrap(), cm.value_of("DST").unwrap());
+                    config.copy_profile(src, dst).map(|_| {
+                        if m.is_present("dry_run") {
+                            print!("{}", config.to_redacted_string())
+                        }
+                    })
+                }
+                // `oasis config profile` lists the profiles, as it did before it had subcommands.
+                None => {
+                    if let Some(v) = config.get("profile") {
+                        println!("{}", v.trim())
+                    }
+                    Ok(())
+                }
+            }
+        }
         ("config", Some(m)) => {
             let key = m.value_of("KEY").unwrap();
             match m.value_of("VALUE") {
+                Some(v) if m.is_present("dry_run") => {
+                    config.set_dry_run();
+                    config
+                        .edit(key, v)
+                        .map(|_| print!("{}", config.to_redacted_string()))
+                }
                 Some(v) => config.edit(key, v),
                 None => {
                     if let Some(v) = config.get(key) {
@@ -73,7 +162,10 @@ fn main() {
             }
         }
         ("set-toolchain", Some(m)) => toolchain::set(m.value_of("VERSION").unwrap()),
+        ("update", Some(m)) => toolchain::update(m.is_present("check")),
         ("upload_metrics", _) => telemetry::upload(),
+        ("update_check", _) => toolchain::check_update(),
+        ("dirs", _) => dirs(),
         _ => {
             cli::build_app().print_long_help().unwrap();
             println!(); // print_long_help() doesn't add trailing new line.
@@ -82,27 +174,94 @@ fn main() {
     }
     .and_then(|_| config.save());
 
+    match app_m.subcommand_name() {
+        // Uploading clears the log, which should stay empty afterward, and the background
+        // update check isn't a command that the user ran.
+        Some("upload_metrics") | Some("update_check") | None => (),
+        Some(subcommand) => telemetry::emit_complete(subcommand, start.elapsed(), result.is_ok()),
+    }
+
     if let Err(err) = result {
         emit!(error, {
-            "args": std::env::args().collect::<Vec<_>>().join(" "),
-            "error": err.to_string()
+            "args": telemetry::redacted_args(std::env::args()),
+            "error": format!("{:#}", err)
         });
-        error!("{}", err);
+        // The alternate format also prints the causes, e.g. why a `BuildTool` failed.
+        error!("{:#}", err);
         std::process::exit(1);
     }
 }
 
-fn log_format(fmt: &mut env_logger::fmt::Formatter, record: &log::Record) -> std::io::Result<()> {
-    use colored::*;
-    use std::io::Write as _;
+/// Returns the value of the path-valued global `flag` (e.g., `--config`), which is needed
+/// before the arguments are parsed because the config and `.env` files affect the parsing.
+fn path_arg(flag: &str) -> Option<std::path::PathBuf> {
+    let flag_eq = format!("{}=", flag);
+    let mut args = std::env::args().skip(1).take_while(|arg| arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next().map(std::path::PathBuf::from);
+        }
+        if arg.starts_with(&flag_eq) {
+            return Some(std::path::PathBuf::from(&arg[flag_eq.len()..]));
+        }
+    }
+    None
+}
 
-    let level = match record.level() {
-        log::Level::Trace => "trace".bold().white(),
-        log::Level::Debug => "debug".bold().white(),
-        log::Level::Info => "info".bold().blue(),
-        log::Level::Warn => "warning".bold().yellow(),
-        log::Level::Error => "error".bold().red(),
+/// Loads variables from the `.env` files in the current directory and the workspace root,
+/// if they exist. Variables that are already set, or set by a closer `.env`, are kept.
+/// The loaded values only affect this process and are never persisted to the config file.
+fn load_dotenv() {
+    let cwd = match std::env::current_dir() {
+        Ok(cwd) => cwd,
+        Err(_) => return,
     };
+    let workspace_root = workspace::Workspace::find_root(&cwd).ok();
+    for dir in std::iter::once(cwd.as_path()).chain(workspace_root.as_deref()) {
+        let dotenv_path = dir.join(".env");
+        if !dotenv_path.is_file() {
+            continue;
+        }
+        if let Err(err) = dotenv::from_path(&dotenv_path) {
+            warn!("could not load `{}`: {}", dotenv_path.display(), err);
+        }
+    }
+}
 
-    writeln!(fmt, "{}: {}", level, record.args())
+/// Sets the level of the logs printed to stderr from `--log-level` or, failing that, from
+/// the number of `-v`s given to both `oasis` and its subcommand.
+fn set_log_level(app_m: &clap::ArgMatches) {
+    let sub_m = app_m.subcommand().1;
+    let level = match sub_m.unwrap_or(app_m).value_of("log_level") {
+        Some(level) => level.parse().unwrap(), // `possible_values` are all valid levels
+        None => {
+            let verbosity = app_m.occurrences_of("log_verbose")
+                + sub_m
+                    .map(|m| m.occurrences_of("verbose"))
+                    .unwrap_or_default();
+            match verbosity {
+                0 => return,
+                1 => log::LevelFilter::Debug,
+                _ => log::LevelFilter::Trace,
+            }
+        }
+    };
+    logger::set_stderr_level(level);
+}
+
+fn set_workspace_root(app_m: &clap::ArgMatches) {
+    let m = app_m.subcommand().1.unwrap_or(app_m);
+    if let Some(root) = m.value_of_os("workspace_root") {
+        workspace::set_root_override(std::path::PathBuf::from(root));
+    }
+}
+
+fn set_http_timeout(app_m: &clap::ArgMatches) -> Result<(), clap::Error> {
+    // Global args are propagated down, so the subcommand's matches see `--timeout` wherever
+    // it was given.
+    let m = app_m.subcommand().1.unwrap_or(app_m);
+    if m.is_present("timeout") {
+        utils::http::set_timeout(value_t!(m, "timeout", u64)?);
+    }
+    Ok(())
 }
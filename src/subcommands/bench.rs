@@ -0,0 +1,112 @@
+use std::{collections::BTreeMap, ffi::OsString};
+
+use crate::{
+    command::{rustc_wrapper, user_env_map, user_envs, BuildTool, ManifestOptions, Verbosity},
+    config::Config,
+    emit,
+    errors::Result,
+    utils::{print_status_in, Status},
+    workspace::{ProjectKind, Target, Workspace},
+};
+
+pub struct BenchOptions<'a> {
+    pub targets: Vec<&'a str>,
+    pub verbosity: Verbosity,
+    pub manifest_opts: ManifestOptions,
+    /// Use existing artifacts instead of building first.
+    pub no_build: bool,
+    /// Environment variables given by `--env KEY=VALUE`.
+    pub envs: Vec<(&'a str, &'a str)>,
+    /// The `RUSTC_WRAPPER` used to build the benches (usually `oasis-build`).
+    pub rustc_wrapper: String,
+    pub bencher_args: Vec<&'a str>,
+}
+
+impl<'a> BenchOptions<'a> {
+    pub fn new(m: &'a clap::ArgMatches, config: &Config) -> Result<Self> {
+        Ok(Self {
+            targets: m.values_of("TARGETS").unwrap_or_default().collect(),
+            verbosity: Verbosity::from(
+                m.occurrences_of("verbose") as i64 - m.occurrences_of("quiet") as i64,
+            ),
+            manifest_opts: ManifestOptions::new(m),
+            no_build: m.is_present("no_build"),
+            envs: user_envs(m)?,
+            rustc_wrapper: rustc_wrapper(config)?,
+            bencher_args: m.values_of("bencher_args").unwrap_or_default().collect(),
+        })
+    }
+}
+
+impl<'a> super::ExecSubcommand for BenchOptions<'a> {
+    fn exec(self) -> Result<()> {
+        let workspace = Workspace::populate()?;
+        let targets = workspace.collect_targets(&self.targets)?;
+        // Benchmark the optimized services that would be deployed.
+        let build_opts = super::BuildOptions {
+            manifest_opts: self.manifest_opts,
+            ..super::BuildOptions::for_subcommand(
+                self.targets.clone(),
+                self.verbosity,
+                self.envs.clone(),
+                self.rustc_wrapper.clone(),
+            )
+        };
+        if self.no_build {
+            super::check_built(&workspace, &targets)?;
+        } else {
+            super::build(&workspace, &targets, build_opts)?;
+        }
+        bench(&targets, self)
+    }
+}
+
+/// Runs the Cargo benches among `targets`. Like tests, benches are compiled natively by the
+/// wrapper and call the services that they depend on using the built Wasm.
+pub fn bench(targets: &[&Target], opts: BenchOptions) -> Result<()> {
+    for target in targets.iter().filter(|t| t.is_benchable()) {
+        if !matches!(target.project.kind, ProjectKind::Rust) {
+            continue;
+        }
+        if opts.verbosity > Verbosity::Quiet {
+            print_status_in(
+                Status::Benchmarking,
+                &target.name,
+                target.project.manifest_path.parent().unwrap(),
+            );
+        }
+        bench_rust(target, &opts)?;
+    }
+    Ok(())
+}
+
+fn bench_rust(target: &Target, opts: &BenchOptions) -> Result<()> {
+    let mut args = vec!["--bench", &target.name];
+    if !opts.bencher_args.is_empty() {
+        args.push("--");
+        args.extend(opts.bencher_args.iter());
+    }
+
+    let mut envs: BTreeMap<_, _> = std::env::vars_os().collect();
+    envs.extend(user_env_map(&opts.envs));
+    envs.insert(
+        OsString::from("RUSTC_WRAPPER"),
+        OsString::from(&opts.rustc_wrapper),
+    );
+
+    emit!(cmd.bench.start, {
+        "project_type": target.project.kind.name(),
+        "rustflags": std::env::var("RUSTFLAGS").ok(),
+    });
+
+    if let Err(e) = BuildTool::for_target(target)
+        .manifest_options(opts.manifest_opts)
+        .bench(args, envs, opts.verbosity)
+    {
+        emit!(cmd.bench.error);
+        return Err(e);
+    }
+
+    emit!(cmd.bench.done);
+    Ok(())
+}
@@ -1,33 +1,117 @@
 use std::{
-    collections::{btree_map::Entry, BTreeMap},
+    collections::{btree_map::Entry, BTreeMap, BTreeSet, HashMap},
     ffi::OsString,
     fs,
-    io::Write as _,
-    path::Path,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
     process::Command,
     str,
+    sync::Mutex,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    command::{BuildTool, Verbosity},
+    cmd,
+    command::{
+        rustc_wrapper, user_env_map, user_envs, BuildTool, BuildToolKind, FeatureOptions,
+        ManifestOptions, Verbosity,
+    },
+    config::Config,
     emit, ensure_dir,
-    errors::Result,
+    errors::{CliError, Result},
     gen::typescript as ts,
-    utils::{print_status, print_status_in, Status},
-    workspace::{Artifacts, ProjectKind, Target, Workspace},
+    utils::{print_status, print_status_ctx, print_status_in, Status},
+    workspace::{Artifacts, Project, ProjectKind, Target, Workspace},
 };
 
+use super::{ifdiff::InterfaceDiff, toolchain};
+
+/// The target for which services are built unless `--target-triple` is given.
+pub const DEFAULT_TARGET_TRIPLE: &str = "wasm32-wasi";
+
 pub struct BuildOptions<'a> {
     pub targets: Vec<&'a str>,
+    /// Whether to build every target in the workspace, as selected by `--all`.
+    pub all: bool,
+    /// The targets given by `--exclude`, which are only built if others depend on them.
+    pub exclude: Vec<&'a str>,
     pub debug: bool,
     pub verbosity: Verbosity,
     pub stack_size: Option<u32>,
     pub wasi: bool,
+    /// The name of the module from which prepared services import WASI functions.
+    pub wasi_abi: WasiAbi,
+    /// The target triple for which Rust services are compiled (e.g., `wasm32-wasi`).
+    pub target_triple: &'a str,
+    pub examples: bool,
+    pub no_clients: bool,
+    /// The `wasm-opt` optimization level (e.g., `z` for `-Oz`), if services should be optimized.
+    pub wasm_opt: Option<&'a str>,
+    pub strip: StripMode,
+    pub ts_types_only: bool,
+    /// Whether cargo should print its messages as JSON (e.g., for consumption by an IDE).
+    pub json_messages: bool,
+    /// Whether to embed the hashes of each service's source files in the service.
+    pub include_sources: bool,
+    /// The name, without extension, under which to also save the selected service's wasm.
+    pub artifact_name: Option<&'a str>,
+    /// Whether to print the paths of the built services, and nothing else, to stdout.
+    pub print_artifact: bool,
+    /// Whether to skip targets whose sources are unchanged since they were last built.
+    pub if_changed: bool,
+    /// Whether to print why each target is built, what it yields, and which tool builds it.
+    pub explain: bool,
+    /// The directory to which `--interface-only` writes the selected services' interfaces,
+    /// in lieu of generating clients and building apps.
+    pub interface_only: Option<&'a Path>,
+    /// Whether to check, or update, each service's interface against a committed copy.
+    pub interface_lock: Option<InterfaceLock<'a>>,
+    pub manifest_opts: ManifestOptions,
+    pub features: FeatureOptions<'a>,
+    /// The phase timings collected by `--profile-build`, if profiling.
+    pub timings: Option<BuildTimings>,
+    /// Where the compiler messages of the built Rust targets are saved, if anywhere.
+    pub diagnostics: Option<BuildDiagnostics>,
+    /// Environment variables given by `--env KEY=VALUE`.
+    pub envs: Vec<(&'a str, &'a str)>,
+    /// The `RUSTC_WRAPPER` used to build Rust services and apps (usually `oasis-build`).
+    pub rustc_wrapper: String,
     pub builder_args: Vec<&'a str>,
 }
 
+/// Where a service's locked interface, `<service>.interface.json`, is kept and whether it
+/// should be rewritten rather than checked.
+pub struct InterfaceLock<'a> {
+    /// The directory containing the locked interfaces. Defaults to the service's manifest dir.
+    pub dir: Option<&'a Path>,
+    pub update: bool,
+}
+
+impl<'a> InterfaceLock<'a> {
+    fn new(m: &'a clap::ArgMatches) -> Option<Self> {
+        let update = m.is_present("update_interface");
+        if !update && !m.is_present("locked_interface") {
+            return None;
+        }
+        Some(Self {
+            dir: m.value_of("locked_interface").map(Path::new),
+            update,
+        })
+    }
+
+    fn path(&self, target: &Target) -> PathBuf {
+        self.dir
+            .unwrap_or_else(|| target.manifest_dir())
+            .join(format!("{}.interface.json", target.name))
+    }
+}
+
 impl<'a> BuildOptions<'a> {
-    pub fn new(m: &'a clap::ArgMatches) -> Result<Self> {
+    pub fn new(m: &'a clap::ArgMatches, config: &Config) -> Result<Self> {
+        let debug = !is_release_build(m, config)?;
+        let json_messages = m.value_of("message_format") == Some("json");
+        let verbosity =
+            Verbosity::from(m.occurrences_of("verbose") as i64 - m.occurrences_of("quiet") as i64);
         Ok(Self {
             stack_size: match value_t!(m, "stack_size", u32) {
                 Ok(stack_size) => Some(stack_size),
@@ -37,79 +121,987 @@ impl<'a> BuildOptions<'a> {
                 }) => None,
                 Err(err) => return Err(err.into()),
             },
-            debug: m.is_present("debug"),
+            debug,
+            strip: match m.value_of("strip") {
+                Some("all") => StripMode::All,
+                Some("custom") => StripMode::Custom,
+                Some(_) => StripMode::None,
+                None if debug => StripMode::None,
+                None => StripMode::Custom,
+            },
             targets: m.values_of("TARGETS").unwrap_or_default().collect(),
+            all: m.is_present("all"),
+            exclude: m.values_of("exclude").unwrap_or_default().collect(),
             wasi: m.is_present("wasi"),
-            verbosity: Verbosity::from(
-                m.occurrences_of("verbose") as i64 - m.occurrences_of("quiet") as i64,
-            ),
+            wasi_abi: match m.value_of("wasi_abi") {
+                Some("preview1") => WasiAbi::Preview1,
+                Some(_) => WasiAbi::Unstable,
+                None if m.is_present("wasi") => WasiAbi::Preview1,
+                None => WasiAbi::Unstable,
+            },
+            target_triple: match m.value_of("target_triple") {
+                Some(triple) if triple != DEFAULT_TARGET_TRIPLE => {
+                    check_target_installed(triple)?;
+                    triple
+                }
+                _ => DEFAULT_TARGET_TRIPLE,
+            },
+            examples: m.is_present("examples"),
+            no_clients: m.is_present("no_clients"),
+            wasm_opt: if m.is_present("wasm_opt") {
+                Some(m.value_of("wasm_opt").unwrap_or("z"))
+            } else {
+                None
+            },
+            ts_types_only: m.is_present("ts_types_only"),
+            manifest_opts: ManifestOptions::new(m),
+            features: FeatureOptions::new(m),
+            diagnostics: m
+                .value_of_os("diagnostics_json")
+                .map(|path| BuildDiagnostics::new(PathBuf::from(path))),
+            timings: if m.is_present("profile_build") {
+                Some(BuildTimings::default())
+            } else {
+                None
+            },
+            json_messages,
+            include_sources: m.is_present("include_sources"),
+            artifact_name: m.value_of("artifact_name"),
+            print_artifact: m.is_present("print_artifact"),
+            if_changed: m.is_present("if_changed"),
+            explain: m.is_present("explain"),
+            interface_only: if m.is_present("interface_only") {
+                Some(Path::new(m.value_of("out_dir").unwrap_or(".")))
+            } else {
+                None
+            },
+            interface_lock: InterfaceLock::new(m),
+            // Keep the output machine-readable by leaving out the CLI's own status messages.
+            verbosity: if json_messages && verbosity > Verbosity::Quiet {
+                Verbosity::Quiet
+            } else {
+                verbosity
+            },
+            envs: user_envs(m)?,
+            rustc_wrapper: rustc_wrapper(config)?,
             builder_args: m.values_of("builder_args").unwrap_or_default().collect(),
         })
     }
+
+    /// Returns the options with which subcommands other than `oasis build` (e.g., `oasis test`)
+    /// build `targets`: an optimized build of the services and their clients, and nothing more.
+    pub fn for_subcommand(
+        targets: Vec<&'a str>,
+        verbosity: Verbosity,
+        envs: Vec<(&'a str, &'a str)>,
+        rustc_wrapper: String,
+    ) -> Self {
+        Self {
+            targets,
+            all: false,
+            exclude: Vec::new(),
+            debug: false,
+            verbosity,
+            stack_size: None,
+            wasi: false,
+            wasi_abi: WasiAbi::Unstable,
+            target_triple: DEFAULT_TARGET_TRIPLE,
+            examples: false,
+            no_clients: false,
+            wasm_opt: None,
+            strip: StripMode::Custom,
+            ts_types_only: false,
+            json_messages: false,
+            include_sources: false,
+            artifact_name: None,
+            print_artifact: false,
+            if_changed: false,
+            explain: false,
+            interface_only: None,
+            interface_lock: None,
+            manifest_opts: ManifestOptions::default(),
+            features: FeatureOptions::default(),
+            timings: None,
+            diagnostics: None,
+            envs,
+            rustc_wrapper,
+            builder_args: Vec::new(),
+        }
+    }
+
+    /// Runs one phase of building `target`, recording its duration if profiling.
+    fn time_phase<T>(
+        &self,
+        target: &Target,
+        phase: &'static str,
+        f: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let start = Instant::now();
+        let result = f();
+        if let Some(timings) = &self.timings {
+            timings.record(&target.name, phase, start.elapsed());
+        }
+        result
+    }
+
+    /// Returns the recorded phase timings of `target` for inclusion in telemetry.
+    fn timings_json(&self, target: &Target) -> Option<serde_json::Value> {
+        self.timings
+            .as_ref()
+            .map(|timings| timings.timings_json(&target.name))
+    }
+}
+
+/// Returns an error if the standard library for `triple` isn't installed for the toolchain
+/// that builds services. The check is skipped if the installed targets can't be listed.
+fn check_target_installed(triple: &str) -> Result<()> {
+    let output = match cmd!(
+        "rustup",
+        "target",
+        "list",
+        "--installed",
+        "--toolchain",
+        crate::rust_toolchain!()
+    ) {
+        Ok(output) => output,
+        Err(e) => {
+            debug!("could not list the installed targets: {}", e);
+            return Ok(());
+        }
+    };
+    if String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .any(|installed| installed.trim() == triple)
+    {
+        return Ok(());
+    }
+    bail!(
+        "the `{0}` target is not installed. Run `rustup target add {0} --toolchain {1}`.",
+        triple,
+        crate::rust_toolchain!()
+    )
+}
+
+/// Returns whether to build with optimizations. An explicit `--debug` or `--release` takes
+/// precedence over `OASIS_BUILD_RELEASE`, which takes precedence over `build.default_release`.
+/// Builds are release builds if none of these are set.
+///
+/// *Note*: `oasis test` and `oasis deploy` don't consult these settings. `oasis test` builds
+/// with the same profile as its tests, and `oasis deploy` always builds with optimizations.
+fn is_release_build(m: &clap::ArgMatches, config: &Config) -> Result<bool> {
+    if m.is_present("debug") {
+        return Ok(false);
+    }
+    if m.is_present("release") {
+        return Ok(true);
+    }
+    if let Ok(release) = std::env::var("OASIS_BUILD_RELEASE") {
+        return match release.as_str() {
+            "1" | "true" => Ok(true),
+            "0" | "false" => Ok(false),
+            _ => Err(anyhow!(
+                "`OASIS_BUILD_RELEASE` must be one of `1`, `true`, `0`, or `false`. Found `{}`.",
+                release
+            )),
+        };
+    }
+    Ok(config.default_release().unwrap_or(true))
+}
+
+/// The durations of build phases, as collected by `--profile-build`.
+/// Timings are behind a lock so that targets built concurrently can share them.
+#[derive(Default)]
+pub struct BuildTimings {
+    timings: Mutex<Vec<PhaseTiming>>,
+}
+
+struct PhaseTiming {
+    target: String,
+    phase: &'static str,
+    duration: Duration,
+}
+
+impl BuildTimings {
+    fn record(&self, target: &str, phase: &'static str, duration: Duration) {
+        self.timings.lock().unwrap().push(PhaseTiming {
+            target: target.to_string(),
+            phase,
+            duration,
+        });
+    }
+
+    fn timings_json(&self, target: &str) -> serde_json::Value {
+        self.timings
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|timing| timing.target == target)
+            .map(|timing| {
+                serde_json::json!({
+                    "phase": timing.phase,
+                    "duration_ms": timing.duration.as_millis() as u64,
+                })
+            })
+            .collect()
+    }
+
+    fn print_summary(&self) {
+        let timings = self.timings.lock().unwrap();
+        if timings.is_empty() {
+            return;
+        }
+        let target_width = timings
+            .iter()
+            .map(|timing| timing.target.len())
+            .chain(std::iter::once("target".len()))
+            .max()
+            .unwrap();
+        let phase_width = timings
+            .iter()
+            .map(|timing| timing.phase.len())
+            .chain(std::iter::once("phase".len()))
+            .max()
+            .unwrap();
+        eprintln!(
+            "\n{: <tw$}  {: <pw$}  {: >10}",
+            "target",
+            "phase",
+            "duration",
+            tw = target_width,
+            pw = phase_width
+        );
+        for timing in timings.iter() {
+            eprintln!(
+                "{: <tw$}  {: <pw$}  {: >10}",
+                timing.target,
+                timing.phase,
+                format!("{:.2?}", timing.duration),
+                tw = target_width,
+                pw = phase_width
+            );
+        }
+        let total: Duration = timings.iter().map(|timing| timing.duration).sum();
+        eprintln!(
+            "{: <tw$}  {: <pw$}  {: >10}",
+            "",
+            "total",
+            format!("{:.2?}", total),
+            tw = target_width,
+            pw = phase_width
+        );
+    }
+}
+
+/// The compiler messages (i.e. errors and warnings) that Cargo reports for each Rust target,
+/// which `--diagnostics-json` saves as a JSON object keyed by target name.
+pub struct BuildDiagnostics {
+    path: PathBuf,
+    messages: Mutex<BTreeMap<String, Vec<serde_json::Value>>>,
+}
+
+impl BuildDiagnostics {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            messages: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Records `line` of Cargo's JSON output if it's a compiler message for `target`, and
+    /// returns the rendered message, if any.
+    fn record(&self, target: &str, line: &str) -> Option<String> {
+        let mut message: serde_json::Value = serde_json::from_str(line).ok()?;
+        if message["reason"] != "compiler-message" {
+            return None;
+        }
+        let message = message["message"].take();
+        let rendered = message["rendered"].as_str().map(str::to_string);
+        self.messages
+            .lock()
+            .unwrap()
+            .entry(target.to_string())
+            .or_default()
+            .push(message);
+        rendered
+    }
+
+    /// Writes the messages recorded so far, so that they're kept even if a build fails.
+    fn save(&self) -> Result<()> {
+        let messages = self.messages.lock().unwrap();
+        fs::write(&self.path, serde_json::to_string_pretty(&*messages)?)
+            .map_err(|e| anyhow!("could not write `{}`: {}", self.path.display(), e))
+    }
+}
+
+/// The source hashes of the targets built with `--if-changed`, which are kept in
+/// `target/.oasis-build-cache.json` under the workspace root. Unlike mtimes, hashes survive
+/// CI caches that don't preserve timestamps.
+struct BuildCache {
+    path: PathBuf,
+    hashes: BTreeMap<String, String>,
+    /// The hash of each source dir, which is shared by the targets of a project.
+    dir_hashes: BTreeMap<PathBuf, String>,
+    /// The targets built during this run, whose dependents must also be rebuilt.
+    rebuilt: BTreeSet<String>,
+}
+
+impl BuildCache {
+    fn load(workspace: &Workspace) -> Self {
+        let path = workspace
+            .root()
+            .join("target")
+            .join(".oasis-build-cache.json");
+        let hashes = fs::read(&path)
+            .ok()
+            .and_then(|cache| serde_json::from_slice(&cache).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            hashes,
+            dir_hashes: BTreeMap::new(),
+            rebuilt: BTreeSet::new(),
+        }
+    }
+
+    fn key(target: &Target) -> String {
+        format!("{}#{}", target.manifest_dir().display(), target.name)
+    }
+
+    /// Returns the current hash of `target`'s sources, those of its local path dependencies,
+    /// and the options that affect its output.
+    fn source_hash(&mut self, target: &Target, opts: &BuildOptions) -> Result<String> {
+        use sha2::Digest as _;
+        let dir = target.manifest_dir();
+        let mut dep_hashes = BTreeMap::new();
+        for dep_dir in path_dependency_dirs(dir)? {
+            let dep_hash = self.dir_hash(&dep_dir)?;
+            dep_hashes.insert(dep_dir.to_string_lossy().into_owned(), dep_hash);
+        }
+        let key = serde_json::json!({
+            "sources": self.dir_hash(dir)?,
+            "path_dependencies": dep_hashes,
+            "options": {
+                "debug": opts.debug,
+                "target_triple": opts.target_triple,
+                "stack_size": opts.stack_size,
+                "wasi": opts.wasi,
+                "wasi_abi": format!("{:?}", opts.wasi_abi),
+                "strip": format!("{:?}", opts.strip),
+                "wasm_opt": opts.wasm_opt,
+                "include_sources": opts.include_sources,
+                "ts_types_only": opts.ts_types_only,
+                "envs": opts.envs,
+                "rustc_wrapper": opts.rustc_wrapper,
+                "builder_args": opts.builder_args,
+                "features": opts.features.features,
+                "all_features": opts.features.all_features,
+                "no_default_features": opts.features.no_default_features,
+            },
+        });
+        Ok(hex::encode(sha2::Sha256::digest(&serde_json::to_vec(
+            &key,
+        )?)))
+    }
+
+    fn dir_hash(&mut self, dir: &Path) -> Result<String> {
+        use sha2::Digest as _;
+        if let Some(dir_hash) = self.dir_hashes.get(dir) {
+            return Ok(dir_hash.to_string());
+        }
+        let sources = serde_json::to_vec(&hash_sources(dir)?)?;
+        let dir_hash = hex::encode(sha2::Sha256::digest(&sources));
+        self.dir_hashes.insert(dir.to_path_buf(), dir_hash.clone());
+        Ok(dir_hash)
+    }
+
+    /// Returns whether `target` can be skipped because neither it nor its dependencies have
+    /// changed and its service, if any, still exists.
+    fn is_fresh(
+        &mut self,
+        workspace: &Workspace,
+        target: &Target,
+        opts: &BuildOptions,
+    ) -> Result<bool> {
+        if matches!(target.project.kind, ProjectKind::Wasm) {
+            return Ok(false);
+        }
+        if target.wasm_path().map(|p| !p.is_file()).unwrap_or_default() {
+            return Ok(false);
+        }
+        if target.yields_artifact(Artifacts::TYPESCRIPT_CLIENT)
+            && !target
+                .artifacts_dir()
+                .join(ts_client_filename(&target.name, opts))
+                .is_file()
+        {
+            return Ok(false);
+        }
+        for dep in workspace.dependencies_of(target)? {
+            if self.rebuilt.contains(&Self::key(dep)) {
+                return Ok(false);
+            }
+        }
+        let hash = self.source_hash(target, opts)?;
+        Ok(self.hashes.get(&Self::key(target)) == Some(&hash))
+    }
+
+    fn record(&mut self, target: &Target, opts: &BuildOptions) -> Result<()> {
+        let key = Self::key(target);
+        if !matches!(target.project.kind, ProjectKind::Wasm) {
+            // Building may have written to the source dir (e.g., `Cargo.lock`), so rehash it.
+            self.dir_hashes.remove(target.manifest_dir());
+            let hash = self.source_hash(target, opts)?;
+            self.hashes.insert(key.clone(), hash);
+        }
+        self.rebuilt.insert(key);
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        ensure_dir!(self.path.parent().unwrap())?;
+        fs::write(&self.path, serde_json::to_vec_pretty(&self.hashes)?)?;
+        Ok(())
+    }
+}
+
+/// Which custom sections `prep_wasm` removes from a service.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StripMode {
+    /// Remove all custom sections, including the `name` section and Oasis metadata.
+    All,
+    /// Remove custom sections not used by the Oasis platform. This is the release default.
+    Custom,
+    /// Keep all custom sections. This is the debug default.
+    None,
+}
+
+impl StripMode {
+    fn keeps_custom(self, section_name: &str) -> bool {
+        match self {
+            StripMode::All => false,
+            StripMode::Custom => section_name.starts_with("oasis"),
+            StripMode::None => true,
+        }
+    }
+}
+
+/// The WASI module, and thereby version, that `prep_wasm` makes a service import.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WasiAbi {
+    /// Rename `wasi_snapshot_preview*` imports to `wasi_unstable`, as the Oasis runtime expects.
+    Unstable,
+    /// Keep the `wasi_snapshot_preview1` imports emitted by the compiler.
+    Preview1,
 }
 
 impl<'a> super::ExecSubcommand for BuildOptions<'a> {
     fn exec(self) -> Result<()> {
         let workspace = crate::workspace::Workspace::populate()?;
-        let targets = workspace.collect_targets(&self.targets)?;
-        build(&workspace, &targets, self)
+        let mut targets = workspace.select_targets(&self.targets, self.all)?;
+        let excluded = workspace.exclude_targets(&mut targets, &self.exclude)?;
+        warn_excluded_dependencies(&workspace, &targets, &excluded)?;
+        if !self.print_artifact {
+            return build(&workspace, &targets, self);
+        }
+        let expects_many = self.all || self.targets.len() > 1;
+        let artifacts = artifact_paths(&targets, expects_many)?;
+        build(&workspace, &targets, self)?;
+        for artifact in artifacts {
+            println!("{}", artifact.display());
+        }
+        Ok(())
     }
 }
 
-pub fn build(workspace: &Workspace, targets: &[&Target], opts: BuildOptions) -> Result<()> {
-    for target in workspace
-        .construct_build_plan(targets)?
+/// Returns the absolute paths of the services among `targets` for `--print-artifact`. More than
+/// one is allowed only if the user `expects_many` (i.e. gave several targets or `--all`).
+fn artifact_paths(targets: &[&Target], expects_many: bool) -> Result<Vec<PathBuf>> {
+    let cwd = std::env::current_dir()?;
+    let services: Vec<&Target> = targets
         .iter()
-        .filter(|t| t.is_buildable())
-    {
-        let proj = target.project;
-        if opts.verbosity > Verbosity::Quiet {
-            print_status_in(
-                Status::Building,
-                &target.name,
-                proj.manifest_path.parent().unwrap(),
-            );
+        .copied()
+        .filter(|t| t.is_buildable() && t.yields_artifact(Artifacts::SERVICE))
+        .collect();
+    match services.as_slice() {
+        [] => bail!("`--print-artifact` requires a service target, but none were selected"),
+        [_, _, ..] if !expects_many => bail!(
+            "`--print-artifact` found {} services: {}. Name the one to print, \
+             or name several targets to print one path per line.",
+            services.len(),
+            services
+                .iter()
+                .map(|t| format!("`{}`", t.name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        _ => Ok(services
+            .iter()
+            .map(|t| cwd.join(t.wasm_path().unwrap()))
+            .collect()),
+    }
+}
+
+/// Warns about the `excluded` targets that will be built anyway because `targets` depend on them.
+pub fn warn_excluded_dependencies(
+    workspace: &Workspace,
+    targets: &[&Target],
+    excluded: &[&Target],
+) -> Result<()> {
+    if excluded.is_empty() {
+        return Ok(());
+    }
+    let build_plan = workspace.construct_build_plan(targets)?;
+    for target in excluded.iter().filter(|t| build_plan.contains(t)) {
+        warn!(
+            "`{}` was excluded, but will be built because other targets depend on it",
+            target.name
+        );
+    }
+    Ok(())
+}
+
+/// Checks that the services needed by `targets` have already been built, for when a
+/// subcommand is asked to use existing artifacts rather than build.
+pub fn check_built(workspace: &Workspace, targets: &[&Target]) -> Result<()> {
+    for target in workspace.construct_build_plan(targets)? {
+        if !target.is_buildable() || !matches!(target.project.kind, ProjectKind::Rust) {
+            continue;
+        }
+        if let Some(wasm_path) = target.wasm_path() {
+            if !wasm_path.is_file() {
+                return Err(CliError::MissingArtifact(
+                    target.name.to_string(),
+                    wasm_path.display().to_string(),
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn build(workspace: &Workspace, targets: &[&Target], opts: BuildOptions) -> Result<()> {
+    let build_plan = workspace.construct_build_plan(targets)?;
+    let projects = workspace.projects_of(&build_plan);
+    for proj in projects.iter() {
+        check_oasis_std_version(proj);
+    }
+    opts.features.check_declared(&projects)?;
+    let named_service = match opts.artifact_name {
+        Some(artifact_name) => Some(named_service(workspace, targets, artifact_name)?),
+        None => None,
+    };
+    if opts.no_clients {
+        remove_client_artifacts(workspace, &build_plan, &opts)?;
+    }
+    let interface_dir = match opts.interface_only {
+        Some(_)
+            if !targets
+                .iter()
+                .any(|t| t.yields_artifact(Artifacts::SERVICE)) =>
+        {
+            bail!("`--interface-only` requires a service target, but none were selected")
         }
+        Some(out_dir) => Some(ensure_dir!(std::env::current_dir()?.join(out_dir))?),
+        None => None,
+    };
+    if opts.explain {
+        explain_build_plan(workspace, targets, &build_plan, &opts)?;
+    }
+    if let Some(diagnostics) = &opts.diagnostics {
+        diagnostics.save()?; // Replace any stale diagnostics, even if nothing is built.
+    }
+    // The timings are printed even if the build fails, which is when they're most useful.
+    let build_targets = || -> Result<()> {
+        let mut interfaces = InterfaceCache::new();
+        let mut build_cache = if opts.if_changed {
+            Some(BuildCache::load(workspace))
+        } else {
+            None
+        };
+        for target in build_plan.iter().filter(|t| {
+            t.is_buildable()
+                && (!t.is_example() || opts.examples || opts.targets.contains(&t.name.as_str()))
+                && (interface_dir.is_none() || t.yields_artifact(Artifacts::SERVICE))
+        }) {
+            let proj = target.project;
+            if let Some(build_cache) = &mut build_cache {
+                if build_cache.is_fresh(workspace, target, &opts)? {
+                    if opts.verbosity > Verbosity::Quiet {
+                        print_status_in(
+                            Status::Fresh,
+                            &target.name,
+                            proj.manifest_path.parent().unwrap(),
+                        );
+                    }
+                    continue;
+                }
+            }
+            if opts.verbosity > Verbosity::Quiet {
+                print_status_in(
+                    Status::Building,
+                    &target.name,
+                    proj.manifest_path.parent().unwrap(),
+                );
+            }
+
+            if target.yields_artifact(Artifacts::SERVICE) {
+                match proj.kind {
+                    ProjectKind::Rust => {
+                        build_rust_service(target, &opts)?;
+                        if named_service == Some(*target) {
+                            save_named_artifact(target, opts.artifact_name.unwrap())?;
+                        }
+                    }
+                    ProjectKind::Wasm => {
+                        let out_file = target.wasm_path().unwrap();
+                        opts.time_phase(target, "prep_wasm", || {
+                            prep_wasm(
+                                &target.path,
+                                &out_file,
+                                &PrepWasmOptions {
+                                    wasi_abi: opts.wasi_abi,
+                                    ..PrepWasmOptions::new(opts.strip)
+                                },
+                            )
+                        })?;
+                        if let Some(level) = opts.wasm_opt {
+                            opts.time_phase(target, "wasm-opt", || {
+                                wasm_opt(&out_file, level, opts.verbosity)
+                            })?;
+                        }
+                    }
+                    ProjectKind::JavaScript { .. } | ProjectKind::TypeScript { .. } => {
+                        unreachable!("[tj]s services don't yet exist")
+                    }
+                }
+            }
+
+            if let Some(interface_dir) = &interface_dir {
+                if targets.contains(target) {
+                    write_interface(target, interface_dir, &opts, &mut interfaces)?;
+                }
+                continue;
+            }
+
+            if target.is_example() {
+                build_rust_app(&target, &opts)?;
+            }
 
-        if target.yields_artifact(Artifacts::SERVICE) {
-            match proj.kind {
-                ProjectKind::Rust => build_rust_service(target, &opts)?,
-                ProjectKind::Wasm => {
-                    let out_file = Path::new(&target.name).with_extension("wasm");
-                    prep_wasm(&Path::new(&target.name), &out_file, opts.debug)?;
+            // The interface is checked against the lock even if no client is generated.
+            let has_client = target.yields_artifact(Artifacts::TYPESCRIPT_CLIENT);
+            if has_client
+                || (opts.interface_lock.is_some() && target.yields_artifact(Artifacts::SERVICE))
+            {
+                let iface = service_interface(target, &opts, &mut interfaces)?;
+                if has_client {
+                    build_typescript_client(&target, iface, &opts)?;
                 }
-                ProjectKind::JavaScript { .. } | ProjectKind::TypeScript { .. } => {
-                    unreachable!("[tj]s services don't yet exist")
+            }
+
+            if target.yields_artifact(Artifacts::APP) {
+                match proj.kind {
+                    ProjectKind::JavaScript { .. } => build_javascript_app(target, &opts)?,
+                    ProjectKind::TypeScript { .. } => {
+                        build_typescript_app(workspace, &target, &opts)?
+                    }
+                    ProjectKind::Rust => {
+                        build_rust_app(&target, &opts)?;
+                        // Building may have created the lock that reveals a wrapped `oasis-std`.
+                        if proj.plain_bins_are_services() {
+                            warn!(
+                                "`{}` was built as a plain binary, but it depends on oasis-std \
+                                 through another crate. Run `oasis build` again to build it as \
+                                 a service.",
+                                target.name
+                            );
+                        }
+                    }
+                    ProjectKind::Wasm => unreachable!("there's no such thing as a Wasm app"),
                 }
             }
+
+            if let Some(build_cache) = &mut build_cache {
+                build_cache.record(target, &opts)?;
+            }
+        }
+        if let Some(build_cache) = &build_cache {
+            build_cache.save()?;
+        }
+        Ok(())
+    };
+    let result = build_targets();
+    if let Some(timings) = &opts.timings {
+        timings.print_summary();
+    }
+    result
+}
+
+/// Prints, for `--explain`, why each target of `build_plan` is built, which artifacts it yields
+/// and why, and which tool builds it.
+fn explain_build_plan(
+    workspace: &Workspace,
+    targets: &[&Target],
+    build_plan: &[&Target],
+    opts: &BuildOptions,
+) -> Result<()> {
+    let quote_names = |targets: &[&Target]| {
+        targets
+            .iter()
+            .map(|t| format!("`{}`", t.name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut top_deps = Vec::with_capacity(targets.len());
+    for top_target in targets {
+        top_deps.push((*top_target, workspace.dependencies_of(top_target)?));
+    }
+
+    eprintln!("Build plan:");
+    for target in build_plan {
+        eprintln!(
+            "  {} ({}, {})",
+            target.name,
+            target.project.kind.name(),
+            target.manifest_dir().display()
+        );
+
+        let mut reasons = Vec::new();
+        if targets.contains(target) {
+            reasons.push(if opts.targets.contains(&target.name.as_str()) {
+                "named directly".to_string()
+            } else if opts.all {
+                "selected by `--all`".to_string()
+            } else {
+                format!("in `{}`", target.manifest_dir().display())
+            });
+        }
+        let dependents: Vec<&Target> = build_plan
+            .iter()
+            .copied()
+            .filter(|t| {
+                workspace
+                    .resolve_dependencies(t)
+                    .iter()
+                    .any(|(_, _, dep)| *dep == Some(*target))
+            })
+            .collect();
+        if !dependents.is_empty() {
+            reasons.push(format!("dependency of {}", quote_names(&dependents)));
+        }
+        eprintln!("    included: {}", reasons.join("; "));
+
+        if !target.is_buildable() {
+            eprintln!("    skipped: it has nothing to build");
+            continue;
+        }
+        if target.is_example() && !opts.examples && !opts.targets.contains(&target.name.as_str()) {
+            eprintln!("    skipped: examples are built only with `--examples` or by name");
+            continue;
         }
 
-        if target.yields_artifact(Artifacts::TYPESCRIPT_CLIENT) {
-            build_typescript_client(&target, &opts)?;
+        let mut artifacts = Vec::new();
+        for (artifact, name) in &[
+            (Artifacts::SERVICE, "service"),
+            (Artifacts::APP, "app"),
+            (Artifacts::EXAMPLE, "example"),
+        ] {
+            if target.yields_artifact(*artifact) {
+                artifacts.push(format!("{} (from its manifest)", name));
+            }
         }
+        for (artifact, name) in &[
+            (Artifacts::RUST_CLIENT, "Rust client"),
+            (Artifacts::TYPESCRIPT_CLIENT, "TypeScript client"),
+        ] {
+            if !target.yields_artifact(*artifact) {
+                continue;
+            }
+            let requirers: Vec<&Target> = top_deps
+                .iter()
+                .filter(|(top, deps)| {
+                    deps.contains(target) && top.required_artifacts().contains(*artifact)
+                })
+                .map(|(top, _)| *top)
+                .collect();
+            artifacts.push(if requirers.is_empty() {
+                format!("{} (from its manifest)", name)
+            } else {
+                format!("{} ({} depends on it)", name, quote_names(&requirers))
+            });
+        }
+        eprintln!("    artifacts: {}", artifacts.join(", "));
 
-        if target.yields_artifact(Artifacts::APP) {
-            match proj.kind {
-                ProjectKind::JavaScript { .. } => build_javascript_app(target, &opts)?,
-                ProjectKind::TypeScript { .. } => build_typescript_app(workspace, &target, &opts)?,
-                ProjectKind::Rust => build_rust_app(&target, &opts)?,
-                ProjectKind::Wasm => unreachable!("there's no such thing as a Wasm app"),
+        let build_tool = match target.project.kind {
+            ProjectKind::Wasm => "none; the module is only prepared".to_string(),
+            ProjectKind::Rust => {
+                format!("cargo, with `{}` as the rustc wrapper", opts.rustc_wrapper)
             }
+            _ => BuildToolKind::detect(target.project).name().to_string(),
+        };
+        eprintln!("    build tool: {}", build_tool);
+    }
+    Ok(())
+}
+
+/// Returns the one selected service that `--artifact-name` applies to.
+fn named_service<'t>(
+    workspace: &Workspace,
+    targets: &[&'t Target],
+    artifact_name: &str,
+) -> Result<&'t Target> {
+    if artifact_name.is_empty() || artifact_name.contains(std::path::is_separator) {
+        bail!(
+            "`--artifact-name` must be a file name. Found `{}`.",
+            artifact_name
+        );
+    }
+    let services: Vec<&Target> = targets
+        .iter()
+        .copied()
+        .filter(|t| {
+            t.yields_artifact(Artifacts::SERVICE) && matches!(t.project.kind, ProjectKind::Rust)
+        })
+        .collect();
+    let service = match services.as_slice() {
+        [service] => *service,
+        [] => bail!("`--artifact-name` requires a service target, but none were selected"),
+        _ => bail!(
+            "`--artifact-name` requires exactly one service target, but {} were selected: {}",
+            services.len(),
+            services
+                .iter()
+                .map(|t| format!("`{}`", t.name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let artifact_path = service
+        .artifacts_dir()
+        .join(format!("{}.wasm", artifact_name));
+    // Even targets that aren't being built have artifacts that mustn't be overwritten.
+    if let Some(other) = workspace
+        .targets()
+        .find(|t| *t != service && t.wasm_path() == Some(artifact_path.clone()))
+    {
+        bail!(
+            "`--artifact-name {}` would overwrite the artifact of `{}`",
+            artifact_name,
+            other.name
+        );
+    }
+    Ok(service)
+}
+
+/// Copies the prepared wasm of `target` to `<artifact_name>.wasm`. The original is kept
+/// because clients, tests, and deploys look for services by target name.
+fn save_named_artifact(target: &Target, artifact_name: &str) -> Result<()> {
+    let wasm_path = target.wasm_path().unwrap();
+    if !wasm_path.is_file() {
+        return Ok(()); // `build_rust_service` has already warned about the missing module.
+    }
+    let named_path = target
+        .artifacts_dir()
+        .join(format!("{}.wasm", artifact_name));
+    fs::copy(&wasm_path, &named_path).map_err(|e| {
+        anyhow!(
+            "could not copy `{}` to `{}`: {}",
+            wasm_path.display(),
+            named_path.display(),
+            e
+        )
+    })?;
+    Ok(())
+}
+
+/// Skips generating clients for `--no-clients`. The existing clients of the skipped targets
+/// are removed because the services will be rebuilt and the clients could no longer match them.
+/// Clients that JavaScript targets in the build plan depend on, even transitively, are still
+/// regenerated, as the dependents would otherwise build against a missing or stale client.
+fn remove_client_artifacts(
+    workspace: &Workspace,
+    build_plan: &[&Target],
+    opts: &BuildOptions,
+) -> Result<()> {
+    let mut js_dependencies = Vec::new(); // (dependency, JS target that depends on it)
+    for js_target in build_plan.iter().filter(|t| {
+        matches!(
+            t.project.kind,
+            ProjectKind::JavaScript { .. } | ProjectKind::TypeScript { .. }
+        )
+    }) {
+        for dep in workspace.dependencies_of(js_target)? {
+            js_dependencies.push((dep, *js_target));
+        }
+    }
+    for target in build_plan.iter() {
+        if !target.yields_artifact(Artifacts::TYPESCRIPT_CLIENT) {
+            continue;
+        }
+        let js_dependents: Vec<_> = js_dependencies
+            .iter()
+            .filter(|(dep, _)| dep == target)
+            .map(|(_, dependent)| format!("`{}`", dependent.name))
+            .collect();
+        if js_dependents.is_empty() {
+            target.remove_artifacts(Artifacts::TYPESCRIPT_CLIENT);
+            let ts_client = target
+                .artifacts_dir()
+                .join(ts_client_filename(&target.name, opts));
+            fs::remove_file(ts_client).ok();
+        } else {
+            warn!(
+                "regenerating the client for `{}`, which {} needs",
+                target.name,
+                js_dependents.join(", ")
+            );
         }
     }
     Ok(())
 }
 
+/// Warns if a project depends on an `oasis-std` that the installed `oasis-build` doesn't
+/// support. Such services build but fail in confusing ways at runtime. The version is the one
+/// locked in `Cargo.lock` or, before the first build, the lowest that the requirement allows.
+fn check_oasis_std_version(proj: &Project) {
+    let oasis_std_ver = match (&proj.oasis_std_version, &proj.oasis_std_req) {
+        (Some(oasis_std_ver), _) => oasis_std_ver.clone(),
+        (None, Some(oasis_std_req)) => match toolchain::min_version(oasis_std_req) {
+            Some(oasis_std_ver) => oasis_std_ver,
+            None => return,
+        },
+        (None, None) => return,
+    };
+    let supported_req = match toolchain::supported_oasis_std() {
+        Some(supported_req) => supported_req,
+        None => return,
+    };
+    if !supported_req.matches(&oasis_std_ver) {
+        warn!(
+            "`{}` depends on oasis-std `{}`, but the installed toolchain supports oasis-std `{}`",
+            proj.manifest_path.display(),
+            oasis_std_ver,
+            supported_req
+        );
+    }
+}
+
 fn build_rust_service(target: &Target, opts: &BuildOptions) -> Result<()> {
-    let mut args = vec!["--target=wasm32-wasi"];
+    let target_arg = format!("--target={}", opts.target_triple);
+    let mut args = vec![target_arg.as_str()];
     if !opts.debug {
         args.push("--release");
     }
     args.push("--bin");
     args.push(&target.name);
+    let feature_args = opts.features.cargo_args(target.project);
+    args.extend(feature_args.iter().map(String::as_str));
+    if opts.json_messages || opts.diagnostics.is_some() {
+        args.push("--message-format=json");
+    }
     args.extend(opts.builder_args.iter());
 
-    let mut envs: BTreeMap<OsString, OsString> = BTreeMap::new();
+    let mut envs = user_env_map(&opts.envs);
     if let Some(stack_size) = opts.stack_size {
         let stack_size_flag = OsString::from(format!(" -C link-args=-zstack-size={}", stack_size));
         match envs.entry(OsString::from("RUSTFLAGS")) {
@@ -122,7 +1114,7 @@ fn build_rust_service(target: &Target, opts: &BuildOptions) -> Result<()> {
     if !opts.wasi {
         envs.insert(
             OsString::from("RUSTC_WRAPPER"),
-            OsString::from("oasis-build"),
+            OsString::from(&opts.rustc_wrapper),
         );
     }
 
@@ -133,7 +1125,9 @@ fn build_rust_service(target: &Target, opts: &BuildOptions) -> Result<()> {
         "rustflags": std::env::var("RUSTFLAGS").ok(),
     });
 
-    if let Err(e) = BuildTool::for_target(target).build(args, envs, opts.verbosity) {
+    if let Err(e) = opts.time_phase(target, "build tool", || {
+        run_cargo_build(target, args, envs, opts)
+    }) {
         emit!(cmd.build.error);
         return Err(e);
     };
@@ -144,7 +1138,7 @@ fn build_rust_service(target: &Target, opts: &BuildOptions) -> Result<()> {
         print_status(Status::Preparing, &wasm_name);
     }
 
-    let mut wasm_dir = target.project.target_dir.join("wasm32-wasi");
+    let mut wasm_dir = target.project.target_dir.join(opts.target_triple);
     wasm_dir.push(if opts.debug { "debug" } else { "release" });
     let wasm_file = wasm_dir.join(&wasm_name);
     if !wasm_file.is_file() {
@@ -152,96 +1146,306 @@ fn build_rust_service(target: &Target, opts: &BuildOptions) -> Result<()> {
         return Ok(());
     };
     emit!(cmd.build.prep_wasm);
-    prep_wasm(
-        &wasm_file,
-        &ensure_dir!(target.artifacts_dir())?.join(&wasm_name),
-        opts.debug,
-    )?;
-    emit!(cmd.build.done);
+    let out_file = ensure_dir!(target.artifacts_dir())?.join(&wasm_name);
+    let mut prep_opts = PrepWasmOptions::new(opts.strip);
+    prep_opts.wasi_abi = opts.wasi_abi;
+    if opts.include_sources {
+        prep_opts.sources_dir = Some(target.manifest_dir().to_path_buf());
+    }
+    opts.time_phase(target, "prep_wasm", || {
+        prep_wasm(&wasm_file, &out_file, &prep_opts)
+    })?;
+    if let Some(level) = opts.wasm_opt {
+        opts.time_phase(target, "wasm-opt", || {
+            wasm_opt(&out_file, level, opts.verbosity)
+        })?;
+    }
+    emit!(cmd.build.done, { "timings": opts.timings_json(target) });
 
     Ok(())
 }
 
+/// Runs `cargo build` for a Rust target. With `--diagnostics-json`, Cargo prints JSON, from
+/// which the compiler messages are collected and, unless JSON was requested, re-rendered.
+fn run_cargo_build<'a>(
+    target: &'a Target,
+    args: Vec<&'a str>,
+    envs: BTreeMap<OsString, OsString>,
+    opts: &'a BuildOptions,
+) -> Result<()> {
+    let build_tool = BuildTool::for_target(target).manifest_options(opts.manifest_opts);
+    let diagnostics = match &opts.diagnostics {
+        Some(diagnostics) => diagnostics,
+        None => return build_tool.build(args, envs, opts.verbosity),
+    };
+    let result = build_tool
+        .on_stdout_line(|line| {
+            let rendered = diagnostics.record(&target.name, line);
+            if opts.json_messages {
+                println!("{}", line);
+            } else if let Some(rendered) = rendered {
+                if opts.verbosity > Verbosity::Silent {
+                    eprint!("{}", rendered);
+                }
+            }
+        })
+        .build(args, envs, opts.verbosity);
+    let saved = diagnostics.save();
+    result.and(saved)
+}
+
+/// Builds a Rust app or, if the target is an example, a Rust example.
 fn build_rust_app(target: &Target, opts: &BuildOptions) -> Result<()> {
     let mut args = Vec::new();
     if !opts.debug {
         args.push("--release");
     }
-    args.push("--bin");
+    args.push(if target.is_example() {
+        "--example"
+    } else {
+        "--bin"
+    });
     args.push(&target.name);
+    let feature_args = opts.features.cargo_args(target.project);
+    args.extend(feature_args.iter().map(String::as_str));
+    if opts.json_messages || opts.diagnostics.is_some() {
+        args.push("--message-format=json");
+    }
     args.extend(opts.builder_args.iter());
 
-    let mut envs: BTreeMap<OsString, OsString> = BTreeMap::new();
+    let mut envs = user_env_map(&opts.envs);
     envs.insert(
         OsString::from("RUSTC_WRAPPER"),
-        OsString::from("oasis-build"),
+        OsString::from(&opts.rustc_wrapper),
     );
 
     emit!(cmd.build.start, {
-        "project_type": format!("{} app", target.project.kind.name()),
+        "project_type": format!(
+            "{} {}",
+            target.project.kind.name(),
+            if target.is_example() { "example" } else { "app" }
+        ),
     });
 
-    if let Err(e) = BuildTool::for_target(target).build(args, envs, opts.verbosity) {
+    if let Err(e) = opts.time_phase(target, "build tool", || {
+        run_cargo_build(target, args, envs, opts)
+    }) {
         emit!(cmd.build.error);
         return Err(e);
     };
 
-    emit!(cmd.build.done);
+    emit!(cmd.build.done, { "timings": opts.timings_json(target) });
 
     Ok(())
 }
 
-pub fn prep_wasm(input_wasm: &Path, output_wasm: &Path, debug: bool) -> Result<()> {
-    let mut module = walrus::Module::from_file(input_wasm)?;
+/// Options that control how `prep_wasm` turns a compiled module into an Oasis service.
+pub struct PrepWasmOptions {
+    pub strip: StripMode,
+    /// Custom sections, as `(name, data)`, to add to the prepared module.
+    pub custom_sections: Vec<(String, Vec<u8>)>,
+    /// Whether to add an `oasis_version` section that describes the current git revision.
+    pub add_version: bool,
+    /// The directory whose source files are hashed into an `oasis_sources` section, if any.
+    pub sources_dir: Option<PathBuf>,
+    pub wasi_abi: WasiAbi,
+}
+
+impl PrepWasmOptions {
+    pub fn new(strip: StripMode) -> Self {
+        Self {
+            strip,
+            custom_sections: Vec::new(),
+            add_version: strip != StripMode::All,
+            sources_dir: None,
+            wasi_abi: WasiAbi::Unstable,
+        }
+    }
+}
+
+pub fn prep_wasm(input_wasm: &Path, output_wasm: &Path, opts: &PrepWasmOptions) -> Result<()> {
+    let strip = opts.strip;
+    let mut module = walrus::ModuleConfig::new()
+        .generate_name_section(strip != StripMode::All)
+        .generate_producers_section(strip != StripMode::All)
+        .parse_file(input_wasm)?;
 
     externalize_mem(&mut module);
 
-    module.imports.iter_mut().for_each(|imp| {
-        if imp.module.starts_with("wasi_snapshot_preview") {
-            imp.module = "wasi_unstable".to_string();
+    if opts.wasi_abi == WasiAbi::Unstable {
+        module.imports.iter_mut().for_each(|imp| {
+            if imp.module.starts_with("wasi_snapshot_preview") {
+                imp.module = "wasi_unstable".to_string();
+            }
+        });
+    }
+
+    let customs_to_delete = module
+        .customs
+        .iter()
+        .filter_map(|(id, custom)| {
+            if strip.keeps_custom(custom.name()) {
+                None
+            } else {
+                Some(id)
+            }
+        })
+        .collect::<Vec<_>>();
+    for id in customs_to_delete {
+        module.customs.delete(id);
+    }
+
+    if opts.add_version {
+        // Add a section with version info for current git repo.
+        let git_sha = match Command::new("git").args(&["rev-parse", "HEAD"]).output() {
+            Ok(output) => strip_trailing_newline(output.stdout),
+            Err(_) => b"(git rev-parse failed)".to_vec(),
+        };
+        let git_has_dirty_index = Command::new("git")
+            .args(&["status", "--porcelain"])
+            .output()
+            .map(|o| !strip_trailing_newline(o.stdout).is_empty())
+            .unwrap_or_default();
+        module.customs.add(walrus::RawCustomSection {
+            name: "oasis_version".to_string(),
+            data: format!(
+                r#"{{"sha":"{}{}","serviceName":"{}"}}"#,
+                String::from_utf8(git_sha)?,
+                if git_has_dirty_index { " (DIRTY)" } else { "" },
+                input_wasm.file_stem().unwrap_or_default().to_string_lossy()
+            )
+            .into_bytes(),
+        });
+    }
+
+    if let Some(sources_dir) = &opts.sources_dir {
+        module.customs.add(walrus::RawCustomSection {
+            name: "oasis_sources".to_string(),
+            data: serde_json::to_vec(&hash_sources(sources_dir)?)?,
+        });
+    }
+
+    for (name, data) in opts.custom_sections.iter() {
+        module.customs.add(walrus::RawCustomSection {
+            name: name.to_string(),
+            data: data.to_vec(),
+        });
+    }
+
+    module.emit_wasm_file(output_wasm)?;
+
+    Ok(())
+}
+
+/// Returns the SHA-256 of each source file in `dir` that is not ignored by git, keyed by
+/// its path relative to `dir`.
+fn hash_sources(dir: &Path) -> Result<serde_json::Value> {
+    use sha2::Digest as _;
+    let mut hashes = BTreeMap::new();
+    let walker = ignore::WalkBuilder::new(dir)
+        // The Cargo target dir is usually, but not necessarily, ignored.
+        .filter_entry(|entry| !(entry.depth() == 1 && entry.file_name() == "target"))
+        .build();
+    for entry in walker {
+        let entry = entry?;
+        if !entry.file_type().map(|ft| ft.is_file()).unwrap_or_default() {
+            continue;
         }
-    });
+        let rel_path = entry.path().strip_prefix(dir).unwrap();
+        hashes.insert(
+            rel_path.to_string_lossy().replace('\\', "/"),
+            hex::encode(sha2::Sha256::digest(&fs::read(entry.path())?)),
+        );
+    }
+    Ok(serde_json::json!({ "sha256": hashes }))
+}
 
-    if !debug {
-        let customs_to_delete = module
-            .customs
-            .iter()
-            .filter_map(|(id, custom)| {
-                if custom.name().starts_with("oasis") {
-                    None
-                } else {
-                    Some(id)
+/// Returns the directories of the crates or packages on which the project in `manifest_dir`
+/// transitively depends by local path (i.e., `path = ...` in `Cargo.toml` or `file:` in
+/// `package.json`).
+fn path_dependency_dirs(manifest_dir: &Path) -> Result<BTreeSet<PathBuf>> {
+    let root_dir = manifest_dir.canonicalize()?;
+    let mut dep_dirs = BTreeSet::new();
+    let mut pending = vec![root_dir.clone()];
+    while let Some(dir) = pending.pop() {
+        let mut dep_paths = Vec::new();
+        if let Ok(manifest) = fs::read_to_string(dir.join("Cargo.toml")) {
+            let manifest: toml::Value = toml::from_str(&manifest)
+                .map_err(|e| anyhow!("could not parse `{}/Cargo.toml`: {}", dir.display(), e))?;
+            let mut dep_tables = vec![
+                manifest.get("dependencies"),
+                manifest.get("build-dependencies"),
+            ];
+            if let Some(platforms) = manifest.get("target").and_then(toml::Value::as_table) {
+                for platform in platforms.values() {
+                    dep_tables.push(platform.get("dependencies"));
+                    dep_tables.push(platform.get("build-dependencies"));
                 }
-            })
-            .collect::<Vec<_>>();
-        for id in customs_to_delete {
-            module.customs.delete(id);
+            }
+            dep_paths.extend(
+                dep_tables
+                    .into_iter()
+                    .filter_map(|deps| deps.and_then(toml::Value::as_table))
+                    .flat_map(|deps| deps.values())
+                    .filter_map(|dep| dep.get("path").and_then(toml::Value::as_str))
+                    .map(str::to_string),
+            );
+        } else if let Ok(manifest) = fs::read(dir.join("package.json")) {
+            let manifest: serde_json::Value = serde_json::from_slice(&manifest)?;
+            if let Some(deps) = manifest.get("dependencies").and_then(|d| d.as_object()) {
+                dep_paths.extend(
+                    deps.values()
+                        .filter_map(|loc| loc.as_str())
+                        .filter(|loc| loc.starts_with("file:"))
+                        .map(|loc| loc["file:".len()..].to_string()),
+                );
+            }
+        }
+        for dep_path in dep_paths {
+            // A dependency that doesn't exist will fail the build, so it needn't be hashed.
+            if let Ok(dep_dir) = dir.join(dep_path).canonicalize() {
+                if dep_dir != root_dir && dep_dirs.insert(dep_dir.clone()) {
+                    pending.push(dep_dir);
+                }
+            }
         }
     }
+    Ok(dep_dirs)
+}
 
-    // Add a section with version info for current git repo.
-    let git_sha = match Command::new("git").args(&["rev-parse", "HEAD"]).output() {
-        Ok(output) => strip_trailing_newline(output.stdout),
-        Err(_) => b"(git rev-parse failed)".to_vec(),
-    };
-    let git_has_dirty_index = Command::new("git")
-        .args(&["status", "--porcelain"])
+/// Optimizes a prepared module in place using Binaryen's `wasm-opt`.
+/// This must run after `prep_wasm` has settled the custom sections, which `wasm-opt` preserves.
+fn wasm_opt(wasm_path: &Path, level: &str, verbosity: Verbosity) -> Result<()> {
+    let size_before = fs::metadata(wasm_path)?.len();
+    let output = Command::new("wasm-opt")
+        .arg(format!("-O{}", level))
+        .arg("--all-features")
+        .arg("--debuginfo") // Keep the `name` section, if `prep_wasm` did.
+        .arg(wasm_path)
+        .arg("-o")
+        .arg(wasm_path)
         .output()
-        .map(|o| !strip_trailing_newline(o.stdout).is_empty())
-        .unwrap_or_default();
-    module.customs.add(walrus::RawCustomSection {
-        name: "oasis_version".to_string(),
-        data: format!(
-            r#"{{"sha":"{}{}","serviceName":"{}"}}"#,
-            String::from_utf8(git_sha)?,
-            if git_has_dirty_index { " (DIRTY)" } else { "" },
-            input_wasm.file_stem().unwrap_or_default().to_string_lossy()
-        )
-        .into_bytes(),
-    });
-
-    module.emit_wasm_file(output_wasm)?;
-
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => anyhow!(
+                "could not run `wasm-opt`. Please install Binaryen \
+                 (https://github.com/WebAssembly/binaryen) and add it to your PATH"
+            ),
+            _ => e.into(),
+        })?;
+    if !output.status.success() {
+        bail!(
+            "`wasm-opt` exited with error:\n{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let size_after = fs::metadata(wasm_path)?.len();
+    if verbosity > Verbosity::Quiet {
+        print_status_ctx(
+            Status::Optimized,
+            wasm_path.file_name().unwrap().to_string_lossy(),
+            format!("{} -> {} bytes", size_before, size_after),
+        );
+    }
     Ok(())
 }
 
@@ -253,30 +1457,41 @@ fn strip_trailing_newline(mut input: Vec<u8>) -> Vec<u8> {
     input
 }
 
+/// Turns an exported memory into an imported one. Modules without a memory export are left
+/// untouched, as are those whose `memory` export isn't actually a memory.
 fn externalize_mem(module: &mut walrus::Module) {
-    let mem_export_id = match module.exports.iter().find(|e| e.name == "memory") {
-        Some(mem) => mem.id(),
+    let (mem_export_id, mem_id) = match module.exports.iter().find_map(|e| match e.item {
+        walrus::ExportItem::Memory(mem_id) if e.name == "memory" => Some((e.id(), mem_id)),
+        _ => None,
+    }) {
+        Some(mem_export) => mem_export,
         None => return,
     };
     module.exports.delete(mem_export_id);
 
-    let mut mem = module.memories.iter_mut().next().unwrap();
-    mem.import = Some(module.imports.add("env", "memory", mem.id()));
+    let mem = module.memories.get_mut(mem_id);
+    if mem.import.is_none() {
+        mem.import = Some(module.imports.add("env", "memory", mem_id));
+    }
 }
 
 fn build_javascript_app(target: &Target, opts: &BuildOptions) -> Result<()> {
     emit!(cmd.build.start, { "project_type": target.project.kind.name() });
 
-    if let Err(e) = BuildTool::for_target(target).build(
-        opts.builder_args.clone(),
-        BTreeMap::new(), /* envs */
-        opts.verbosity,
-    ) {
+    if let Err(e) = opts.time_phase(target, "build tool", || {
+        BuildTool::for_target(target)
+            .manifest_options(opts.manifest_opts)
+            .build(
+                opts.builder_args.clone(),
+                user_env_map(&opts.envs),
+                opts.verbosity,
+            )
+    }) {
         emit!(cmd.build.error);
         return Err(e);
     }
 
-    emit!(cmd.build.done);
+    emit!(cmd.build.done, { "timings": opts.timings_json(target) });
     Ok(())
 }
 
@@ -286,41 +1501,131 @@ fn build_typescript_app(workspace: &Workspace, target: &Target, opts: &BuildOpti
     });
 
     let clients_dir = ensure_dir!(target.clients_dir())?;
+    let mut ts_filenames = BTreeSet::new();
     for dep in workspace.dependencies_of(target)? {
-        let ts_filename = format!("{}.ts", ts::module_name(&dep.name));
-        let ts_client = clients_dir.join(&ts_filename);
-        fs::copy(dep.artifacts_dir().join(&ts_filename), &ts_client)?;
+        let ts_filename = ts_client_filename(&dep.name, opts);
+        // Copy then rename so that a concurrent build never sees a partially written client.
+        let part_path = clients_dir.join(format!(".{}.part", ts_filename));
+        fs::copy(dep.artifacts_dir().join(&ts_filename), &part_path)?;
+        fs::rename(&part_path, clients_dir.join(&ts_filename))?;
+        ts_filenames.insert(ts_filename);
     }
+    remove_stale_clients(&clients_dir, &ts_filenames)?;
 
-    if let Err(e) = BuildTool::for_target(target).build(
-        opts.builder_args.clone(),
-        BTreeMap::new(), /* envs */
-        opts.verbosity,
-    ) {
+    if let Err(e) = opts.time_phase(target, "build tool", || {
+        BuildTool::for_target(target)
+            .manifest_options(opts.manifest_opts)
+            .build(
+                opts.builder_args.clone(),
+                user_env_map(&opts.envs),
+                opts.verbosity,
+            )
+    }) {
         emit!(cmd.build.error);
         return Err(e);
     }
 
-    emit!(cmd.build.done);
+    emit!(cmd.build.done, { "timings": opts.timings_json(target) });
+    Ok(())
+}
+
+/// Removes the generated clients in `clients_dir` that aren't among `ts_filenames`, such as
+/// those of services that are no longer dependencies. Other files are left alone.
+fn remove_stale_clients(clients_dir: &Path, ts_filenames: &BTreeSet<String>) -> Result<()> {
+    for entry in fs::read_dir(clients_dir)? {
+        let path = entry?.path();
+        let filename = match path.file_name().and_then(|f| f.to_str()) {
+            Some(filename) if filename.ends_with(".ts") => filename,
+            _ => continue,
+        };
+        if ts_filenames.contains(filename) || !is_generated_client(&path) {
+            continue;
+        }
+        debug!("removing stale client `{}`", path.display());
+        fs::remove_file(&path)?;
+    }
     Ok(())
 }
 
-fn build_typescript_client(target: &Target, _opts: &BuildOptions) -> Result<()> {
+fn is_generated_client(path: &Path) -> bool {
+    use std::io::Read as _;
+    let mut header = vec![0u8; GENERATED_CLIENT_HEADER.len()];
+    fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut header))
+        .map(|_| header == GENERATED_CLIENT_HEADER.as_bytes())
+        .unwrap_or_default()
+}
+
+/// The start of the comment that begins every generated TypeScript client.
+const GENERATED_CLIENT_HEADER: &str = "// This file was AUTOGENERATED from ";
+
+/// The interfaces extracted during a single `build`, keyed by target. Extraction loads and
+/// parses the whole Wasm, so it's done at most once per target.
+type InterfaceCache = HashMap<*const Target, oasis_rpc::Interface>;
+
+/// Returns the interface of the built service `target`, which is checked against the
+/// `--locked-interface`, if any, when it's first extracted.
+fn service_interface<'c>(
+    target: &Target,
+    opts: &BuildOptions,
+    interfaces: &'c mut InterfaceCache,
+) -> Result<&'c oasis_rpc::Interface> {
+    let key = target as *const Target;
+    if !interfaces.contains_key(&key) {
+        let wasm_path = target
+            .wasm_path()
+            .expect("service target must yield a wasm artifact");
+        let iface = opts
+            .time_phase(target, "interface extraction", || {
+                crate::subcommands::ifextract::extract_interface(
+                    oasis_rpc::import::ImportLocation::Path(wasm_path),
+                    target.manifest_dir(),
+                    false, /* refresh */
+                )
+            })?
+            .pop()
+            .unwrap();
+
+        if opts.verbosity > Verbosity::Quiet {
+            print_status(Status::Interface, interface_summary(&iface));
+        }
+
+        if let Some(interface_lock) = &opts.interface_lock {
+            check_locked_interface(&iface, &interface_lock.path(target), interface_lock, opts)?;
+        }
+
+        interfaces.insert(key, iface);
+    }
+    Ok(&interfaces[&key])
+}
+
+/// Writes the interface of the service `target` to `<out_dir>/<target>.json`.
+fn write_interface(
+    target: &Target,
+    out_dir: &Path,
+    opts: &BuildOptions,
+    interfaces: &mut InterfaceCache,
+) -> Result<()> {
+    let iface = service_interface(target, opts, interfaces)?;
+
+    let iface_path = out_dir.join(format!("{}.json", target.name));
+    fs::write(&iface_path, iface.to_string()?)
+        .map_err(|e| anyhow!("could not write `{}`: {}", iface_path.display(), e))?;
+    Ok(())
+}
+
+fn build_typescript_client(
+    target: &Target,
+    iface: &oasis_rpc::Interface,
+    opts: &BuildOptions,
+) -> Result<()> {
     let wasm_path = target
         .wasm_path()
         .expect("service target must yield a wasm artifact");
     let bytecode = fs::read(&wasm_path)
         .map_err(|e| anyhow::anyhow!("could not read `{}`: {}", wasm_path.display(), e))?;
 
-    let iface = crate::subcommands::ifextract::extract_interface(
-        oasis_rpc::import::ImportLocation::Path(wasm_path.clone()),
-        target.manifest_dir(),
-    )?
-    .pop()
-    .unwrap();
-
-    let ts_file =
-        ensure_dir!(target.artifacts_dir())?.join(format!("{}.ts", ts::module_name(&target.name)));
+    let ts_file = ensure_dir!(target.artifacts_dir())?.join(ts_client_filename(&target.name, opts));
     let mut out_file = fs::OpenOptions::new()
         .create(true)
         .write(true)
@@ -332,18 +1637,392 @@ fn build_typescript_client(target: &Target, _opts: &BuildOptions) -> Result<()>
     out_file
         .write_all(
             format!(
-                "// This file was AUTOGENERATED from {}.\n\
-                 // It contains a client for the `{}` interface.\n\
+                "{}{}.\n\
+                 // It contains {} for the `{}` interface.\n\
                  // DO NOT EDIT. To regenerate, run `oasis build <myfile>.rs`.\n\n",
+                GENERATED_CLIENT_HEADER,
                 wasm_path.display(),
+                if opts.ts_types_only {
+                    "type declarations"
+                } else {
+                    "a client"
+                },
                 iface.name
             )
             .as_bytes(),
         )
         .map_err(output_error_handler)?;
-    out_file
-        .write_all(ts::generate(&iface, &bytecode).to_string().as_bytes())
-        .map_err(output_error_handler)?;
-    crate::cmd!("npx", "prettier", "--write", &ts_file).ok();
+    opts.time_phase(target, "ts generation", || {
+        let ts_code = if opts.ts_types_only {
+            ts::generate_declarations(iface)
+        } else {
+            ts::generate(iface, &bytecode)
+        };
+        out_file
+            .write_all(ts_code.to_string().as_bytes())
+            .map_err(output_error_handler)
+    })?;
+    opts.time_phase(target, "prettier", || {
+        crate::cmd!("npx", "prettier", "--write", &ts_file)
+    })
+    .ok();
     Ok(())
 }
+
+fn ts_client_filename(service_name: &str, opts: &BuildOptions) -> String {
+    let extension = if opts.ts_types_only { "d.ts" } else { "ts" };
+    format!("{}.{}", ts::module_name(service_name), extension)
+}
+
+/// Fails if `iface` would break the clients of the interface locked at `lock_path` or, if
+/// `interface_lock.update`, replaces the locked interface with `iface`. With JSON messages,
+/// the breaking `InterfaceDiff` is also printed to stdout.
+fn check_locked_interface(
+    iface: &oasis_rpc::Interface,
+    lock_path: &Path,
+    interface_lock: &InterfaceLock,
+    opts: &BuildOptions,
+) -> Result<()> {
+    if interface_lock.update {
+        fs::write(lock_path, iface.to_string().unwrap())
+            .map_err(|e| anyhow!("could not write `{}`: {}", lock_path.display(), e))?;
+        return Ok(());
+    }
+    let locked_iface: oasis_rpc::Interface = match fs::read(lock_path) {
+        Ok(locked_iface) => serde_json::from_slice(&locked_iface)
+            .map_err(|e| anyhow!("could not parse `{}`: {}", lock_path.display(), e))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => bail!(
+            "`{}` does not exist. Run with `--update-interface` to create it.",
+            lock_path.display()
+        ),
+        Err(e) => bail!("could not read `{}`: {}", lock_path.display(), e),
+    };
+    let diff = InterfaceDiff::between(&locked_iface, iface);
+    if diff.breaking {
+        if opts.json_messages {
+            println!("{}", serde_json::to_string(&diff)?);
+        }
+        bail!(
+            "the interface of `{}` is incompatible with `{}`:\n  {}\n\
+             Run with `--update-interface` to accept the changes.",
+            iface.name,
+            lock_path.display(),
+            diff.breaking_changes().join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+/// Returns a one-line summary of an interface, e.g. `ledger: 7 rpcs, 2 events, 1-arg constructor`.
+fn interface_summary(iface: &oasis_rpc::Interface) -> String {
+    let pluralize = |count: usize, noun: &str| {
+        format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+    };
+    let num_events = iface
+        .type_defs
+        .iter()
+        .filter(|type_def| matches!(type_def, oasis_rpc::TypeDef::Event { .. }))
+        .count();
+    format!(
+        "{}: {}, {}, {}-arg constructor",
+        iface.name,
+        pluralize(iface.functions.len(), "rpc"),
+        pluralize(num_events, "event"),
+        iface.constructor.inputs.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    const FOREIGN_SECTION: &str = "foreign";
+
+    /// Writes a module with a `name` section, Oasis metadata, and a foreign custom section.
+    fn write_test_module() -> PathBuf {
+        let mut module = walrus::Module::default();
+        module.name = Some("test_module".to_string());
+
+        let mem = module.memories.add_local(false, 1, None);
+        module.exports.add("memory", mem);
+
+        let mut func = walrus::FunctionBuilder::new(&mut module.types, &[], &[]);
+        func.name("named_func".to_string());
+        let func = func.finish(Vec::new(), &mut module.funcs);
+        module.exports.add("named_func", func);
+
+        for name in &["oasis-interface", FOREIGN_SECTION] {
+            module.customs.add(walrus::RawCustomSection {
+                name: name.to_string(),
+                data: b"data".to_vec(),
+            });
+        }
+        let wasm_path = std::env::temp_dir().join(format!("{}.wasm", uuid::Uuid::new_v4()));
+        module.emit_wasm_file(&wasm_path).unwrap();
+        wasm_path
+    }
+
+    /// Runs `prep_wasm` on the test module and returns whether the `name` section was kept
+    /// along with the names of the remaining custom sections.
+    fn prep_test_module(strip: StripMode) -> (bool, Vec<String>) {
+        let input_wasm = write_test_module();
+        let output_wasm = input_wasm.with_extension("out.wasm");
+        prep_wasm(&input_wasm, &output_wasm, &PrepWasmOptions::new(strip)).unwrap();
+        let module = walrus::Module::from_file(&output_wasm).unwrap();
+        fs::remove_file(input_wasm).ok();
+        fs::remove_file(output_wasm).ok();
+        let mut customs: Vec<_> = module
+            .customs
+            .iter()
+            .map(|(_, custom)| custom.name().to_string())
+            .collect();
+        customs.sort();
+        (module.name.is_some(), customs)
+    }
+
+    #[test]
+    fn test_strip_none() {
+        assert_eq!(
+            prep_test_module(StripMode::None),
+            (
+                true,
+                vec![
+                    FOREIGN_SECTION.to_string(),
+                    "oasis-interface".to_string(),
+                    "oasis_version".to_string()
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_strip_custom() {
+        assert_eq!(
+            prep_test_module(StripMode::Custom),
+            (
+                true,
+                vec!["oasis-interface".to_string(), "oasis_version".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn test_strip_custom_legacy_mantle() {
+        let mut module = walrus::Module::default();
+        for name in &["mantle-interface", "oasis-interface"] {
+            module.customs.add(walrus::RawCustomSection {
+                name: name.to_string(),
+                data: b"data".to_vec(),
+            });
+        }
+        let input_wasm = std::env::temp_dir().join(format!("{}.wasm", uuid::Uuid::new_v4()));
+        module.emit_wasm_file(&input_wasm).unwrap();
+
+        let output_wasm = input_wasm.with_extension("out.wasm");
+        let opts = PrepWasmOptions {
+            add_version: false,
+            ..PrepWasmOptions::new(StripMode::Custom)
+        };
+        prep_wasm(&input_wasm, &output_wasm, &opts).unwrap();
+        let module = walrus::Module::from_file(&output_wasm).unwrap();
+        fs::remove_file(input_wasm).ok();
+        fs::remove_file(output_wasm).ok();
+
+        // Sections from before the platform moved to the `oasis` prefix are not preserved.
+        let customs: Vec<_> = module.customs.iter().map(|(_, c)| c.name()).collect();
+        assert_eq!(customs, vec!["oasis-interface"]);
+    }
+
+    #[test]
+    fn test_include_sources() {
+        let sources_dir = std::env::temp_dir().join(format!("oasis-src-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(sources_dir.join("src")).unwrap();
+        fs::create_dir_all(sources_dir.join("target")).unwrap();
+        fs::write(sources_dir.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(sources_dir.join("target/service.wasm"), "").unwrap();
+
+        let input_wasm = write_test_module();
+        let output_wasm = input_wasm.with_extension("out.wasm");
+        let opts = PrepWasmOptions {
+            sources_dir: Some(sources_dir.clone()),
+            ..PrepWasmOptions::new(StripMode::Custom)
+        };
+        prep_wasm(&input_wasm, &output_wasm, &opts).unwrap();
+        let module = walrus::Module::from_file(&output_wasm).unwrap();
+        fs::remove_file(input_wasm).ok();
+        fs::remove_file(output_wasm).ok();
+        fs::remove_dir_all(sources_dir).ok();
+
+        let (_, sources) = module
+            .customs
+            .iter()
+            .find(|(_, custom)| custom.name() == "oasis_sources")
+            .expect("missing `oasis_sources` section");
+        let sources: serde_json::Value =
+            serde_json::from_slice(&sources.data(&Default::default())).unwrap();
+        assert_eq!(
+            sources,
+            serde_json::json!({
+                "sha256": {
+                    "src/main.rs":
+                        "ef32637cb9c3ec2e3968c9cbdf26a5e9c172be94f88af533e14bd43f892d5297",
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_debug_keeps_names() {
+        let input_wasm = write_test_module();
+        let output_wasm = input_wasm.with_extension("out.wasm");
+        prep_wasm(
+            &input_wasm,
+            &output_wasm,
+            &PrepWasmOptions::new(StripMode::None),
+        )
+        .unwrap();
+        let module = walrus::Module::from_file(&output_wasm).unwrap();
+        fs::remove_file(input_wasm).ok();
+        fs::remove_file(output_wasm).ok();
+
+        // The memory should have been externalized without disturbing the `name` section.
+        assert!(module.imports.iter().any(|imp| imp.name == "memory"));
+        assert_eq!(module.name.as_deref(), Some("test_module"));
+        assert!(module
+            .funcs
+            .iter()
+            .any(|func| func.name.as_deref() == Some("named_func")));
+    }
+
+    #[test]
+    fn test_prep_memoryless() {
+        let mut module = walrus::Module::default();
+        let fd_write_ty = module.types.add(&[], &[]);
+        let (fd_write, _) = module.add_import_func("wasi_unstable", "fd_write", fd_write_ty);
+        module.exports.add("memory", fd_write); // Not actually a memory.
+        let input_wasm = std::env::temp_dir().join(format!("{}.wasm", uuid::Uuid::new_v4()));
+        module.emit_wasm_file(&input_wasm).unwrap();
+
+        let output_wasm = input_wasm.with_extension("out.wasm");
+        prep_wasm(
+            &input_wasm,
+            &output_wasm,
+            &PrepWasmOptions::new(StripMode::Custom),
+        )
+        .unwrap();
+        let module = walrus::Module::from_file(&output_wasm).unwrap();
+        fs::remove_file(input_wasm).ok();
+        fs::remove_file(output_wasm).ok();
+
+        assert_eq!(module.memories.iter().count(), 0);
+        assert!(module.exports.iter().any(|exp| exp.name == "memory"));
+    }
+
+    #[test]
+    fn test_prep_extra_custom_sections() {
+        let input_wasm = std::env::temp_dir().join(format!("{}.wasm", uuid::Uuid::new_v4()));
+        walrus::Module::default()
+            .emit_wasm_file(&input_wasm)
+            .unwrap();
+
+        let output_wasm = input_wasm.with_extension("out.wasm");
+        let opts = PrepWasmOptions {
+            custom_sections: vec![("oasis-extra".to_string(), b"extra".to_vec())],
+            add_version: false,
+            ..PrepWasmOptions::new(StripMode::Custom)
+        };
+        prep_wasm(&input_wasm, &output_wasm, &opts).unwrap();
+        let module = walrus::Module::from_file(&output_wasm).unwrap();
+        fs::remove_file(input_wasm).ok();
+        fs::remove_file(output_wasm).ok();
+
+        let customs: Vec<_> = module.customs.iter().map(|(_, c)| c.name()).collect();
+        assert_eq!(customs, vec!["oasis-extra"]);
+    }
+
+    /// Runs `prep_wasm` on a module that imports `wasi_snapshot_preview1.fd_write` and
+    /// returns the module of the prepared import.
+    fn prep_wasi_module(wasi_abi: WasiAbi) -> String {
+        let mut module = walrus::Module::default();
+        let fd_write_ty = module.types.add(&[], &[]);
+        module.add_import_func("wasi_snapshot_preview1", "fd_write", fd_write_ty);
+        let input_wasm = std::env::temp_dir().join(format!("{}.wasm", uuid::Uuid::new_v4()));
+        module.emit_wasm_file(&input_wasm).unwrap();
+
+        let output_wasm = input_wasm.with_extension("out.wasm");
+        let opts = PrepWasmOptions {
+            add_version: false,
+            wasi_abi,
+            ..PrepWasmOptions::new(StripMode::Custom)
+        };
+        prep_wasm(&input_wasm, &output_wasm, &opts).unwrap();
+        let module = walrus::Module::from_file(&output_wasm).unwrap();
+        fs::remove_file(input_wasm).ok();
+        fs::remove_file(output_wasm).ok();
+
+        let fd_write = module.imports.iter().find(|imp| imp.name == "fd_write");
+        fd_write.unwrap().module.clone()
+    }
+
+    #[test]
+    fn test_wasi_abi() {
+        assert_eq!(prep_wasi_module(WasiAbi::Unstable), "wasi_unstable");
+        assert_eq!(
+            prep_wasi_module(WasiAbi::Preview1),
+            "wasi_snapshot_preview1"
+        );
+    }
+
+    #[test]
+    fn test_strip_all() {
+        assert_eq!(prep_test_module(StripMode::All), (false, Vec::new()));
+    }
+
+    #[test]
+    fn test_build_diagnostics() {
+        let diag_path = std::env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+        let diagnostics = BuildDiagnostics::new(diag_path.clone());
+        let warning = serde_json::json!({
+            "reason": "compiler-message",
+            "message": { "level": "warning", "rendered": "warning: unused variable\n" },
+        });
+        assert_eq!(
+            diagnostics.record("a", &warning.to_string()),
+            Some("warning: unused variable\n".to_string())
+        );
+        assert_eq!(
+            diagnostics.record("a", r#"{"reason":"compiler-artifact"}"#),
+            None
+        );
+        assert_eq!(diagnostics.record("a", "not json"), None);
+        diagnostics.save().unwrap();
+
+        let saved: serde_json::Value =
+            serde_json::from_slice(&fs::read(&diag_path).unwrap()).unwrap();
+        fs::remove_file(diag_path).ok();
+        assert_eq!(saved, serde_json::json!({ "a": [warning["message"]] }));
+    }
+
+    #[test]
+    fn test_interface_summary() {
+        use crate::subcommands::ifextract::fixture;
+        let field = |name: &str| fixture::field(name, oasis_rpc::Type::U64);
+        let rpc = |name: &str| fixture::rpc(name, vec![field("amount")], None);
+        let mut iface = fixture::interface();
+        iface.functions = vec![rpc("deposit")];
+        iface.constructor.inputs = vec![field("owner"), field("limit")];
+        assert_eq!(
+            interface_summary(&iface),
+            "Fixture: 1 rpc, 0 events, 2-arg constructor"
+        );
+
+        iface.functions.push(rpc("withdraw"));
+        iface.constructor.inputs.clear();
+        assert_eq!(
+            interface_summary(&iface),
+            "Fixture: 2 rpcs, 0 events, 0-arg constructor"
+        );
+    }
+}
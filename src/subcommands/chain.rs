@@ -1,49 +1,182 @@
 use std::{
+    fs,
     io::{BufRead as _, BufReader},
-    process::{Command, Stdio},
+    net::TcpListener,
+    path::Path,
+    process::{Child, Command, Stdio},
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 use colored::{Color, Colorize as _};
+use reqwest::Url;
 
-use crate::{command::Verbosity, errors::Result};
+use crate::{
+    command::Verbosity,
+    config::is_reachable,
+    errors::Result,
+    utils::{print_status, Status},
+};
+
+/// The port on which the chain serves Web3 over WebSocket unless `--eth-port` is given.
+const DEFAULT_ETH_PORT: u16 = 8546;
+
+/// The ports on which the gateway serves clients and its private API, unless the chain is
+/// started with `--eth-port 0`.
+const DEFAULT_GATEWAY_PORT: u16 = 1234;
+const DEFAULT_GATEWAY_PRIVATE_PORT: u16 = 1235;
+
+/// How long `--wait-for-ready` waits when no number of seconds is given.
+const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(30);
 
-pub struct ChainOptions {
+pub struct ChainOptions<'a> {
     pub verbosity: Verbosity,
+    /// The port on which the chain serves Web3, or 0 to pick free ones for the chain and gateway.
+    pub eth_port: u16,
+    /// Start the chain in the background and return instead of waiting for it to exit.
+    pub detach: bool,
+    /// How long to wait for the detached chain to accept connections, if at all.
+    pub wait_for_ready: Option<Duration>,
+    /// Where the detached chain and gateway write their output, if anywhere.
+    pub log_dir: Option<&'a Path>,
+    /// Args passed through to `oasis-chain`.
+    pub chain_args: Vec<&'a str>,
 }
 
-impl ChainOptions {
-    pub fn new<'a>(m: &'a clap::ArgMatches) -> Result<Self> {
+impl<'a> ChainOptions<'a> {
+    pub fn new(m: &'a clap::ArgMatches) -> Result<Self> {
         Ok(Self {
             verbosity: Verbosity::from(m.occurrences_of("verbose") as i64),
+            eth_port: match value_t!(m, "eth_port", u16) {
+                Ok(eth_port) => eth_port,
+                Err(clap::Error {
+                    kind: clap::ErrorKind::ArgumentNotFound,
+                    ..
+                }) => DEFAULT_ETH_PORT,
+                Err(err) => return Err(err.into()),
+            },
+            detach: m.is_present("detach"),
+            wait_for_ready: if m.is_present("wait_for_ready") {
+                match value_t!(m, "wait_for_ready", u64) {
+                    Ok(secs) => Some(Duration::from_secs(secs)),
+                    Err(clap::Error {
+                        kind: clap::ErrorKind::ArgumentNotFound,
+                        ..
+                    }) => Some(DEFAULT_READY_TIMEOUT),
+                    Err(err) => return Err(err.into()),
+                }
+            } else {
+                None
+            },
+            log_dir: m.value_of_os("log_dir").map(Path::new),
+            chain_args: m.values_of("chain_args").unwrap_or_default().collect(),
         })
     }
 }
 
-impl super::ExecSubcommand for ChainOptions {
+impl<'a> super::ExecSubcommand for ChainOptions<'a> {
     fn exec(self) -> Result<()> {
         run_chain(self)
     }
 }
 
-pub fn run_chain(opts: ChainOptions) -> Result<()> {
-    let gateway_args = vec![
-        "--eth.wallet.private_keys",
-        "b5144c6bda090723de712e52b92b4c758d78348ddce9aa80ca8ef51125bfb308",
+/// The ports on which the chain and gateway listen.
+#[derive(Clone, Copy, Debug)]
+struct Ports {
+    eth: u16,
+    gateway: u16,
+    gateway_private: u16,
+}
+
+impl Default for Ports {
+    fn default() -> Self {
+        Self {
+            eth: DEFAULT_ETH_PORT,
+            gateway: DEFAULT_GATEWAY_PORT,
+            gateway_private: DEFAULT_GATEWAY_PRIVATE_PORT,
+        }
+    }
+}
+
+impl Ports {
+    /// Returns ports that are free to bind, as chosen by the OS, for `--eth-port 0`. The ports
+    /// are released before they're returned, so another process could, rarely, take one first.
+    fn free() -> Result<Self> {
+        let bind = || {
+            TcpListener::bind(("127.0.0.1", 0))
+                .map_err(|e| anyhow!("could not find a free port: {}", e))
+        };
+        // The listeners are held at once so that the OS can't hand out the same port twice.
+        let listeners = [bind()?, bind()?, bind()?];
+        let port = |i: usize| -> Result<u16> { Ok(listeners[i].local_addr()?.port()) };
+        Ok(Self {
+            eth: port(0)?,
+            gateway: port(1)?,
+            gateway_private: port(2)?,
+        })
+    }
+}
+
+fn chain_args(eth_port: u16, passthrough_args: &[&str]) -> Vec<String> {
+    let mut args = vec!["--ws-port".to_string(), eth_port.to_string()];
+    args.extend(passthrough_args.iter().map(|arg| arg.to_string()));
+    args
+}
+
+fn gateway_args(ports: Ports) -> Vec<String> {
+    vec![
+        "--eth.wallet.private_keys".to_string(),
+        "b5144c6bda090723de712e52b92b4c758d78348ddce9aa80ca8ef51125bfb308".to_string(),
         //^ zeroth account, with address 0xb8b3666d8fea887d97ab54f571b8e5020c5c8b58
-        "--eth.url",
-        "ws://localhost:8546",
-        "--bind_public.max_body_bytes",
-        "1048576", // 1 MiB
-        "--bind_private.http_port",
-        "1235",
-    ];
+        "--eth.url".to_string(),
+        eth_url(ports.eth),
+        "--bind_public.http_port".to_string(),
+        ports.gateway.to_string(),
+        "--bind_public.max_body_bytes".to_string(),
+        "1048576".to_string(), // 1 MiB
+        "--bind_private.http_port".to_string(),
+        ports.gateway_private.to_string(),
+    ]
+}
+
+fn eth_url(eth_port: u16) -> String {
+    format!("ws://localhost:{}", eth_port)
+}
+
+fn gateway_url(gateway_port: u16) -> String {
+    format!("http://localhost:{}", gateway_port)
+}
+
+pub fn run_chain(opts: ChainOptions) -> Result<()> {
+    let ports = match opts.eth_port {
+        0 => Ports::free()?,
+        eth => Ports {
+            eth,
+            ..Ports::default()
+        },
+    };
+    let chain_args = chain_args(ports.eth, &opts.chain_args);
+    let gateway_args = gateway_args(ports);
+
+    // Harnesses that pass `--eth-port 0` read the URLs from here.
+    print_status(
+        Status::Starting,
+        format!("local chain at {}", eth_url(ports.eth)),
+    );
+    print_status(
+        Status::Starting,
+        format!("gateway at {}", gateway_url(ports.gateway)),
+    );
+
+    if opts.detach {
+        return run_detached(chain_args, gateway_args, ports, &opts);
+    }
 
     // crate::emit!(cmd.chain);
     match opts.verbosity {
         Verbosity::Silent | Verbosity::Quiet => unreachable!(), // no --quiet option
         Verbosity::Normal => {
-            let mut chain_subproc = Command::new("oasis-chain").spawn()?;
+            let mut chain_subproc = Command::new("oasis-chain").args(chain_args).spawn()?;
             let mut gateway_subproc = Command::new("oasis-gateway")
                 .stdout(Stdio::null())
                 .stderr(Stdio::null())
@@ -54,7 +187,7 @@ pub fn run_chain(opts: ChainOptions) -> Result<()> {
             chain_subproc.wait()?;
         }
         Verbosity::Verbose | Verbosity::High | Verbosity::Debug => {
-            let chain_handle = spawn_muxed("oasis-chain", Vec::new(), Color::Cyan);
+            let chain_handle = spawn_muxed("oasis-chain", chain_args, Color::Cyan);
             let gateway_handle = spawn_muxed("oasis-gateway", gateway_args, Color::Magenta);
             gateway_handle.join().unwrap();
             chain_handle.join().unwrap();
@@ -64,7 +197,134 @@ pub fn run_chain(opts: ChainOptions) -> Result<()> {
     Ok(())
 }
 
-fn spawn_muxed(command: &'static str, args: Vec<&'static str>, color: Color) -> JoinHandle<()> {
+/// Starts the chain and gateway as background processes that outlive `oasis`. With
+/// `--wait-for-ready`, returns only once both accept connections, and stops them if either
+/// never does.
+fn run_detached(
+    chain_args: Vec<String>,
+    gateway_args: Vec<String>,
+    ports: Ports,
+    opts: &ChainOptions,
+) -> Result<()> {
+    let (chain_stdout, chain_stderr) = detached_output(opts.log_dir, "oasis-chain")?;
+    let mut chain_subproc = Command::new("oasis-chain")
+        .args(chain_args)
+        .stdout(chain_stdout)
+        .stderr(chain_stderr)
+        .spawn()
+        .map_err(|e| anyhow!("could not start `oasis-chain`: {}", e))?;
+    let (gateway_stdout, gateway_stderr) = detached_output(opts.log_dir, "oasis-gateway")?;
+    let mut gateway_subproc = match Command::new("oasis-gateway")
+        .args(gateway_args)
+        .stdout(gateway_stdout)
+        .stderr(gateway_stderr)
+        .spawn()
+    {
+        Ok(gateway_subproc) => gateway_subproc,
+        Err(e) => {
+            chain_subproc.kill().ok();
+            bail!("could not start `oasis-gateway`: {}", e);
+        }
+    };
+
+    let timeout = match opts.wait_for_ready {
+        Some(timeout) => timeout,
+        None => return Ok(()),
+    };
+    // Both share the timeout, so that it bounds how long `oasis chain` takes in total.
+    let start = Instant::now();
+    for (name, url) in &[
+        ("local chain", eth_url(ports.eth)),
+        ("gateway", gateway_url(ports.gateway)),
+    ] {
+        let url = Url::parse(url)?;
+        if let Err(e) = wait_until_reachable(&url, start, timeout) {
+            gateway_subproc.kill().ok();
+            chain_subproc.kill().ok();
+            return Err(e);
+        }
+        print_status(Status::Ready, format!("{} at {}", name, url));
+    }
+    Ok(())
+}
+
+/// Returns the stdout and stderr of a detached `command`, which are appended to
+/// `<log_dir>/<command>.log`, or discarded if there's no `log_dir`.
+fn detached_output(log_dir: Option<&Path>, command: &str) -> Result<(Stdio, Stdio)> {
+    let log_dir = match log_dir {
+        Some(log_dir) => log_dir,
+        None => return Ok((Stdio::null(), Stdio::null())),
+    };
+    let log_path = log_dir.join(format!("{}.log", command));
+    let open_log = || -> std::io::Result<fs::File> {
+        fs::create_dir_all(log_dir)?;
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+    };
+    let log_file =
+        open_log().map_err(|e| anyhow!("could not open `{}`: {}", log_path.display(), e))?;
+    Ok((Stdio::from(log_file.try_clone()?), Stdio::from(log_file)))
+}
+
+/// Polls `url` until it accepts connections or `timeout` has elapsed since `start`.
+pub fn wait_until_reachable(url: &Url, start: Instant, timeout: Duration) -> Result<()> {
+    while !is_reachable(url) {
+        if start.elapsed() > timeout {
+            bail!(
+                "the local chain did not start listening on `{}` within {}s",
+                url,
+                timeout.as_secs()
+            );
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+    Ok(())
+}
+
+/// A local chain and gateway running in the background until dropped.
+pub struct LocalChain {
+    chain_subproc: Child,
+    gateway_subproc: Child,
+}
+
+impl LocalChain {
+    /// Starts a quiet local chain and gateway, as for `oasis chain`.
+    pub fn spawn() -> Result<Self> {
+        let mut chain_subproc = Command::new("oasis-chain")
+            .args(chain_args(DEFAULT_ETH_PORT, &[]))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| anyhow!("could not start `oasis-chain`: {}", e))?;
+        let gateway_subproc = match Command::new("oasis-gateway")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .args(gateway_args(Ports::default()))
+            .spawn()
+        {
+            Ok(gateway_subproc) => gateway_subproc,
+            Err(e) => {
+                chain_subproc.kill().ok();
+                bail!("could not start `oasis-gateway`: {}", e);
+            }
+        };
+        Ok(Self {
+            chain_subproc,
+            gateway_subproc,
+        })
+    }
+}
+
+impl Drop for LocalChain {
+    fn drop(&mut self) {
+        self.gateway_subproc.kill().ok();
+        self.chain_subproc.kill().ok();
+    }
+}
+
+fn spawn_muxed(command: &'static str, args: Vec<String>, color: Color) -> JoinHandle<()> {
     thread::spawn(move || {
         let mut subproc = Command::new(command)
             .args(&args)
@@ -4,17 +4,25 @@ use crate::{
     workspace::{ProjectKind, Workspace},
 };
 
-pub fn clean(target_strs: &[&str]) -> Result<(), crate::errors::Error> {
+pub fn clean(target_strs: &[&str], exclude_strs: &[&str]) -> Result<(), crate::errors::Error> {
     let workspace = Workspace::populate()?;
-    let targets = workspace
+    let mut targets = workspace
         .collect_targets(target_strs)?
         .into_iter()
         .filter(|t| t.is_cleanable())
         .collect::<Vec<_>>();
+    workspace.exclude_targets(&mut targets, exclude_strs)?;
     for proj in workspace.projects_of(&targets) {
         emit!(cmd.clean, { "project_type": proj.kind.name() });
         match &proj.kind {
-            ProjectKind::Wasm => std::fs::remove_file(&proj.targets[0].name)?,
+            ProjectKind::Wasm => {
+                // Only remove the prepared module; the input may itself be a `.wasm`.
+                let target = &proj.targets[0];
+                let wasm_path = target.wasm_path().unwrap();
+                if wasm_path != target.path && wasm_path.is_file() {
+                    std::fs::remove_file(&wasm_path)?;
+                }
+            }
             _ => BuildTool::for_project(proj).clean()?,
         };
     }
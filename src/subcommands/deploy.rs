@@ -1,14 +1,20 @@
-use std::{collections::BTreeMap, ffi::OsString};
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
 
 use colored::*;
+use reqwest::Url;
 
 use crate::{
-    command::{BuildTool, Verbosity},
-    config::{Config, DEFAULT_GATEWAY_URL},
+    command::{ephemeral_profile, rustc_wrapper, user_env_map, user_envs, BuildTool, Verbosity},
+    config::{parse_gateway_url, reachable_gateway, Config, Profile, DEFAULT_GATEWAY_URL},
     emit,
     errors::{ProfileError, ProfileErrorKind, Result},
-    utils::{print_status_in, Status},
-    workspace::{ProjectKind, Target, Workspace},
+    utils::{print_status_ctx, print_status_in, Status},
+    workspace::{Artifacts, ProjectKind, Target, Workspace},
 };
 
 macro_rules! print_need_deploy_key_message {
@@ -36,16 +42,37 @@ API token in and hit enter. You're ready to try your deploy again!
 
 pub struct DeployOptions<'a> {
     pub targets: Vec<&'a str>,
-    pub profile: &'a str,
+    /// Whether to deploy every target in the workspace, as selected by `--all`.
+    pub all: bool,
+    /// The name of the selected profile, or `None` if it was given by `--gateway`.
+    pub profile: Option<&'a str>,
+    /// The URL of the first reachable gateway of the selected profile.
+    pub gateway: String,
+    /// The credential given with `--gateway`, which the deployer can't otherwise look up.
+    pub credential: Option<String>,
     pub verbosity: Verbosity,
+    /// Use existing artifacts instead of building first.
+    pub no_build: bool,
+    /// Where to write a bundle of the built services, given by `--to-file`, instead of deploying.
+    pub to_file: Option<&'a Path>,
+    /// Environment variables given by `--env KEY=VALUE`.
+    pub envs: Vec<(&'a str, &'a str)>,
+    /// The `RUSTC_WRAPPER` used to build Rust services and apps (usually `oasis-build`).
+    pub rustc_wrapper: String,
     pub deployer_args: Vec<&'a str>,
 }
 
 impl<'a> DeployOptions<'a> {
     pub fn new(m: &'a clap::ArgMatches, config: &Config) -> Result<Self> {
+        if m.is_present("to_file") {
+            return Self::for_bundle(m, config);
+        }
+        if let Some(profile) = ephemeral_profile(m)? {
+            return Self::with_profile(m, config, None, &profile);
+        }
         let profile_name = m.value_of("profile").unwrap();
-        match config.profile(profile_name) {
-            Ok(_) => (),
+        let profile = match config.profile(profile_name) {
+            Ok(profile) => profile,
             Err(ProfileError {
                 kind: ProfileErrorKind::MissingKey("credential"),
                 ..
@@ -63,13 +90,72 @@ impl<'a> DeployOptions<'a> {
                 ));
             }
             Err(e) => return Err(e.into()),
+        };
+        Self::with_profile(m, config, Some(profile_name), &profile)
+    }
+
+    /// Returns the options of `--to-file`, which records the gateway in the bundle but, as the
+    /// bundle is deployed later, doesn't need a credential.
+    fn for_bundle(m: &'a clap::ArgMatches, config: &Config) -> Result<Self> {
+        if let Some(gateway) = m.value_of("gateway") {
+            if m.occurrences_of("profile") > 0 {
+                bail!("`--gateway` cannot be used with `--profile`");
+            }
+            let gateway = parse_gateway_url(gateway)
+                .map_err(|e| anyhow!("invalid gateway `{}`: {}", gateway, e))?;
+            return Self::with_gateway(m, config, None, &gateway, None);
         }
+        let profile_name = m.value_of("profile").unwrap();
+        let gateways = config.profile_gateways(profile_name)?;
+        Self::with_gateway(
+            m,
+            config,
+            Some(profile_name),
+            reachable_gateway(&gateways),
+            None,
+        )
+    }
+
+    fn with_profile(
+        m: &'a clap::ArgMatches,
+        config: &Config,
+        profile_name: Option<&'a str>,
+        profile: &Profile,
+    ) -> Result<Self> {
+        let credential = if profile_name.is_none() {
+            Some(profile.credential.to_string())
+        } else {
+            None
+        };
+        Self::with_gateway(
+            m,
+            config,
+            profile_name,
+            profile.reachable_gateway(),
+            credential,
+        )
+    }
+
+    fn with_gateway(
+        m: &'a clap::ArgMatches,
+        config: &Config,
+        profile_name: Option<&'a str>,
+        gateway: &Url,
+        credential: Option<String>,
+    ) -> Result<Self> {
         Ok(Self {
             profile: profile_name,
+            gateway: gateway.to_string(),
+            credential,
             targets: m.values_of("TARGETS").unwrap_or_default().collect(),
+            all: m.is_present("all"),
             verbosity: Verbosity::from(
                 m.occurrences_of("verbose") as i64 - m.occurrences_of("quiet") as i64,
             ),
+            no_build: m.is_present("no_build"),
+            to_file: m.value_of_os("to_file").map(Path::new),
+            envs: user_envs(m)?,
+            rustc_wrapper: rustc_wrapper(config)?,
             deployer_args: m.values_of("deployer_args").unwrap_or_default().collect(),
         })
     }
@@ -78,18 +164,101 @@ impl<'a> DeployOptions<'a> {
 impl<'a> super::ExecSubcommand for DeployOptions<'a> {
     fn exec(self) -> Result<()> {
         let workspace = Workspace::populate()?;
-        let targets = workspace.collect_targets(&self.targets)?;
-        let build_opts = super::BuildOptions {
-            targets: self.targets.clone(),
-            debug: false,
-            verbosity: self.verbosity,
-            stack_size: None,
-            wasi: false,
-            builder_args: Vec::new(),
-        };
-        super::build(&workspace, &targets, build_opts)?;
-        deploy(&targets, self)
+        let targets = workspace.select_targets(&self.targets, self.all)?;
+        // Deployed services are always built with optimizations.
+        let build_opts = super::BuildOptions::for_subcommand(
+            self.targets.clone(),
+            self.verbosity,
+            self.envs.clone(),
+            self.rustc_wrapper.clone(),
+        );
+        if self.no_build {
+            super::check_built(&workspace, &targets)?;
+        } else {
+            super::build(&workspace, &targets, build_opts)?;
+        }
+        match self.to_file {
+            Some(bundle_path) => write_bundle(&workspace, &targets, &self, bundle_path),
+            None => deploy(&targets, self),
+        }
+    }
+}
+
+/// Packages the services needed by `targets` into a gzipped tarball that can be deployed later.
+/// For each service, the bundle contains `<name>.wasm` and its interface, `<name>.json`.
+/// `manifest.json` lists the services, their constructors, and the gateway to deploy to.
+fn write_bundle(
+    workspace: &Workspace,
+    targets: &[&Target],
+    opts: &DeployOptions,
+    bundle_path: &Path,
+) -> Result<()> {
+    let services: Vec<&Target> = workspace
+        .construct_build_plan(targets)?
+        .into_iter()
+        .filter(|t| {
+            t.yields_artifact(Artifacts::SERVICE) && matches!(t.project.kind, ProjectKind::Rust)
+        })
+        .collect();
+    if services.is_empty() {
+        bail!("`--to-file` requires a service target, but none were selected");
+    }
+
+    let bundle_file = fs::File::create(bundle_path)
+        .map_err(|e| anyhow!("could not create `{}`: {}", bundle_path.display(), e))?;
+    let mut ar = tar::Builder::new(flate2::write::GzEncoder::new(
+        bundle_file,
+        flate2::Compression::default(),
+    ));
+    let mut manifest_services = Vec::with_capacity(services.len());
+    for service in services.iter() {
+        let wasm_path = service.wasm_path().unwrap();
+        let iface = crate::subcommands::ifextract::extract_interface(
+            oasis_rpc::import::ImportLocation::Path(wasm_path.clone()),
+            service.manifest_dir(),
+            false, /* refresh */
+        )?
+        .pop()
+        .unwrap();
+        let wasm_name = format!("{}.wasm", service.name);
+        let iface_name = format!("{}.json", service.name);
+        ar.append_path_with_name(&wasm_path, &wasm_name)?;
+        append_bytes(&mut ar, &iface_name, &serde_json::to_vec_pretty(&iface)?)?;
+        manifest_services.push(serde_json::json!({
+            "name": service.name,
+            "wasm": wasm_name,
+            "interface": iface_name,
+            "constructor": iface.constructor,
+        }));
     }
+    let manifest = serde_json::json!({
+        "gateway": opts.gateway,
+        "services": manifest_services,
+    });
+    append_bytes(
+        &mut ar,
+        "manifest.json",
+        &serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    ar.into_inner()?.finish()?;
+
+    emit!(cmd.deploy.bundle, { "num_services": services.len() });
+    if opts.verbosity > Verbosity::Quiet {
+        print_status_ctx(
+            Status::Bundled,
+            format!("{} services", services.len()),
+            bundle_path.display(),
+        );
+    }
+    Ok(())
+}
+
+fn append_bytes(ar: &mut tar::Builder<impl Write>, name: &str, bytes: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    ar.append_data(&mut header, name, bytes)
 }
 
 pub fn deploy(targets: &[&Target], opts: DeployOptions) -> Result<()> {
@@ -130,11 +299,20 @@ fn deploy_javascript(target: &Target, opts: &DeployOptions) -> Result<()> {
         args.extend(opts.deployer_args.iter());
     }
 
-    let mut envs = BTreeMap::new();
+    let mut envs = user_env_map(&opts.envs);
+    if let Some(profile) = opts.profile {
+        envs.insert(OsString::from("OASIS_PROFILE"), OsString::from(profile));
+    }
     envs.insert(
-        OsString::from("OASIS_PROFILE"),
-        OsString::from(&opts.profile),
+        OsString::from("OASIS_GATEWAY_URL"),
+        OsString::from(&opts.gateway),
     );
+    if let Some(credential) = &opts.credential {
+        envs.insert(
+            OsString::from("OASIS_CREDENTIAL"),
+            OsString::from(credential),
+        );
+    }
     if let Err(e) = BuildTool::for_target(target).deploy(args, envs, opts.verbosity) {
         emit!(cmd.deploy.error);
         return Err(e);
@@ -0,0 +1,11 @@
+use crate::{dirs, errors::Result};
+
+/// Prints where `oasis` looks for its files, after applying the `XDG_*` and `OASIS_*_DIR`
+/// overrides. The directories are not created.
+pub fn dirs() -> Result<()> {
+    println!("config: {}", dirs::oasis_config_dir().display());
+    println!("data:   {}", dirs::oasis_data_dir().display());
+    println!("cache:  {}", dirs::oasis_cache_dir().display());
+    println!("bin:    {}", dirs::bin_dir().display());
+    Ok(())
+}
@@ -0,0 +1,314 @@
+use std::fmt::Write as _;
+
+use oasis_rpc::import::ImportLocation;
+
+use crate::{
+    emit,
+    errors::Result,
+    workspace::{Project, Target, Workspace},
+};
+
+pub fn graph(format: &str) -> Result<()> {
+    emit!(cmd.graph, { "format": format });
+    let workspace = Workspace::populate()?;
+    let graph = Graph::of(&workspace);
+    let rendered = match format {
+        "mermaid" => graph.to_mermaid(),
+        "json" => serde_json::to_string_pretty(&graph.to_json()).unwrap(),
+        _ => graph.to_dot(),
+    };
+    println!("{}", rendered);
+    Ok(())
+}
+
+/// The dependency graph of every target in a workspace, grouped by project.
+struct Graph {
+    clusters: Vec<Cluster>,
+    edges: Vec<Edge>,
+}
+
+/// The targets of a single project, or the dependencies that are outside of the workspace.
+struct Cluster {
+    label: String,
+    kind: &'static str,
+    nodes: Vec<Node>,
+}
+
+struct Node {
+    id: String,
+    label: String,
+}
+
+struct Edge {
+    from: String,
+    to: String,
+    /// The kind of `ImportLocation` through which the dependency is imported.
+    kind: &'static str,
+}
+
+const EXTERNAL_KIND: &str = "external";
+
+impl Graph {
+    fn of(workspace: &Workspace) -> Self {
+        let targets: Vec<&Target> = workspace.targets().collect();
+        let node_id =
+            |target: &Target| format!("t{}", targets.iter().position(|t| *t == target).unwrap());
+
+        let mut clusters: Vec<Cluster> = Vec::new();
+        let mut cluster_projects: Vec<&Project> = Vec::new();
+        for &target in targets.iter() {
+            let proj = target.project;
+            let proj_dir = proj.manifest_path.parent().unwrap();
+            let label = match proj_dir.strip_prefix(workspace.root()) {
+                Ok(rel_dir) if rel_dir.as_os_str().is_empty() => ".".to_string(),
+                Ok(rel_dir) => rel_dir.display().to_string(),
+                Err(_) => proj_dir.display().to_string(),
+            };
+            let node = Node {
+                id: node_id(target),
+                label: target.name.to_string(),
+            };
+            match cluster_projects.iter().position(|p| std::ptr::eq(*p, proj)) {
+                Some(i) => clusters[i].nodes.push(node),
+                None => {
+                    cluster_projects.push(proj);
+                    clusters.push(Cluster {
+                        label,
+                        kind: proj.kind.name(),
+                        nodes: vec![node],
+                    });
+                }
+            }
+        }
+
+        let mut external = Cluster {
+            label: "external".to_string(),
+            kind: EXTERNAL_KIND,
+            nodes: Vec::new(),
+        };
+        let mut edges = Vec::new();
+        for &target in targets.iter() {
+            for (dep_name, import_loc, dep) in workspace.resolve_dependencies(target) {
+                let (kind, loc) = match import_loc {
+                    ImportLocation::Path(path) => ("path", path.display().to_string()),
+                    ImportLocation::Url(url) => ("url", url.to_string()),
+                };
+                let to = match dep {
+                    Some(dep) => node_id(dep),
+                    None => {
+                        let label = format!("{} ({})", dep_name, loc);
+                        match external.nodes.iter().find(|n| n.label == label) {
+                            Some(node) => node.id.clone(),
+                            None => {
+                                let id = format!("x{}", external.nodes.len());
+                                external.nodes.push(Node {
+                                    id: id.clone(),
+                                    label,
+                                });
+                                id
+                            }
+                        }
+                    }
+                };
+                edges.push(Edge {
+                    from: node_id(target),
+                    to,
+                    kind,
+                });
+            }
+        }
+        if !external.nodes.is_empty() {
+            clusters.push(external);
+        }
+
+        Self { clusters, edges }
+    }
+
+    fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph workspace {{").unwrap();
+        writeln!(dot, "    node [shape=box, style=filled];").unwrap();
+        for (i, cluster) in self.clusters.iter().enumerate() {
+            writeln!(dot, "    subgraph cluster_{} {{", i).unwrap();
+            writeln!(
+                dot,
+                "        label=\"{} ({})\";",
+                escape_dot(&cluster.label),
+                cluster.kind
+            )
+            .unwrap();
+            for node in cluster.nodes.iter() {
+                writeln!(
+                    dot,
+                    "        {} [label=\"{}\", fillcolor=\"{}\"];",
+                    node.id,
+                    escape_dot(&node.label),
+                    kind_color(cluster.kind)
+                )
+                .unwrap();
+            }
+            writeln!(dot, "    }}").unwrap();
+        }
+        for edge in self.edges.iter() {
+            writeln!(
+                dot,
+                "    {} -> {} [label=\"{}\"];",
+                edge.from, edge.to, edge.kind
+            )
+            .unwrap();
+        }
+        write!(dot, "}}").unwrap();
+        dot
+    }
+
+    fn to_mermaid(&self) -> String {
+        let mut mmd = String::new();
+        writeln!(mmd, "graph LR").unwrap();
+        for (i, cluster) in self.clusters.iter().enumerate() {
+            writeln!(
+                mmd,
+                "    subgraph p{} [\"{} ({})\"]",
+                i,
+                escape_mermaid(&cluster.label),
+                cluster.kind
+            )
+            .unwrap();
+            for node in cluster.nodes.iter() {
+                writeln!(
+                    mmd,
+                    "        {}[\"{}\"]",
+                    node.id,
+                    escape_mermaid(&node.label)
+                )
+                .unwrap();
+            }
+            writeln!(mmd, "    end").unwrap();
+        }
+        for edge in self.edges.iter() {
+            writeln!(mmd, "    {} -->|{}| {}", edge.from, edge.kind, edge.to).unwrap();
+        }
+        for cluster in self.clusters.iter() {
+            writeln!(
+                mmd,
+                "    classDef {} fill:{}",
+                cluster.kind,
+                kind_color(cluster.kind)
+            )
+            .unwrap();
+            let ids: Vec<&str> = cluster.nodes.iter().map(|n| n.id.as_str()).collect();
+            writeln!(mmd, "    class {} {}", ids.join(","), cluster.kind).unwrap();
+        }
+        mmd.pop(); // trailing newline
+        mmd
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "projects": self.clusters.iter().map(|cluster| serde_json::json!({
+                "name": cluster.label,
+                "kind": cluster.kind,
+                "targets": cluster.nodes.iter().map(|node| serde_json::json!({
+                    "id": node.id,
+                    "name": node.label,
+                })).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+            "dependencies": self.edges.iter().map(|edge| serde_json::json!({
+                "from": edge.from,
+                "to": edge.to,
+                "kind": edge.kind,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+fn kind_color(kind: &str) -> &'static str {
+    match kind {
+        "rust" => "#dea584",
+        "javascript" => "#f1e05a",
+        "typescript" => "#2b7489",
+        "wasm" => "#654ff0",
+        _ => "#dddddd",
+    }
+}
+
+fn escape_dot(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_mermaid(label: &str) -> String {
+    label.replace('"', "#quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_graph() -> Graph {
+        let node = |id: &str, label: &str| Node {
+            id: id.to_string(),
+            label: label.to_string(),
+        };
+        Graph {
+            clusters: vec![
+                Cluster {
+                    label: "services".to_string(),
+                    kind: "rust",
+                    nodes: vec![node("t0", "a"), node("t1", "b")],
+                },
+                Cluster {
+                    label: "external".to_string(),
+                    kind: EXTERNAL_KIND,
+                    nodes: vec![node("x0", "c (https://example.com/c.wasm)")],
+                },
+            ],
+            edges: vec![
+                Edge {
+                    from: "t0".to_string(),
+                    to: "t1".to_string(),
+                    kind: "path",
+                },
+                Edge {
+                    from: "t0".to_string(),
+                    to: "x0".to_string(),
+                    kind: "url",
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_dot() {
+        let dot = test_graph().to_dot();
+        assert!(dot.starts_with("digraph workspace {"));
+        assert!(dot.contains("label=\"services (rust)\";"));
+        assert!(dot.contains("t0 [label=\"a\", fillcolor=\"#dea584\"];"));
+        assert!(dot.contains("t0 -> t1 [label=\"path\"];"));
+        assert!(dot.contains("t0 -> x0 [label=\"url\"];"));
+        assert!(dot.ends_with('}'));
+    }
+
+    #[test]
+    fn test_mermaid() {
+        let mmd = test_graph().to_mermaid();
+        assert!(mmd.starts_with("graph LR\n"));
+        assert!(mmd.contains("subgraph p0 [\"services (rust)\"]"));
+        assert!(mmd.contains("x0[\"c (https://example.com/c.wasm)\"]"));
+        assert!(mmd.contains("t0 -->|path| t1"));
+        assert!(mmd.contains("class t0,t1 rust"));
+    }
+
+    #[test]
+    fn test_json() {
+        let json = test_graph().to_json();
+        assert_eq!(json["projects"][0]["targets"][1]["name"], "b");
+        assert_eq!(json["projects"][1]["kind"], "external");
+        assert_eq!(json["dependencies"][1]["to"], "x0");
+        assert_eq!(json["dependencies"][1]["kind"], "url");
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape_dot(r#"a "b""#), r#"a \"b\""#);
+        assert_eq!(escape_mermaid(r#"a "b""#), "a #quot;b#quot;");
+    }
+}
@@ -0,0 +1,176 @@
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+use oasis_rpc::{Interface, TypeDef};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{emit, errors::Result};
+
+use super::ifextract::{extract_interface, parse_import_location};
+
+/// Prints how the interface at `new_loc` differs from the one at `old_loc`. Each location is
+/// either an interface JSON file or anything that `oasis ifextract` accepts.
+pub fn ifdiff(old_loc: &str, new_loc: &str, json: bool) -> Result<()> {
+    emit!(cmd.ifdiff, { "json": json });
+    let diff = InterfaceDiff::between(&load_interface(old_loc)?, &load_interface(new_loc)?);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        print!("{}", diff);
+    }
+    Ok(())
+}
+
+fn load_interface(loc: &str) -> Result<Interface> {
+    let path = Path::new(loc);
+    if path
+        .extension()
+        .map(|ext| ext == "json")
+        .unwrap_or_default()
+        && path.is_file()
+    {
+        let iface_json =
+            fs::read(path).map_err(|e| anyhow!("could not read `{}`: {}", path.display(), e))?;
+        return serde_json::from_slice(&iface_json)
+            .map_err(|e| anyhow!("could not parse `{}`: {}", path.display(), e));
+    }
+    let cwd = std::env::current_dir()?;
+    let mut ifaces = extract_interface(parse_import_location(loc), &cwd, false /* refresh */)?;
+    match ifaces.len() {
+        1 => Ok(ifaces.pop().unwrap()),
+        n => bail!(
+            "`{}` contains {} interfaces, but only one can be compared",
+            loc,
+            n
+        ),
+    }
+}
+
+/// The changes from one version of an interface to the next. Added rpcs and types are
+/// compatible, while anything that was removed or changed (including an rpc that moved to
+/// another index) would break existing clients.
+#[derive(Default, Serialize)]
+pub struct InterfaceDiff {
+    pub functions: ItemChanges,
+    pub type_defs: ItemChanges,
+    pub constructor_changed: bool,
+    pub breaking: bool,
+}
+
+/// The names of the items (e.g., rpcs) that were added, removed, or changed.
+#[derive(Default, Serialize)]
+pub struct ItemChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl ItemChanges {
+    fn between(
+        old: BTreeMap<&str, serde_json::Value>,
+        new: BTreeMap<&str, serde_json::Value>,
+    ) -> Self {
+        let mut changes = Self::default();
+        for (name, old_item) in old.iter() {
+            match new.get(name) {
+                None => changes.removed.push(name.to_string()),
+                Some(new_item) if new_item != old_item => changes.changed.push(name.to_string()),
+                Some(_) => (),
+            }
+        }
+        changes.added = new
+            .keys()
+            .filter(|name| !old.contains_key(*name))
+            .map(|name| name.to_string())
+            .collect();
+        changes
+    }
+
+    fn is_breaking(&self) -> bool {
+        !self.removed.is_empty() || !self.changed.is_empty()
+    }
+}
+
+impl InterfaceDiff {
+    pub fn between(old: &Interface, new: &Interface) -> Self {
+        fn by_name<'i, T: Serialize>(
+            items: &'i [T],
+            name: impl Fn(&'i T) -> &'i str,
+        ) -> BTreeMap<&'i str, serde_json::Value> {
+            items
+                .iter()
+                .map(|item| (name(item), serde_json::to_value(item).unwrap()))
+                .collect()
+        }
+        // Clients call an rpc by its index, so an rpc that moved is as breaking as one that
+        // changed. This happens, e.g., when an rpc is inserted before existing ones.
+        fn rpcs_by_name(rpcs: &[oasis_rpc::Function]) -> BTreeMap<&str, serde_json::Value> {
+            rpcs.iter()
+                .enumerate()
+                .map(|(i, rpc)| (rpc.name.as_str(), json!({ "index": i, "rpc": rpc })))
+                .collect()
+        }
+        let type_name = |type_def: &TypeDef| match type_def {
+            TypeDef::Struct { name, .. }
+            | TypeDef::Enum { name, .. }
+            | TypeDef::Event { name, .. } => name.as_str(),
+        };
+
+        let functions =
+            ItemChanges::between(rpcs_by_name(&old.functions), rpcs_by_name(&new.functions));
+        let type_defs = ItemChanges::between(
+            by_name(&old.type_defs, type_name),
+            by_name(&new.type_defs, type_name),
+        );
+        let constructor_changed = serde_json::to_value(&old.constructor).unwrap()
+            != serde_json::to_value(&new.constructor).unwrap();
+        let breaking = functions.is_breaking() || type_defs.is_breaking() || constructor_changed;
+        Self {
+            functions,
+            type_defs,
+            constructor_changed,
+            breaking,
+        }
+    }
+
+    /// Describes each change that would break existing clients, e.g. "removed rpc `transfer`".
+    pub fn breaking_changes(&self) -> Vec<String> {
+        let mut changes = Vec::new();
+        for (kind, items) in &[("rpc", &self.functions), ("type", &self.type_defs)] {
+            changes.extend(
+                items
+                    .removed
+                    .iter()
+                    .map(|n| format!("removed {} `{}`", kind, n)),
+            );
+            changes.extend(
+                items
+                    .changed
+                    .iter()
+                    .map(|n| format!("changed {} `{}`", kind, n)),
+            );
+        }
+        if self.constructor_changed {
+            changes.push("changed the constructor".to_string());
+        }
+        changes
+    }
+}
+
+impl fmt::Display for InterfaceDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (kind, items) in &[("rpc", &self.functions), ("type", &self.type_defs)] {
+            for name in items.added.iter() {
+                writeln!(f, "added {} `{}`", kind, name)?;
+            }
+        }
+        for change in self.breaking_changes() {
+            writeln!(f, "{}", change)?;
+        }
+        if self.breaking {
+            writeln!(f, "The changes would break existing clients.")
+        } else {
+            writeln!(f, "The changes are compatible with existing clients.")
+        }
+    }
+}
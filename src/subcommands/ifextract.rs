@@ -1,45 +1,381 @@
-use std::path::Path;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, SystemTime},
+};
 
 use oasis_rpc::{
-    import::{ImportLocation, ImportedService, Importer},
+    import::{ImportLocation, Importer},
     Interface,
 };
 
-use crate::errors::Result;
+use crate::{
+    ensure_dir,
+    errors::{CliError, Error, Result},
+    oasis_xdg_dir,
+};
 
-pub fn ifextract(import_location: &str, out_dir: &std::path::Path) -> Result<()> {
-    crate::emit!(cmd.ifextract);
-    let import_location = if let Ok(url) = import_location.parse() {
-        ImportLocation::Url(url)
+/// How long an interface fetched from a URL is reused before it is fetched again.
+const IMPORT_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub fn ifextract(import_location: &str, out_dir: &Path, refresh: bool) -> Result<()> {
+    crate::emit!(cmd.ifextract, { "refresh": refresh });
+    // Resolved before importing, as an `Importer` may change the current directory.
+    let cwd = std::env::current_dir()?;
+    let out_dir = if out_dir == Path::new("-") {
+        None
     } else {
-        ImportLocation::Path(std::path::PathBuf::from(import_location))
+        let out_dir = cwd.join(out_dir);
+        Some(ensure_dir!(out_dir)?)
     };
-    for ImportedService { interface, .. } in
-        Importer::for_location(import_location, &std::env::current_dir().unwrap())?.import_all()?
-    {
-        if interface.name.contains(std::path::MAIN_SEPARATOR) {
-            return Err(anyhow!("Malformed interface name: `{}`", interface.name));
-        }
-        let iface_pretty = interface.to_string().unwrap();
-        if out_dir == std::path::Path::new("-") {
-            println!("{}", iface_pretty);
-        } else {
-            std::fs::write(
-                out_dir.join(format!("{}.json", interface.name)),
-                iface_pretty.as_bytes(),
+    let interfaces = extract_interface(parse_import_location(import_location), &cwd, refresh)?;
+    match out_dir {
+        Some(out_dir) => write_interfaces(&interfaces, &out_dir),
+        None => {
+            for interface in interfaces {
+                println!("{}", interface.to_string().unwrap());
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Writes each interface to `<out_dir>/<name>.json`. A service URL can yield many interfaces,
+/// so one that can't be written doesn't stop the others, but fails the whole once all are tried.
+fn write_interfaces(interfaces: &[Interface], out_dir: &Path) -> Result<()> {
+    let mut written = Vec::new();
+    let mut failed = Vec::new();
+    for interface in interfaces {
+        let iface_filename = format!("{}.json", interface.name);
+        let write = || -> Result<()> {
+            if interface.name.contains(std::path::MAIN_SEPARATOR) {
+                bail!("malformed interface name");
+            }
+            fs::write(
+                out_dir.join(&iface_filename),
+                interface.to_string()?.as_bytes(),
             )?;
+            Ok(())
+        };
+        match write() {
+            Ok(()) => written.push(format!("`{}`", iface_filename)),
+            Err(e) => failed.push(format!("`{}`: {}", iface_filename, e)),
         }
     }
-    Ok(())
+    if failed.is_empty() {
+        return Ok(());
+    }
+    bail!(
+        "could not write {} of {} interfaces to `{}`:\n  {}\n{}",
+        failed.len(),
+        interfaces.len(),
+        out_dir.display(),
+        failed.join("\n  "),
+        if written.is_empty() {
+            "No interfaces were written.".to_string()
+        } else {
+            format!("Wrote {}.", written.join(", "))
+        }
+    )
 }
 
+/// Parses an import location given on the command line, which is a URL or else a path.
+pub fn parse_import_location(import_location: &str) -> ImportLocation {
+    if let Ok(url) = import_location.parse() {
+        ImportLocation::Url(url)
+    } else {
+        ImportLocation::Path(PathBuf::from(import_location))
+    }
+}
+
+/// Extracts the interfaces at `import_loc`. Interfaces fetched from a URL are cached
+/// and reused until they expire, unless `refresh` is set.
 pub fn extract_interface(
     import_loc: ImportLocation,
     import_base_path: &Path,
+    refresh: bool,
+) -> Result<Vec<Interface>> {
+    match import_loc {
+        ImportLocation::Url(url) => {
+            let cache_dir = oasis_xdg_dir!(cache)?.join("imports");
+            let cache = ImportCache::new(ensure_dir!(cache_dir)?);
+            cache.get_or_import(url.as_str(), refresh, || {
+                import_remote_interfaces(ImportLocation::Url(url.clone()), import_base_path)
+            })
+        }
+        import_loc => import_interfaces(import_loc, import_base_path),
+    }
+}
+
+fn import_interfaces(
+    import_loc: ImportLocation,
+    import_base_path: &Path,
 ) -> Result<Vec<Interface>> {
-    Ok(Importer::for_location(import_loc, import_base_path)?
-        .import_all()?
-        .into_iter()
-        .map(|imported_service| imported_service.interface)
-        .collect())
+    let location = location_string(&import_loc);
+    let local_path = match &import_loc {
+        ImportLocation::Path(path) => Some(import_base_path.join(path)),
+        ImportLocation::Url(url) if url.scheme() == "file" => url.to_file_path().ok(),
+        ImportLocation::Url(_) => None,
+    };
+    if let Some(path) = &local_path {
+        if !path.exists() {
+            return Err(CliError::ImportNotFound(location).into());
+        }
+        let is_wasm_ext = path
+            .extension()
+            .map(|ext| ext == "wasm")
+            .unwrap_or_default();
+        if is_wasm_ext && !crate::workspace::is_wasm_file(path) {
+            let reason = "the file is not a Wasm module".to_string();
+            return Err(CliError::InvalidImport(location, reason).into());
+        }
+    }
+    let import = || -> Result<Vec<Interface>> {
+        Ok(Importer::for_location(import_loc, import_base_path)?
+            .import_all()?
+            .into_iter()
+            .map(|imported_service| imported_service.interface)
+            .collect())
+    };
+    import().map_err(|err| classify_import_error(location, local_path.is_some(), err))
+}
+
+fn location_string(import_loc: &ImportLocation) -> String {
+    match import_loc {
+        ImportLocation::Path(path) => path.display().to_string(),
+        ImportLocation::Url(url) => url.to_string(),
+    }
+}
+
+/// Says why the import of `location` failed, so that, e.g., a 404 and a malformed module
+/// don't look the same. A `local` module that could be read can only have been malformed.
+fn classify_import_error(location: String, local: bool, err: Error) -> Error {
+    for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return match e.status() {
+                Some(status) => CliError::ImportHttpStatus(location, status.as_u16()),
+                None => CliError::ImportUnreachable(location, e.to_string()),
+            }
+            .into();
+        }
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return CliError::ImportNotFound(location).into();
+            }
+        }
+    }
+    if local {
+        CliError::InvalidImport(location, format!("{:#}", err)).into()
+    } else {
+        CliError::ImportFailed(location, format!("{:#}", err)).into()
+    }
+}
+
+/// `Importer` makes its own requests and offers no way to set their timeout, so the import is
+/// abandoned if it outlives the configured network timeout. This is intended: the import thread
+/// is left detached rather than joined, its result is discarded when it eventually finishes,
+/// and it's torn down along with the process, which exits on the resulting error.
+fn import_remote_interfaces(
+    import_loc: ImportLocation,
+    import_base_path: &Path,
+) -> Result<Vec<Interface>> {
+    let (tx, rx) = mpsc::channel();
+    let location = location_string(&import_loc);
+    let import_base_path = import_base_path.to_path_buf();
+    std::thread::spawn(move || tx.send(import_interfaces(import_loc, &import_base_path)));
+    let timeout = crate::utils::http::timeout();
+    rx.recv_timeout(timeout).map_err(|_| {
+        let reason = format!("timed out after {}s", timeout.as_secs());
+        CliError::ImportUnreachable(location, reason)
+    })?
+}
+
+/// Stores the interfaces fetched from a URL as JSON files named by the hash of the URL.
+struct ImportCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl ImportCache {
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            ttl: IMPORT_CACHE_TTL,
+        }
+    }
+
+    fn get_or_import(
+        &self,
+        url: &str,
+        refresh: bool,
+        import: impl FnOnce() -> Result<Vec<Interface>>,
+    ) -> Result<Vec<Interface>> {
+        let cache_path = self
+            .dir
+            .join(format!("{}.json", crate::workspace::url_hash(url)));
+        if !refresh {
+            if let Some(ifaces) = self.read_fresh(&cache_path) {
+                return Ok(ifaces);
+            }
+        }
+        let ifaces = import()?;
+        // A cache that can't be written only means that the next import will be slower.
+        if let Err(e) = self.write(&cache_path, &ifaces) {
+            debug!("could not cache imports from `{}`: {}", url, e);
+        }
+        Ok(ifaces)
+    }
+
+    fn read_fresh(&self, cache_path: &Path) -> Option<Vec<Interface>> {
+        let modified = fs::metadata(cache_path).and_then(|md| md.modified()).ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if age > self.ttl {
+            return None;
+        }
+        serde_json::from_slice(&fs::read(cache_path).ok()?).ok()
+    }
+
+    fn write(&self, cache_path: &Path, ifaces: &[Interface]) -> Result<()> {
+        // Write to a temporary file so that a concurrent build never reads a partial entry.
+        let part_path = cache_path.with_extension("part");
+        fs::write(&part_path, serde_json::to_vec(ifaces)?)?;
+        fs::rename(&part_path, cache_path)?;
+        Ok(())
+    }
+}
+
+/// Builders of the interfaces that tests start from.
+#[cfg(test)]
+pub(crate) mod fixture {
+    use super::*;
+
+    /// Returns the interface of `tests/res/fixture.wasm` without its type definitions.
+    pub fn interface() -> Interface {
+        let fixture_wasm = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/res/fixture.wasm");
+        let mut iface =
+            extract_interface(ImportLocation::Path(fixture_wasm), Path::new("."), false)
+                .unwrap()
+                .pop()
+                .unwrap();
+        iface.type_defs.clear();
+        iface
+    }
+
+    pub fn field(name: &str, ty: oasis_rpc::Type) -> oasis_rpc::Field {
+        oasis_rpc::Field {
+            name: name.to_string(),
+            ty,
+        }
+    }
+
+    pub fn rpc(
+        name: &str,
+        inputs: Vec<oasis_rpc::Field>,
+        output: Option<oasis_rpc::Type>,
+    ) -> oasis_rpc::Function {
+        oasis_rpc::Function {
+            name: name.to_string(),
+            mutability: oasis_rpc::StateMutability::Mutable,
+            inputs,
+            output,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    fn test_cache(ttl: Duration) -> ImportCache {
+        let dir = std::env::temp_dir().join(format!("oasis-imports-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        ImportCache { dir, ttl }
+    }
+
+    #[test]
+    fn test_classify_import_error() {
+        let not_found = std::io::Error::from(std::io::ErrorKind::NotFound);
+        let err = classify_import_error("a.wasm".to_string(), true, not_found.into());
+        assert_eq!(err.to_string(), "could not import `a.wasm`: no such file");
+
+        let err = classify_import_error("a.wasm".to_string(), true, anyhow!("bad section"));
+        assert!(matches!(
+            err.downcast_ref::<CliError>(),
+            Some(CliError::InvalidImport(_, _))
+        ));
+
+        let url = "https://example.com/a.wasm".to_string();
+        let err = classify_import_error(url, false, anyhow!("bad section"));
+        assert!(matches!(
+            err.downcast_ref::<CliError>(),
+            Some(CliError::ImportFailed(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_write_interfaces_keeps_going() {
+        let fixture = fixture::interface();
+        let renamed = |name: &str| -> Interface {
+            let mut iface_json = serde_json::to_value(&fixture).unwrap();
+            iface_json["name"] = name.into();
+            serde_json::from_value(iface_json).unwrap()
+        };
+        let interfaces = vec![renamed("A"), renamed("B"), renamed("C")];
+
+        let out_dir = std::env::temp_dir().join(format!("oasis-ifaces-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(out_dir.join("B.json")).unwrap(); // `B.json` can't be written.
+        let err = write_interfaces(&interfaces, &out_dir)
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("could not write 1 of 3 interfaces"));
+        assert!(err.contains("`B.json`: "));
+        assert!(err.ends_with("Wrote `A.json`, `C.json`."));
+        assert!(out_dir.join("A.json").is_file());
+        assert!(out_dir.join("C.json").is_file());
+
+        fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[test]
+    fn test_cached_import() {
+        let cache = test_cache(IMPORT_CACHE_TTL);
+        let url = "https://example.com/remote.wasm";
+        let imports = Cell::new(0);
+        let import = || {
+            imports.update(|n| n + 1);
+            Ok(Vec::new())
+        };
+
+        cache.get_or_import(url, false, import).unwrap();
+        cache.get_or_import(url, false, import).unwrap();
+        assert_eq!(imports.get(), 1);
+
+        cache.get_or_import(url, true, import).unwrap();
+        assert_eq!(imports.get(), 2);
+
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn test_expired_import() {
+        let cache = test_cache(Duration::from_secs(0));
+        let url = "https://example.com/remote.wasm";
+        let imports = Cell::new(0);
+        let import = || {
+            imports.update(|n| n + 1);
+            Ok(Vec::new())
+        };
+
+        cache.get_or_import(url, false, import).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        cache.get_or_import(url, false, import).unwrap();
+        assert_eq!(imports.get(), 2);
+
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
 }
@@ -1,6 +1,7 @@
 use std::{
     fs,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
 use heck::{CamelCase, SnakeCase};
@@ -11,14 +12,26 @@ use crate::{
     emit,
     errors::{CliError, Result},
     utils::{print_status_in, Status},
+    workspace::Workspace,
 };
 
 const TEMPLATE_REPO_URL: &str = "https://github.com/oasislabs/template";
 const TEMPLATE_TGZ_BYTES: &[u8] = include_bytes!(env!("TEMPLATE_INCLUDE_PATH"));
 
+/// How many times to try cloning the template repo before using the embedded template.
+const CLONE_ATTEMPTS: u32 = 3;
+/// How long to wait after the first failed clone. Each later attempt waits proportionally longer.
+const CLONE_BACKOFF: Duration = Duration::from_millis(500);
+
 pub struct InitOptions<'a> {
     project_type: &'a str,
     dest: PathBuf,
+    /// A local directory from which to copy the project, instead of the default template.
+    template: Option<PathBuf>,
+    /// Whether the project may be created inside of an existing project.
+    nested: bool,
+    /// Whether to leave the project out of version control, rather than `git init` it.
+    no_git: bool,
     verbosity: Verbosity,
 }
 
@@ -32,6 +45,9 @@ impl<'a> InitOptions<'a> {
         Ok(Self {
             project_type,
             dest: PathBuf::from(m.value_of("NAME").unwrap_or(".")),
+            template: m.value_of("template").map(PathBuf::from),
+            nested: m.is_present("nested"),
+            no_git: m.is_present("no_git"),
             verbosity: Verbosity::from(
                 m.occurrences_of("verbose") as i64 - m.occurrences_of("quiet") as i64,
             ),
@@ -64,25 +80,37 @@ fn init_rust(opts: &InitOptions) -> Result<()> {
     if dest.exists() {
         return Err(CliError::FileAlreadyExists(dest.display().to_string()).into());
     }
+    if !opts.nested {
+        if let Some(manifest_path) = enclosing_manifest(dest) {
+            warn!(
+                "`{}` is inside of the project at `{}`, which would then contain nested projects",
+                dest.display(),
+                manifest_path.display()
+            );
+            bail!("refusing to create a nested project. Pass `--nested` to create it anyway.");
+        }
+    }
+    if let Some(template) = &opts.template {
+        check_template_dir(template)?;
+    }
     fs::create_dir_all(dest)?;
 
-    match clone_template_repo(dest) {
-        Ok(_) => {
-            emit!(cmd.init, { "type": "rust", "source": "repo" });
-        }
-        Err(err) => {
-            emit!(cmd.init, { "type": "rust", "source": "tgz", "repo_err": err.to_string() });
-            debug!("Could not clone template repo: {}", err);
-            unpack_template_tgz(dest)
-                .map_err(|err| anyhow!("Could not unpack template archive: {}", err))?;
+    match &opts.template {
+        Some(template) => {
+            copy_template_dir(template, dest)?;
+            emit!(cmd.init, { "type": "rust", "source": "local" });
         }
+        None => init_from_default_template(dest)?,
     }
-    match cmd!("git", "rev-parse", "--git-dir") {
-        Ok(_) => {
-            fs::remove_dir_all(dest.join(".github")).ok();
-        }
-        Err(_) => {
-            cmd!("git", "init", dest)?;
+    if !opts.no_git {
+        // A project added to an existing repo is left to that repo's git and CI config.
+        match cmd!(in dest, "git", "rev-parse", "--git-dir") {
+            Ok(_) => {
+                fs::remove_dir_all(dest.join(".github")).ok();
+            }
+            Err(_) => {
+                cmd!("git", "init", dest)?;
+            }
         }
     }
 
@@ -99,37 +127,178 @@ fn init_rust(opts: &InitOptions) -> Result<()> {
     Ok(())
 }
 
-fn clone_template_repo(dest: &Path) -> Result<()> {
-    let dest = dest.canonicalize()?;
-    cmd!("git", "clone", TEMPLATE_REPO_URL, &dest)?;
-    let orig_dir = std::env::current_dir()?;
-    std::env::set_current_dir(&dest)?;
-    let do_clone = || {
-        let version_req = semver::VersionReq::parse(env!("TEMPLATE_VER")).unwrap();
-        let tags_str = String::from_utf8(cmd!("git", "tag", "-l", "v*.*.*")?.stdout).unwrap();
-        let best_tag = tags_str
-            .trim()
-            .split('\n')
-            .filter_map(|t| {
-                let ver = semver::Version::parse(&t[1..]).expect(t);
-                if version_req.matches(&ver) {
-                    Some((ver, t))
-                } else {
-                    None
+/// Populates `dest` from the template repo or, if it can't be cloned, the embedded template.
+fn init_from_default_template(dest: &Path) -> Result<()> {
+    match clone_template_repo(dest) {
+        Ok(_) => {
+            emit!(cmd.init, { "type": "rust", "source": "repo" });
+        }
+        Err(err) => {
+            emit!(cmd.init, { "type": "rust", "source": "tgz", "repo_err": err.to_string() });
+            match err {
+                CloneError::Network(err) => {
+                    debug!(
+                        "Could not clone template repo, so using the embedded template: {}",
+                        err
+                    )
                 }
-            })
-            .max()
-            .unwrap()
-            .1;
+                CloneError::Unexpected(err) => {
+                    warn!(
+                        "the template repo is not as expected, so using the embedded template: {}",
+                        err
+                    );
+                    // Don't let the partial checkout mingle with the embedded template.
+                    fs::remove_dir_all(dest)?;
+                    fs::create_dir_all(dest)?;
+                }
+            }
+            unpack_template_tgz(dest)
+                .map_err(|err| anyhow!("Could not unpack template archive: {}", err))?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the manifest of the existing project, if any, that would contain `dest`.
+/// The search stops at the root of the workspace that would contain `dest`.
+fn enclosing_manifest(dest: &Path) -> Option<PathBuf> {
+    let dest = std::env::current_dir().ok()?.join(dest);
+    // `dest` doesn't exist yet, so canonicalize the nearest ancestor that does.
+    let existing_dir = dest
+        .ancestors()
+        .find(|dir| dir.is_dir())?
+        .canonicalize()
+        .ok()?;
+    let workspace_root = Workspace::find_root(&existing_dir)
+        .ok()?
+        .canonicalize()
+        .ok()?;
+    existing_dir
+        .ancestors()
+        .take_while(|dir| dir.starts_with(&workspace_root))
+        .flat_map(|dir| {
+            ["Cargo.toml", "package.json"]
+                .iter()
+                .map(move |manifest_name| dir.join(manifest_name))
+        })
+        .find(|manifest_path| manifest_path.is_file())
+}
+
+fn check_template_dir(template: &Path) -> Result<()> {
+    if !template.is_dir() {
+        bail!("the template `{}` is not a directory", template.display());
+    }
+    if !template.join("Cargo.toml").is_file() && !template.join("package.json").is_file() {
+        bail!(
+            "the template `{}` contains neither a `Cargo.toml` nor a `package.json`",
+            template.display()
+        );
+    }
+    Ok(())
+}
+
+/// Recursively copies the contents of `template` into `dest`, except for build and VCS state.
+fn copy_template_dir(template: &Path, dest: &Path) -> Result<()> {
+    let walker = walkdir::WalkDir::new(template)
+        .into_iter()
+        .filter_entry(|f| f.depth() == 0 || !is_generated_dir(f));
+    for f in walker {
+        let f = f?;
+        let dest_path = dest.join(f.path().strip_prefix(template).unwrap());
+        if f.file_type().is_dir() {
+            fs::create_dir_all(&dest_path)?;
+        } else {
+            fs::copy(f.path(), &dest_path).map_err(|e| {
+                anyhow!(
+                    "could not copy `{}` from template: {}",
+                    f.path().display(),
+                    e
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether `f` is a directory that belongs to a checkout or build of a project,
+/// rather than to the project itself.
+fn is_generated_dir(f: &walkdir::DirEntry) -> bool {
+    f.file_type().is_dir() && (f.file_name() == ".git" || f.file_name() == "target")
+}
+
+/// Why the template repo could not be used, which determines how loudly to fall back to the
+/// embedded template.
+#[derive(thiserror::Error, Debug)]
+enum CloneError {
+    /// The repo could not be fetched, as is expected when offline.
+    #[error("{0}")]
+    Network(anyhow::Error),
+
+    /// The repo was fetched but could not be checked out, which is a bug in the template.
+    #[error("{0}")]
+    Unexpected(anyhow::Error),
+}
+
+fn clone_template_repo(dest: &Path) -> Result<(), CloneError> {
+    let dest = dest
+        .canonicalize()
+        .map_err(|e| CloneError::Unexpected(e.into()))?;
+    clone_with_retry(&dest).map_err(CloneError::Network)?;
+    let orig_dir = std::env::current_dir().map_err(|e| CloneError::Unexpected(e.into()))?;
+    std::env::set_current_dir(&dest).map_err(|e| CloneError::Unexpected(e.into()))?;
+    let checkout = || -> Result<()> {
+        let version_req = semver::VersionReq::parse(env!("TEMPLATE_VER")).unwrap();
+        let tags_str = String::from_utf8(cmd!("git", "tag", "-l", "v*.*.*")?.stdout)?;
+        let best_tag = best_template_tag(&tags_str, &version_req).ok_or_else(|| {
+            anyhow!(
+                "no tag matches the template version requirement `{}`",
+                version_req
+            )
+        })?;
         cmd!("git", "reset", "--hard", best_tag)?;
         std::fs::remove_dir_all(dest.join(".git"))?;
         Ok(())
     };
-    let result = do_clone();
-    std::env::set_current_dir(orig_dir)?;
+    let result = checkout().map_err(CloneError::Unexpected);
+    std::env::set_current_dir(orig_dir).map_err(|e| CloneError::Unexpected(e.into()))?;
     result
 }
 
+fn clone_with_retry(dest: &Path) -> Result<()> {
+    let mut attempt = 1;
+    loop {
+        match cmd!("git", "clone", TEMPLATE_REPO_URL, dest) {
+            Ok(_) => return Ok(()),
+            Err(err) if attempt < CLONE_ATTEMPTS => {
+                debug!(
+                    "could not clone template repo (attempt {} of {}): {}",
+                    attempt, CLONE_ATTEMPTS, err
+                );
+                std::thread::sleep(CLONE_BACKOFF * attempt);
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Returns the newest `vX.Y.Z` tag in the newline-separated `tags` that satisfies `version_req`.
+fn best_template_tag<'a>(tags: &'a str, version_req: &semver::VersionReq) -> Option<&'a str> {
+    tags.lines()
+        .map(str::trim)
+        .filter(|tag| tag.starts_with('v'))
+        .filter_map(|tag| {
+            let ver = semver::Version::parse(&tag[1..]).ok()?;
+            if version_req.matches(&ver) {
+                Some((ver, tag))
+            } else {
+                None
+            }
+        })
+        .max()
+        .map(|(_, tag)| tag)
+}
+
 fn unpack_template_tgz(dest: &Path) -> Result<()> {
     let mut ar = tar::Archive::new(flate2::read::GzDecoder::new(TEMPLATE_TGZ_BYTES));
     for entry in ar.entries()? {
@@ -139,21 +308,72 @@ fn unpack_template_tgz(dest: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Replaces the template's name with `project_name` in each of the project's text files.
+/// Binary files (e.g., images) and the contents of `.git` and `target` are left untouched.
 fn rename_project(dir: &Path, project_name: &str) -> Result<()> {
     let project_name = project_name.to_snake_case();
     let service_name = project_name.to_camel_case();
-    for f in walkdir::WalkDir::new(dir).into_iter() {
+    let walker = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|f| f.depth() == 0 || !is_generated_dir(f));
+    for f in walker {
         let f = f?;
         if !f.file_type().is_file() {
             continue;
         }
         let p = f.path();
-        std::fs::write(
-            p,
-            std::fs::read_to_string(p)?
-                .replace("quickstart", &project_name)
-                .replace("Quickstart", &service_name),
-        )?;
+        let text = match String::from_utf8(std::fs::read(p)?) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        let renamed = text
+            .replace("quickstart", &project_name)
+            .replace("Quickstart", &service_name);
+        if renamed != text {
+            std::fs::write(p, renamed)?;
+        }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rename_project() {
+        let dir = std::env::temp_dir().join(format!("oasis-init-{}", uuid::Uuid::new_v4()));
+        for subdir in &["src", ".git", "target"] {
+            fs::create_dir_all(dir.join(subdir)).unwrap();
+        }
+        fs::write(dir.join("src/main.rs"), "struct Quickstart; // quickstart").unwrap();
+        fs::write(dir.join(".git/description"), "quickstart").unwrap();
+        fs::write(dir.join("target/quickstart.d"), "quickstart").unwrap();
+        let binary = b"\0asm\xff\xfequickstart".to_vec();
+        fs::write(dir.join("logo.png"), &binary).unwrap();
+
+        rename_project(&dir, "my_project").unwrap();
+
+        let read = |path: &str| fs::read(dir.join(path)).unwrap();
+        assert_eq!(read("src/main.rs"), b"struct MyProject; // my_project");
+        assert_eq!(read(".git/description"), b"quickstart");
+        assert_eq!(read("target/quickstart.d"), b"quickstart");
+        assert_eq!(read("logo.png"), binary);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_best_template_tag() {
+        let version_req = semver::VersionReq::parse("^0.2").unwrap();
+        let tags = "v0.1.9\nv0.2.0\nv0.2.10\nv0.2.9\nv0.3.0\nvnext\n";
+        assert_eq!(best_template_tag(tags, &version_req), Some("v0.2.10"));
+    }
+
+    #[test]
+    fn test_no_matching_template_tag() {
+        let version_req = semver::VersionReq::parse("^0.2").unwrap();
+        assert_eq!(best_template_tag("v0.1.0\nv0.3.0\n", &version_req), None);
+        assert_eq!(best_template_tag("", &version_req), None);
+    }
+}
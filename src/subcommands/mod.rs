@@ -1,21 +1,37 @@
+mod bench;
 mod build;
 mod chain;
 mod clean;
 mod deploy;
+mod dirs;
+mod graph;
+mod ifdiff;
 mod ifextract;
 mod init;
+mod run;
+mod size;
 mod test;
 pub mod toolchain;
+mod verify;
+mod whoami;
 
 use crate::errors::Error;
 
-pub use build::{build, BuildOptions};
+pub use bench::{bench, BenchOptions};
+pub use build::{build, check_built, BuildOptions, StripMode, WasiAbi, DEFAULT_TARGET_TRIPLE};
 pub use chain::{run_chain, ChainOptions};
 pub use clean::clean;
 pub use deploy::{deploy, DeployOptions};
+pub use dirs::dirs;
+pub use graph::graph;
+pub use ifdiff::ifdiff;
 pub use ifextract::ifextract;
 pub use init::{init, InitOptions};
+pub use run::{run, RunOptions};
+pub use size::size;
 pub use test::{test, TestOptions};
+pub use verify::verify;
+pub use whoami::whoami;
 
 pub trait ExecSubcommand {
     fn exec(self) -> Result<(), Error>;
@@ -0,0 +1,309 @@
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::PathBuf,
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use oasis_rpc::{import::ImportLocation, Interface};
+use reqwest::Url;
+
+use crate::{
+    command::{rustc_wrapper, user_env_map, user_envs, Verbosity},
+    config::{is_reachable, Config, Credential},
+    emit,
+    errors::{CliError, Result},
+    gen::typescript as ts,
+    utils::{print_status, Status},
+    workspace::{Artifacts, ProjectKind, Target, Workspace},
+};
+
+use super::chain::{wait_until_reachable, LocalChain};
+
+/// How long to wait for a local chain started by `oasis run` to accept connections.
+const LOCAL_CHAIN_STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct RunOptions<'a> {
+    pub service: &'a str,
+    /// The RPC to call once the service is deployed, if any.
+    pub rpc: Option<&'a str>,
+    /// The JSON-encoded arguments of `rpc`, in order.
+    pub args: Vec<&'a str>,
+    /// The JSON-encoded arguments of the service's constructor, in order.
+    pub ctor_args: Vec<&'a str>,
+    /// The first reachable gateway of the selected profile.
+    pub gateway: Url,
+    pub credential: Credential,
+    pub verbosity: Verbosity,
+    /// Environment variables given by `--env KEY=VALUE`.
+    pub envs: Vec<(&'a str, &'a str)>,
+    /// The `RUSTC_WRAPPER` used to build Rust services (usually `oasis-build`).
+    pub rustc_wrapper: String,
+}
+
+impl<'a> RunOptions<'a> {
+    pub fn new(m: &'a clap::ArgMatches, config: &Config) -> Result<Self> {
+        let profile = config.profile(m.value_of("profile").unwrap())?;
+        Ok(Self {
+            service: m.value_of("SERVICE").unwrap(),
+            rpc: m.value_of("RPC"),
+            args: m.values_of("ARGS").unwrap_or_default().collect(),
+            ctor_args: m.values_of("ctor_args").unwrap_or_default().collect(),
+            gateway: profile.reachable_gateway().clone(),
+            credential: profile.credential,
+            verbosity: Verbosity::from(
+                m.occurrences_of("verbose") as i64 - m.occurrences_of("quiet") as i64,
+            ),
+            envs: user_envs(m)?,
+            rustc_wrapper: rustc_wrapper(config)?,
+        })
+    }
+}
+
+impl<'a> super::ExecSubcommand for RunOptions<'a> {
+    fn exec(self) -> Result<()> {
+        let workspace = Workspace::populate()?;
+        let services: Vec<&Target> = workspace
+            .collect_targets(&[self.service])?
+            .into_iter()
+            .filter(|t| {
+                t.yields_artifact(Artifacts::SERVICE) && matches!(t.project.kind, ProjectKind::Rust)
+            })
+            .collect();
+        let service = match services.as_slice() {
+            [service] => *service,
+            [] => bail!("`{}` is not a Rust service", self.service),
+            _ => bail!("`{}` refers to more than one service", self.service),
+        };
+        // The gateway limits the size of the deployed service, so it is optimized.
+        let build_opts = super::BuildOptions::for_subcommand(
+            vec![self.service],
+            self.verbosity,
+            self.envs.clone(),
+            self.rustc_wrapper.clone(),
+        );
+        super::build(&workspace, &[service], build_opts)?;
+        run(&workspace, service, self)
+    }
+}
+
+/// Deploys the built `service` and calls the selected RPC using the generated TypeScript
+/// client, which knows how to encode the arguments and decode the output.
+pub fn run(workspace: &Workspace, service: &Target, opts: RunOptions) -> Result<()> {
+    emit!(cmd.run, { "rpc": opts.rpc.is_some(), "num_args": opts.args.len() });
+
+    let iface = super::ifextract::extract_interface(
+        ImportLocation::Path(service.wasm_path().unwrap()),
+        service.manifest_dir(),
+        false, /* refresh */
+    )?
+    .pop()
+    .unwrap();
+    let ctor_args = named_args(
+        &iface,
+        &iface.constructor.inputs,
+        &opts.ctor_args,
+        "the constructor",
+    )?;
+    let rpc_call = match opts.rpc {
+        Some(rpc_name) => {
+            let rpc = match iface.functions.iter().find(|f| f.name == rpc_name) {
+                Some(rpc) => rpc,
+                None => bail!("`{}` has no rpc named `{}`", service.name, rpc_name),
+            };
+            let what = format!("`{}`", rpc_name);
+            Some((
+                rpc_name,
+                named_args(&iface, &rpc.inputs, &opts.args, &what)?,
+            ))
+        }
+        None if !opts.args.is_empty() => bail!("arguments were given, but no rpc to call"),
+        None => None,
+    };
+
+    let _local_chain = ensure_gateway(&opts.gateway, opts.verbosity)?;
+
+    let client_dir = service.artifacts_dir();
+    let script_path = client_dir.join(format!(".{}.run.ts", ts::module_name(&iface.name)));
+    fs::write(
+        &script_path,
+        runner_script(&iface, &opts.credential, ctor_args, rpc_call),
+    )?;
+    if opts.verbosity > Verbosity::Quiet {
+        print_status(Status::Deploying, &service.name);
+    }
+    let status = Command::new("npx")
+        .args(&["ts-node", "--transpile-only"])
+        .arg(&script_path)
+        .current_dir(&client_dir)
+        .envs(user_env_map(&opts.envs))
+        .env("NODE_PATH", node_path(workspace)?)
+        .env("OASIS_GATEWAY_URL", opts.gateway.as_str())
+        .env("OASIS_CREDENTIAL", opts.credential.to_string())
+        .status();
+    fs::remove_file(&script_path).ok();
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => {
+            Err(CliError::ProcessExit("ts-node".to_string(), status.code().unwrap_or(1)).into())
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Err(CliError::ExecNotFound("npx".to_string()).into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns the JSON object of named arguments that the generated client expects, given the
+/// JSON-encoded positional `args` of a constructor or RPC. The arguments are checked against
+/// the types of `inputs` so that mistakes are reported before anything is deployed.
+fn named_args(
+    iface: &Interface,
+    inputs: &[oasis_rpc::Field],
+    args: &[&str],
+    what: &str,
+) -> Result<Option<String>> {
+    if inputs.is_empty() && !args.is_empty() {
+        bail!("{} takes no arguments, but {} were given", what, args.len());
+    }
+    if args.len() != inputs.len() {
+        bail!(
+            "{} takes {} argument(s) ({}), but {} were given",
+            what,
+            inputs.len(),
+            inputs
+                .iter()
+                .map(|inp| inp.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            args.len()
+        );
+    }
+    if inputs.is_empty() {
+        return Ok(None);
+    }
+    let mut named_args = serde_json::Map::new();
+    for (input, arg) in inputs.iter().zip(args.iter()) {
+        let value = serde_json::from_str(arg)
+            .map_err(|e| anyhow!("`{}` of {} is not valid JSON: {}", input.name, what, e))?;
+        crate::abi::encode_args(
+            &iface.type_defs,
+            std::slice::from_ref(&input.ty),
+            std::slice::from_ref(&value),
+        )
+        .map_err(|e| anyhow!("`{}` of {} is invalid: {}", input.name, what, e))?;
+        named_args.insert(ts::var_name(&input.name), value);
+    }
+    Ok(Some(serde_json::Value::Object(named_args).to_string()))
+}
+
+/// Starts a local chain if `gateway` is a local gateway that isn't yet running. The chain
+/// is stopped when the returned `LocalChain` is dropped.
+fn ensure_gateway(gateway: &Url, verbosity: Verbosity) -> Result<Option<LocalChain>> {
+    if is_reachable(gateway) {
+        return Ok(None);
+    }
+    if !matches!(gateway.host_str(), Some("localhost") | Some("127.0.0.1")) {
+        bail!("the gateway at `{}` is unreachable", gateway);
+    }
+    if verbosity > Verbosity::Quiet {
+        print_status(Status::Starting, "local chain");
+    }
+    let local_chain = LocalChain::spawn()?;
+    wait_until_reachable(gateway, Instant::now(), LOCAL_CHAIN_STARTUP_TIMEOUT)?;
+    Ok(Some(local_chain))
+}
+
+/// Lets the runner script, which lives with the generated clients, import `oasis-std` from
+/// the `node_modules` of any JavaScript project in the workspace.
+fn node_path(workspace: &Workspace) -> Result<OsString> {
+    let mut node_modules_dirs: Vec<PathBuf> = Vec::new();
+    for target in workspace.targets() {
+        let proj = target.project;
+        if !matches!(
+            proj.kind,
+            ProjectKind::JavaScript { .. } | ProjectKind::TypeScript { .. }
+        ) {
+            continue;
+        }
+        let node_modules_dir = proj.manifest_path.with_file_name("node_modules");
+        if node_modules_dir.is_dir() && !node_modules_dirs.contains(&node_modules_dir) {
+            node_modules_dirs.push(node_modules_dir);
+        }
+    }
+    if let Some(node_path) = std::env::var_os("NODE_PATH") {
+        node_modules_dirs.extend(std::env::split_paths(&node_path));
+    }
+    Ok(std::env::join_paths(node_modules_dirs)?)
+}
+
+/// Generates a script that deploys the service and calls `rpc_call`, as `(name, args)`,
+/// printing the output as JSON. The gateway and credential are read from the environment
+/// so that the credential is never written to disk.
+fn runner_script(
+    iface: &Interface,
+    credential: &Credential,
+    ctor_args: Option<String>,
+    rpc_call: Option<(&str, Option<String>)>,
+) -> String {
+    let class_name = ts::class_name(&iface.name);
+    let connect = match credential {
+        Credential::ApiToken(_) => "new oasis.gateways.Gateway(url, credential)",
+        Credential::Mnemonic(_) => {
+            "new oasis.gateways.Web3Gateway(url, oasis.Wallet.fromMnemonic(credential))"
+        }
+        Credential::PrivateKey(_) => {
+            "new oasis.gateways.Web3Gateway(url, new oasis.Wallet(credential))"
+        }
+    };
+    let deploy_args = ctor_args
+        .map(|args| format!(", {}", args))
+        .unwrap_or_default();
+    let call = match rpc_call {
+        Some((rpc_name, args)) => format!(
+            "const output = await service.{}({});\n    \
+             console.log(JSON.stringify(output, toJson, 2));",
+            ts::var_name(rpc_name),
+            args.unwrap_or_default()
+        ),
+        None => String::new(),
+    };
+    format!(
+        r#"import * as oasis from "oasis-std";
+import {{ {class_name} }} from "./{module_name}";
+
+function toJson(_key: string, value: any): any {{
+    if (typeof value === "bigint") {{
+        return value.toString();
+    }}
+    if (value instanceof Uint8Array) {{
+        return Buffer.from(value).toString("hex");
+    }}
+    return value;
+}}
+
+async function main() {{
+    const url = process.env.OASIS_GATEWAY_URL!;
+    const credential = process.env.OASIS_CREDENTIAL!;
+    const gateway = {connect};
+    const service = await {class_name}.deploy(gateway{deploy_args});
+    console.error(`deployed {service_name} at ${{service.address.hex}}`);
+    {call}
+}}
+
+main()
+    .then(() => process.exit(0))
+    .catch(err => {{
+        console.error(err);
+        process.exit(1);
+    }});
+"#,
+        class_name = class_name,
+        module_name = ts::module_name(&iface.name),
+        connect = connect,
+        deploy_args = deploy_args,
+        service_name = iface.name,
+        call = call,
+    )
+}
@@ -0,0 +1,143 @@
+use std::{fs, io::Read as _};
+
+use crate::errors::Result;
+
+/// Prints a breakdown of the sections, imports, and exports of a (possibly stdin) Wasm module.
+pub fn size(wasm_path: &str) -> Result<()> {
+    crate::emit!(cmd.size);
+    let bytecode = read_wasm(wasm_path)?;
+
+    let sections = section_sizes(&bytecode)?;
+    let module = walrus::Module::from_buffer(&bytecode)?;
+
+    println!("{}: {} bytes", wasm_path, bytecode.len());
+    let name_width = sections
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or_default();
+    for (name, size) in sections.iter() {
+        println!("  {: <width$}  {: >10}", name, size, width = name_width);
+    }
+
+    println!("imports: {}", module.imports.iter().count());
+    println!("exports: {}", module.exports.iter().count());
+
+    let mem_is_external = module
+        .imports
+        .iter()
+        .any(|imp| matches!(imp.kind, walrus::ImportKind::Memory(_)));
+    println!(
+        "memory: {}",
+        if mem_is_external {
+            "externalized"
+        } else {
+            "internal"
+        }
+    );
+
+    let mut wasi_modules: Vec<_> = module
+        .imports
+        .iter()
+        .map(|imp| imp.module.as_str())
+        .filter(|module| module.starts_with("wasi"))
+        .collect();
+    wasi_modules.sort();
+    wasi_modules.dedup();
+    println!(
+        "wasi imports: {}",
+        if wasi_modules.is_empty() {
+            "none".to_string()
+        } else {
+            wasi_modules.join(", ")
+        }
+    );
+    if wasi_modules.iter().any(|&module| module != "wasi_unstable") {
+        warn!("wasi imports should come from `wasi_unstable`. Was this built by `oasis build`?");
+    }
+
+    Ok(())
+}
+
+/// Reads the Wasm module at `wasm_path` or, if it's `-`, from stdin.
+pub fn read_wasm(wasm_path: &str) -> Result<Vec<u8>> {
+    if wasm_path == "-" {
+        let mut bytecode = Vec::new();
+        std::io::stdin().read_to_end(&mut bytecode)?;
+        Ok(bytecode)
+    } else {
+        fs::read(wasm_path).map_err(|e| anyhow!("could not read `{}`: {}", wasm_path, e))
+    }
+}
+
+/// Returns the name and size, in bytes, of each section in the module, in order of appearance.
+/// The size includes the section's header.
+fn section_sizes(bytecode: &[u8]) -> Result<Vec<(String, usize)>> {
+    use wasmparser::SectionCode;
+    let parse_err =
+        |err: wasmparser::BinaryReaderError| anyhow!("{} at offset {}", err.message, err.offset);
+    let mut reader = wasmparser::ModuleReader::new(bytecode).map_err(parse_err)?;
+    let mut sections = Vec::new();
+    let mut section_start = reader.current_position();
+    while !reader.eof() {
+        let section = reader.read().map_err(parse_err)?;
+        let name = match section.code {
+            SectionCode::Custom { name, .. } => format!("custom `{}`", name),
+            SectionCode::Type => "type".to_string(),
+            SectionCode::Import => "import".to_string(),
+            SectionCode::Function => "function".to_string(),
+            SectionCode::Table => "table".to_string(),
+            SectionCode::Memory => "memory".to_string(),
+            SectionCode::Global => "global".to_string(),
+            SectionCode::Export => "export".to_string(),
+            SectionCode::Start => "start".to_string(),
+            SectionCode::Element => "element".to_string(),
+            SectionCode::Code => "code".to_string(),
+            SectionCode::Data => "data".to_string(),
+            SectionCode::DataCount => "datacount".to_string(),
+        };
+        let section_end = section.range().end;
+        sections.push((name, section_end - section_start));
+        section_start = section_end;
+    }
+    Ok(sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_section_sizes() {
+        let mut module = walrus::Module::default();
+        let mem = module.memories.add_local(false, 1, None);
+        module.exports.add("memory", mem);
+        module.customs.add(walrus::RawCustomSection {
+            name: "oasis-interface".to_string(),
+            data: vec![0; 200],
+        });
+        let bytecode = module.emit_wasm();
+
+        let sections = section_sizes(&bytecode).unwrap();
+        assert_eq!(
+            sections.iter().map(|(_, size)| size).sum::<usize>() + 8,
+            bytecode.len()
+        );
+        let (_, custom_size) = sections
+            .iter()
+            .find(|(name, _)| name == "custom `oasis-interface`")
+            .unwrap();
+        // id + 2-byte LEB128 length + name length + name + data
+        assert_eq!(*custom_size, 1 + 2 + 1 + "oasis-interface".len() + 200);
+        assert!(sections.iter().any(|(name, _)| name == "memory"));
+        assert!(sections.iter().any(|(name, _)| name == "export"));
+    }
+
+    #[test]
+    fn test_section_sizes_truncated() {
+        let mut bytecode = walrus::Module::default().emit_wasm();
+        bytecode.extend_from_slice(&[0, 100, 4]);
+        assert!(section_sizes(&bytecode).is_err());
+        assert!(section_sizes(b"not wasm").is_err());
+    }
+}
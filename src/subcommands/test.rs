@@ -1,7 +1,11 @@
-use std::{collections::BTreeMap, ffi::OsString};
+use std::{collections::BTreeMap, ffi::OsString, path::PathBuf, time::Duration};
 
 use crate::{
-    command::{BuildTool, Verbosity},
+    cmd,
+    command::{
+        ephemeral_profile, rustc_wrapper, user_env_map, user_envs, BuildTool, FeatureOptions,
+        ManifestOptions, Verbosity,
+    },
     config::Config,
     emit,
     errors::Result,
@@ -11,25 +15,72 @@ use crate::{
 
 pub struct TestOptions<'a> {
     pub targets: Vec<&'a str>,
+    /// Whether to test every target in the workspace, as selected by `--all`.
+    pub all: bool,
+    /// The targets given by `--exclude`, which are only built if others depend on them.
+    pub exclude: Vec<&'a str>,
     pub release: bool,
-    pub profile: &'a str,
+    /// The name of the selected profile, or `None` if it was given by `--gateway`.
+    pub profile: Option<&'a str>,
+    /// The URL of the first reachable gateway of the selected profile.
+    pub gateway: String,
+    /// The credential given with `--gateway`, which the tests can't otherwise look up.
+    pub credential: Option<String>,
     pub verbosity: Verbosity,
+    pub manifest_opts: ManifestOptions,
+    /// The Cargo features with which Rust services and apps are built and tested.
+    pub features: FeatureOptions<'a>,
+    /// Use existing artifacts instead of building first.
+    pub no_build: bool,
+    /// How long each target's tests may run before they're killed.
+    pub timeout: Option<Duration>,
+    /// Only test the targets affected by changes since this git ref.
+    pub changed_since: Option<&'a str>,
+    /// Environment variables given by `--env KEY=VALUE`.
+    pub envs: Vec<(&'a str, &'a str)>,
+    /// The `RUSTC_WRAPPER` used to build Rust services and apps (usually `oasis-build`).
+    pub rustc_wrapper: String,
     pub tester_args: Vec<&'a str>,
 }
 
 impl<'a> TestOptions<'a> {
     pub fn new(m: &'a clap::ArgMatches, config: &Config) -> Result<Self> {
-        let profile_name = m.value_of("profile").unwrap();
-        if let Err(e) = config.profile(profile_name) {
-            return Err(e.into());
-        }
+        let (profile_name, profile) = match ephemeral_profile(m)? {
+            Some(profile) => (None, profile),
+            None => {
+                let profile_name = m.value_of("profile").unwrap();
+                (Some(profile_name), config.profile(profile_name)?)
+            }
+        };
         Ok(Self {
             release: m.is_present("release"),
             targets: m.values_of("TARGETS").unwrap_or_default().collect(),
+            all: m.is_present("all"),
+            exclude: m.values_of("exclude").unwrap_or_default().collect(),
             profile: profile_name,
+            gateway: profile.reachable_gateway().to_string(),
+            credential: if profile_name.is_none() {
+                Some(profile.credential.to_string())
+            } else {
+                None
+            },
             verbosity: Verbosity::from(
                 m.occurrences_of("verbose") as i64 - m.occurrences_of("quiet") as i64,
             ),
+            manifest_opts: ManifestOptions::new(m),
+            features: FeatureOptions::new(m),
+            changed_since: m.value_of("changed_since"),
+            timeout: match value_t!(m, "test_timeout", u64) {
+                Ok(secs) => Some(Duration::from_secs(secs)),
+                Err(clap::Error {
+                    kind: clap::ErrorKind::ArgumentNotFound,
+                    ..
+                }) => None,
+                Err(err) => return Err(err.into()),
+            },
+            no_build: m.is_present("no_build"),
+            envs: user_envs(m)?,
+            rustc_wrapper: rustc_wrapper(config)?,
             tester_args: m.values_of("tester_args").unwrap_or_default().collect(),
         })
     }
@@ -38,21 +89,65 @@ impl<'a> TestOptions<'a> {
 impl<'a> super::ExecSubcommand for TestOptions<'a> {
     fn exec(self) -> Result<()> {
         let workspace = Workspace::populate()?;
-        let targets = workspace.collect_targets(&self.targets)?;
+        let mut targets = workspace.select_targets(&self.targets, self.all)?;
+        let excluded = workspace.exclude_targets(&mut targets, &self.exclude)?;
+        if let Some(base_ref) = self.changed_since {
+            match changed_paths(base_ref) {
+                Ok(changed_paths) => {
+                    let affected_targets = workspace.affected_targets(&changed_paths);
+                    targets.retain(|t| affected_targets.contains(t));
+                    if targets.is_empty() && self.verbosity > Verbosity::Quiet {
+                        println!("no targets were affected by changes since `{}`", base_ref);
+                    }
+                }
+                Err(e) => warn!(
+                    "could not diff against `{}`. Testing all targets. {}",
+                    base_ref, e
+                ),
+            }
+        }
         let build_opts = super::BuildOptions {
-            targets: self.targets.clone(),
-            debug: false,
-            verbosity: self.verbosity,
-            stack_size: None,
-            wasi: false,
-            builder_args: Vec::new(),
+            debug: !self.release, // Build with the same profile as the tests.
+            strip: if self.release {
+                super::StripMode::Custom
+            } else {
+                super::StripMode::None
+            },
+            manifest_opts: self.manifest_opts,
+            features: self.features.clone(),
+            ..super::BuildOptions::for_subcommand(
+                self.targets.clone(),
+                self.verbosity,
+                self.envs.clone(),
+                self.rustc_wrapper.clone(),
+            )
         };
-        super::build(&workspace, &targets, build_opts)?;
+        if self.no_build {
+            super::check_built(&workspace, &targets)?;
+        } else {
+            super::warn_excluded_dependencies(&workspace, &targets, &excluded)?;
+            super::build(&workspace, &targets, build_opts)?;
+        }
         test(&targets, self)
     }
 }
 
+/// Returns the absolute paths of the files changed since `base_ref`.
+fn changed_paths(base_ref: &str) -> Result<Vec<PathBuf>> {
+    // `git diff` lists paths relative to the repo root, which needn't be the workspace root.
+    let output = cmd!("git", "rev-parse", "--show-toplevel")?;
+    let repo_root = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim_end());
+    let output = cmd!("git", "diff", "--name-only", base_ref)?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| repo_root.join(line))
+        .collect())
+}
+
 pub fn test(targets: &[&Target], opts: TestOptions) -> Result<()> {
+    let projects: Vec<_> = targets.iter().map(|t| t.project).collect();
+    opts.features.check_declared(&projects)?;
     for target in targets.iter().filter(|t| t.is_testable()) {
         let proj = &target.project;
         let print_status = || {
@@ -92,6 +187,8 @@ fn test_rust(target: &Target, opts: &TestOptions) -> Result<()> {
         args.push("--test");
     }
     args.push(&target.name);
+    let feature_args = opts.features.cargo_args(target.project);
+    args.extend(feature_args.iter().map(String::as_str));
 
     if !opts.tester_args.is_empty() {
         args.push("--");
@@ -99,10 +196,12 @@ fn test_rust(target: &Target, opts: &TestOptions) -> Result<()> {
     }
 
     let mut envs: BTreeMap<_, _> = std::env::vars_os().collect();
+    envs.extend(user_env_map(&opts.envs));
     envs.insert(
         OsString::from("RUSTC_WRAPPER"),
-        OsString::from("oasis-build"),
+        OsString::from(&opts.rustc_wrapper),
     );
+    insert_profile_envs(&mut envs, opts);
 
     emit!(cmd.test.start, {
         "project_type": target.project.kind.name(),
@@ -110,7 +209,11 @@ fn test_rust(target: &Target, opts: &TestOptions) -> Result<()> {
         "rustflags": std::env::var("RUSTFLAGS").ok(),
     });
 
-    if let Err(e) = BuildTool::for_target(target).test(args, envs, opts.verbosity) {
+    if let Err(e) = BuildTool::for_target(target)
+        .manifest_options(opts.manifest_opts)
+        .timeout(opts.timeout)
+        .test(args, envs, opts.verbosity)
+    {
         emit!(cmd.test.error);
         return Err(e);
     };
@@ -131,12 +234,13 @@ fn test_javascript(target: &Target, opts: &TestOptions) -> Result<()> {
         args.extend(opts.tester_args.iter());
     }
 
-    let mut envs = BTreeMap::new();
-    envs.insert(
-        OsString::from("OASIS_PROFILE"),
-        OsString::from(&opts.profile),
-    );
-    if let Err(e) = BuildTool::for_target(target).test(args, envs, opts.verbosity) {
+    let mut envs = user_env_map(&opts.envs);
+    insert_profile_envs(&mut envs, opts);
+    if let Err(e) = BuildTool::for_target(target)
+        .manifest_options(opts.manifest_opts)
+        .timeout(opts.timeout)
+        .test(args, envs, opts.verbosity)
+    {
         emit!(cmd.test.error);
         return Err(e);
     }
@@ -144,3 +248,20 @@ fn test_javascript(target: &Target, opts: &TestOptions) -> Result<()> {
     emit!(cmd.test.done);
     Ok(())
 }
+
+/// Tells the tests which profile was selected and where its gateway is.
+fn insert_profile_envs(envs: &mut BTreeMap<OsString, OsString>, opts: &TestOptions) {
+    if let Some(profile) = opts.profile {
+        envs.insert(OsString::from("OASIS_PROFILE"), OsString::from(profile));
+    }
+    envs.insert(
+        OsString::from("OASIS_GATEWAY_URL"),
+        OsString::from(&opts.gateway),
+    );
+    if let Some(credential) = &opts.credential {
+        envs.insert(
+            OsString::from("OASIS_CREDENTIAL"),
+            OsString::from(credential),
+        );
+    }
+}
@@ -1,9 +1,15 @@
 use std::{
-    collections::BTreeSet, fs, io::Read, os::unix::fs::PermissionsExt as _, path::Path,
+    collections::BTreeSet,
+    fs,
+    io::Read,
+    os::unix::fs::PermissionsExt as _,
+    path::Path,
     str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
+    config::Config,
     errors::{CliError, Error},
     oasis_xdg_dir, utils,
 };
@@ -12,6 +18,17 @@ const OASIS_GENESIS_YEAR: u8 = 19;
 const WEEKS_IN_YEAR: u8 = 54;
 const INSTALLED_RELEASE_FILE: &str = "installed_release";
 const TOOLS_URL: &str = "https://tools.oasis.dev";
+/// Records, in the cache dir, when the latest release was last looked up and what it was.
+const UPDATE_CHECK_FILE: &str = "update_check.json";
+const UPDATE_CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// The subcommands that shouldn't be interrupted by a nudge to update.
+const NO_UPDATE_CHECK_SUBCOMMANDS: &[&str] = &[
+    "update",
+    "update_check",
+    "set-toolchain",
+    "upload_metrics",
+    "gen_completions",
+];
 
 cfg_if::cfg_if! {
     if #[cfg(target_os = "linux")] {
@@ -28,6 +45,46 @@ pub fn installed_release() -> Result<Release, Error> {
     Ok(serde_json::from_slice(&fs::read(installed_release_file)?)?)
 }
 
+/// The `oasis-std` versions supported by `oasis-build`, keyed by the first release that
+/// supports them.
+const OASIS_STD_REQS: &[((u8, u8), &str)] = &[((19, 36), "^0.2"), ((20, 1), "^0.3")];
+
+/// Returns the `oasis-std` versions supported by the installed toolchain, if it's a named
+/// release (i.e. not `unstable`).
+pub fn supported_oasis_std() -> Option<semver::VersionReq> {
+    match ReleaseVersion::from_str(installed_release().ok()?.name()).ok()? {
+        ReleaseVersion::Named { year, week, .. } => oasis_std_req_for(year, week),
+        _ => None,
+    }
+}
+
+fn oasis_std_req_for(year: u8, week: u8) -> Option<semver::VersionReq> {
+    OASIS_STD_REQS
+        .iter()
+        .rev()
+        .find(|(since, _)| *since <= (year, week))
+        .map(|(_, req)| semver::VersionReq::parse(req).unwrap())
+}
+
+/// Returns the lowest version allowed by a Cargo version requirement (e.g., `0.3.0` for `^0.3`).
+pub fn min_version(req: &str) -> Option<semver::Version> {
+    let ver = req
+        .split(',')
+        .next()?
+        .trim()
+        .trim_start_matches(|c| "^~=>".contains(c))
+        .trim();
+    let mut parts: Vec<&str> = ver.split('.').collect();
+    if parts.iter().any(|p| p.contains('*')) {
+        parts.truncate(parts.iter().position(|p| p.contains('*')).unwrap());
+    }
+    if parts.is_empty() || parts.len() > 3 {
+        return None;
+    }
+    parts.resize(3, "0");
+    semver::Version::parse(&parts.join(".")).ok()
+}
+
 pub fn set(version: &str) -> Result<(), Error> {
     if version == "current" {
         // ^ This is effectively a post-install hook.
@@ -36,14 +93,9 @@ pub fn set(version: &str) -> Result<(), Error> {
             .unwrap_or_else(|_| crate::dirs::home_dir().join(".cargo"))
             .join("bin/rustup");
         crate::cli::gen_completions()?;
+        crate::cmd!(&rustup, "toolchain", "install", crate::rust_toolchain!())?;
         crate::cmd!(
-            &rustup.to_str().unwrap(),
-            "toolchain",
-            "install",
-            crate::rust_toolchain!()
-        )?;
-        crate::cmd!(
-            &rustup.to_str().unwrap(),
+            &rustup,
             "target",
             "add",
             "wasm32-wasi",
@@ -53,9 +105,6 @@ pub fn set(version: &str) -> Result<(), Error> {
         return Ok(());
     }
 
-    let bin_dir = crate::ensure_xdg_dir!(bin)?;
-    let cache_dir = oasis_xdg_dir!(cache)?;
-
     let requested_version = ReleaseVersion::from_str(version)?;
 
     let installed_release = installed_release().unwrap_or_default();
@@ -81,6 +130,152 @@ pub fn set(version: &str) -> Result<(), Error> {
         return Ok(());
     }
 
+    install(&release, &tools_client)
+}
+
+/// Installs the latest release if it's newer than the installed one. If `check_only`, the
+/// update is only reported, as an error, so that scripts can tell that one is available.
+pub fn update(check_only: bool) -> Result<(), Error> {
+    let installed_release = installed_release().unwrap_or_default();
+    let installed_name = if installed_release.name.is_empty() {
+        "none"
+    } else {
+        installed_release.name()
+    };
+
+    let tools_client = ToolsClient::new()?;
+    let latest_release =
+        match Release::for_version(ReleaseVersion::Latest, tools_client.fetch_manifest()?) {
+            Some(release) => release,
+            None => return Err(CliError::UnknownToolchain("latest".to_string()).into()),
+        };
+
+    if !is_newer(latest_release.name(), installed_name) {
+        println!("{} is up-to-date", installed_name);
+        return Ok(());
+    }
+
+    if check_only {
+        bail!(
+            "a toolchain update is available: {} -> {}. Run `oasis update` to install it.",
+            installed_name,
+            latest_release.name()
+        );
+    }
+
+    install(&latest_release, &tools_client)?;
+    println!(
+        "updated the toolchain: {} -> {}",
+        installed_name,
+        latest_release.name()
+    );
+    Ok(())
+}
+
+/// Returns whether the release named `latest` should replace the one named `installed`.
+fn is_newer(latest: &str, installed: &str) -> bool {
+    match (
+        ReleaseVersion::from_str(latest),
+        ReleaseVersion::from_str(installed),
+    ) {
+        (Ok(latest_version), Ok(installed_version)) => latest_version > installed_version,
+        (Ok(_), Err(_)) => true, // Nothing, or something unrecognizable, is installed.
+        (Err(_), _) => false,
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct UpdateCheck {
+    /// Seconds since the Unix epoch.
+    checked_at: u64,
+    /// The name of the latest release, if the check succeeded.
+    latest: Option<String>,
+}
+
+/// Prints a nudge if the last update check found a newer release than the installed one,
+/// and starts another check in the background if the last one is over a day old.
+/// Does nothing unless opted into by `toolchain.update_check`.
+pub fn notify_update(config: &Config, subcommand: Option<&str>) -> Result<(), Error> {
+    let args: Vec<String> = std::env::args().collect();
+    let is_offline = args
+        .iter()
+        .any(|arg| arg == "--offline" || arg == "--frozen")
+        || std::env::var("CARGO_NET_OFFLINE").map_or(false, |v| v == "true");
+    let is_opted_out = std::env::var_os("CI").is_some()
+        || std::env::var("OASIS_SKIP_UPDATE_CHECK").map_or(false, |v| v == "1");
+    if !config.update_check()
+        || is_offline
+        || is_opted_out
+        || subcommand.map_or(true, |s| NO_UPDATE_CHECK_SUBCOMMANDS.contains(&s))
+    {
+        return Ok(());
+    }
+
+    let check_path = oasis_xdg_dir!(cache)?.join(UPDATE_CHECK_FILE);
+    let last_check: UpdateCheck = fs::read(&check_path)
+        .ok()
+        .and_then(|check| serde_json::from_slice(&check).ok())
+        .unwrap_or_default();
+
+    if let (Some(latest), Ok(installed)) = (&last_check.latest, installed_release()) {
+        if is_newer(latest, installed.name()) {
+            eprintln!(
+                "a newer toolchain ({}) is available; run `oasis update`",
+                latest
+            );
+        }
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    if now.saturating_sub(last_check.checked_at) < UPDATE_CHECK_INTERVAL.as_secs() {
+        return Ok(());
+    }
+    // Record the attempt first so that a check that fails (e.g., offline) isn't retried by
+    // every subsequent command.
+    fs::write(
+        &check_path,
+        serde_json::to_string(&UpdateCheck {
+            checked_at: now,
+            latest: last_check.latest,
+        })
+        .unwrap(),
+    )?;
+    std::process::Command::new(&args[0])
+        .arg("update_check")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+/// Looks up the latest release and records it for `notify_update`.
+pub fn check_update() -> Result<(), Error> {
+    let latest_release = Release::for_version(
+        ReleaseVersion::Latest,
+        ToolsClient::new()?.fetch_manifest()?,
+    );
+    fs::write(
+        oasis_xdg_dir!(cache)?.join(UPDATE_CHECK_FILE),
+        serde_json::to_string(&UpdateCheck {
+            checked_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            latest: latest_release.map(|release| release.name),
+        })
+        .unwrap(),
+    )?;
+    Ok(())
+}
+
+/// Downloads the tools of `release` and moves them into the bin dir.
+fn install(release: &Release, tools_client: &ToolsClient) -> Result<(), Error> {
+    let bin_dir = crate::ensure_xdg_dir!(bin)?;
+    let cache_dir = oasis_xdg_dir!(cache)?;
+
     for tool in release.tools.iter() {
         utils::print_status_ctx(utils::Status::Downloading, &tool.name, &tool.ver);
         tools_client
@@ -98,7 +293,7 @@ pub fn set(version: &str) -> Result<(), Error> {
 
     fs::write(
         oasis_xdg_dir!(data)?.join(INSTALLED_RELEASE_FILE),
-        serde_json::to_string_pretty(&release).unwrap(),
+        serde_json::to_string_pretty(release).unwrap(),
     )
     .ok(); // This isn't catastropic. We'll just have to re-download later.
 
@@ -353,11 +548,12 @@ impl ToolsClient {
 
     #[cfg(not(test))]
     fn fetch_manifest(&self) -> Result<impl Read, Error> {
-        Ok(self
-            .0
-            .get("")
-            .send()
-            .map_err(|e| anyhow!("could not fetch releases: {}", e))?)
+        Ok(self.0.get("").send().map_err(|e| {
+            anyhow!(
+                "could not fetch releases: {}",
+                utils::http::describe_error(e)
+            )
+        })?)
     }
 
     #[cfg(test)]
@@ -381,12 +577,21 @@ impl ToolsClient {
         if out_path.exists() {
             return Ok(());
         }
-        let mut res = self.0.get(&tool.s3_key).send()?;
+        let mut res = self
+            .0
+            .get(&tool.s3_key)
+            .send()
+            .map_err(utils::http::describe_error)?;
+        // Download to a temporary file so that a timed out download isn't later mistaken
+        // for a complete tool.
+        let part_path = out_path.with_extension("part");
         let mut f = fs::OpenOptions::new()
             .create(true)
             .write(true)
-            .open(out_dir.join(&tool.name_ver))?;
-        res.copy_to(&mut f)?;
+            .truncate(true)
+            .open(&part_path)?;
+        res.copy_to(&mut f).map_err(utils::http::describe_error)?;
+        fs::rename(&part_path, &out_path)?;
         Ok(())
     }
 }
@@ -426,6 +631,35 @@ mod tests {
         assert!(ReleaseVersion::from_str("19.55").is_err());
     }
 
+    #[test]
+    fn test_oasis_std_req_for() {
+        let v = |s| semver::Version::parse(s).unwrap();
+        assert!(oasis_std_req_for(19, 30).is_none());
+        assert!(oasis_std_req_for(19, 36).unwrap().matches(&v("0.2.1")));
+        assert!(!oasis_std_req_for(19, 50).unwrap().matches(&v("0.3.0")));
+        assert!(oasis_std_req_for(20, 34).unwrap().matches(&v("0.3.0")));
+    }
+
+    #[test]
+    fn test_min_version() {
+        let v = |s| Some(semver::Version::parse(s).unwrap());
+        assert_eq!(min_version("0.3"), v("0.3.0"));
+        assert_eq!(min_version("^0.2.4"), v("0.2.4"));
+        assert_eq!(min_version(">= 0.3, < 0.5"), v("0.3.0"));
+        assert_eq!(min_version("0.3.*"), v("0.3.0"));
+        assert_eq!(min_version("*"), None);
+    }
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("20.19", "19.20"));
+        assert!(is_newer("20.19", ""));
+        assert!(!is_newer("20.19", "20.19"));
+        assert!(!is_newer("19.20", "20.19"));
+        assert!(!is_newer("20.19", "unstable"));
+        assert!(!is_newer("", "19.20"));
+    }
+
     #[test]
     fn test_version_ord() {
         let named_early = ReleaseVersion::from_str("19.36").unwrap();
@@ -0,0 +1,146 @@
+use colored::*;
+
+use crate::errors::Result;
+
+use super::size::read_wasm;
+
+/// Checks that a Wasm module upholds the invariants established by `prep_wasm`.
+pub fn verify(wasm_path: &str) -> Result<()> {
+    crate::emit!(cmd.verify);
+    let bytecode = read_wasm(wasm_path)?;
+    let checks = check_module(&bytecode);
+    for (check, passed) in checks.iter() {
+        println!(
+            "{: >12} {}",
+            if *passed {
+                "Pass".green()
+            } else {
+                "Fail".red()
+            },
+            check
+        );
+    }
+    let num_failed = checks.iter().filter(|(_, passed)| !passed).count();
+    if num_failed > 0 {
+        bail!(
+            "`{}` failed {} of {} checks",
+            wasm_path,
+            num_failed,
+            checks.len()
+        );
+    }
+    Ok(())
+}
+
+/// Returns a description of each check and whether the module passed it.
+fn check_module(bytecode: &[u8]) -> Vec<(String, bool)> {
+    let mut checks = Vec::new();
+
+    let validation_err = wasmparser::validate(bytecode, None /* default config */).err();
+    checks.push((
+        match &validation_err {
+            Some(err) => format!(
+                "module is valid Wasm ({} at offset {})",
+                err.message, err.offset
+            ),
+            None => "module is valid Wasm".to_string(),
+        },
+        validation_err.is_none(),
+    ));
+    let module = match walrus::Module::from_buffer(bytecode) {
+        Ok(module) if validation_err.is_none() => module,
+        _ => return checks,
+    };
+
+    checks.push((
+        "memory is imported".to_string(),
+        module
+            .imports
+            .iter()
+            .any(|imp| matches!(imp.kind, walrus::ImportKind::Memory(_))),
+    ));
+    checks.push((
+        "memory is not exported".to_string(),
+        !module
+            .exports
+            .iter()
+            .any(|exp| matches!(exp.item, walrus::ExportItem::Memory(_))),
+    ));
+    checks.push((
+        "wasi imports are from `wasi_unstable`".to_string(),
+        module
+            .imports
+            .iter()
+            .filter(|imp| imp.module.starts_with("wasi"))
+            .all(|imp| imp.module == "wasi_unstable"),
+    ));
+
+    let has_valid_version = module
+        .customs
+        .iter()
+        .find(|(_, custom)| custom.name() == "oasis_version")
+        .and_then(|(_, custom)| {
+            serde_json::from_slice::<serde_json::Value>(&custom.data(&Default::default())).ok()
+        })
+        .map(|version| version.get("sha").and_then(|sha| sha.as_str()).is_some())
+        .unwrap_or_default();
+    let has_interface = module.customs.iter().any(|(_, custom)| {
+        custom.name() == "oasis-interface" && !custom.data(&Default::default()).is_empty()
+    });
+    checks.push((
+        "has an `oasis_version` or `oasis-interface` section".to_string(),
+        has_valid_version || has_interface,
+    ));
+
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed_checks(module: &mut walrus::Module) -> Vec<String> {
+        check_module(&module.emit_wasm())
+            .into_iter()
+            .filter_map(|(check, passed)| if passed { None } else { Some(check) })
+            .collect()
+    }
+
+    #[test]
+    fn test_verify_prepped() {
+        let mut module = walrus::Module::default();
+        module.add_import_memory("env", "memory", false, 1, None);
+        let fd_write_ty = module.types.add(&[], &[]);
+        module.add_import_func("wasi_unstable", "fd_write", fd_write_ty);
+        module.customs.add(walrus::RawCustomSection {
+            name: "oasis_version".to_string(),
+            data: br#"{"sha":"abc123","serviceName":"test"}"#.to_vec(),
+        });
+        assert!(failed_checks(&mut module).is_empty());
+    }
+
+    #[test]
+    fn test_verify_unprepped() {
+        let mut module = walrus::Module::default();
+        let mem = module.memories.add_local(false, 1, None);
+        module.exports.add("memory", mem);
+        let fd_write_ty = module.types.add(&[], &[]);
+        module.add_import_func("wasi_snapshot_preview1", "fd_write", fd_write_ty);
+        assert_eq!(
+            failed_checks(&mut module),
+            vec![
+                "memory is imported",
+                "memory is not exported",
+                "wasi imports are from `wasi_unstable`",
+                "has an `oasis_version` or `oasis-interface` section",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verify_invalid() {
+        let checks = check_module(b"\0asm\x01\0\0\0\x0b");
+        assert_eq!(checks.len(), 1);
+        assert!(!checks[0].1);
+    }
+}
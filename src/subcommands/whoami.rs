@@ -0,0 +1,19 @@
+use crate::{config::Config, emit, errors::Result};
+
+/// Prints the kind of credential of a profile and the address of its account. The secret
+/// itself is never printed.
+pub fn whoami(config: &Config, profile_name: &str) -> Result<()> {
+    emit!(cmd.whoami);
+    let profile = config.profile(profile_name)?;
+    let credential = &profile.credential;
+    println!("profile:    {}", profile_name);
+    for gateway in profile.gateways.iter() {
+        println!("gateway:    {}", gateway);
+    }
+    println!("credential: {}", credential.kind());
+    match credential.address() {
+        Some(address) => println!("address:    {}", address),
+        None => println!("token:      {}", credential.masked()),
+    }
+    Ok(())
+}
@@ -2,7 +2,9 @@ use std::{
     cell::RefCell,
     fs::{File, OpenOptions},
     io::{prelude::*, BufReader},
+    path::Path,
     sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use flate2::{write::GzEncoder, Compression};
@@ -12,7 +14,10 @@ use once_cell::sync::OnceCell;
 use crate::errors::{CliError, Error};
 
 const SUBMIT_URL: &str = "https://telemetry.oasiscloud.io";
-const UPLOAD_THRESHOLD_FILESIZE: u64 = 50 * 1024; // 50 KiB
+
+/// Version of the `Event` schema. Bump whenever the set of always-present fields changes.
+/// v2: added `cli_version` and `toolchain`.
+const EVENT_SCHEMA_VERSION: u32 = 2;
 
 static TLM: OnceCell<Telemetry> = OnceCell::new();
 
@@ -20,15 +25,20 @@ struct Telemetry {
     user_id: String,
     log_file: Mutex<RefCell<File>>,
     session_id: u32,
+    cli_version: &'static str,
+    toolchain: Option<String>,
 }
 
 #[derive(serde::Serialize)]
-struct Event {
-    event: &'static str,
+struct Event<'a> {
+    schema: u32,
+    event: &'a str,
     #[serde(skip_serializing_if = "Option::is_none")]
     data: Option<serde_json::Value>,
     timestamp: u64,
     session_id: u32,
+    cli_version: &'a str,
+    toolchain: Option<&'a str>, // serialized as `null` when no release is installed
 }
 
 pub fn init(config: &crate::config::Config) -> Result<(), Error> {
@@ -43,18 +53,29 @@ pub fn init(config: &crate::config::Config) -> Result<(), Error> {
         let args = std::env::args().collect::<Vec<_>>();
         let upload_args = &["upload_metrics"];
         let is_upload = &args[1..] == upload_args;
-        if metadata.len() >= UPLOAD_THRESHOLD_FILESIZE && !is_upload {
+        let last_upload_path = last_upload_path()?;
+        let now = unix_secs(SystemTime::now());
+        if !is_upload && should_upload(tcfg, metadata.len(), last_upload(&last_upload_path), now) {
             std::process::Command::new(&args[0])
                 .args(upload_args)
                 .stdout(std::process::Stdio::null())
                 .stderr(std::process::Stdio::null())
                 .spawn()?;
+            // Recorded at spawn so that commands run during the upload don't start another.
+            // Failing to record it only risks an early upload, so telemetry is still enabled.
+            if let Err(err) = std::fs::write(&last_upload_path, now.to_string()) {
+                info!("could not write `{}`: {}", last_upload_path.display(), err);
+            }
         }
     }
 
     TLM.set(Telemetry {
         user_id: tcfg.user_id.clone(),
         session_id: std::process::id(),
+        cli_version: crate_version!(),
+        toolchain: crate::subcommands::toolchain::installed_release()
+            .ok()
+            .map(|r| r.name().to_string()),
         log_file: Mutex::new(RefCell::new(
             OpenOptions::new()
                 .create(true)
@@ -72,10 +93,83 @@ pub fn metrics_path() -> Result<std::path::PathBuf, Error> {
     Ok(crate::oasis_xdg_dir!(data)?.join("metrics.jsonl"))
 }
 
-pub fn __emit(event: &'static str, data: serde_json::Value) -> Result<(), Error> {
+/// The file that holds the time, in seconds since the Unix epoch, of the last upload.
+fn last_upload_path() -> Result<std::path::PathBuf, Error> {
+    Ok(crate::oasis_xdg_dir!(data)?.join("metrics.last_upload"))
+}
+
+fn last_upload(last_upload_path: &Path) -> Option<u64> {
+    std::fs::read_to_string(last_upload_path)
+        .ok()
+        .and_then(|secs| secs.trim().parse().ok())
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Returns whether the metrics, which are `metrics_len` bytes, should be uploaded `now`, which
+/// is when they exceed the configured threshold and the last upload is long enough ago.
+fn should_upload(
+    tcfg: &crate::config::Telemetry,
+    metrics_len: u64,
+    last_upload: Option<u64>,
+    now: u64,
+) -> bool {
+    metrics_len >= tcfg.upload_threshold_bytes
+        && last_upload
+            .map(|last_upload| now.saturating_sub(last_upload) >= tcfg.upload_min_interval_secs)
+            .unwrap_or(true)
+}
+
+/// Records that `subcommand` finished, successfully or not, after `duration`, as the event
+/// `cmd.<subcommand>.complete`. The arguments are left out, as they may contain secrets.
+pub fn emit_complete(subcommand: &str, duration: Duration, success: bool) {
+    let data = serde_json::json!({
+        "duration_ms": duration.as_millis() as u64,
+        "success": success,
+    });
+    if let Err(err) = __emit(&format!("cmd.{}.complete", subcommand), data) {
+        info!("could not append to log: {}", err);
+    }
+}
+
+/// The flags whose values are secrets, which are never recorded.
+const SECRET_FLAGS: &[&str] = &["--credential"];
+
+/// Joins the command-line `args` for recording in an event, with secret values redacted.
+pub fn redacted_args(args: impl IntoIterator<Item = String>) -> String {
+    let mut redact_next = false;
+    args.into_iter()
+        .map(|arg| {
+            if redact_next {
+                redact_next = false;
+                return "<redacted>".to_string();
+            }
+            if SECRET_FLAGS.contains(&arg.as_str()) {
+                redact_next = true;
+                return arg;
+            }
+            match SECRET_FLAGS
+                .iter()
+                .find(|flag| arg.starts_with(&format!("{}=", flag)))
+            {
+                Some(flag) => format!("{}=<redacted>", flag),
+                None => arg,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn __emit(event: &str, data: serde_json::Value) -> Result<(), Error> {
     let Telemetry {
         session_id,
         log_file,
+        cli_version,
+        toolchain,
         ..
     } = match TLM.get() {
         Some(tlm) => tlm,
@@ -91,17 +185,13 @@ pub fn __emit(event: &'static str, data: serde_json::Value) -> Result<(), Error>
             log_file,
             "{}",
             &serde_json::to_string(&Event {
+                schema: EVENT_SCHEMA_VERSION,
                 event,
-                data: if data.as_array().unwrap().is_empty() {
-                    None
-                } else {
-                    Some(data)
-                },
+                data: event_data(data),
                 session_id: *session_id,
-                timestamp: std::time::SystemTime::now()
-                    .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
+                timestamp: unix_secs(SystemTime::now()),
+                cli_version,
+                toolchain: toolchain.as_deref(),
             })?
         )?;
         log_file.flush()?;
@@ -113,6 +203,16 @@ pub fn __emit(event: &'static str, data: serde_json::Value) -> Result<(), Error>
     result
 }
 
+/// Returns the `data` of an event, or `None` if there's none to record. `emit!` always passes
+/// an array, but other values are kept rather than rejected.
+fn event_data(data: serde_json::Value) -> Option<serde_json::Value> {
+    match &data {
+        serde_json::Value::Null => None,
+        serde_json::Value::Array(items) if items.is_empty() => None,
+        _ => Some(data),
+    }
+}
+
 pub fn upload() -> Result<(), Error> {
     let Telemetry {
         user_id, log_file, ..
@@ -170,3 +270,79 @@ macro_rules! emit {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn test_event_data() {
+        assert_eq!(event_data(json!(null)), None);
+        assert_eq!(event_data(json!([])), None);
+        assert_eq!(event_data(json!([1])), Some(json!([1])));
+        assert_eq!(event_data(json!({ "a": 1 })), Some(json!({ "a": 1 })));
+        assert_eq!(event_data(json!({})), Some(json!({})));
+    }
+
+    #[test]
+    fn test_redacted_args() {
+        let args = |args: &str| args.split(' ').map(String::from).collect::<Vec<_>>();
+        assert_eq!(
+            redacted_args(args("oasis test --credential secret --gateway url")),
+            "oasis test --credential <redacted> --gateway url"
+        );
+        assert_eq!(
+            redacted_args(args("oasis deploy --credential=secret a")),
+            "oasis deploy --credential=<redacted> a"
+        );
+        assert_eq!(
+            redacted_args(args("oasis build --credentials")),
+            "oasis build --credentials"
+        );
+    }
+
+    #[test]
+    fn test_should_upload() {
+        let tcfg = crate::config::Telemetry {
+            upload_threshold_bytes: 100,
+            upload_min_interval_secs: 60,
+            ..Default::default()
+        };
+        assert!(!should_upload(&tcfg, 99, None, 1000));
+        assert!(should_upload(&tcfg, 100, None, 1000));
+        assert!(!should_upload(&tcfg, 100, Some(950), 1000));
+        assert!(should_upload(&tcfg, 100, Some(940), 1000));
+        assert!(!should_upload(&tcfg, 99, Some(0), 1000));
+        // A last upload in the future (e.g., after the clock was changed) waits out the interval.
+        assert!(!should_upload(&tcfg, 100, Some(1010), 1000));
+
+        let tcfg = crate::config::Telemetry {
+            upload_min_interval_secs: 0,
+            ..tcfg
+        };
+        assert!(should_upload(&tcfg, 100, Some(1000), 1000));
+    }
+
+    #[test]
+    fn test_serialize_event() {
+        let event = |data| {
+            serde_json::to_value(&Event {
+                schema: EVENT_SCHEMA_VERSION,
+                event: "test.event",
+                data: event_data(data),
+                timestamp: 0,
+                session_id: 0,
+                cli_version: "0.0.0",
+                toolchain: None,
+            })
+            .unwrap()
+        };
+        assert_eq!(event(json!({ "a": 1 }))["data"], json!({ "a": 1 }));
+        assert_eq!(event(json!([{ "a": 1 }]))["data"], json!([{ "a": 1 }]));
+        assert_eq!(event(json!("a"))["data"], json!("a"));
+        assert!(event(json!([])).get("data").is_none());
+        assert!(event(json!(null)).get("data").is_none());
+    }
+}
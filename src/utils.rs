@@ -7,9 +7,15 @@ pub enum Status {
     Building,
     Preparing,
     Testing,
+    Benchmarking,
     Deploying,
+    Starting,
     Downloading,
     Created,
+    Bundled,
+    Interface,
+    Optimized,
+    Ready,
 }
 
 impl fmt::Display for Status {
@@ -22,9 +28,15 @@ impl fmt::Display for Status {
                 Self::Building => "Building".cyan(),
                 Self::Preparing => "Preparing".cyan(),
                 Self::Testing => "Testing".cyan(),
+                Self::Benchmarking => "Benchmarking".cyan(),
                 Self::Deploying => "Deploying".cyan(),
+                Self::Starting => "Starting".cyan(),
                 Self::Downloading => "Downloading".cyan(),
                 Self::Created => "Created".green(),
+                Self::Bundled => "Bundled".green(),
+                Self::Interface => "Interface".cyan(),
+                Self::Optimized => "Optimized".green(),
+                Self::Ready => "Ready".green(),
             }
         )
     }
@@ -57,8 +69,36 @@ pub fn print_status_ctx(status: Status, what: impl fmt::Display, ctx: impl fmt::
 }
 
 pub mod http {
+    use std::{
+        sync::atomic::{AtomicU64, Ordering},
+        time::Duration,
+    };
+
     use reqwest::{header::HeaderMap, Error, IntoUrl, RequestBuilder, Url};
 
+    /// The number of seconds that a network operation may take if `--timeout` is not given.
+    pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+    static TIMEOUT_SECS: AtomicU64 = AtomicU64::new(DEFAULT_TIMEOUT_SECS);
+
+    /// Sets the timeout of all subsequently built clients.
+    pub fn set_timeout(secs: u64) {
+        TIMEOUT_SECS.store(secs, Ordering::Relaxed);
+    }
+
+    pub fn timeout() -> Duration {
+        Duration::from_secs(TIMEOUT_SECS.load(Ordering::Relaxed))
+    }
+
+    /// Returns an error that says how long the request waited, if it timed out.
+    pub fn describe_error(err: Error) -> anyhow::Error {
+        if err.is_timeout() {
+            anyhow!("timed out after {}s", timeout().as_secs())
+        } else {
+            err.into()
+        }
+    }
+
     pub struct ClientBuilder {
         url: Result<Url, Error>,
         inner: reqwest::ClientBuilder,
@@ -82,7 +122,9 @@ pub mod http {
                         url
                     }
                 }),
-                inner: reqwest::Client::builder().use_sys_proxy(),
+                inner: reqwest::Client::builder()
+                    .use_sys_proxy()
+                    .timeout(timeout()),
             }
         }
 
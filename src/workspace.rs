@@ -9,12 +9,22 @@ use std::{
 
 use bitflags::bitflags;
 use oasis_rpc::import::ImportLocation;
+use once_cell::sync::OnceCell;
 
 use crate::{
-    cmd,
+    cmd, ensure_dir,
     errors::{Result, WorkspaceError},
+    oasis_xdg_dir,
 };
 
+/// The workspace root given by `--workspace-root`, which takes precedence over detection.
+static ROOT_OVERRIDE: OnceCell<PathBuf> = OnceCell::new();
+
+/// Makes `Workspace::populate` use `root` instead of searching for the workspace root.
+pub fn set_root_override(root: PathBuf) {
+    ROOT_OVERRIDE.set(root).ok();
+}
+
 pub struct Workspace {
     root: PathBuf,
 
@@ -29,12 +39,9 @@ pub struct Workspace {
 impl Workspace {
     pub fn populate() -> Result<Self> {
         let cwd = std::env::current_dir().unwrap(); // Checked during initialization.
-        let repo_root = cwd
-            .ancestors()
-            .find(|a| a.join(".git").exists())
-            .ok_or_else(|| WorkspaceError::NoWorkspace(cwd.display().to_string()))?;
+        let repo_root = Self::find_root(&cwd)?;
 
-        let mut walk_builder = ignore::WalkBuilder::new(repo_root);
+        let mut walk_builder = ignore::WalkBuilder::new(&repo_root);
         walk_builder.sort_by_file_path(|a, b| {
             match a.components().count().cmp(&b.components().count()) {
                 std::cmp::Ordering::Equal => a.cmp(b),
@@ -65,25 +72,110 @@ impl Workspace {
         debug!("detected workspace containing: {:?}", projects);
 
         Ok(Self {
-            root: repo_root.to_path_buf(),
+            root: repo_root,
             projects: UnsafeCell::new(projects),
         })
     }
 
+    /// Returns the root of the workspace that contains `cwd`: the `--workspace-root`, else the
+    /// enclosing git repo, else the nearest directory with a manifest.
+    pub fn find_root(cwd: &Path) -> Result<PathBuf> {
+        if let Some(root) = ROOT_OVERRIDE.get() {
+            if !root.is_dir() {
+                bail!("the workspace root `{}` is not a directory", root.display());
+            }
+            // The override was given relative to where `oasis` runs, not to `cwd`.
+            return Ok(canonicalize_path(&std::env::current_dir()?, root).into_owned());
+        }
+        if let Some(repo_root) = cwd.ancestors().find(|a| a.join(".git").exists()) {
+            return Ok(repo_root.to_path_buf());
+        }
+        let manifest_root = cwd
+            .ancestors()
+            .find(|a| a.join("Cargo.toml").is_file() || a.join("package.json").is_file())
+            .ok_or_else(|| WorkspaceError::NoWorkspace(cwd.display().to_string()))?;
+        debug!(
+            "`{}` is not in a git repository, so using `{}` as the workspace root",
+            cwd.display(),
+            manifest_root.display()
+        );
+        Ok(manifest_root.to_path_buf())
+    }
+
     /// Collects the set of top-level dependencies that are matched by the input `target_strs`.
     /// A valid target str is either the name of a service or a path in the workspace that
     /// points to a directory that contains services. Like git, `:/` refers to the workspace root.
+    /// Returns an error if targets were explicitly requested but none of them matched.
     pub fn collect_targets<'a, 't>(
         &'a self,
         target_strs: &'t [&'t str],
     ) -> Result<Vec<&'a Target>> {
-        let cwd = std::env::current_dir()?;
-        let target_strs = if target_strs.is_empty() {
-            Cow::Owned(vec![cwd.to_str().unwrap()])
+        let targets = if target_strs.is_empty() {
+            // Refer to the cwd as `.` so that its path needn't be valid UTF-8.
+            TopTargets::new(self, &["."]).collect_targets()?
         } else {
-            Cow::Borrowed(target_strs)
+            TopTargets::new(self, target_strs).collect_targets()?
         };
-        TopTargets::new(self, &target_strs).collect_targets()
+        if targets.is_empty() {
+            // Wasm targets get their own projects, so there are none only if nothing was found.
+            self.check_has_projects()?;
+            if !target_strs.is_empty() {
+                return Err(WorkspaceError::NoMatchingTargets(target_strs.join(" ")).into());
+            }
+        }
+        Ok(targets)
+    }
+
+    /// Collects every target in the workspace if `all` (i.e. `--all`), or else the targets
+    /// matched by `target_strs`. Unlike running without targets from the workspace root,
+    /// `--all` selects the same targets from any directory.
+    pub fn select_targets<'a, 't>(
+        &'a self,
+        target_strs: &'t [&'t str],
+        all: bool,
+    ) -> Result<Vec<&'a Target>> {
+        if all {
+            self.check_has_projects()?;
+            Ok(self.targets().collect())
+        } else {
+            self.collect_targets(target_strs)
+        }
+    }
+
+    /// Removes the targets matched by `exclude_strs`, which are interpreted like target strs
+    /// except that names may also be globs (e.g., `test-*`). Returns the removed targets.
+    pub fn exclude_targets<'a>(
+        &'a self,
+        targets: &mut Vec<&'a Target>,
+        exclude_strs: &[&str],
+    ) -> Result<Vec<&'a Target>> {
+        if exclude_strs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (globs, exclude_strs): (Vec<&str>, Vec<&str>) = exclude_strs
+            .iter()
+            .copied()
+            .partition(|s| s.contains(|ch| ch == '*' || ch == '?'));
+        let mut excluded_targets = TopTargets::new(self, &exclude_strs).collect_targets()?;
+        for glob in globs {
+            let matched: Vec<&Target> = self
+                .targets()
+                .filter(|t| glob_matches(glob, &t.name))
+                .collect();
+            if matched.is_empty() {
+                warn!("no target matching `{}` found in the workspace", glob);
+            }
+            excluded_targets.extend(matched);
+        }
+        let mut removed = Vec::new();
+        targets.retain(|t| {
+            let is_excluded = excluded_targets.contains(t);
+            if is_excluded {
+                removed.push(*t);
+            }
+            !is_excluded
+        });
+        Ok(removed)
     }
 
     /// Returns the input targets and their dependencies in topologically sorted order.
@@ -136,10 +228,13 @@ impl Workspace {
             if let Some((transitive_dep_name, import_loc)) =
                 dep.dependencies.iter().nth(next_dep_idx)
             {
-                let lookup_base = dep.manifest_dir();
                 let dep_name = &dep.name;
-                let transitive_dep_target =
-                    self.lookup_target(&transitive_dep_name, &import_loc, lookup_base)?;
+                let transitive_dep_target = self.lookup_target(
+                    dep,
+                    &transitive_dep_name,
+                    &import_loc,
+                    true, /* fetch */
+                )?;
                 if unresolved_deps
                     .iter()
                     .any(|(ud, _)| *ud == transitive_dep_target)
@@ -162,6 +257,67 @@ impl Workspace {
         Ok(sorted_deps)
     }
 
+    /// Returns the targets affected by changes to `changed_paths`, which are absolute.
+    /// A target is affected if it contains a changed path or depends on a target that does.
+    /// Changes to files shared by a project's targets (e.g., its manifest) affect all of them.
+    pub fn affected_targets<'a>(&'a self, changed_paths: &[PathBuf]) -> Vec<&'a Target> {
+        let is_changed = |target: &Target| {
+            changed_paths.iter().any(|path| {
+                path.starts_with(&target.path)
+                    || (path.starts_with(target.manifest_dir())
+                        && !target
+                            .project
+                            .targets
+                            .iter()
+                            .any(|t| t != target && path.starts_with(&t.path)))
+            })
+        };
+        let mut affected_targets: Vec<&Target> =
+            self.targets().filter(|target| is_changed(target)).collect();
+        let mut i = 0;
+        while let Some(affected_target) = affected_targets.get(i).copied() {
+            for dependent in self.dependents_of(affected_target) {
+                if !affected_targets.contains(&dependent) {
+                    affected_targets.push(dependent);
+                }
+            }
+            i += 1;
+        }
+        affected_targets
+    }
+
+    /// Returns the targets that directly depend on this `Target`.
+    /// Dependencies that cannot be resolved to a target in this workspace are ignored.
+    pub fn dependents_of<'a>(&'a self, target: &Target) -> Vec<&'a Target> {
+        self.targets()
+            .filter(|candidate| {
+                candidate.dependencies.iter().any(|(dep_name, import_loc)| {
+                    self.lookup_target(candidate, dep_name, import_loc, false /* fetch */)
+                        .map(|dep| dep == target)
+                        .unwrap_or_default()
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the declared dependencies of this `Target` along with the targets that they
+    /// resolve to, if any. Dependencies outside of this workspace (e.g., URLs) resolve to `None`.
+    pub fn resolve_dependencies<'a>(
+        &'a self,
+        target: &'a Target,
+    ) -> Vec<(&'a str, &'a ImportLocation, Option<&'a Target>)> {
+        target
+            .dependencies
+            .iter()
+            .map(|(dep_name, import_loc)| {
+                let dep = self
+                    .lookup_target(target, dep_name, import_loc, false /* fetch */)
+                    .ok();
+                (dep_name.as_str(), import_loc, dep)
+            })
+            .collect()
+    }
+
     pub fn projects_of(&self, targets: &[&Target]) -> Vec<&Project> {
         let mut projects: Vec<&Project> = targets.iter().map(|t| t.project).collect();
         projects.sort_unstable_by_key(|p| *p as *const Project);
@@ -169,16 +325,111 @@ impl Workspace {
         projects
     }
 
+    /// Resolves a dependency of `dependent`. A path in the `patch` section of the dependent's
+    /// project takes precedence over the dependency's declared import location.
+    /// A dependency on a remote Wasm module resolves to a cached download, but only if `fetch`.
     fn lookup_target(
         &self,
+        dependent: &Target,
         name: &str,
         import_loc: &ImportLocation,
-        import_base_path: &Path,
+        fetch: bool,
     ) -> Result<&Target> {
-        let path = match import_loc {
-            ImportLocation::Path(path) => canonicalize_path(import_base_path, path),
-            _ => bail!("unsupported import location: {:?}", import_loc),
+        let patches = &dependent.project.patches;
+        let patched_path = patches.get(name).or_else(|| match import_loc {
+            ImportLocation::Url(url) => patches.get(url.as_str()),
+            ImportLocation::Path(_) => None,
+        });
+        if let Some(path) = patched_path {
+            return self.lookup_local_target(name, Cow::Borrowed(path));
+        }
+        let unresolvable = |reason: &str| -> crate::errors::Error {
+            let import_str = match import_loc {
+                ImportLocation::Path(path) => path.display().to_string(),
+                ImportLocation::Url(url) => url.to_string(),
+            };
+            WorkspaceError::UnresolvableImport(name.to_string(), import_str, reason.to_string())
+                .into()
         };
+        // Every variant is matched so that new kinds of imports must be handled here.
+        match import_loc {
+            ImportLocation::Path(path) => {
+                self.lookup_local_target(name, canonicalize_path(dependent.manifest_dir(), path))
+            }
+            ImportLocation::Url(url) => match url.scheme() {
+                "file" => match url.to_file_path() {
+                    Ok(path) => self.lookup_local_target(name, Cow::Owned(path)),
+                    Err(()) => Err(unresolvable("the URL is not a local file path")),
+                },
+                "http" | "https" if url.path().ends_with(".wasm") => {
+                    if !fetch {
+                        return Err(unresolvable(
+                            "remote modules are only fetched when building",
+                        ));
+                    }
+                    self.fetch_wasm_target(name, url)
+                }
+                "http" | "https" => Err(unresolvable(&format!(
+                    "only URLs of `.wasm` modules can be fetched. To build `{}` from source, \
+                     add its path to the `patch` section of `{}`.",
+                    name,
+                    dependent.project.manifest_path.display()
+                ))),
+                scheme => Err(unresolvable(&format!(
+                    "`{}:` imports are resolved by the build tool, not the workspace. \
+                     To build `{}` from source, add its path to the `patch` section of `{}`.",
+                    scheme,
+                    name,
+                    dependent.project.manifest_path.display()
+                ))),
+            },
+        }
+    }
+
+    /// Downloads the Wasm module at `url`, unless it's already cached, and adds it to the
+    /// workspace as a target named `name`.
+    fn fetch_wasm_target(&self, name: &str, url: &url::Url) -> Result<&Target> {
+        let wasm_path = fetch_wasm(url.as_str())?;
+        if let Some(target) = self
+            .targets()
+            .find(|t| t.name == name && t.path == wasm_path)
+        {
+            return Ok(target);
+        }
+        Ok(self.add_wasm_target(name.to_string(), &wasm_path))
+    }
+
+    /// Adds a raw Wasm module at `path` to the workspace as a target in its own project.
+    fn add_wasm_target(&self, name: String, path: &Path) -> &Target {
+        let mut proj = Box::pin(Project {
+            target_dir: path.parent().unwrap().to_path_buf(),
+            manifest_path: path.to_path_buf(),
+            monorepo_root: None,
+            kind: ProjectKind::Wasm,
+            oasis_std_req: None,
+            oasis_std_version: None,
+            package_name: None,
+            features: BTreeSet::new(),
+            lock_path: None,
+            has_plain_bins: false,
+            patches: BTreeMap::new(),
+            targets: Vec::with_capacity(1),
+        });
+        let proj_ref = unsafe { &*(&*proj as *const Project) }; // @see `struct Workspace`
+        proj.targets.push(Target {
+            name,
+            path: path.to_path_buf(),
+            phases: Phases::BUILD | Phases::CLEAN,
+            dependencies: BTreeMap::new(),
+            project: proj_ref,
+            artifacts: Cell::new(Artifacts::SERVICE),
+        });
+        unsafe { &mut *self.projects.get() }.push(proj); // @see `struct Workspace`
+        self.projects().last().unwrap().targets.first().unwrap()
+    }
+
+    /// Returns the target named `name` in the project that contains `path`.
+    fn lookup_local_target(&self, name: &str, path: Cow<Path>) -> Result<&Target> {
         for proj in self.projects().iter() {
             if !path.starts_with(proj.manifest_path.parent().unwrap())
                 && !path.starts_with(&proj.target_dir)
@@ -194,10 +445,28 @@ impl Workspace {
         Err(WorkspaceError::MissingDependency(format!("{} ({})", name, path.display())).into())
     }
 
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns every target in this workspace.
+    pub fn targets(&self) -> impl Iterator<Item = &Target> {
+        self.projects().iter().flat_map(|proj| proj.targets.iter())
+    }
+
     fn projects(&self) -> &[Pin<Box<Project>>] {
         unsafe { (&*self.projects.get()).as_slice() } // @see `struct Workspace`
     }
 
+    /// Fails with a hint to run `oasis init` if the workspace contains no projects at all,
+    /// which is distinct from none of its targets matching the user's selection.
+    fn check_has_projects(&self) -> Result<()> {
+        if self.projects().is_empty() {
+            return Err(WorkspaceError::NoProjects(self.root.display().to_string()).into());
+        }
+        Ok(())
+    }
+
     fn load_projects_from_manifest(manifest_path: &Path) -> Result<Vec<Pin<Box<Project>>>> {
         debug!(
             "loading projects from manifest: {}",
@@ -239,33 +508,65 @@ impl Workspace {
             )
         })?;
 
+        let lock_path = metadata.workspace_root.join("Cargo.lock");
+        let cargo_lock = CargoLock::load(&lock_path);
         let mut projects = Vec::new();
         for pkg in metadata.packages {
+            let is_oasis_package = pkg.is_oasis_package(cargo_lock.as_ref());
+            let is_app_package = pkg.dependencies.iter().any(|d| d.name == "oasis-client");
+            let manifest_path = PathBuf::from(pkg.manifest_path);
+            let oasis_meta = pkg
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.oasis.as_ref());
+            let patches = oasis_meta
+                .map(|oasis_meta| {
+                    resolve_patches(manifest_path.parent().unwrap(), &oasis_meta.patch)
+                })
+                .unwrap_or_default();
             let mut proj = Box::pin(Project {
                 target_dir: metadata.target_directory.to_path_buf(),
-                manifest_path: PathBuf::from(pkg.manifest_path),
+                manifest_path,
+                monorepo_root: None,
                 kind: ProjectKind::Rust,
+                oasis_std_req: pkg
+                    .dependencies
+                    .iter()
+                    .find(|d| d.name == "oasis-std")
+                    .map(|d| d.req.to_string()),
+                oasis_std_version: cargo_lock
+                    .as_ref()
+                    .and_then(|lock| lock.dependency_version(&pkg, "oasis-std")),
+                package_name: Some(pkg.name.to_string()),
+                features: pkg.declared_features(),
+                lock_path: Some(lock_path.clone()),
+                has_plain_bins: !is_app_package && !is_oasis_package,
+                patches,
                 targets: Vec::new(),
             });
             let proj_ref = unsafe { &*(&*proj as *const Project) }; // @see `struct Workspace`
             for target in pkg.targets {
                 let is_buildable = target.kind[0] == "bin"; // may include unit tests
                 let is_testable = target.kind[0] == "test"; // integration tests
+                let is_benchable = target.kind[0] == "bench";
+                let is_example = target.kind[0] == "example";
 
                 let mut phases = Phases::CLEAN; // Cargo projects are always cleanable
-                if is_buildable {
+                if is_buildable || is_example {
                     phases |= Phases::BUILD;
                 }
                 if is_buildable || is_testable {
                     phases |= Phases::TEST;
                 }
+                if is_benchable {
+                    phases |= Phases::BENCH;
+                }
 
-                let deps = match &pkg.metadata {
-                    Some(metadata) => {
+                let deps = match oasis_meta {
+                    Some(oasis_meta) => {
                         let unpack_dep = |(name, loc): (&String, &ImportLocation)| {
                             (name.to_string(), loc.clone())
                         };
-                        let oasis_meta = &metadata.oasis;
                         let mut deps: BTreeMap<_, _> = oasis_meta
                             .service_dependencies
                             .get(&target.name)
@@ -273,17 +574,23 @@ impl Workspace {
                                 target_meta.dependencies.iter().map(unpack_dep).collect()
                             })
                             .unwrap_or_default();
-                        if is_testable {
+                        if is_testable || is_benchable {
                             deps.extend(oasis_meta.dev_dependencies.iter().map(unpack_dep));
                         }
                         deps
                     }
                     None => BTreeMap::default(),
                 };
-                let artifacts = if pkg.dependencies.iter().any(|d| d.name == "oasis-client") {
+                let artifacts = if is_example {
+                    Artifacts::EXAMPLE
+                } else if is_app_package {
                     Artifacts::APP
-                } else {
+                } else if is_oasis_package {
                     Artifacts::SERVICE
+                } else {
+                    // A plain binary is built natively, like an app, so that it doesn't get
+                    // prepared as a service.
+                    Artifacts::APP
                 };
                 proj.targets.push(Target {
                     project: proj_ref,
@@ -310,7 +617,7 @@ impl Workspace {
             .map(|lerna| !lerna.is_null())
             .unwrap_or_default()
         {
-            return Ok(Vec::new()); // there are subpackages to be found
+            return Self::load_lerna_projects(manifest_path.parent().unwrap());
         }
 
         let oasis_config = manifest.get("oasis").and_then(|oasis| oasis.as_object());
@@ -321,6 +628,15 @@ impl Workspace {
             .and_then(|d| serde_json::from_value::<BTreeMap<String, String>>(d).ok())
             .unwrap_or_default();
 
+        let patches: BTreeMap<_, _> = oasis_config
+            .and_then(|oasis| oasis.get("patch"))
+            .cloned()
+            .and_then(|p| serde_json::from_value::<BTreeMap<String, String>>(p).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(dep, path)| (dep, PathBuf::from(path.trim_start_matches("file:"))))
+            .collect();
+
         let mut phases = Phases::empty();
         if !service_deps.is_empty() {
             phases |= Phases::BUILD;
@@ -351,30 +667,29 @@ impl Workspace {
             .map(PathBuf::from)
             .unwrap_or_else(|| manifest_dir.to_path_buf());
 
-        let tsconfig: Option<serde_json::Map<String, serde_json::Value>> =
-            fs::read(manifest_dir.join("tsconfig.json"))
-                .ok()
-                .and_then(|tsconfig| serde_json::from_slice(&tsconfig).ok());
+        let tsconfig_path = manifest_dir.join("tsconfig.json");
+        let is_typescript = fs::read(&tsconfig_path)
+            .ok()
+            .and_then(|tsconfig| serde_json::from_slice::<serde_json::Value>(&tsconfig).ok())
+            .is_some();
 
         let mut proj = Box::pin(Project {
-            kind: if tsconfig.is_some() {
+            kind: if is_typescript {
                 ProjectKind::TypeScript { clients_dir }
             } else {
                 ProjectKind::JavaScript { clients_dir }
             },
             manifest_path: manifest_path.to_path_buf(),
-            target_dir: tsconfig
-                .and_then(|tsconfig| {
-                    tsconfig
-                        .get("compilerOptions")
-                        .and_then(|opts| opts.as_object())
-                        .and_then(|opts| opts.get("outDir")) // TODO: support `extends`
-                        .map(|out_dir| {
-                            canonicalize_path(manifest_dir, Path::new(out_dir.as_str().unwrap()))
-                                .to_path_buf()
-                        })
-                })
+            monorepo_root: None,
+            oasis_std_req: None,
+            oasis_std_version: None,
+            package_name: None,
+            features: BTreeSet::new(),
+            lock_path: None,
+            has_plain_bins: false,
+            target_dir: tsconfig_out_dir(&tsconfig_path)
                 .unwrap_or_else(|| manifest_dir.to_path_buf()),
+            patches: resolve_patches(manifest_dir, &patches),
             targets: Vec::new(),
         });
 
@@ -410,6 +725,108 @@ impl Workspace {
 
         Ok(vec![proj])
     }
+
+    /// Loads the packages matched by the `packages` globs of a Lerna monorepo's `lerna.json`.
+    /// Packages are found even if they'd otherwise be skipped by the workspace walker.
+    fn load_lerna_projects(lerna_root: &Path) -> Result<Vec<Pin<Box<Project>>>> {
+        let lerna_config: serde_json::Map<String, serde_json::Value> =
+            match fs::read(lerna_root.join("lerna.json")) {
+                Ok(lerna_json) => serde_json::from_slice(&lerna_json).map_err(|e| {
+                    anyhow::anyhow!(
+                        "unable to parse `{}`: {}",
+                        lerna_root.join("lerna.json").display(),
+                        e
+                    )
+                })?,
+                Err(_) => serde_json::Map::new(),
+            };
+        let package_globs = lerna_config
+            .get("packages")
+            .cloned()
+            .and_then(|pkgs| serde_json::from_value::<Vec<String>>(pkgs).ok())
+            .unwrap_or_else(|| vec![DEFAULT_LERNA_PACKAGES.to_string()]);
+
+        let mut overrides = ignore::overrides::OverrideBuilder::new(lerna_root);
+        for package_glob in package_globs.iter() {
+            overrides.add(&format!(
+                "{}/package.json",
+                package_glob.trim_end_matches('/')
+            ))?;
+        }
+        let mut walk_builder = ignore::WalkBuilder::new(lerna_root);
+        walk_builder
+            .overrides(overrides.build()?)
+            .sort_by_file_path(|a, b| a.cmp(b));
+
+        let root_manifest_path = lerna_root.join("package.json");
+        let mut projects = Vec::new();
+        for de in walk_builder.build() {
+            let de = de?;
+            if !de.file_type().map(|ft| ft.is_file()).unwrap_or_default()
+                || de.file_name() != "package.json"
+                || de.path() == root_manifest_path
+            {
+                continue;
+            }
+            for mut proj in Self::load_javascript_projects(de.path())? {
+                if proj.monorepo_root.is_none() {
+                    proj.monorepo_root = Some(lerna_root.to_path_buf());
+                }
+                projects.push(proj);
+            }
+        }
+        Ok(projects)
+    }
+}
+
+const DEFAULT_LERNA_PACKAGES: &str = "packages/*";
+
+/// Returns the hex SHA-256 of `url`, which names cached downloads and imports. Unlike
+/// `DefaultHasher`, the digest is stable across Rust releases, so caches stay valid.
+pub(crate) fn url_hash(url: &str) -> String {
+    use sha2::Digest as _;
+    hex::encode(sha2::Sha256::digest(url.as_bytes()))
+}
+
+/// Returns whether `path` is a file that begins with the Wasm magic number, which lets
+/// a module be built even when it has no `.wasm` extension.
+pub fn is_wasm_file(path: &Path) -> bool {
+    use std::io::Read as _;
+    let mut magic = [0u8; 4];
+    path.is_file()
+        && fs::File::open(path)
+            .and_then(|mut f| f.read_exact(&mut magic))
+            .is_ok()
+        && magic == *b"\0asm"
+}
+
+/// Returns the path of the cached download of the Wasm module at `url`, downloading it first
+/// if needed. Downloads are keyed by the hash of their URL.
+fn fetch_wasm(url: &str) -> Result<PathBuf> {
+    let cache_dir = oasis_xdg_dir!(cache)?.join("wasm");
+    let wasm_path = ensure_dir!(cache_dir)?.join(format!("{}.download", url_hash(url)));
+    if !wasm_path.is_file() {
+        crate::utils::print_status(crate::utils::Status::Downloading, url);
+        download_wasm(url, &wasm_path)
+            .map_err(|e| anyhow!("could not download `{}`: {}", url, e))?;
+    }
+    Ok(wasm_path)
+}
+
+fn download_wasm(url: &str, wasm_path: &Path) -> Result<()> {
+    let mut res = crate::utils::http::ClientBuilder::new(url)
+        .build()?
+        .get("")
+        .send()
+        .and_then(|res| res.error_for_status())
+        .map_err(crate::utils::http::describe_error)?;
+    // Download to a temporary file so that an interrupted download isn't later mistaken
+    // for a cached module.
+    let part_path = wasm_path.with_extension("part");
+    res.copy_to(&mut fs::File::create(&part_path)?)
+        .map_err(crate::utils::http::describe_error)?;
+    fs::rename(&part_path, wasm_path)?;
+    Ok(())
 }
 
 struct TopTargets<'a, 't> {
@@ -423,6 +840,9 @@ struct TopTargets<'a, 't> {
 
     /// Paths to raw Wasm targets
     wasm_paths: BTreeSet<&'t Path>,
+
+    /// URLs of raw Wasm targets that need to be downloaded
+    wasm_urls: BTreeSet<&'t str>,
 }
 
 impl<'a, 't> TopTargets<'a, 't> {
@@ -432,10 +852,18 @@ impl<'a, 't> TopTargets<'a, 't> {
         let mut target_names = BTreeSet::new();
         let mut search_paths = BTreeMap::new();
         let mut wasm_paths = BTreeSet::new();
+        let mut wasm_urls = BTreeSet::new();
 
         for target_str in target_strs {
             let target_path = Path::new(target_str);
-            if target_str.ends_with(".wasm") || *target_str == "a.out" {
+            if (target_str.starts_with("https://") || target_str.starts_with("http://"))
+                && target_str.ends_with(".wasm")
+            {
+                wasm_urls.insert(*target_str);
+                continue;
+            }
+            if target_str.ends_with(".wasm") || *target_str == "a.out" || is_wasm_file(target_path)
+            {
                 wasm_paths.insert(target_path);
                 continue;
             }
@@ -464,11 +892,13 @@ impl<'a, 't> TopTargets<'a, 't> {
             target_names,
             search_paths,
             wasm_paths,
+            wasm_urls,
         }
     }
 
     fn collect_targets(self) -> Result<Vec<&'a Target>> {
         let mut targets = Vec::new();
+        self.collect_remote_wasm_targets(&mut targets)?;
         self.collect_wasm_targets(&mut targets);
         self.collect_path_targets(&mut targets);
         self.collect_named_targets(&mut targets)?;
@@ -481,34 +911,23 @@ impl<'a, 't> TopTargets<'a, 't> {
                 warn!("`{}` does not exist", path.display());
                 continue;
             }
-            let mut proj = Box::pin(Project {
-                target_dir: path.parent().unwrap().to_path_buf(),
-                manifest_path: path.to_path_buf(),
-                kind: ProjectKind::Wasm,
-                targets: Vec::with_capacity(1),
-            });
-            let proj_ref = unsafe { &*(&*proj as *const Project) }; // @see `struct Workspace`
-            proj.targets.push(Target {
-                name: path.to_str().unwrap().to_string(),
-                path: path.to_path_buf(),
-                phases: Phases::BUILD,
-                dependencies: BTreeMap::new(),
-                project: proj_ref,
-                artifacts: Cell::new(Artifacts::SERVICE),
-            });
-            unsafe { &mut *self.workspace.projects.get() }.push(proj); // @see `struct Workspace`
             targets.push(
                 self.workspace
-                    .projects()
-                    .last()
-                    .unwrap()
-                    .targets
-                    .first()
-                    .unwrap(),
+                    .add_wasm_target(path.display().to_string(), path),
             );
         }
     }
 
+    /// Downloads each Wasm URL into the cache and then treats it like a local Wasm target.
+    /// Downloads are keyed by the hash of their URL, so repeat builds reuse the cached module.
+    fn collect_remote_wasm_targets(&self, targets: &mut Vec<&'a Target>) -> Result<()> {
+        for url in self.wasm_urls.iter() {
+            let wasm_path = fetch_wasm(url)?;
+            targets.push(self.workspace.add_wasm_target(url.to_string(), &wasm_path));
+        }
+        Ok(())
+    }
+
     fn collect_path_targets(&self, targets: &mut Vec<&'a Target>) {
         for (path, target_str) in self.search_paths.iter() {
             if !path.exists() {
@@ -562,7 +981,24 @@ impl<'a, 't> TopTargets<'a, 't> {
 pub struct Project {
     pub target_dir: PathBuf,
     pub manifest_path: PathBuf,
+    /// The root of the (e.g., Lerna) monorepo that contains this project, if any.
+    pub monorepo_root: Option<PathBuf>,
     pub kind: ProjectKind,
+    /// The `oasis-std` version requirement of a Rust project that depends on it.
+    pub oasis_std_req: Option<String>,
+    /// The `oasis-std` version in the project's `Cargo.lock`, if it has been resolved.
+    pub oasis_std_version: Option<semver::Version>,
+    /// The name of a Rust project's package.
+    pub package_name: Option<String>,
+    /// The Cargo features that a Rust project declares, including its optional dependencies.
+    pub features: BTreeSet<String>,
+    /// The `Cargo.lock` of a Rust project's Cargo workspace.
+    pub lock_path: Option<PathBuf>,
+    /// Whether a Rust project's bins were loaded as plain binaries rather than services or apps.
+    pub has_plain_bins: bool,
+    /// Local paths that override the import locations of dependencies, keyed by the
+    /// dependency's name or URL.
+    pub patches: BTreeMap<String, PathBuf>,
     pub targets: Vec<Target>,
 }
 
@@ -574,6 +1010,23 @@ pub enum ProjectKind {
     Wasm,
 }
 
+impl Project {
+    /// Returns whether this project's plain bins turn out to be Oasis services because its
+    /// current `Cargo.lock` shows that it depends on `oasis-std` through another crate. The
+    /// lock may not have existed or been up-to-date when the project was loaded.
+    pub fn plain_bins_are_services(&self) -> bool {
+        if !self.has_plain_bins {
+            return false;
+        }
+        match (&self.lock_path, &self.package_name) {
+            (Some(lock_path), Some(package_name)) => CargoLock::load(lock_path)
+                .map(|lock| lock.depends_on(package_name, "oasis-std"))
+                .unwrap_or_default(),
+            _ => false,
+        }
+    }
+}
+
 impl ProjectKind {
     pub fn name(&self) -> &str {
         match self {
@@ -600,10 +1053,19 @@ impl Target {
         self.phases.contains(Phases::BUILD)
     }
 
+    /// Returns whether this is a Cargo example, which is only built when requested.
+    pub fn is_example(&self) -> bool {
+        self.yields_artifact(Artifacts::EXAMPLE)
+    }
+
     pub fn is_testable(&self) -> bool {
         self.phases.contains(Phases::TEST)
     }
 
+    pub fn is_benchable(&self) -> bool {
+        self.phases.contains(Phases::BENCH)
+    }
+
     pub fn is_deployable(&self) -> bool {
         self.phases.contains(Phases::DEPLOY)
     }
@@ -616,6 +1078,11 @@ impl Target {
         self.artifacts.get().intersects(artifact)
     }
 
+    /// Stops this target from yielding the given artifacts (e.g., when they're not wanted).
+    pub fn remove_artifacts(&self, artifacts: Artifacts) {
+        self.artifacts.update(|yielded| yielded - artifacts);
+    }
+
     pub fn manifest_dir(&self) -> &Path {
         self.project.manifest_path.parent().unwrap()
     }
@@ -641,10 +1108,14 @@ impl Target {
         }
     }
 
+    /// Returns the path of the service's built Wasm. A Wasm target is prepared into its input's
+    /// path with a `.wasm` extension, so `a.out` yields `a.wasm` beside it and `service.wasm`
+    /// is prepared in place.
     pub fn wasm_path(&self) -> Option<PathBuf> {
         if self.yields_artifact(Artifacts::SERVICE) {
             Some(match self.project.kind {
                 ProjectKind::Rust => self.artifacts_dir().join(format!("{}.wasm", self.name)),
+                ProjectKind::Wasm => self.path.with_extension("wasm"),
                 _ => unreachable!(),
             })
         } else {
@@ -686,6 +1157,7 @@ bitflags! {
         const TEST   = 0b0000_0010;
         const DEPLOY = 0b0000_0100;
         const CLEAN  = 0b0000_1000;
+        const BENCH  = 0b0001_0000;
     }
 }
 
@@ -695,6 +1167,7 @@ bitflags! {
         const APP               = 0b0000_0010;
         const RUST_CLIENT       = 0b0000_0100;
         const TYPESCRIPT_CLIENT = 0b0000_1000;
+        const EXAMPLE           = 0b0001_0000;
     }
 }
 
@@ -703,11 +1176,13 @@ struct CargoMetadata {
     #[serde(default)]
     packages: Vec<CargoPackage>,
     target_directory: PathBuf,
+    workspace_root: PathBuf,
 }
 
 #[derive(Debug, Deserialize)]
 struct CargoPackage {
     name: String,
+    version: String,
     #[serde(default)]
     targets: Vec<CargoTarget>,
     manifest_path: String,
@@ -715,11 +1190,110 @@ struct CargoPackage {
     metadata: Option<PackageMetadata>,
     #[serde(default)]
     dependencies: Vec<CargoDependency>,
+    #[serde(default)]
+    features: BTreeMap<String, Vec<String>>,
+}
+
+impl CargoPackage {
+    /// Returns the features that `--features` can select, which include optional dependencies.
+    fn declared_features(&self) -> BTreeSet<String> {
+        let optional_deps = self.dependencies.iter().filter(|d| d.optional);
+        self.features
+            .keys()
+            .cloned()
+            .chain(optional_deps.map(|d| d.name.to_string()))
+            .collect()
+    }
+
+    /// Returns whether the package's bins are Oasis services, rather than plain binaries,
+    /// which is so if it has `[package.metadata.oasis]` or depends on `oasis-std`, either
+    /// directly or, according to `cargo_lock`, through other crates.
+    fn is_oasis_package(&self, cargo_lock: Option<&CargoLock>) -> bool {
+        self.dependencies.iter().any(|d| d.name == "oasis-std")
+            || self
+                .metadata
+                .as_ref()
+                .map(|metadata| metadata.oasis.is_some())
+                .unwrap_or_default()
+            || cargo_lock
+                .map(|lock| lock.depends_on(&self.name, "oasis-std"))
+                .unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct CargoDependency {
     name: String,
+    /// The version requirement (e.g., `^0.3`).
+    req: String,
+    #[serde(default)]
+    optional: bool,
+}
+
+/// The packages of a `Cargo.lock`, which record the versions that dependencies resolved to.
+#[derive(Debug, Deserialize)]
+struct CargoLock {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LockedPackage {
+    name: String,
+    version: String,
+    /// Each is either `name` or, if several versions are locked, `name version [source]`.
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+impl CargoLock {
+    fn load(lock_path: &Path) -> Option<Self> {
+        toml::from_str(&fs::read_to_string(lock_path).ok()?).ok()
+    }
+
+    /// Returns whether the package named `pkg_name` depends on `dep_name`, possibly through
+    /// other packages (e.g., a crate that wraps `oasis-std`).
+    fn depends_on<'l>(&'l self, pkg_name: &'l str, dep_name: &str) -> bool {
+        let mut visited = BTreeSet::new();
+        let mut unvisited = vec![pkg_name];
+        while let Some(name) = unvisited.pop() {
+            if !visited.insert(name) {
+                continue;
+            }
+            for locked_pkg in self.package.iter().filter(|p| p.name == name) {
+                for dep in locked_pkg.dependencies.iter() {
+                    let dep = dep.split(' ').next().unwrap();
+                    if dep == dep_name {
+                        return true;
+                    }
+                    unvisited.push(dep);
+                }
+            }
+        }
+        false
+    }
+
+    /// Returns the locked version of `pkg`'s dependency named `dep_name`.
+    fn dependency_version(&self, pkg: &CargoPackage, dep_name: &str) -> Option<semver::Version> {
+        let locked_pkg = self
+            .package
+            .iter()
+            .find(|p| p.name == pkg.name && p.version == pkg.version)?;
+        let dep = locked_pkg
+            .dependencies
+            .iter()
+            .find(|dep| dep.split(' ').next() == Some(dep_name))?;
+        let version = match dep.split(' ').nth(1) {
+            Some(version) => version,
+            None => self
+                .package
+                .iter()
+                .find(|p| p.name == dep_name)?
+                .version
+                .as_str(),
+        };
+        semver::Version::parse(version).ok()
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -731,8 +1305,9 @@ struct CargoTarget {
 
 #[derive(Default, Debug, Deserialize)]
 struct PackageMetadata {
+    /// `[package.metadata.oasis]`, whose presence, even if empty, marks an Oasis package.
     #[serde(default)]
-    oasis: OasisMetadata,
+    oasis: Option<OasisMetadata>,
 }
 
 type ServiceDependencies = BTreeMap<String, ImportLocation>;
@@ -741,6 +1316,8 @@ type ServiceDependencies = BTreeMap<String, ImportLocation>;
 struct OasisMetadata {
     #[serde(default, rename = "dev-dependencies")]
     dev_dependencies: ServiceDependencies,
+    #[serde(default)]
+    patch: BTreeMap<String, PathBuf>,
     #[serde(default, flatten)]
     service_dependencies: BTreeMap<String, OasisDeps>,
 }
@@ -751,6 +1328,91 @@ struct OasisDeps {
     dependencies: ServiceDependencies,
 }
 
+/// Resolves the paths of a manifest's `patch` section against the manifest's directory.
+fn resolve_patches(
+    manifest_dir: &Path,
+    patches: &BTreeMap<String, PathBuf>,
+) -> BTreeMap<String, PathBuf> {
+    patches
+        .iter()
+        .map(|(dep, path)| {
+            (
+                dep.to_string(),
+                canonicalize_path(manifest_dir, path).into_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Returns the effective `compilerOptions.outDir` of a `tsconfig.json`, following `extends`.
+/// Like `tsc`, a relative `outDir` is resolved against the config file that declares it.
+fn tsconfig_out_dir(tsconfig_path: &Path) -> Option<PathBuf> {
+    let mut tsconfig_path = tsconfig_path.to_path_buf();
+    for _ in 0..MAX_TSCONFIG_EXTENDS_DEPTH {
+        let tsconfig: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_slice(&fs::read(&tsconfig_path).ok()?).ok()?;
+        let tsconfig_dir = tsconfig_path.parent().unwrap();
+
+        if let Some(out_dir) = tsconfig
+            .get("compilerOptions")
+            .and_then(|opts| opts.get("outDir"))
+            .and_then(|out_dir| out_dir.as_str())
+        {
+            return Some(canonicalize_path(tsconfig_dir, Path::new(out_dir)).into_owned());
+        }
+
+        let extends = tsconfig.get("extends").and_then(|ext| ext.as_str())?;
+        if !extends.starts_with('.') && !Path::new(extends).is_absolute() {
+            return None; // configs from node modules don't specify a project-relative `outDir`
+        }
+        let extends = if extends.ends_with(".json") {
+            Cow::Borrowed(extends)
+        } else {
+            Cow::Owned(format!("{}.json", extends))
+        };
+        tsconfig_path = canonicalize_path(tsconfig_dir, Path::new(extends.as_ref())).into_owned();
+    }
+    warn!(
+        "`{}` exceeds the maximum `extends` depth",
+        tsconfig_path.display()
+    );
+    None
+}
+
+const MAX_TSCONFIG_EXTENDS_DEPTH: usize = 16;
+
+/// Returns whether `name` matches `glob`, in which `*` matches any run of characters and
+/// `?` matches any one character.
+fn glob_matches(glob: &str, name: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // The positions after the last `*` and at which the name was matched against it,
+    // to which matching backtracks when the rest of the glob doesn't match.
+    let mut backtrack = None;
+    let (mut g, mut n) = (0, 0);
+    while n < name.len() {
+        match glob.get(g) {
+            Some('*') => {
+                backtrack = Some((g + 1, n));
+                g += 1;
+            }
+            Some(&ch) if ch == '?' || ch == name[n] => {
+                g += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_g, star_n)) => {
+                    backtrack = Some((star_g, star_n + 1));
+                    g = star_g;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|ch| *ch == '*')
+}
+
 /// Removes `.` and `..` from `path` given an already-dedotted `base` path.
 fn canonicalize_path<'a>(base: &Path, path: &'a Path) -> Cow<'a, Path> {
     if path.is_absolute() {
@@ -778,6 +1440,45 @@ fn canonicalize_path<'a>(base: &Path, path: &'a Path) -> Cow<'a, Path> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_is_wasm_file() {
+        let tests_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+        assert!(is_wasm_file(&tests_dir.join("res/fixture.wasm")));
+        assert!(!is_wasm_file(&tests_dir.join("res/multiproj/Cargo.toml")));
+        assert!(!is_wasm_file(&tests_dir.join("res")));
+        assert!(!is_wasm_file(&tests_dir.join("does-not-exist")));
+    }
+
+    #[test]
+    fn test_url_hash() {
+        let url = "https://example.com/c.wasm";
+        assert_eq!(
+            url_hash(url),
+            "89abdaa1ce738ec1b7c77dd2443299b2349313d7ab04b7092d2942a5eaea5066"
+        );
+        assert_ne!(url_hash(url), url_hash("http://example.com/c.wasm"));
+    }
+
+    #[test]
+    fn test_remote_wasm_target_strs() {
+        let manifest_path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/res/multiproj/Cargo.toml");
+        let workspace = Workspace {
+            root: manifest_path.parent().unwrap().to_path_buf(),
+            projects: UnsafeCell::new(Workspace::load_cargo_projects(&manifest_path).unwrap()),
+        };
+        let target_strs = [
+            "https://example.com/a.wasm",
+            "http://localhost:8080/b.wasm",
+            "http://localhost:8080/c",
+        ];
+        let top_targets = TopTargets::new(&workspace, &target_strs);
+        assert_eq!(
+            top_targets.wasm_urls.into_iter().collect::<Vec<_>>(),
+            vec!["http://localhost:8080/b.wasm", "https://example.com/a.wasm"]
+        );
+    }
+
     #[test]
     fn test_canonlicalize_abspath() {
         let base = Path::new("/");
@@ -798,4 +1499,267 @@ mod tests {
         let abspath = Path::new("../../../../test");
         assert_eq!(canonicalize_path(&base, &abspath), Path::new("/test"));
     }
+
+    fn dependent_names(workspace: &Workspace, target_name: &str) -> Vec<String> {
+        let target = workspace.targets().find(|t| t.name == target_name).unwrap();
+        let mut names: Vec<_> = workspace
+            .dependents_of(target)
+            .into_iter()
+            .map(|t| t.name.to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_dependents_of() {
+        let manifest_path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/res/multiproj/Cargo.toml");
+        let workspace = Workspace {
+            root: manifest_path.parent().unwrap().to_path_buf(),
+            projects: UnsafeCell::new(Workspace::load_cargo_projects(&manifest_path).unwrap()),
+        };
+        assert_eq!(dependent_names(&workspace, "b"), vec!["a"]);
+        assert_eq!(dependent_names(&workspace, "c"), vec!["a", "b"]);
+        assert!(dependent_names(&workspace, "a").is_empty());
+        assert!(dependent_names(&workspace, "d").is_empty());
+    }
+
+    #[test]
+    fn test_patched_dependency() {
+        let manifest_path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/res/patched/Cargo.toml");
+        let workspace = Workspace {
+            root: manifest_path.parent().unwrap().to_path_buf(),
+            projects: UnsafeCell::new(Workspace::load_cargo_projects(&manifest_path).unwrap()),
+        };
+        assert_eq!(dependent_names(&workspace, "svc"), vec!["app"]);
+
+        let app = workspace.targets().find(|t| t.name == "app").unwrap();
+        let deps = workspace.dependencies_of(app).unwrap();
+        assert_eq!(deps.len(), 1);
+        assert_eq!(deps[0].name, "svc");
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("a", "a"));
+        assert!(!glob_matches("a", "ab"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("test-*", "test-svc"));
+        assert!(!glob_matches("test-*", "svc-test"));
+        assert!(glob_matches("*-svc", "my-svc"));
+        assert!(glob_matches("a*b*c", "aXbYbZc"));
+        assert!(glob_matches("s?c", "svc"));
+        assert!(!glob_matches("s?c", "sc"));
+    }
+
+    #[test]
+    fn test_lookup_target_import_locations() {
+        let manifest_path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/res/multiproj/Cargo.toml");
+        let workspace = Workspace {
+            root: manifest_path.parent().unwrap().to_path_buf(),
+            projects: UnsafeCell::new(Workspace::load_cargo_projects(&manifest_path).unwrap()),
+        };
+        let a = workspace.targets().find(|t| t.name == "a").unwrap();
+        let lookup = |loc: &str| {
+            let url = url::Url::parse(loc).unwrap();
+            workspace.lookup_target(a, "c", &ImportLocation::Url(url), false /* fetch */)
+        };
+
+        let c_url = url::Url::from_file_path(workspace.root.join("target/service/c.wasm"));
+        let c = lookup(c_url.unwrap().as_str()).unwrap();
+        assert_eq!(c.name, "c");
+
+        let err = |loc: &str| lookup(loc).unwrap_err().to_string();
+        assert!(err("https://example.com/c.wasm").contains("only fetched when building"));
+        assert!(err("https://example.com/c").contains("only URLs of `.wasm` modules"));
+        assert!(err("oasis://c").contains("`oasis:` imports are resolved by the build tool"));
+    }
+
+    #[test]
+    fn test_exclude_targets() {
+        let manifest_path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/res/multiproj/Cargo.toml");
+        let workspace = Workspace {
+            root: manifest_path.parent().unwrap().to_path_buf(),
+            projects: UnsafeCell::new(Workspace::load_cargo_projects(&manifest_path).unwrap()),
+        };
+        let names = |targets: &[&Target]| {
+            let mut names: Vec<String> = targets.iter().map(|t| t.name.to_string()).collect();
+            names.sort();
+            names
+        };
+
+        let mut targets: Vec<&Target> = workspace.targets().collect();
+        let removed = workspace
+            .exclude_targets(&mut targets, &["a", "c*"])
+            .unwrap();
+        assert_eq!(names(&removed), vec!["a", "c"]);
+        assert_eq!(names(&targets), vec!["b", "d"]);
+
+        let removed = workspace.exclude_targets(&mut targets, &["?"]).unwrap();
+        assert_eq!(names(&removed), vec!["b", "d"]);
+        assert!(targets.is_empty());
+
+        assert!(workspace
+            .exclude_targets(&mut targets, &["nonexistent"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_load_cargo_examples() {
+        let manifest_path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/res/examples/Cargo.toml");
+        let projects = Workspace::load_cargo_projects(&manifest_path).unwrap();
+        let targets = &projects[0].targets;
+
+        let example = targets.iter().find(|t| t.name == "demo").unwrap();
+        assert!(example.is_example());
+        assert!(example.is_buildable());
+        assert!(!example.is_testable());
+        assert!(!example.is_benchable());
+
+        let bench = targets.iter().find(|t| t.name == "throughput").unwrap();
+        assert!(bench.is_benchable());
+        assert!(!bench.is_buildable());
+        assert!(!bench.is_testable());
+
+        let service = targets.iter().find(|t| t.name == "service").unwrap();
+        assert!(!service.is_example());
+        assert!(service.is_buildable());
+        assert!(service.yields_artifact(Artifacts::SERVICE));
+    }
+
+    #[test]
+    fn test_locked_dependency_version() {
+        let cargo_lock: CargoLock = toml::from_str(
+            r#"
+            [[package]]
+            name = "a"
+            version = "0.1.0"
+            dependencies = ["oasis-std"]
+
+            [[package]]
+            name = "b"
+            version = "0.1.0"
+            dependencies = ["oasis-std 0.2.7 (registry+https://example.com/index)"]
+
+            [[package]]
+            name = "c"
+            version = "0.1.0"
+
+            [[package]]
+            name = "oasis-std"
+            version = "0.3.1"
+            "#,
+        )
+        .unwrap();
+        let pkg = |name: &str| CargoPackage {
+            name: name.to_string(),
+            version: "0.1.0".to_string(),
+            targets: Vec::new(),
+            manifest_path: String::new(),
+            metadata: None,
+            dependencies: Vec::new(),
+            features: BTreeMap::new(),
+        };
+        let version = |name: &str| cargo_lock.dependency_version(&pkg(name), "oasis-std");
+        assert_eq!(version("a"), Some(semver::Version::new(0, 3, 1)));
+        assert_eq!(version("b"), Some(semver::Version::new(0, 2, 7)));
+        assert_eq!(version("c"), None);
+        assert_eq!(version("d"), None);
+    }
+
+    #[test]
+    fn test_wrapped_oasis_std() {
+        let cargo_lock: CargoLock = toml::from_str(
+            r#"
+            [[package]]
+            name = "app"
+            version = "0.1.0"
+            dependencies = ["wrapper", "serde"]
+
+            [[package]]
+            name = "wrapper"
+            version = "0.1.0"
+            dependencies = ["oasis-std 0.3.1 (registry+https://example.com/index)"]
+
+            [[package]]
+            name = "plain"
+            version = "0.1.0"
+            dependencies = ["serde"]
+
+            [[package]]
+            name = "serde"
+            version = "1.0.104"
+
+            [[package]]
+            name = "oasis-std"
+            version = "0.3.1"
+            "#,
+        )
+        .unwrap();
+        assert!(cargo_lock.depends_on("app", "oasis-std"));
+        assert!(cargo_lock.depends_on("wrapper", "oasis-std"));
+        assert!(!cargo_lock.depends_on("plain", "oasis-std"));
+        assert!(!cargo_lock.depends_on("missing", "oasis-std"));
+
+        let pkg = CargoPackage {
+            name: "app".to_string(),
+            version: "0.1.0".to_string(),
+            targets: Vec::new(),
+            manifest_path: String::new(),
+            metadata: None,
+            dependencies: Vec::new(),
+            features: BTreeMap::new(),
+        };
+        assert!(pkg.is_oasis_package(Some(&cargo_lock)));
+        assert!(!pkg.is_oasis_package(None));
+    }
+
+    #[test]
+    fn test_load_cargo_plain_bin() {
+        let manifest_path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/res/plain/Cargo.toml");
+        let projects = Workspace::load_cargo_projects(&manifest_path).unwrap();
+        let bin = projects[0]
+            .targets
+            .iter()
+            .find(|t| t.name == "plain")
+            .unwrap();
+        assert!(bin.is_buildable());
+        assert!(bin.is_testable());
+        assert!(!bin.yields_artifact(Artifacts::SERVICE));
+        assert!(bin.yields_artifact(Artifacts::APP));
+        assert!(bin.wasm_path().is_none());
+    }
+
+    #[test]
+    fn test_load_cargo_oasis_marker() {
+        let manifest_path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/res/marker/Cargo.toml");
+        let projects = Workspace::load_cargo_projects(&manifest_path).unwrap();
+        let bin = projects[0]
+            .targets
+            .iter()
+            .find(|t| t.name == "marker")
+            .unwrap();
+        assert!(bin.yields_artifact(Artifacts::SERVICE));
+        assert!(bin.dependencies.is_empty());
+    }
+
+    #[test]
+    fn test_tsconfig_out_dir_extends() {
+        let fixture_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/res/tsconfig-extends");
+        assert_eq!(
+            tsconfig_out_dir(&fixture_dir.join("app/tsconfig.json")).unwrap(),
+            fixture_dir.join("dist")
+        );
+        assert_eq!(
+            tsconfig_out_dir(&fixture_dir.join("app/nested/tsconfig.json")).unwrap(),
+            fixture_dir.join("dist")
+        );
+    }
 }
@@ -121,6 +121,14 @@ class MockTool:
         """Creates an executable mock tool at the provied location."""
         shutil.copy(self.mock_tool_path, path)
 
+    def create_passthrough_at(self, path, real_tool, subcommand, user_script=''):
+        """Creates an executable mock tool that runs `real_tool` for `subcommand`
+           (e.g., `cargo metadata`, which workspace discovery needs) and mocks the rest."""
+        with open(path, 'w') as f_tool:
+            f_tool.write(f'#!/bin/bash\n[ "$1" = {subcommand} ] && exec {real_tool} "$@"\n')
+            f_tool.write(self.create(user_script).split('\n', 1)[1])
+        os.chmod(path, 0o755)
+
     @staticmethod
     def parse_output(output):
         """Parses a mock tool output into
@@ -163,6 +171,18 @@ def mock_tool():
     return MockTool()
 
 
+@pytest.fixture()
+def mock_cargo(oenv, mock_tool):  # pylint:disable=redefined-outer-name
+    """Returns a function that creates a mock `cargo` that still runs `cargo metadata`
+       and runs `user_script` for everything else. The function returns the mock's path."""
+    def _create(user_script=''):
+        mock_cargo_path = osp.join(oenv.bin_dir, 'cargo')
+        real_cargo = osp.join(oenv.env['CARGO_HOME'], 'bin', 'cargo')
+        mock_tool.create_passthrough_at(mock_cargo_path, real_cargo, 'metadata', user_script)
+        return mock_cargo_path
+    return _create
+
+
 def _mtime(path):
     return max(
         max(osp.getmtime(osp.join(dir_name, f)) for f in files)
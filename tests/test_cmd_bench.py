@@ -0,0 +1,26 @@
+"""Tests `oasis bench`."""
+
+import os
+import os.path as osp
+import shutil
+from subprocess import PIPE
+
+
+def test_bench_rust(oenv, mock_tool, mock_cargo, temp_dir):
+    multiproj_dir = osp.join(temp_dir, 'multiproj')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'), multiproj_dir)
+    os.mkdir(osp.join(multiproj_dir, 'benches'))
+    with open(osp.join(multiproj_dir, 'benches', 'rpcs.rs'), 'w') as f_bench:
+        f_bench.write('fn main() {}\n')
+    oenv.run('git init .', cwd=multiproj_dir)
+
+    cargo = mock_cargo()
+
+    cp = oenv.run('oasis bench rpcs -- --save-baseline main', cwd=multiproj_dir, stdout=PIPE)
+    cargo_args = [inv['args'] for inv in mock_tool.parse_output(cp.stdout)
+                  if inv['name'] == cargo]
+    benches = [args for args in cargo_args if 'bench' in args]
+    assert len(benches) == 1
+    bench_args = benches[0]
+    assert bench_args[bench_args.index('--bench') + 1] == 'rpcs'
+    assert bench_args[-3:] == ['--', '--save-baseline', 'main']
@@ -1,9 +1,14 @@
 """Tests `oasis build`."""
 
+import glob
+import http.server
+import json
 import os
 import os.path as osp
+import re
 import shutil
 from subprocess import PIPE
+import threading
 
 
 def test_build_multiproj(oenv, temp_dir):
@@ -21,6 +26,12 @@ def test_build_multiproj(oenv, temp_dir):
     # test not found
     cp = oenv.run('oasis build e', cwd=multiproj_dir, check=False, stderr=PIPE)
     assert 'no target named `e` found' in cp.stderr
+    assert 'no targets matched `e`' in cp.stderr
+    assert cp.returncode != 0
+
+    cp = oenv.run('oasis test e f', cwd=multiproj_dir, check=False, stderr=PIPE)
+    assert 'no targets matched `e f`' in cp.stderr
+    assert cp.returncode != 0
 
     cp = oenv.run('oasis build ./asdf', cwd=multiproj_dir, check=False, stderr=PIPE)
     assert '`./asdf` does not refer to a target nor a directory' in cp.stderr
@@ -55,3 +66,486 @@ def test_build_multiproj(oenv, temp_dir):
     oenv.run('oasis build :/', cwd=workspace_dir)
     for svc in ['a', 'b', 'c', 'd']:
         assert osp.isfile(osp.join(target_dir, f'{svc}.wasm'))
+
+
+def test_build_artifact_name(oenv, temp_dir):
+    multiproj_dir = osp.join(temp_dir, 'multiproj')
+    target_dir = osp.join(multiproj_dir, 'target', 'service')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'), multiproj_dir)
+    oenv.run('git init .', cwd=multiproj_dir)
+
+    cp = oenv.run('oasis build --artifact-name service', cwd=multiproj_dir, check=False,
+                  stderr=PIPE)
+    assert 'requires exactly one service target' in cp.stderr
+
+    cp = oenv.run('oasis build b --artifact-name c', cwd=multiproj_dir, check=False, stderr=PIPE)
+    assert 'would overwrite the artifact of `c`' in cp.stderr
+
+    # `d` isn't built along with `c`, but its artifact is still protected.
+    cp = oenv.run('oasis build c --artifact-name d', cwd=multiproj_dir, check=False, stderr=PIPE)
+    assert 'would overwrite the artifact of `d`' in cp.stderr
+
+    oenv.run('oasis build b --artifact-name service', cwd=multiproj_dir)
+    with open(osp.join(target_dir, 'b.wasm'), 'rb') as f_b, \
+            open(osp.join(target_dir, 'service.wasm'), 'rb') as f_service:
+        assert f_b.read() == f_service.read()
+
+
+def test_build_lerna(oenv, mock_tool, temp_dir):
+    lerna_dir = osp.join(temp_dir, 'lerna')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'lerna'), lerna_dir)
+    oenv.run('git init .', cwd=lerna_dir)
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'yarn'))
+
+    cp = oenv.run('oasis build', cwd=lerna_dir, stdout=PIPE)
+    [build_invocation] = mock_tool.parse_output(cp.stdout)
+    assert build_invocation['args'][:3] == ['--cwd', osp.join(lerna_dir, 'modules', 'app'), 'build']
+
+
+def test_build_json_messages(oenv, mock_tool, mock_cargo):
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'yarn'))
+    cargo = mock_cargo()
+    proj_dir = oenv.create_project()
+
+    cp = oenv.run('oasis build --message-format json', cwd=proj_dir, stdout=PIPE, stderr=PIPE,
+                  check=False)
+    cargo_builds = [inv for inv in mock_tool.parse_output(cp.stdout)
+                    if inv['name'] == cargo and 'build' in inv['args']]
+    assert cargo_builds
+    assert all('--message-format=json' in inv['args'] for inv in cargo_builds)
+    assert 'Building' not in cp.stderr
+
+
+def test_build_locked_by_default(oenv, mock_tool, mock_cargo):
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'yarn'))
+    cargo = mock_cargo()
+    proj_dir = oenv.create_project()
+
+    for flags, manifest_arg in [('', '--locked'), ('--frozen', '--frozen')]:
+        cp = oenv.run(f'oasis build {flags}', cwd=proj_dir, stdout=PIPE, check=False)
+        cargo_builds = [inv['args'] for inv in mock_tool.parse_output(cp.stdout)
+                        if inv['name'] == cargo and 'build' in inv['args']]
+        assert cargo_builds
+        assert all(manifest_arg in args for args in cargo_builds)
+
+
+def test_build_log_level(oenv, temp_dir):
+    multiproj_dir = osp.join(temp_dir, 'multiproj')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'), multiproj_dir)
+    oenv.run('git init .', cwd=multiproj_dir)
+
+    def build_logs(args, env=None):
+        cp = oenv.run(f'oasis {args}', env=env, cwd=multiproj_dir, check=False, stderr=PIPE)
+        assert 'no targets matched `e`' in cp.stderr
+        return cp.stderr
+
+    assert 'detected workspace' not in build_logs('build e')
+    assert 'detected workspace' in build_logs('build e --log-level debug')
+    assert 'detected workspace' in build_logs('-v build e')
+    assert 'detected workspace' in build_logs('build -v e')
+    # `RUST_LOG` takes precedence over `--log-level`.
+    assert 'detected workspace' not in build_logs('build e --log-level debug',
+                                                  env={'RUST_LOG': 'error'})
+
+
+def test_build_locked_interface(oenv, temp_dir):
+    multiproj_dir = osp.join(temp_dir, 'multiproj')
+    lock_path = osp.join(temp_dir, 'a.interface.json')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'), multiproj_dir)
+    oenv.run('git init .', cwd=multiproj_dir)
+
+    cp = oenv.run(f'oasis build a --locked-interface={temp_dir}', cwd=multiproj_dir, check=False,
+                  stderr=PIPE)
+    assert 'Run with `--update-interface` to create it' in cp.stderr
+
+    oenv.run(f'oasis build a --locked-interface={temp_dir} --update-interface', cwd=multiproj_dir)
+    oenv.run(f'oasis build a --locked-interface={temp_dir}', cwd=multiproj_dir)
+
+    with open(lock_path) as f_iface:
+        iface = json.load(f_iface)
+    iface['functions'].append(dict(iface['functions'][0], name='removed_rpc'))
+    with open(lock_path, 'w') as f_iface:
+        json.dump(iface, f_iface)
+    cp = oenv.run(f'oasis build a --locked-interface={temp_dir}', cwd=multiproj_dir, check=False,
+                  stderr=PIPE)
+    assert 'removed rpc `removed_rpc`' in cp.stderr
+
+    # The interface is checked even when no client is generated.
+    cp = oenv.run(f'oasis build a --locked-interface={temp_dir} --no-clients', cwd=multiproj_dir,
+                  check=False, stderr=PIPE)
+    assert 'removed rpc `removed_rpc`' in cp.stderr
+
+
+def test_build_tool_failure(oenv, mock_cargo):
+    proj_dir = oenv.create_project()
+    mock_cargo('exit 101')
+
+    cp = oenv.run('oasis build', cwd=proj_dir, check=False, stderr=PIPE)
+    assert re.search(r'building `\w+` \(`cargo build`\) failed: '
+                     r'process `cargo` exited with code `101`', cp.stderr)
+
+
+def test_build_target_triple(oenv, mock_tool, mock_cargo):
+    proj_dir = oenv.create_project()
+
+    cp = oenv.run('oasis build --target-triple wasm32-unknown-nowhere', cwd=proj_dir,
+                  check=False, stderr=PIPE)
+    assert 'the `wasm32-unknown-nowhere` target is not installed' in cp.stderr
+    assert cp.returncode != 0
+
+    cargo = mock_cargo()
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'yarn'))
+    cp = oenv.run('oasis build --target-triple wasm32-wasi', cwd=proj_dir, stdout=PIPE)
+    builds = [inv['args'] for inv in mock_tool.parse_output(cp.stdout)
+              if inv['name'] == cargo and '--bin' in inv['args']]
+    assert builds
+    assert any('--target=wasm32-wasi' in args for args in builds)
+
+    # The mock `rustup` reports the triple as installed, and the mock `cargo` builds nothing,
+    # so the missing module shows where the service was expected.
+    mock_rustup = osp.join(oenv.bin_dir, 'rustup')
+    with open(mock_rustup, 'w') as f_rustup:
+        f_rustup.write(mock_tool.create('echo wasm32-unknown-unknown'))
+    os.chmod(mock_rustup, 0o755)
+    cp = oenv.run('oasis build --target-triple wasm32-unknown-unknown', cwd=proj_dir,
+                  stdout=PIPE, stderr=PIPE)
+    builds = [inv['args'] for inv in mock_tool.parse_output(cp.stdout)
+              if inv['name'] == cargo and '--bin' in inv['args']]
+    assert builds
+    assert all('--target=wasm32-unknown-unknown' in args for args in builds)
+    wasm_dir = osp.join(osp.realpath(proj_dir), 'target', 'wasm32-unknown-unknown')
+    assert re.search(re.escape(wasm_dir) + r'/(debug|release)/\w+\.wasm is not a regular file',
+                     cp.stderr)
+
+
+def test_build_path_with_spaces(oenv, temp_dir):
+    proj_dir = osp.join(temp_dir, 'my project')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'), proj_dir)
+    oenv.run('git init .', cwd=proj_dir)
+
+    oenv.run('oasis build c', cwd=proj_dir)
+    assert osp.isfile(osp.join(proj_dir, 'target', 'service', 'c.wasm'))
+
+    oenv.run('oasis build', cwd=osp.join(proj_dir, 'src'))
+    assert osp.isfile(osp.join(proj_dir, 'target', 'service', 'a.wasm'))
+
+
+def test_build_all(oenv, mock_tool, mock_cargo, temp_dir):
+    multiproj_dir = osp.join(temp_dir, 'multiproj')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'), multiproj_dir)
+    oenv.run('git init .', cwd=multiproj_dir)
+    docs_dir = osp.join(multiproj_dir, 'docs')
+    os.mkdir(docs_dir)
+
+    mock_cargo()
+
+    def _built_bins(cmd):
+        cp = oenv.run(cmd, cwd=docs_dir, stdout=PIPE, stderr=PIPE)
+        return {inv['args'][inv['args'].index('--bin') + 1]
+                for inv in mock_tool.parse_output(cp.stdout) if '--bin' in inv['args']}
+
+    assert not _built_bins('oasis build')
+    assert _built_bins('oasis build --all') == {'a', 'b', 'c', 'd'}
+
+    cp = oenv.run('oasis build --all a', cwd=docs_dir, stderr=PIPE, check=False)
+    assert 'cannot be used with' in cp.stderr
+
+
+def test_build_exclude(oenv, mock_tool, mock_cargo, temp_dir):
+    multiproj_dir = osp.join(temp_dir, 'multiproj')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'), multiproj_dir)
+    oenv.run('git init .', cwd=multiproj_dir)
+
+    mock_cargo()
+
+    def _build(cmd):
+        cp = oenv.run(cmd, cwd=multiproj_dir, stdout=PIPE, stderr=PIPE)
+        built_bins = {inv['args'][inv['args'].index('--bin') + 1]
+                      for inv in mock_tool.parse_output(cp.stdout) if '--bin' in inv['args']}
+        return built_bins, cp.stderr
+
+    # `d` is a leaf, so excluding it skips it.
+    built_bins, stderr = _build('oasis build --exclude d')
+    assert built_bins == {'a', 'b', 'c'}
+    assert 'was excluded' not in stderr
+
+    # `a` depends on `b` and `c`, so they're built anyway.
+    built_bins, stderr = _build('oasis build a --exclude b --exclude "c*"')
+    assert built_bins == {'a', 'b', 'c'}
+    assert '`b` was excluded, but will be built because other targets depend on it' in stderr
+    assert '`c` was excluded' in stderr
+
+
+def test_build_a_out(oenv, temp_dir):
+    fixture_wasm = osp.join(osp.dirname(__file__), 'res', 'fixture.wasm')
+    shutil.copy(fixture_wasm, osp.join(temp_dir, 'a.out'))
+    shutil.copy(fixture_wasm, osp.join(temp_dir, 'bare'))
+    oenv.run('git init .', cwd=temp_dir)
+
+    oenv.run('oasis build a.out bare', cwd=temp_dir)
+    assert osp.isfile(osp.join(temp_dir, 'a.wasm'))
+    assert osp.isfile(osp.join(temp_dir, 'bare.wasm'))
+
+    oenv.run('oasis clean a.out', cwd=temp_dir)
+    assert not osp.isfile(osp.join(temp_dir, 'a.wasm'))
+    assert osp.isfile(osp.join(temp_dir, 'a.out'))
+    assert osp.isfile(osp.join(temp_dir, 'bare.wasm'))
+
+
+def test_build_wasm_opt(oenv, mock_tool, temp_dir):
+    shutil.copy(osp.join(osp.dirname(__file__), 'res', 'fixture.wasm'),
+                osp.join(temp_dir, 'a.out'))
+    oenv.run('git init .', cwd=temp_dir)
+    # `wasm-opt`'s output is captured, so the mock logs its invocations to a file instead.
+    mock_log = osp.join(temp_dir, 'wasm-opt.log')
+    mock_wasm_opt = osp.join(oenv.bin_dir, 'wasm-opt')
+    with open(mock_wasm_opt, 'w') as f_tool:
+        f_tool.write(mock_tool.create().replace('\n', f'\nexec >> {mock_log}\n', 1))
+    os.chmod(mock_wasm_opt, 0o755)
+
+    oenv.run('oasis build --wasm-opt a.out', cwd=temp_dir)
+    cp = oenv.run('oasis build --wasm-opt=2 a.out', cwd=temp_dir, stderr=PIPE)
+    assert re.search(r'Optimized a\.wasm \(\d+ -> \d+ bytes\)', cp.stderr)
+    with open(mock_log) as f_log:
+        invocations = mock_tool.parse_output(f_log.read())
+    assert [inv['args'] for inv in invocations] == [
+        [level, '--all-features', '--debuginfo', 'a.wasm', '-o', 'a.wasm']
+        for level in ['-Oz', '-O2']
+    ]
+
+
+def test_build_print_artifact(oenv, temp_dir):
+    fixture_wasm = osp.join(osp.dirname(__file__), 'res', 'fixture.wasm')
+    shutil.copy(fixture_wasm, osp.join(temp_dir, 'a.out'))
+    shutil.copy(fixture_wasm, osp.join(temp_dir, 'bare'))
+    oenv.run('git init .', cwd=temp_dir)
+
+    real_dir = osp.realpath(temp_dir)
+
+    cp = oenv.run('oasis build --print-artifact a.out', cwd=temp_dir, stdout=PIPE)
+    assert cp.stdout == osp.join(real_dir, 'a.wasm') + '\n'
+
+    cp = oenv.run('oasis build --print-artifact a.out bare', cwd=temp_dir, stdout=PIPE)
+    assert cp.stdout.splitlines() == [osp.join(real_dir, 'a.wasm'),
+                                      osp.join(real_dir, 'bare.wasm')]
+
+    multiproj_dir = osp.join(temp_dir, 'multiproj')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'), multiproj_dir)
+    cp = oenv.run('oasis build --print-artifact', cwd=multiproj_dir, stderr=PIPE, check=False)
+    assert cp.returncode != 0
+    assert '`--print-artifact` found 4 services' in cp.stderr
+
+
+def test_build_remote_wasm(oenv, temp_dir):
+    with open(osp.join(osp.dirname(__file__), 'res', 'fixture.wasm'), 'rb') as f_wasm:
+        fixture_wasm = f_wasm.read()
+    requests = []
+
+    class WasmHandler(http.server.BaseHTTPRequestHandler):
+        def do_GET(self):  # pylint:disable=invalid-name
+            requests.append(self.path)
+            self.send_response(200)
+            self.send_header('Content-Length', len(fixture_wasm))
+            self.end_headers()
+            self.wfile.write(fixture_wasm)
+
+        def log_message(self, *_args):  # pylint:disable=arguments-differ
+            pass
+
+    server = http.server.HTTPServer(('localhost', 0), WasmHandler)
+    threading.Thread(target=server.serve_forever, daemon=True).start()
+    url = f'http://localhost:{server.server_address[1]}/remote.wasm'
+    cache_dir = osp.realpath(osp.join(oenv.home_dir, '.cache', 'oasis', 'wasm'))
+    oenv.run('git init .', cwd=temp_dir)
+    try:
+        for _ in range(2):
+            cp = oenv.run(f'oasis build --print-artifact {url}', cwd=temp_dir, stdout=PIPE)
+            artifact = osp.realpath(cp.stdout.rstrip())
+            assert osp.dirname(artifact) == cache_dir
+            assert osp.isfile(artifact)
+    finally:
+        server.shutdown()
+    assert requests == ['/remote.wasm']  # the second build uses the cached download
+
+
+def test_build_without_git(oenv, temp_dir):
+    # An unpacked tarball of the fixture has no `.git`, so the root is the nearest manifest dir.
+    proj_dir = osp.join(temp_dir, 'monorepo', 'multiproj')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'), proj_dir)
+
+    oenv.run('oasis build c', cwd=osp.join(proj_dir, 'src'))
+    assert osp.isfile(osp.join(proj_dir, 'target', 'service', 'c.wasm'))
+
+    monorepo_dir = osp.dirname(proj_dir)
+    cp = oenv.run('oasis build', cwd=monorepo_dir, stderr=PIPE, check=False)
+    assert cp.returncode != 0
+    assert 'could not find workspace' in cp.stderr
+
+    oenv.run('oasis build --workspace-root . d', cwd=monorepo_dir)
+    assert osp.isfile(osp.join(proj_dir, 'target', 'service', 'd.wasm'))
+
+
+def test_build_empty_workspace(oenv, temp_dir):
+    oenv.run('git init .', cwd=temp_dir)
+    cp = oenv.run('oasis build', cwd=temp_dir, stderr=PIPE, check=False)
+    assert cp.returncode != 0
+    assert f'no Oasis projects found under `{osp.realpath(temp_dir)}`' in cp.stderr
+    assert 'oasis init' in cp.stderr
+
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'),
+                    osp.join(temp_dir, 'multiproj'))
+    cp = oenv.run('oasis build nonexistent', cwd=temp_dir, stderr=PIPE, check=False)
+    assert cp.returncode != 0
+    assert 'no targets matched `nonexistent`' in cp.stderr
+    assert 'no Oasis projects' not in cp.stderr
+
+
+def test_build_removes_stale_clients(oenv, mock_tool, temp_dir):
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'),
+                    osp.join(temp_dir, 'multiproj'))
+    app_dir = osp.join(temp_dir, 'app')
+    os.mkdir(app_dir)
+    with open(osp.join(app_dir, 'tsconfig.json'), 'w') as f_tsconfig:
+        f_tsconfig.write('{}')
+    with open(osp.join(app_dir, 'index.ts'), 'w') as f_index:
+        f_index.write('export {};\n')
+    oenv.run('git init .', cwd=temp_dir)
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'yarn'))
+
+    def _build_with_deps(deps):
+        with open(osp.join(app_dir, 'package.json'), 'w') as f_manifest:
+            json.dump({
+                'name': 'app',
+                'scripts': {'build': 'tsc'},
+                'oasis': {'serviceDependencies': {dep: 'file:../multiproj' for dep in deps}},
+            }, f_manifest)
+        oenv.run('oasis build', cwd=app_dir)
+
+    _build_with_deps(['c', 'd'])
+    assert osp.isfile(osp.join(app_dir, 'c.ts'))
+    assert osp.isfile(osp.join(app_dir, 'd.ts'))
+
+    _build_with_deps(['c'])
+    assert osp.isfile(osp.join(app_dir, 'c.ts'))
+    assert not osp.isfile(osp.join(app_dir, 'd.ts'))
+    assert osp.isfile(osp.join(app_dir, 'index.ts'))
+    assert not [f for f in os.listdir(app_dir) if f.endswith('.part')]
+
+
+def test_build_no_clients(oenv, mock_tool, temp_dir):
+    service_dir = osp.join(temp_dir, 'multiproj', 'target', 'service')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'),
+                    osp.join(temp_dir, 'multiproj'))
+    app_dir = osp.join(temp_dir, 'app')
+    os.mkdir(app_dir)
+    with open(osp.join(app_dir, 'tsconfig.json'), 'w') as f_tsconfig:
+        f_tsconfig.write('{}')
+    with open(osp.join(app_dir, 'package.json'), 'w') as f_manifest:
+        json.dump({
+            'name': 'app',
+            'scripts': {'build': 'tsc'},
+            'oasis': {'serviceDependencies': {'a': 'file:../multiproj'}},
+        }, f_manifest)
+    oenv.run('git init .', cwd=temp_dir)
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'yarn'))
+
+    oenv.run('oasis build', cwd=app_dir)
+    for svc in ['a', 'b', 'c']:
+        assert osp.isfile(osp.join(service_dir, f'{svc}.ts'))
+
+    # `app` depends on `a`, which depends on `b` and `c`, so `app` needs all of their clients.
+    cp = oenv.run('oasis build --no-clients', cwd=app_dir, stderr=PIPE)
+    for svc in ['a', 'b', 'c']:
+        assert f'regenerating the client for `{svc}`, which `app` needs' in cp.stderr
+        assert osp.isfile(osp.join(service_dir, f'{svc}.ts'))
+    assert osp.isfile(osp.join(app_dir, 'a.ts'))
+
+    # Without `app` in the build, the clients would be stale, so they're removed.
+    oenv.run('oasis build --no-clients', cwd=osp.join(temp_dir, 'multiproj'))
+    for svc in ['a', 'b', 'c']:
+        assert not osp.isfile(osp.join(service_dir, f'{svc}.ts'))
+
+
+def test_build_if_changed(oenv, temp_dir):
+    proj_dir = osp.join(temp_dir, 'multiproj')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'), proj_dir)
+    oenv.run('git init .', cwd=proj_dir)
+
+    def _build(args=''):
+        cp = oenv.run(f'oasis build --if-changed {args} b', cwd=proj_dir, stderr=PIPE)
+        return (set(re.findall(r'Building (\w+)', cp.stderr)),
+                set(re.findall(r'Fresh (\w+)', cp.stderr)))
+
+    assert _build() == ({'b', 'c'}, set())
+    assert osp.isfile(osp.join(proj_dir, 'target', '.oasis-build-cache.json'))
+    assert _build() == (set(), {'b', 'c'})
+
+    with open(osp.join(proj_dir, 'src', 'bin', 'c.rs'), 'a') as f_c:
+        f_c.write('\n// changed\n')
+    assert _build() == ({'b', 'c'}, set())
+
+    os.remove(osp.join(proj_dir, 'target', 'service', 'b.wasm'))
+    built, fresh = _build()
+    assert built == {'b'} and fresh == {'c'}
+
+    # Options that change the output also invalidate the cache.
+    assert _build('--strip all') == ({'b', 'c'}, set())
+    assert _build('--strip all') == (set(), {'b', 'c'})
+    assert _build('--strip all --all-features') == ({'b', 'c'}, set())
+
+    # As do changes to local path dependencies outside of the project.
+    dep_dir = osp.join(temp_dir, 'pathdep')
+    os.makedirs(osp.join(dep_dir, 'src'))
+    with open(osp.join(dep_dir, 'Cargo.toml'), 'w') as f_manifest:
+        f_manifest.write('[package]\nname = "pathdep"\nversion = "0.1.0"\nedition = "2018"\n')
+    with open(osp.join(dep_dir, 'src', 'lib.rs'), 'w') as f_lib:
+        f_lib.write('pub fn dep() {}\n')
+    manifest_path = osp.join(proj_dir, 'Cargo.toml')
+    with open(manifest_path) as f_manifest:
+        manifest = f_manifest.read()
+    with open(manifest_path, 'w') as f_manifest:
+        f_manifest.write(manifest.replace(
+            '[dependencies]\n', '[dependencies]\npathdep = { path = "../pathdep" }\n'))
+    assert _build() == ({'b', 'c'}, set())
+    assert _build() == (set(), {'b', 'c'})
+    with open(osp.join(dep_dir, 'src', 'lib.rs'), 'a') as f_lib:
+        f_lib.write('// changed\n')
+    assert _build() == ({'b', 'c'}, set())
+
+
+def test_build_explain(oenv, temp_dir):
+    proj_dir = osp.join(temp_dir, 'multiproj')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'), proj_dir)
+    oenv.run('git init .', cwd=proj_dir)
+
+    cp = oenv.run('oasis build --explain a', cwd=proj_dir, stdout=PIPE, stderr=PIPE)
+    explanations = {}
+    for line in cp.stderr.split('Build plan:\n', 1)[1].splitlines():
+        if re.match(r'  \S', line):
+            name = line.split()[0]
+            explanations[name] = ''
+        elif line.startswith('    '):
+            explanations[name] += line + '\n'
+    assert 'included: named directly' in explanations['a']
+    assert 'dependency of `a`' in explanations['b']
+    assert re.search(r'dependency of `\w`, `\w`', explanations['c'])
+    assert 'Rust client (`a` depends on it)' in explanations['c']
+    assert 'build tool: cargo' in explanations['a']
+
+
+def test_build_interface_only(oenv, temp_dir):
+    multiproj_dir = osp.join(temp_dir, 'multiproj')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'), multiproj_dir)
+    oenv.run('git init .', cwd=multiproj_dir)
+
+    oenv.run('oasis build --interface-only b --out-dir ifaces', cwd=multiproj_dir)
+    with open(osp.join(multiproj_dir, 'ifaces', 'b.json')) as f_iface:
+        assert 'functions' in json.load(f_iface)
+    # The dependency is built, but only the selected service's interface is written.
+    assert osp.isfile(osp.join(multiproj_dir, 'target', 'service', 'c.wasm'))
+    assert os.listdir(osp.join(multiproj_dir, 'ifaces')) == ['b.json']
+    assert not glob.glob(osp.join(multiproj_dir, '**', '*.ts'), recursive=True)
+
+    cp = oenv.run('oasis build --out-dir ifaces b', cwd=multiproj_dir, check=False, stderr=PIPE)
+    assert cp.returncode != 0
+    assert '--interface-only' in cp.stderr
@@ -0,0 +1,123 @@
+"""Tests `oasis chain`."""
+
+import os
+import os.path as osp
+import re
+import socket
+import time
+from subprocess import PIPE
+
+LISTEN_SCRIPT = """
+import socket
+import sys
+
+PORT = int(sys.argv[sys.argv.index(sys.argv[1], 2) + 1])
+with socket.socket() as listener:
+    listener.settimeout(30)
+    listener.bind(('127.0.0.1', PORT))
+    listener.listen()
+    listener.accept()
+"""
+
+
+def _arg(invocation, flag):
+    return invocation['args'][invocation['args'].index(flag) + 1]
+
+
+def test_chain_auto_port(oenv, mock_tool, temp_dir):
+    mock_chain = osp.join(oenv.bin_dir, 'oasis-chain')
+    mock_tool.create_at(mock_chain)
+    # The gateway's output is discarded, so the mock logs its invocations to a file instead.
+    gateway_log = osp.join(temp_dir, 'oasis-gateway.log')
+    mock_gateway = osp.join(oenv.bin_dir, 'oasis-gateway')
+    with open(mock_gateway, 'w') as f_tool:
+        f_tool.write(mock_tool.create().replace('\n', f'\nexec > {gateway_log}\n', 1))
+    os.chmod(mock_gateway, 0o755)
+
+    def _run_chain(args=''):
+        cp = oenv.run(f'oasis chain {args}', stdout=PIPE, stderr=PIPE)
+        [chain] = mock_tool.parse_output(cp.stdout)
+        with open(gateway_log) as f_log:
+            [gateway] = mock_tool.parse_output(f_log.read())
+        return chain, gateway, cp.stderr
+
+    chain, gateway, stderr = _run_chain()
+    assert chain['args'] == ['--ws-port', '8546']
+    assert _arg(gateway, '--bind_public.http_port') == '1234'
+    assert _arg(gateway, '--bind_private.http_port') == '1235'
+    assert 'local chain at ws://localhost:8546' in stderr
+    assert 'gateway at http://localhost:1234' in stderr
+
+    chain, gateway, stderr = _run_chain('--eth-port 0')
+    eth_port = _arg(chain, '--ws-port')
+    gateway_port = _arg(gateway, '--bind_public.http_port')
+    ports = {eth_port, gateway_port, _arg(gateway, '--bind_private.http_port')}
+    assert len(ports) == 3 and not ports & {'0', '8546', '1234', '1235'}
+    assert _arg(gateway, '--eth.url') == f'ws://localhost:{eth_port}'
+    assert re.search(rf'local chain at ws://localhost:{eth_port}\b', stderr)
+    assert re.search(rf'gateway at http://localhost:{gateway_port}\b', stderr)
+
+
+def test_chain_wait_for_ready(oenv, mock_tool, temp_dir):
+    mock_chain = osp.join(oenv.bin_dir, 'oasis-chain')
+    mock_gateway = osp.join(oenv.bin_dir, 'oasis-gateway')
+    mock_tool.create_at(mock_chain)
+    mock_tool.create_at(mock_gateway)
+
+    cp = oenv.run('oasis chain --detach', stdout=PIPE, stderr=PIPE)
+    assert 'Ready' not in cp.stderr
+
+    # The mock chain exits without listening, so it never becomes ready.
+    cp = oenv.run('oasis chain --detach --wait-for-ready=1', stderr=PIPE, check=False)
+    assert cp.returncode != 0
+    assert 'did not start listening on `ws://localhost:8546/` within 1s' in cp.stderr
+
+    with socket.socket() as listener:
+        listener.bind(('127.0.0.1', 0))
+        listener.listen()
+        eth_port = listener.getsockname()[1]
+        cp = oenv.run(f'oasis chain --detach --wait-for-ready=1 --eth-port {eth_port}',
+                      stderr=PIPE, check=False)
+    # The chain is up, but the mock gateway isn't.
+    assert cp.returncode != 0
+    assert f'Ready local chain at ws://localhost:{eth_port}/' in cp.stderr
+    assert 'did not start listening on `http://localhost:1234/` within 1s' in cp.stderr
+
+    # Each mock listens on its port until the first connection, as the readiness check makes.
+    listen_script = osp.join(temp_dir, 'listen.py')
+    with open(listen_script, 'w') as f_script:
+        f_script.write(LISTEN_SCRIPT)
+    for mock, port_flag in [(mock_chain, '--ws-port'), (mock_gateway, '--bind_public.http_port')]:
+        with open(mock, 'w') as f_tool:
+            f_tool.write(mock_tool.create(f'exec python3 {listen_script} {port_flag} "$@"'))
+    cp = oenv.run('oasis chain --detach --wait-for-ready=10 --eth-port 0', stderr=PIPE)
+    eth_url, gateway_url = re.findall(r'Starting (?:local chain|gateway) at (\S+)', cp.stderr)
+    assert f'Ready local chain at {eth_url}/' in cp.stderr
+    assert f'Ready gateway at {gateway_url}/' in cp.stderr
+
+
+def test_chain_args(oenv, mock_tool, temp_dir):
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'oasis-chain'))
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'oasis-gateway'))
+
+    cp = oenv.run('oasis chain --help', stdout=PIPE)
+    for flag in ['--eth-port', '--detach', '--wait-for-ready', '--log-dir']:
+        assert flag in cp.stdout
+
+    cp = oenv.run('oasis chain --no-such-flag', stderr=PIPE, check=False)
+    assert cp.returncode != 0
+
+    cp = oenv.run('oasis chain -- --no-such-flag 1', stdout=PIPE)
+    [chain] = mock_tool.parse_output(cp.stdout)
+    assert chain['args'] == ['--ws-port', '8546', '--no-such-flag', '1']
+
+    log_dir = osp.join(temp_dir, 'logs')
+    oenv.run(f'oasis chain --detach --log-dir {log_dir} -- --no-such-flag 1')
+    log_path = osp.join(log_dir, 'oasis-chain.log')
+    for _ in range(50):  # The detached mock may not have written its log yet.
+        if osp.getsize(log_path) > 0:
+            break
+        time.sleep(0.1)
+    with open(log_path) as f:
+        [chain] = mock_tool.parse_output(f.read())
+    assert chain['args'] == ['--ws-port', '8546', '--no-such-flag', '1']
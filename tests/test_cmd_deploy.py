@@ -1,6 +1,9 @@
 """Tests `oasis deploy`."""
 
+import json
 import os.path as osp
+import shutil
+import tarfile
 from subprocess import PIPE
 
 import pytest
@@ -36,3 +39,30 @@ def test_deploy_profile(oenv, mock_tool):
 
     cp = oenv.run('oasis deploy --profile local', cwd=app_dir, stdout=PIPE)
     assert mock_tool.parse_output(cp.stdout)[1]['env']['OASIS_PROFILE'] == 'local'
+
+
+def test_deploy_to_file(oenv, temp_dir):
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'),
+                    osp.join(temp_dir, 'multiproj'))
+    proj_dir = osp.join(temp_dir, 'multiproj')
+    oenv.run('git init .', cwd=proj_dir)
+
+    bundle_path = osp.join(temp_dir, 'bundle.tar.gz')
+    oenv.run(f'oasis deploy --profile local --to-file {bundle_path} b', cwd=proj_dir)
+
+    with tarfile.open(bundle_path, 'r:gz') as bundle:
+        assert set(bundle.getnames()) == {'b.wasm', 'b.json', 'c.wasm', 'c.json', 'manifest.json'}
+        manifest = json.load(bundle.extractfile('manifest.json'))
+        iface = json.load(bundle.extractfile('b.json'))
+    services = {svc['name']: svc for svc in manifest['services']}
+    assert set(services) == {'b', 'c'}
+    assert services['b']['constructor'] == iface['constructor']
+    assert manifest['gateway'].startswith('ws')
+
+    # The bundle is deployed later, so writing it doesn't need a credential.
+    with open(oenv.config_file, 'a') as f_config:
+        f_config.write('\n[profile.nocred]\ngateway = "http://localhost:1234"\n')
+    oenv.run(f'oasis deploy --profile nocred --to-file {bundle_path} b', cwd=proj_dir)
+    with tarfile.open(bundle_path, 'r:gz') as bundle:
+        manifest = json.load(bundle.extractfile('manifest.json'))
+    assert manifest['gateway'] == 'http://localhost:1234/'
@@ -0,0 +1,47 @@
+"""Tests `oasis ifdiff`."""
+
+import json
+import os.path as osp
+from subprocess import PIPE
+
+FIXTURE_WASM = osp.abspath(osp.join(osp.dirname(__file__), 'res', 'fixture.wasm'))
+
+
+def test_ifdiff(oenv):
+    oenv.run(f'oasis ifextract {FIXTURE_WASM}')
+    old_path = osp.join(oenv.home_dir, 'Fixture.json')
+    with open(old_path) as f_iface:
+        iface = json.load(f_iface)
+    iface['functions'].append(dict(iface['functions'][0], name='added_rpc'))
+    with open(osp.join(oenv.home_dir, 'Added.json'), 'w') as f_iface:
+        json.dump(iface, f_iface)
+    existing_rpcs = [f['name'] for f in iface['functions'][:-1]]
+    iface['functions'].insert(0, iface['functions'].pop())
+    with open(osp.join(oenv.home_dir, 'Inserted.json'), 'w') as f_iface:
+        json.dump(iface, f_iface)
+
+    cp = oenv.run(f'oasis ifdiff --json Fixture.json {FIXTURE_WASM}', stdout=PIPE)
+    diff = json.loads(cp.stdout)
+    assert diff['functions'] == {'added': [], 'removed': [], 'changed': []}
+    assert not diff['breaking']
+
+    cp = oenv.run('oasis ifdiff --json Fixture.json Added.json', stdout=PIPE)
+    diff = json.loads(cp.stdout)
+    assert diff['functions']['added'] == ['added_rpc']
+    assert not diff['breaking']
+
+    cp = oenv.run('oasis ifdiff --json Added.json Fixture.json', stdout=PIPE)
+    diff = json.loads(cp.stdout)
+    assert diff['functions']['removed'] == ['added_rpc']
+    assert diff['breaking']
+
+    cp = oenv.run('oasis ifdiff Added.json Fixture.json', stdout=PIPE)
+    assert 'removed rpc `added_rpc`' in cp.stdout
+    assert 'would break existing clients' in cp.stdout
+
+    # Inserting an rpc shifts the indices by which clients call the existing ones.
+    cp = oenv.run('oasis ifdiff --json Fixture.json Inserted.json', stdout=PIPE)
+    diff = json.loads(cp.stdout)
+    assert diff['functions']['added'] == ['added_rpc']
+    assert sorted(diff['functions']['changed']) == sorted(existing_rpcs)
+    assert diff['breaking']
@@ -27,3 +27,25 @@ def test_ifextract_to_dir(oenv):
 def test_ifextract_to_stdout(oenv):
     output = oenv.run(f'oasis ifextract file://{FIXTURE_WASM} --out -', stdout=PIPE)
     assert _iface_is_sane(json.loads(output.stdout))
+
+
+def test_ifextract_to_relative_dir(oenv):
+    cwd = osp.join(oenv.home_dir, 'cwd')
+    oenv.run(f'mkdir {cwd}')
+    oenv.run(f'oasis ifextract {FIXTURE_WASM} -o iface_dir/nested', cwd=cwd)
+    with open(osp.join(cwd, 'iface_dir', 'nested', 'Fixture.json')) as f_iface:
+        assert _iface_is_sane(json.load(f_iface))
+
+
+def test_ifextract_missing_file(oenv):
+    cp = oenv.run('oasis ifextract missing.wasm', check=False, stderr=PIPE)
+    assert cp.returncode != 0
+    assert 'could not import `missing.wasm`: no such file' in cp.stderr
+
+
+def test_ifextract_malformed_wasm(oenv):
+    with open(osp.join(oenv.home_dir, 'bad.wasm'), 'wb') as f_wasm:
+        f_wasm.write(b'not wasm')
+    cp = oenv.run('oasis ifextract bad.wasm', check=False, stderr=PIPE)
+    assert cp.returncode != 0
+    assert 'could not import `bad.wasm`: not a valid service' in cp.stderr
@@ -0,0 +1,79 @@
+"""Tests `oasis init`."""
+
+import os
+import os.path as osp
+from subprocess import PIPE
+
+
+def test_init_template(oenv, temp_dir):
+    template_dir = osp.join(temp_dir, 'template')
+    os.makedirs(osp.join(template_dir, 'src'))
+    os.makedirs(osp.join(template_dir, 'target'))
+    with open(osp.join(template_dir, 'Cargo.toml'), 'w') as f_manifest:
+        f_manifest.write('[package]\nname = "quickstart"\n')
+    with open(osp.join(template_dir, 'src', 'main.rs'), 'w') as f_main:
+        f_main.write('struct Quickstart;\n')
+    with open(osp.join(template_dir, 'target', 'stale.wasm'), 'wb') as f_wasm:
+        f_wasm.write(b'\0asm')
+
+    proj_dir = osp.join(temp_dir, 'my_proj')
+    oenv.run(f'oasis init --template {template_dir} {proj_dir}')
+
+    with open(osp.join(proj_dir, 'Cargo.toml')) as f_manifest:
+        assert 'name = "my_proj"' in f_manifest.read()
+    with open(osp.join(proj_dir, 'src', 'main.rs')) as f_main:
+        assert f_main.read() == 'struct MyProj;\n'
+    assert not osp.exists(osp.join(proj_dir, 'target'))
+
+
+def test_init_template_invalid(oenv, temp_dir):
+    proj_dir = osp.join(temp_dir, 'proj')
+
+    cp = oenv.run(f'oasis init --template {osp.join(temp_dir, "missing")} {proj_dir}',
+                  check=False, stderr=PIPE)
+    assert 'is not a directory' in cp.stderr
+
+    cp = oenv.run(f'oasis init --template {temp_dir} {proj_dir}', check=False, stderr=PIPE)
+    assert 'contains neither a `Cargo.toml` nor a `package.json`' in cp.stderr
+    assert not osp.exists(proj_dir)
+
+
+def test_init_nested(oenv, temp_dir):
+    outer_dir = osp.join(temp_dir, 'outer')
+    os.makedirs(outer_dir)
+    with open(osp.join(outer_dir, 'Cargo.toml'), 'w') as f_manifest:
+        f_manifest.write('[package]\nname = "outer"\n')
+
+    cp = oenv.run('oasis init inner', cwd=outer_dir, check=False, stderr=PIPE)
+    assert 'Pass `--nested` to create it anyway' in cp.stderr
+    assert not osp.exists(osp.join(outer_dir, 'inner'))
+
+    oenv.run('oasis init --nested inner', cwd=outer_dir)
+    assert osp.isfile(osp.join(outer_dir, 'inner', 'Cargo.toml'))
+
+    # Projects above the workspace root belong to another workspace.
+    workspace_dir = osp.join(outer_dir, 'workspace')
+    os.makedirs(workspace_dir)
+    oenv.run('oasis --workspace-root . init inner', cwd=workspace_dir)
+    assert osp.isfile(osp.join(workspace_dir, 'inner', 'Cargo.toml'))
+
+
+def test_init_no_git(oenv, temp_dir):
+    template_dir = osp.join(temp_dir, 'template')
+    os.makedirs(osp.join(template_dir, '.github'))
+    with open(osp.join(template_dir, 'Cargo.toml'), 'w') as f_manifest:
+        f_manifest.write('[package]\nname = "quickstart"\n')
+
+    proj_dir = osp.join(temp_dir, 'proj')
+    oenv.run(f'oasis init --no-git --template {template_dir} {proj_dir}')
+    assert not osp.exists(osp.join(proj_dir, '.git'))
+    assert osp.isdir(osp.join(proj_dir, '.github'))
+
+    # A project created inside of an existing repo becomes part of that repo.
+    repo_dir = osp.join(temp_dir, 'repo')
+    os.makedirs(repo_dir)
+    oenv.run('git init', cwd=repo_dir)
+    sub_dir = osp.join(repo_dir, 'sub')
+    oenv.run(f'oasis init --template {template_dir} {sub_dir}')
+    assert not osp.exists(osp.join(sub_dir, '.git'))
+    assert not osp.exists(osp.join(sub_dir, '.github'))
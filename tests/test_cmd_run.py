@@ -0,0 +1,19 @@
+"""Tests `oasis run`."""
+
+import os.path as osp
+import shutil
+from subprocess import PIPE
+
+
+def test_run_invalid_rpc(oenv, temp_dir):
+    multiproj_dir = osp.join(temp_dir, 'multiproj')
+    shutil.copytree(osp.join(osp.dirname(__file__), 'res', 'multiproj'), multiproj_dir)
+    oenv.run('git init .', cwd=multiproj_dir)
+
+    def run_errors(args):
+        cp = oenv.run(f'oasis run {args}', cwd=multiproj_dir, check=False, stderr=PIPE)
+        assert cp.returncode != 0
+        return cp.stderr
+
+    assert '`a` has no rpc named `nope`' in run_errors('a nope')
+    assert '`say_hello` takes no arguments, but 1 were given' in run_errors('a say_hello 1')
@@ -1,12 +1,17 @@
 """Tests `oasis set-toolchain`."""
 
 import os.path as osp
+import socket
 import subprocess
 from subprocess import PIPE
 import sys
+import time
 
 import pytest
 
+# pylint: disable=relative-beyond-top-level
+from .conftest import TARGET_DIR
+
 MOCK_SERVER_PY = osp.join(osp.dirname(__file__), 'mock_tools_server.py')
 
 
@@ -50,3 +55,61 @@ def test_set_toolchain_named(oenv, tools_proxy, mock_tool):
     invocation = mock_tool.parse_output(cp.stdout)[0]
     assert invocation['name'] == osp.join(oenv.bin_dir, 'oasis-chain')
     assert invocation['user'] == f'{sys.platform} 19.20 oasis-chain abcdef0'
+
+
+def test_update(oenv, tools_proxy, mock_tool):
+    env = {'http_proxy': tools_proxy}
+    cp = oenv.run('oasis update --check', input='', env=env, stderr=PIPE, check=False)
+    assert 'a toolchain update is available: none -> 20.19' in cp.stderr
+    assert cp.returncode != 0
+
+    oenv.run('oasis set-toolchain 19.20', input='', env=env)
+    # `oasis` now refers to the mock that was just installed.
+    oasis = osp.join(TARGET_DIR, 'oasis')
+    cp = oenv.run(f'{oasis} update', input='', env=env, stdout=PIPE)
+    assert 'updated the toolchain: 19.20 -> 20.19' in cp.stdout
+    cp = oenv.run('oasis-chain', stdout=PIPE)
+    assert mock_tool.parse_output(cp.stdout)[0]['user'].endswith('20.19 oasis-chain 0fedcba')
+
+    cp = oenv.run(f'{oasis} update --check', input='', env=env, stdout=PIPE)
+    assert '20.19 is up-to-date' in cp.stdout
+
+
+@pytest.fixture
+def hung_proxy():
+    """Yields the URL of a proxy that accepts connections but never responds."""
+    with socket.socket() as sock:
+        sock.bind(('localhost', 0))
+        sock.listen()
+        yield f'http://localhost:{sock.getsockname()[1]}'
+
+
+def test_set_toolchain_timeout(oenv, hung_proxy):
+    env = {'http_proxy': hung_proxy, 'OASIS_HTTP_TIMEOUT': '1'}
+    cp = oenv.run('oasis set-toolchain latest', input='', env=env, stderr=PIPE, check=False)
+    assert 'timed out after 1s' in cp.stderr
+    assert cp.returncode != 0
+
+
+def test_update_check(oenv, tools_proxy):
+    env = {'http_proxy': tools_proxy}
+    oenv.run('oasis set-toolchain 19.20', input='', env=env)
+    oasis = osp.join(TARGET_DIR, 'oasis')  # `oasis` now refers to the installed mock
+    check_file = osp.join(oenv.home_dir, '.cache', 'oasis', 'update_check.json')
+
+    oenv.run(f'{oasis} dirs', env=env)
+    assert not osp.exists(check_file)  # not opted in
+
+    oenv.run(f'{oasis} config toolchain.update_check true')
+    oenv.run(f'{oasis} dirs', env=env)
+    for _ in range(100):  # the check runs in the background
+        with open(check_file) as f_check:
+            if '20.19' in f_check.read():
+                break
+        time.sleep(0.1)
+
+    cp = oenv.run(f'{oasis} dirs', env=env, stderr=PIPE)
+    assert 'a newer toolchain (20.19) is available; run `oasis update`' in cp.stderr
+
+    cp = oenv.run(f'{oasis} dirs', env={'CI': 'true', **env}, stderr=PIPE)
+    assert 'a newer toolchain' not in cp.stderr
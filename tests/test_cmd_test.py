@@ -1,6 +1,8 @@
 """Tests `oasis test`."""
 
+import os
 import os.path as osp
+import re
 from subprocess import PIPE
 
 # pylint: disable=relative-beyond-top-level
@@ -40,3 +42,165 @@ def test_testing_profile_options(oenv, mock_tool):
 
     cp = oenv.run('oasis test --profile oasisbook', cwd=proj_dir, stderr=PIPE, check=False)
     assert '`profile.oasisbook` does not exist' in cp.stderr
+
+
+def test_changed_since(oenv, mock_tool):
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'yarn'))
+    proj_dir = oenv.create_project()
+    oenv.run('git add -A', cwd=proj_dir)
+    oenv.run('git -c user.name=oasis -c user.email=oasis@localhost commit -qm init', cwd=proj_dir)
+
+    cp = oenv.run('oasis test --changed-since HEAD', cwd=proj_dir, stdout=PIPE)
+    assert not mock_tool.parse_output(cp.stdout)
+
+    oenv.run('echo >> yarn.lock', cwd=proj_dir)
+    cp = oenv.run('oasis test --changed-since HEAD', cwd=proj_dir, stdout=PIPE)
+    assert mock_tool.parse_output(cp.stdout)
+
+    cp = oenv.run('oasis test --changed-since not-a-ref', cwd=proj_dir, stdout=PIPE, stderr=PIPE)
+    assert 'Testing all targets' in cp.stderr
+    assert mock_tool.parse_output(cp.stdout)
+
+
+def test_changed_since_nested_workspace(oenv, mock_tool):
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'yarn'))
+    repo_dir = osp.join(oenv.home_dir, 'monorepo')
+    os.makedirs(repo_dir)
+    oenv.run('git init -q', cwd=repo_dir)
+    oenv.run('oasis init app', cwd=repo_dir)
+    oenv.run('git add -A', cwd=repo_dir)
+    oenv.run('git -c user.name=oasis -c user.email=oasis@localhost commit -qm init', cwd=repo_dir)
+    app_dir = osp.join(repo_dir, 'app')
+
+    oenv.run('echo >> yarn.lock', cwd=app_dir)
+    cp = oenv.run('oasis --workspace-root . test --changed-since HEAD', cwd=app_dir, stdout=PIPE)
+    assert mock_tool.parse_output(cp.stdout)
+
+
+def test_release_builds_once(oenv, mock_tool, mock_cargo):
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'yarn'))
+    cargo = mock_cargo()
+    proj_dir = oenv.create_project()
+
+    cp = oenv.run('oasis test --release', cwd=proj_dir, stdout=PIPE, check=False)
+    cargo_args = [inv['args'] for inv in mock_tool.parse_output(cp.stdout)
+                  if inv['name'] == cargo]
+    builds = [args for args in cargo_args if 'build' in args]
+    tests = [args for args in cargo_args if 'test' in args]
+    assert builds and tests
+    assert all('--release' in args for args in builds + tests)
+    built_bins = [args[args.index('--bin') + 1] for args in builds]
+    assert len(built_bins) == len(set(built_bins))
+
+
+def _create_crate(crate_dir, name, features):
+    os.makedirs(osp.join(crate_dir, 'src'))
+    with open(osp.join(crate_dir, 'Cargo.toml'), 'w') as f_manifest:
+        f_manifest.write(f'[package]\nname = "{name}"\nversion = "0.1.0"\nedition = "2018"\n\n'
+                         '[features]\n' + ''.join(f'{feature} = []\n' for feature in features))
+    with open(osp.join(crate_dir, 'src', 'main.rs'), 'w') as f_main:
+        f_main.write('fn main() {}\n')
+
+
+def test_features(oenv, mock_tool, mock_cargo, temp_dir):
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'yarn'))
+    cargo = mock_cargo()
+    _create_crate(osp.join(temp_dir, 'one'), 'one', ['a', 'b'])
+    _create_crate(osp.join(temp_dir, 'two'), 'two', ['b', 'c'])
+    oenv.run('git init .', cwd=temp_dir)
+
+    # Each crate only gets the features that it declares.
+    cp = oenv.run('oasis test --features "a b" --features two/c --no-default-features '
+                  '-- --nocapture', cwd=temp_dir, stdout=PIPE, check=False)
+    cargo_args = [inv['args'] for inv in mock_tool.parse_output(cp.stdout)
+                  if inv['name'] == cargo]
+    builds = [args for args in cargo_args if 'build' in args]
+    tests = [args for args in cargo_args if 'test' in args]
+    assert builds and tests
+    crate_features = {'one': 'a b', 'two': 'b c'}
+    for args in builds + tests:
+        crate = args[args.index('--bin') + 1]
+        assert args[args.index('--features') + 1] == crate_features[crate]
+        assert '--no-default-features' in args
+    for args in tests:
+        assert args.index('--features') < args.index('--') < args.index('--nocapture')
+
+    for features in ['d', 'one/c']:
+        cp = oenv.run(f'oasis test --features {features}', cwd=temp_dir, stderr=PIPE,
+                      check=False)
+        assert cp.returncode != 0
+        assert f'no package that is being built has the feature `{features}`' in cp.stderr
+
+
+def test_env_passthrough(oenv, mock_tool):
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'yarn'))
+    proj_dir = oenv.create_project()
+
+    cp = oenv.run('oasis test --env MY_VAR=a=b --env OASIS_PROFILE=x', cwd=proj_dir,
+                  stdout=PIPE, stderr=PIPE)
+    test_invocation = next(inv for inv in mock_tool.parse_output(cp.stdout)
+                           if 'test' in inv['args'])
+    assert test_invocation['env']['MY_VAR'] == 'a=b'
+    assert test_invocation['env']['OASIS_PROFILE'] == 'local'
+    assert '`--env OASIS_PROFILE` may be overridden' in cp.stderr
+
+    cp = oenv.run('oasis test --env MY_VAR', cwd=proj_dir, stderr=PIPE, check=False)
+    assert 'Expected `KEY=VALUE`' in cp.stderr
+
+
+def test_rust_profile_envs(oenv, mock_tool, mock_cargo):
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'yarn'))
+    cargo = mock_cargo()
+    proj_dir = oenv.create_project()
+
+    cp = oenv.run('oasis test', cwd=proj_dir, stdout=PIPE, check=False)
+    cargo_tests = [inv for inv in mock_tool.parse_output(cp.stdout)
+                   if inv['name'] == cargo and 'test' in inv['args']]
+    assert cargo_tests
+    for inv in cargo_tests:
+        assert inv['env']['OASIS_PROFILE'] == 'local'
+        assert inv['env']['OASIS_GATEWAY_URL'] == 'ws://localhost:8546/'
+
+
+def test_no_build_missing_artifacts(oenv, mock_tool, mock_cargo):
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'yarn'))
+    cargo = mock_cargo()
+    proj_dir = oenv.create_project()
+
+    cp = oenv.run('oasis test --no-build', cwd=proj_dir, stdout=PIPE, stderr=PIPE, check=False)
+    assert 'has not been built' in cp.stderr
+    assert cp.returncode != 0
+    assert not any(inv['name'] == cargo and 'build' in inv['args']
+                   for inv in mock_tool.parse_output(cp.stdout))
+
+
+def test_testing_gateway_options(oenv, mock_tool):
+    mock_tool.create_at(osp.join(oenv.bin_dir, 'yarn'))
+    proj_dir = oenv.create_project()
+
+    cp = oenv.run(f'oasis test --gateway http://localhost:1234 --credential {SAMPLE_KEY}',
+                  cwd=proj_dir, stdout=PIPE)
+    test_env = mock_tool.parse_output(cp.stdout)[1]['env']
+    assert test_env['OASIS_GATEWAY_URL'] == 'http://localhost:1234/'
+    assert test_env['OASIS_CREDENTIAL'] == SAMPLE_KEY
+    assert 'OASIS_PROFILE' not in test_env
+
+    cp = oenv.run('oasis test --gateway http://localhost:1234', cwd=proj_dir, stderr=PIPE,
+                  check=False)
+    assert '`--gateway` requires `--credential` or `OASIS_CREDENTIAL`' in cp.stderr
+
+    cp = oenv.run(f'oasis test --gateway localhost --credential {SAMPLE_KEY}', cwd=proj_dir,
+                  stderr=PIPE, check=False)
+    assert 'invalid gateway `localhost`' in cp.stderr
+
+
+def test_test_timeout(oenv):
+    mock_yarn = osp.join(oenv.bin_dir, 'yarn')
+    with open(mock_yarn, 'w') as f_yarn:
+        f_yarn.write('#!/bin/bash\nfor arg in "$@"; do [ "$arg" = test ] && sleep 30; done\n')
+    os.chmod(mock_yarn, 0o755)
+    proj_dir = oenv.create_project()
+
+    cp = oenv.run('oasis test --test-timeout 1', cwd=proj_dir, stderr=PIPE, check=False)
+    assert re.search(r'testing `[\w-]+` \(`yarn test`\) failed: process `yarn` timed out after 1s',
+                     cp.stderr)
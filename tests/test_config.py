@@ -1,5 +1,7 @@
 """Tests `oasis config` and the generation of the config files."""
 
+import json
+import os
 import os.path as osp
 import re
 from subprocess import PIPE
@@ -41,6 +43,60 @@ def test_telemetry_enabled(oenv):
     assert osp.isfile(oenv.metrics_file)
 
 
+def test_telemetry_upload_settings(oenv):
+    oenv.telemetry_config()
+    oenv.run('oasis config telemetry.upload_threshold_bytes 1024')
+    oenv.run('oasis config telemetry.upload_min_interval_secs 0')
+    cp = oenv.run('oasis config telemetry.upload_threshold_bytes', stdout=PIPE)
+    assert cp.stdout == '1024\n'
+    oenv.run('oasis config telemetry.upload_threshold_bytes 8589934592')  # more than a `u32`
+    cp = oenv.run('oasis config telemetry.upload_threshold_bytes', stdout=PIPE)
+    assert cp.stdout == '8589934592\n'
+    oenv.run('oasis config telemetry.upload_threshold_bytes 1024')
+    cp = oenv.run('oasis config telemetry.upload_min_interval_secs', stdout=PIPE)
+    assert cp.stdout == '0\n'
+
+    cp = oenv.run('oasis config telemetry.upload_threshold_bytes lots', stderr=PIPE, check=False)
+    assert cp.returncode != 0
+    assert 'must be a non-negative integer' in cp.stderr
+
+
+def test_telemetry_event_fields(oenv):
+    oenv.telemetry_config()
+    oenv.run('oasis init test')
+    with open(oenv.metrics_file) as f_metrics:
+        events = [json.loads(line) for line in f_metrics if line.strip()]
+    assert events
+    for event in events:
+        assert event['cli_version']
+        assert 'toolchain' in event  # `null` when no toolchain is installed
+
+
+def test_telemetry_redacts_credential(oenv):
+    oenv.telemetry_config()
+    for credential_arg in [f'--credential "{SAMPLE_KEY}"', f'--credential="{SAMPLE_KEY}"']:
+        cp = oenv.run(f'oasis test --gateway http://localhost:1 {credential_arg}', check=False)
+        assert cp.returncode != 0
+    with open(oenv.metrics_file) as f_metrics:
+        metrics = f_metrics.read()
+    assert '--credential' in metrics
+    assert SAMPLE_KEY not in metrics
+
+
+def test_telemetry_complete_event(oenv):
+    oenv.telemetry_config()
+    oenv.run('oasis init test')
+    oenv.run('oasis config profile.default.num_tokens 9001', stderr=PIPE, check=False)
+    with open(oenv.metrics_file) as f_metrics:
+        events = [json.loads(line) for line in f_metrics if line.strip()]
+    events = {e['event']: e for e in events if e['event'].endswith('.complete')}
+    init_data = events['cmd.init.complete']['data']
+    assert init_data['success']
+    assert isinstance(init_data['duration_ms'], int)
+    assert set(init_data) == {'duration_ms', 'success'}
+    assert not events['cmd.config.complete']['data']['success']
+
+
 def test_edit_invalid_key(oenv):
     cp = oenv.run('oasis config profile.default.num_tokens 9001', stderr=PIPE, check=False)
     assert 'unknown profile configuration key `num_tokens`' in cp.stderr
@@ -82,3 +138,242 @@ def test_edit_gateway(oenv):
 def test_edit_gateway_invalid(oenv):
     cp = oenv.run('oasis config profile.default.gateway "not://a-url!"', check=False, stderr=PIPE)
     assert 'invalid' in cp.stderr
+
+
+def test_edit_build_default_release(oenv):
+    oenv.run('oasis config build.default_release false')
+    cp = oenv.run('oasis config build.default_release', stdout=PIPE)
+    assert cp.stdout.rstrip() == 'false'
+
+    cp = oenv.run('oasis config build.default_release maybe', check=False, stderr=PIPE)
+    assert '`build.default_release` must be `true` or `false`' in cp.stderr
+
+
+def test_build_release_env_invalid(oenv):
+    env = {'OASIS_BUILD_RELEASE': 'maybe'}
+    cp = oenv.run('oasis build', env=env, check=False, stderr=PIPE)
+    assert '`OASIS_BUILD_RELEASE` must be one of' in cp.stderr
+
+
+def test_build_rustc_wrapper(oenv):
+    oenv.run('oasis config build.rustc_wrapper /nonexistent/oasis-build')
+    cp = oenv.run('oasis build', check=False, stderr=PIPE)
+    assert 'the rustc wrapper `/nonexistent/oasis-build` does not exist' in cp.stderr
+
+    env = {'OASIS_BUILD_BIN': 'relative/oasis-build'}
+    cp = oenv.run('oasis build', env=env, check=False, stderr=PIPE)
+    assert 'must be an absolute path' in cp.stderr
+
+
+def test_dotenv(oenv):
+    with open(osp.join(oenv.home_dir, '.env'), 'w') as f_dotenv:
+        f_dotenv.write('OASIS_BUILD_RELEASE=maybe\n')
+
+    cp = oenv.run('oasis build', check=False, stderr=PIPE)
+    assert '`OASIS_BUILD_RELEASE` must be one of' in cp.stderr
+
+    cp = oenv.run('oasis build', env={'OASIS_BUILD_RELEASE': '1'}, check=False, stderr=PIPE)
+    assert 'OASIS_BUILD_RELEASE' not in cp.stderr
+
+    cp = oenv.run('oasis build --no-dotenv', check=False, stderr=PIPE)
+    assert 'OASIS_BUILD_RELEASE' not in cp.stderr
+
+    # `--no-dotenv` after `--` belongs to the build tool.
+    cp = oenv.run('oasis build -- --no-dotenv', check=False, stderr=PIPE)
+    assert '`OASIS_BUILD_RELEASE` must be one of' in cp.stderr
+
+
+def test_dotenv_workspace_root(oenv):
+    workspace_dir = osp.join(oenv.home_dir, 'workspace')
+    sub_dir = osp.join(workspace_dir, 'sub')
+    os.makedirs(sub_dir)
+    with open(osp.join(workspace_dir, '.env'), 'w') as f_dotenv:
+        f_dotenv.write('OASIS_BUILD_RELEASE=maybe\n')
+
+    cp = oenv.run('oasis build --workspace-root ..', cwd=sub_dir, check=False, stderr=PIPE)
+    assert '`OASIS_BUILD_RELEASE` must be one of' in cp.stderr
+
+    # Without a git repo, the workspace root is the nearest directory with a manifest.
+    open(osp.join(workspace_dir, 'package.json'), 'w').close()
+    cp = oenv.run('oasis build', cwd=sub_dir, check=False, stderr=PIPE)
+    assert '`OASIS_BUILD_RELEASE` must be one of' in cp.stderr
+
+
+def test_migrate_legacy_config(oenv):
+    oenv.default_config()
+    legacy_config = f'''[logging]
+path_stdout = "/tmp/oasis.out"
+path_stderr = "/tmp/oasis.err"
+enabled = true
+id = "legacy-user"
+
+[profile]
+default = {{ endpoint = "https://gateway.devnet.oasiscloud.io", private_key = "{SAMPLE_KEY}" }}
+
+[profile.local]
+endpoint = "ws://localhost:8546"
+mnemonic = "{SAMPLE_MNEMONIC}"
+'''
+    with open(oenv.config_file, 'w') as f_config:
+        f_config.write(legacy_config)
+
+    oenv.run('oasis config migrate')
+    with open(oenv.config_file + '.bak') as f_backup:
+        assert f_backup.read() == legacy_config
+
+    def _get(key):
+        return oenv.run(f'oasis config {key}', stdout=PIPE).stdout.rstrip()
+
+    assert _get('profile.default.gateway') == 'https://gateway.devnet.oasiscloud.io'
+    assert _get('profile.default.credential') == SAMPLE_KEY
+    assert _get('profile.local.gateway') == 'ws://localhost:8546'
+    assert _get('profile.local.credential') == SAMPLE_MNEMONIC
+    assert _get('telemetry.enabled') == 'true'
+    assert _get('telemetry.user_id') == 'legacy-user'
+    assert not _get('logging')
+
+    cp = oenv.run('oasis config migrate', stdout=PIPE)
+    assert 'already up to date' in cp.stdout
+
+
+def test_edit_dry_run(oenv):
+    oenv.run(f'oasis config profile.default.credential "{SAMPLE_KEY}"')
+    with open(oenv.config_file) as f_config:
+        orig_config = f_config.read()
+
+    cp = oenv.run('oasis config --dry-run profile.default.gateway http://localhost:1234',
+                  stdout=PIPE)
+    assert 'gateway = "http://localhost:1234/"' in cp.stdout
+    assert 'credential = "<redacted>"' in cp.stdout
+    assert SAMPLE_KEY not in cp.stdout
+    assert '[profile.local]' in cp.stdout
+
+    with open(oenv.config_file) as f_config:
+        assert f_config.read() == orig_config
+
+
+def test_edit_preserves_comments(oenv):
+    oenv.default_config()
+    commented_config = '''# My Oasis configuration.
+[profile.default]
+gateway = "https://gateway.devnet.oasiscloud.io"  # the devnet
+
+# Telemetry helps improve the CLI.
+[telemetry]
+enabled = false  # opt-in
+user_id = "abc"
+'''
+    with open(oenv.config_file, 'w') as f_config:
+        f_config.write(commented_config)
+
+    oenv.run('oasis config telemetry.enabled true')
+    oenv.run('oasis config profile.default.gateway http://localhost:1234')
+
+    with open(oenv.config_file) as f_config:
+        config = f_config.read()
+    assert config == commented_config \
+        .replace('enabled = false', 'enabled = true') \
+        .replace('https://gateway.devnet.oasiscloud.io', 'http://localhost:1234/')
+
+
+def test_whoami(oenv):
+    cp = oenv.run('oasis whoami -p local', stdout=PIPE)
+    assert 'credential: mnemonic' in cp.stdout
+    assert 'address:    0xb8b3666d8fea887d97ab54f571b8e5020c5c8b58' in cp.stdout
+    assert SAMPLE_MNEMONIC not in cp.stdout
+
+    oenv.run(f'oasis config profile.default.credential "{SAMPLE_TOKEN}"')
+    cp = oenv.run('oasis whoami', stdout=PIPE)
+    assert 'credential: API token' in cp.stdout
+    assert f'token:      {SAMPLE_TOKEN[:4]}*' in cp.stdout
+    assert SAMPLE_TOKEN not in cp.stdout
+
+
+def test_logging_to_file(oenv, temp_dir):
+    oenv.run('oasis config logging.enabled true')
+    oenv.run(f'oasis config logging.dir {temp_dir}')
+    cp = oenv.run('oasis config logging.max_size lots', check=False, stderr=PIPE)
+    assert '`logging.max_size` must be a number of bytes' in cp.stderr
+
+    oenv.run('oasis build', cwd=temp_dir, check=False, stderr=PIPE)
+    with open(osp.join(temp_dir, 'oasis.log')) as f_log:
+        records = [json.loads(line) for line in f_log]
+    assert any(r['level'] == 'error' and 'could not find workspace' in r['message']
+               for r in records)
+
+
+def test_alternate_config_file(oenv, temp_dir):
+    alt_config = osp.join(temp_dir, 'alt.toml')
+    with open(alt_config, 'w') as f_config:
+        f_config.write(f'[profile.default]\ngateway = "http://localhost:1234"\n'
+                       f'credential = "{SAMPLE_KEY}"\n')
+
+    cp = oenv.run(f'oasis --config {alt_config} config profile.default.gateway', stdout=PIPE)
+    assert cp.stdout.rstrip() == 'http://localhost:1234'
+
+    oenv.run(f'oasis config --config={alt_config} profile.default.gateway http://localhost:5678')
+    with open(alt_config) as f_config:
+        assert 'http://localhost:5678' in f_config.read()
+    with open(oenv.config_file) as f_config:
+        assert 'http://localhost:5678' not in f_config.read()
+
+    with open(alt_config, 'w') as f_config:
+        f_config.write('[profile.default\n')
+    cp = oenv.run(f'oasis --config {alt_config} config profile.default.gateway http://localhost:1',
+                  stderr=PIPE, check=False)
+    assert cp.returncode != 0
+    assert 'could not load config file' in cp.stderr
+    with open(oenv.config_file) as f_config:
+        assert 'http://localhost:1"' not in f_config.read()
+
+
+def test_dir_overrides(oenv, temp_dir):
+    cp = oenv.run('oasis dirs', stdout=PIPE)
+    dirs = dict(line.split(':', 1) for line in cp.stdout.splitlines())
+    assert dirs['config'].strip() == oenv.config_dir
+    assert dirs['data'].strip() == oenv.data_dir
+
+    alt_config_dir = osp.join(temp_dir, 'config')
+    alt_data_dir = osp.join(temp_dir, 'data')
+    env = {'OASIS_CONFIG_DIR': alt_config_dir, 'OASIS_DATA_DIR': alt_data_dir}
+    cp = oenv.run('oasis dirs', env=env, stdout=PIPE)
+    dirs = dict(line.split(':', 1) for line in cp.stdout.splitlines())
+    assert dirs['config'].strip() == alt_config_dir
+    assert dirs['data'].strip() == alt_data_dir
+
+    oenv.run('oasis config profile.default.gateway http://localhost:5678', env=env)
+    with open(osp.join(alt_config_dir, 'config.toml')) as f_config:
+        assert 'http://localhost:5678' in f_config.read()
+
+    cp = oenv.run('oasis dirs', env={'OASIS_CONFIG_DIR': 'relative/config'}, stdout=PIPE)
+    dirs = dict(line.split(':', 1) for line in cp.stdout.splitlines())
+    assert dirs['config'].strip() == oenv.config_dir
+
+
+def test_copy_profile(oenv):
+    oenv.run(f'oasis config profile.default.credential "{SAMPLE_KEY}"')
+    oenv.run('oasis config profile copy default staging')
+    cp = oenv.run('oasis config profile.staging.credential', stdout=PIPE)
+    assert cp.stdout.rstrip() == SAMPLE_KEY
+
+    oenv.run('oasis config profile.staging.gateway http://localhost:1234')
+    cp = oenv.run('oasis config profile.default.gateway', stdout=PIPE)
+    assert cp.stdout.rstrip() == 'https://gateway.devnet.oasiscloud.io'
+
+    cp = oenv.run('oasis config profile copy local staging', check=False, stderr=PIPE)
+    assert '`profile.staging` already exists' in cp.stderr
+    cp = oenv.run('oasis config profile copy nonexistent other', check=False, stderr=PIPE)
+    assert '`profile.nonexistent` does not exist' in cp.stderr
+
+    cp = oenv.run('oasis config profile', stdout=PIPE)
+    assert 'staging' in cp.stdout
+
+
+def test_unknown_key_warning(oenv):
+    oenv.default_config()
+    with open(oenv.config_file, 'a') as f_config:
+        f_config.write('\n[profile.typo]\ngatway = "ws://localhost:8546"\n')
+
+    cp = oenv.run('oasis config profile.local.gateway', stdout=PIPE, stderr=PIPE)
+    assert cp.stdout.rstrip() == 'ws://localhost:8546'
+    assert 'unknown configuration key `profile.typo.gatway`' in cp.stderr